@@ -0,0 +1,274 @@
+//! Benchmarks the server on generated ledgers of 10k, 100k and 500k postings
+//! split over several include files, driving it over an in-memory connection
+//! like a client would.
+//!
+//! Run with `cargo bench --bench large_ledger`; the ledger sizes can be
+//! narrowed down with criterion's filter, e.g. `cargo bench -- 10000`. The
+//! allocations of an edit are printed after the timings of each size.
+
+#[allow(dead_code)]
+#[path = "../tests/support/mod.rs"]
+mod support;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use serde_json::json;
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use support::TestServer;
+
+const POSTINGS: [usize; 3] = [10_000, 100_000, 500_000];
+const INCLUDE_FILES: usize = 10;
+const ACCOUNTS: usize = 50;
+/// Opened at the end of the last include file, so the forest is complete
+/// once it shows up in the workspace symbols.
+const SENTINEL: &str = "Assets:Sentinel";
+
+/// Writes a ledger with `postings` postings to a temporary directory and
+/// returns the path of its root file.
+fn generate_ledger(postings: usize) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("beancount-bench-{postings}"));
+    let root = dir.join("main.beancount");
+    if root.exists() {
+        return root;
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut main = String::from("option \"operating_currency\" \"USD\"\n\n");
+    for account in 0..ACCOUNTS {
+        writeln!(main, "2020-01-01 open Expenses:Category{account} USD").unwrap();
+    }
+    writeln!(main, "2020-01-01 open Assets:Bank:Checking USD\n").unwrap();
+    for file in 0..INCLUDE_FILES {
+        writeln!(main, "include \"part-{file}.beancount\"").unwrap();
+    }
+    std::fs::write(&root, main).unwrap();
+
+    // every transaction has two postings
+    let transactions_per_file = postings / 2 / INCLUDE_FILES;
+    for file in 0..INCLUDE_FILES {
+        let mut part = String::new();
+        for transaction in 0..transactions_per_file {
+            let day = transaction % 28 + 1;
+            let month = transaction / 28 % 12 + 1;
+            let account = (file * transactions_per_file + transaction) % ACCOUNTS;
+            writeln!(
+                part,
+                "2021-{month:02}-{day:02} * \"Payee {account}\" \"Transaction {transaction}\"\n  Expenses:Category{account}  {}.{:02} USD\n  Assets:Bank:Checking\n",
+                transaction % 500,
+                transaction % 100,
+            )
+            .unwrap();
+        }
+        if file == INCLUDE_FILES - 1 {
+            writeln!(part, "2021-12-31 open {SENTINEL} USD").unwrap();
+        }
+        std::fs::write(dir.join(format!("part-{file}.beancount")), part).unwrap();
+    }
+    root
+}
+
+/// Starts a server on `root` and waits until the whole forest has been
+/// parsed.
+fn start_server(root: &Path) -> TestServer {
+    let mut server = TestServer::new(json!({ "journal_file": root.to_str().unwrap() }));
+    loop {
+        let symbols = server.request::<lsp_types::request::WorkspaceSymbolRequest>(
+            lsp_types::WorkspaceSymbolParams {
+                query: SENTINEL.to_string(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        );
+        if symbols
+            .as_array()
+            .is_some_and(|symbols| !symbols.is_empty())
+        {
+            return server;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Opens `path` in the editor, returning the document and its number of lines.
+fn open(server: &mut TestServer, path: &Path) -> (lsp_types::TextDocumentIdentifier, u32) {
+    let text = std::fs::read_to_string(path).unwrap();
+    let document = server.open(path.to_str().unwrap(), &text);
+    (document, text.lines().count() as u32)
+}
+
+/// Inserts nothing at the start of `document`, which still makes the server
+/// update its state, and then asks for a hover, which takes a snapshot.
+fn type_and_hover(
+    server: &mut TestServer,
+    document: &lsp_types::TextDocumentIdentifier,
+    version: i32,
+) -> serde_json::Value {
+    let position = lsp_types::Position::new(0, 0);
+    server.notify::<lsp_types::notification::DidChangeTextDocument>(
+        lsp_types::DidChangeTextDocumentParams {
+            text_document: lsp_types::VersionedTextDocumentIdentifier::new(
+                document.uri.clone(),
+                version,
+            ),
+            content_changes: vec![lsp_types::TextDocumentContentChangeEvent {
+                range: Some(lsp_types::Range::new(position, position)),
+                range_length: None,
+                text: String::new(),
+            }],
+        },
+    );
+    server.request::<lsp_types::request::HoverRequest>(lsp_types::HoverParams {
+        text_document_position_params: lsp_types::TextDocumentPositionParams::new(
+            document.clone(),
+            position,
+        ),
+        work_done_progress_params: Default::default(),
+    })
+}
+
+/// Counts the allocations of the whole process, the server threads included,
+/// as criterion only measures time.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations `f` made and the bytes they requested.
+fn allocations(f: impl FnOnce()) -> (usize, usize) {
+    let count = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    f();
+    (
+        ALLOCATIONS.load(Ordering::Relaxed) - count,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+fn forest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("forest");
+    group.sample_size(10);
+    for postings in POSTINGS {
+        let root = generate_ledger(postings);
+        group.bench_with_input(BenchmarkId::from_parameter(postings), &root, |b, root| {
+            b.iter_custom(|iterations| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iterations {
+                    let start = Instant::now();
+                    let server = start_server(root);
+                    total += start.elapsed();
+                    server.shutdown();
+                }
+                total
+            })
+        });
+    }
+    group.finish();
+}
+
+fn providers(c: &mut Criterion) {
+    for postings in POSTINGS {
+        let root = generate_ledger(postings);
+        let part = root.with_file_name("part-0.beancount");
+        let mut server = start_server(&root);
+        let (document, lines) = open(&mut server, &part);
+
+        let mut group = c.benchmark_group("providers");
+        group.sample_size(20);
+        group.bench_function(BenchmarkId::new("completion", postings), |b| {
+            b.iter(|| {
+                server.request::<lsp_types::request::Completion>(lsp_types::CompletionParams {
+                    text_document_position: lsp_types::TextDocumentPositionParams::new(
+                        document.clone(),
+                        lsp_types::Position::new(1, 4),
+                    ),
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                    context: None,
+                })
+            })
+        });
+        group.bench_function(BenchmarkId::new("formatting", postings), |b| {
+            b.iter(|| {
+                server.request::<lsp_types::request::Formatting>(
+                    lsp_types::DocumentFormattingParams {
+                        text_document: document.clone(),
+                        options: lsp_types::FormattingOptions {
+                            tab_size: 4,
+                            insert_spaces: true,
+                            ..Default::default()
+                        },
+                        work_done_progress_params: Default::default(),
+                    },
+                )
+            })
+        });
+        group.bench_function(BenchmarkId::new("inlay_hints", postings), |b| {
+            b.iter(|| {
+                server.request::<lsp_types::request::InlayHintRequest>(lsp_types::InlayHintParams {
+                    text_document: document.clone(),
+                    range: lsp_types::Range::new(
+                        lsp_types::Position::new(0, 0),
+                        lsp_types::Position::new(lines, 0),
+                    ),
+                    work_done_progress_params: Default::default(),
+                })
+            })
+        });
+        // an edit followed by a cheap request, so this mostly measures
+        // updating the state and taking the snapshot while typing
+        let mut version = 0;
+        group.bench_function(BenchmarkId::new("typing", postings), |b| {
+            b.iter(|| {
+                version += 1;
+                type_and_hover(&mut server, &document, version)
+            })
+        });
+        group.finish();
+
+        let iterations = 100;
+        let (count, bytes) = allocations(|| {
+            for _ in 0..iterations {
+                version += 1;
+                type_and_hover(&mut server, &document, version);
+            }
+        });
+        println!(
+            "providers/typing/{postings}: {} allocations, {} bytes per edit",
+            count / iterations,
+            bytes / iterations
+        );
+        server.shutdown();
+    }
+}
+
+criterion_group!(benches, forest, providers);
+criterion_main!(benches);
@@ -0,0 +1,654 @@
+use crate::beancount_data::PRECISION;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Add;
+use std::ops::AddAssign;
+use std::ops::Mul;
+use std::ops::Neg;
+use std::ops::Sub;
+use std::str::FromStr;
+
+/// The separators numbers are written with, like `1,234.56` or `1.234,56`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberFormat {
+    pub decimal_separator: char,
+    pub grouping_separator: Option<char>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            grouping_separator: Some(','),
+        }
+    }
+}
+
+impl NumberFormat {
+    /// The format with a decimal comma, like `1.234,56`.
+    pub const DECIMAL_COMMA: NumberFormat = NumberFormat {
+        decimal_separator: ',',
+        grouping_separator: Some('.'),
+    };
+
+    /// Guesses the format of the numbers in `text`: a comma followed by one or
+    /// two digits, or a dot followed by three digits and a comma, is taken as
+    /// a decimal comma. Defaults to a decimal point.
+    pub fn infer(text: &str) -> Self {
+        let mut decimal_commas = 0;
+        let mut decimal_points = 0;
+        for number in text
+            .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .filter(|number| number.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let number = number.trim_end_matches(['.', ',']);
+            match (number.rfind(','), number.rfind('.')) {
+                (Some(comma), Some(point)) if comma > point => decimal_commas += 1,
+                (Some(_), Some(_)) => decimal_points += 1,
+                (Some(comma), None) if number.len() - comma - 1 != 3 => decimal_commas += 1,
+                (None, Some(point)) if number.len() - point - 1 != 3 => decimal_points += 1,
+                _ => {}
+            }
+        }
+        if decimal_commas > decimal_points {
+            Self::DECIMAL_COMMA
+        } else {
+            Self::default()
+        }
+    }
+
+    /// `number` with its integer digits grouped by threes, like `1,000,000.00`,
+    /// or unchanged without a grouping separator.
+    pub fn group(&self, number: &str) -> String {
+        let Some(separator) = self.grouping_separator else {
+            return number.to_string();
+        };
+        let number = self.ungroup(number);
+        let digits_start = number
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(number.len());
+        let digits_end = number[digits_start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(number.len(), |end| digits_start + end);
+        let digits = &number[digits_start..digits_end];
+        let mut grouped = String::from(&number[..digits_start]);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(separator);
+            }
+            grouped.push(digit);
+        }
+        grouped.push_str(&number[digits_end..]);
+        grouped
+    }
+
+    /// `number` without the grouping separators of its integer digits.
+    pub fn ungroup(&self, number: &str) -> String {
+        let Some(separator) = self.grouping_separator else {
+            return number.to_string();
+        };
+        let decimals = number.find(self.decimal_separator).unwrap_or(number.len());
+        let (integer, fraction) = number.split_at(decimals);
+        integer.replace(separator, "") + fraction
+    }
+
+    /// `number` written in this format, without grouping.
+    pub fn display(&self, number: Decimal) -> String {
+        let text = number.to_string();
+        if self.decimal_separator == '.' {
+            text
+        } else {
+            text.replace('.', &self.decimal_separator.to_string())
+        }
+    }
+
+    /// Whether `c` can be part of a number written in this format.
+    pub fn is_number_char(&self, c: char) -> bool {
+        c.is_ascii_digit() || c == self.decimal_separator || Some(c) == self.grouping_separator
+    }
+}
+
+/// A fixed point decimal number, as used for beancount amounts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    pub fn new(mantissa: i128, scale: u32) -> Self {
+        Self { mantissa, scale }
+    }
+
+    /// Parses a number like `-1,234.56`. Grouping commas are ignored.
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::parse_with(text, NumberFormat::default())
+    }
+
+    /// Parses a number written in `format`, like `-1.234,56` with a decimal
+    /// comma.
+    pub fn parse_with(text: &str, format: NumberFormat) -> Option<Self> {
+        let text = text.trim();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, text.strip_prefix('+').unwrap_or(text).trim_start()),
+        };
+        let mut mantissa: i128 = 0;
+        let mut scale = 0;
+        let mut seen_point = false;
+        let mut seen_digit = false;
+        for c in digits.chars() {
+            match c {
+                '0'..='9' => {
+                    mantissa = mantissa
+                        .checked_mul(10)?
+                        .checked_add(c as i128 - '0' as i128)?;
+                    seen_digit = true;
+                    if seen_point {
+                        scale += 1;
+                    }
+                }
+                c if c == format.decimal_separator && !seen_point => seen_point = true,
+                c if Some(c) == format.grouping_separator && !seen_point => {}
+                _ => return None,
+            }
+        }
+        if !seen_digit {
+            return None;
+        }
+        Some(Self {
+            mantissa: if negative { -mantissa } else { mantissa },
+            scale,
+        })
+    }
+
+    /// Evaluates an arithmetic expression like `(100.00 / 3)` or `-2 * 5.5`
+    /// with numbers written in `format`. Quotients keep
+    /// [`PRECISION`](crate::beancount_data::PRECISION) fractional digits.
+    pub fn evaluate(text: &str, format: NumberFormat) -> Option<Self> {
+        let mut tokens = vec![];
+        let mut chars = text.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if format.is_number_char(c) {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek().filter(|c| format.is_number_char(**c)) {
+                    number.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Number(Decimal::parse_with(&number, format)?));
+            } else {
+                tokens.push(Token::Operator(c));
+                chars.next();
+            }
+        }
+        let mut tokens = tokens.into_iter().peekable();
+        let value = parse_sum(&mut tokens)?;
+        tokens.next().is_none().then_some(value)
+    }
+
+    /// Evaluates an expression like [`Decimal::evaluate`], dropping trailing
+    /// zeros down to the scale of its most precise number, so `(100.00 / 4)`
+    /// is `25.00` and `100 / 3` keeps all its decimals.
+    pub fn evaluate_at_literal_scale(text: &str, format: NumberFormat) -> Option<Self> {
+        let value = Self::evaluate(text, format)?.normalize();
+        let scale = text
+            .split(|c: char| !format.is_number_char(c))
+            .filter_map(|number| Self::parse_with(number, format))
+            .map(|number| number.scale)
+            .max()
+            .unwrap_or_default();
+        Some(value.with_scale(value.scale.max(scale)))
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.mantissa < 0
+    }
+
+    pub fn abs(self) -> Self {
+        Self {
+            mantissa: self.mantissa.abs(),
+            scale: self.scale,
+        }
+    }
+
+    /// Rounds (half away from zero) or pads the number to `scale` digits.
+    pub fn with_scale(self, scale: u32) -> Self {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => self,
+            Ordering::Greater => Self {
+                mantissa: self.mantissa * 10i128.pow(scale - self.scale),
+                scale,
+            },
+            Ordering::Less => {
+                let divisor = 10i128.pow(self.scale - scale);
+                let quotient = self.mantissa / divisor;
+                let remainder = self.mantissa % divisor;
+                let rounded = if remainder.abs() * 2 >= divisor {
+                    quotient + self.mantissa.signum()
+                } else {
+                    quotient
+                };
+                Self {
+                    mantissa: rounded,
+                    scale,
+                }
+            }
+        }
+    }
+
+    /// Divides by `other`, keeping `scale` fractional digits.
+    pub fn checked_div(self, other: Decimal, scale: u32) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        // (a / 10^sa) / (b / 10^sb) = a * 10^(sb + scale + 1 - sa) / b / 10^(scale + 1)
+        let shift = (other.scale + scale + 1) as i64 - self.scale as i64;
+        let numerator = if shift >= 0 {
+            self.mantissa
+                .checked_mul(10i128.checked_pow(shift as u32)?)?
+        } else {
+            self.mantissa / 10i128.checked_pow((-shift) as u32)?
+        };
+        let quotient = Self {
+            mantissa: numerator / other.mantissa,
+            scale: scale + 1,
+        };
+        Some(quotient.with_scale(scale))
+    }
+
+    /// Removes trailing fractional zeros.
+    pub fn normalize(self) -> Self {
+        let mut result = self;
+        while result.scale > 0 && result.mantissa % 10 == 0 {
+            result.mantissa /= 10;
+            result.scale -= 1;
+        }
+        result
+    }
+
+    fn aligned(self, other: Decimal) -> (i128, i128, u32) {
+        let scale = self.scale.max(other.scale);
+        (
+            self.with_scale(scale).mantissa,
+            other.with_scale(scale).mantissa,
+            scale,
+        )
+    }
+}
+
+enum Token {
+    Number(Decimal),
+    Operator(char),
+}
+
+type Tokens = std::iter::Peekable<std::vec::IntoIter<Token>>;
+
+/// `term (('+' | '-') term)*`
+fn parse_sum(tokens: &mut Tokens) -> Option<Decimal> {
+    let mut value = parse_product(tokens)?;
+    while let Some(Token::Operator(operator @ ('+' | '-'))) = tokens.peek() {
+        let operator = *operator;
+        tokens.next();
+        let rhs = parse_product(tokens)?;
+        value = if operator == '+' {
+            value + rhs
+        } else {
+            value - rhs
+        };
+    }
+    Some(value)
+}
+
+/// `factor (('*' | '/') factor)*`
+fn parse_product(tokens: &mut Tokens) -> Option<Decimal> {
+    let mut value = parse_factor(tokens)?;
+    while let Some(Token::Operator(operator @ ('*' | '/'))) = tokens.peek() {
+        let operator = *operator;
+        tokens.next();
+        let rhs = parse_factor(tokens)?;
+        value = if operator == '*' {
+            value * rhs
+        } else {
+            value.checked_div(rhs, PRECISION)?
+        };
+    }
+    Some(value)
+}
+
+/// `number | ('+' | '-') factor | '(' sum ')'`
+fn parse_factor(tokens: &mut Tokens) -> Option<Decimal> {
+    match tokens.next()? {
+        Token::Number(number) => Some(number),
+        Token::Operator('-') => parse_factor(tokens).map(|value| -value),
+        Token::Operator('+') => parse_factor(tokens),
+        Token::Operator('(') => {
+            let value = parse_sum(tokens)?;
+            matches!(tokens.next(), Some(Token::Operator(')'))).then_some(value)
+        }
+        Token::Operator(_) => None,
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b, _) = self.aligned(*other);
+        a.cmp(&b)
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        Decimal::new(a + b, scale)
+    }
+}
+
+impl AddAssign for Decimal {
+    fn add_assign(&mut self, other: Decimal) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        Decimal::new(a - b, scale)
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: Decimal) -> Decimal {
+        Decimal::new(self.mantissa * other.mantissa, self.scale + other.scale)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(-self.mantissa, self.scale)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Decimal::parse(s).ok_or_else(|| anyhow::anyhow!("invalid number: {}", s))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        if self.scale == 0 {
+            return write!(f, "{sign}{digits}");
+        }
+        let scale = self.scale as usize;
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        write!(f, "{sign}{int}.{frac}")
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let text = match &value {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Number(number) => number.to_string(),
+            _ => return Err(serde::de::Error::custom("expected a decimal number")),
+        };
+        Decimal::parse(&text).ok_or_else(|| serde::de::Error::custom("invalid decimal number"))
+    }
+}
+
+/// A number together with its currency.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Amount {
+    pub number: Decimal,
+    pub currency: String,
+}
+
+impl Amount {
+    pub fn new(number: Decimal, currency: impl Into<String>) -> Self {
+        Self {
+            number,
+            currency: currency.into(),
+        }
+    }
+
+    /// Parses an amount like `10.00 USD`.
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::parse_with(text, NumberFormat::default())
+    }
+
+    /// Parses an amount whose number is written in `format`.
+    pub fn parse_with(text: &str, format: NumberFormat) -> Option<Self> {
+        let (number, currency) = text.trim().rsplit_once(char::is_whitespace)?;
+        Some(Self {
+            number: Decimal::parse_with(number, format)?,
+            currency: currency.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.number, self.currency)
+    }
+}
+
+/// The whole number expression `node` is part of, like `(100.00 / 3)` for
+/// the node of `3`, or the `number` node itself outside of an expression.
+pub fn number_expr_for_tree_sitter_node(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    let is_number =
+        |node: &tree_sitter::Node| node.kind() == "number" || node.kind().ends_with("number_expr");
+    let mut expression = Some(node).filter(is_number)?;
+    while let Some(parent) = expression.parent().filter(is_number) {
+        expression = parent;
+    }
+    Some(expression)
+}
+
+/// Reads the number of a `number` node, or evaluates a number expression
+/// node like `binary_number_expr`.
+pub fn number_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    format: NumberFormat,
+) -> Option<Decimal> {
+    if node.kind() == "number" || node.kind().ends_with("number_expr") {
+        Decimal::evaluate(&text_for_tree_sitter_node(content, node), format)
+    } else {
+        None
+    }
+}
+
+/// Reads the amount of an `amount`, `incomplete_amount` or `amount_tolerance`
+/// node. Incomplete amounts without a number or currency yield `None`.
+pub fn amount_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    format: NumberFormat,
+) -> Option<Amount> {
+    let mut cursor = node.walk();
+    let mut number = None;
+    let mut currency = None;
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "currency" => {
+                currency = Some(text_for_tree_sitter_node(content, &child));
+                // the grammar only knows decimal points, so a number like
+                // `1.234,56` is split up; read everything before the currency
+                let before = content
+                    .byte_slice(node.start_byte()..child.start_byte())
+                    .to_string();
+                if let Some(whole) = Decimal::evaluate(&before, format) {
+                    number = Some(whole);
+                }
+            }
+            _ if number.is_none() => number = number_for_tree_sitter_node(content, &child, format),
+            _ => {}
+        }
+    }
+    Some(Amount::new(number?, currency?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_decimal_parse() {
+        assert_eq!(Decimal::parse("1,234.50"), Some(Decimal::new(123450, 2)));
+        assert_eq!(Decimal::parse("- 3"), Some(Decimal::new(-3, 0)));
+        assert_eq!(Decimal::parse("abc"), None);
+        assert_eq!(Decimal::parse("."), None);
+    }
+
+    #[test]
+    fn handle_decimal_parse_with_decimal_comma() {
+        let format = NumberFormat::DECIMAL_COMMA;
+        assert_eq!(
+            Decimal::parse_with("-1.234,56", format),
+            Some(Decimal::new(-123456, 2))
+        );
+        assert_eq!(
+            Amount::parse_with("10,5 EUR", format),
+            Some(Amount::new(Decimal::new(105, 1), "EUR"))
+        );
+        assert_eq!(Decimal::parse_with("1,2,3", format), None);
+    }
+
+    #[test]
+    fn handle_number_format_group() {
+        let format = NumberFormat::default();
+        assert_eq!(format.group("1000000.00"), "1,000,000.00");
+        assert_eq!(format.group("-1500"), "-1,500");
+        assert_eq!(format.group("100,0.5"), "1,000.5");
+        assert_eq!(format.ungroup("1,000,000.00"), "1000000.00");
+        let format = NumberFormat::DECIMAL_COMMA;
+        assert_eq!(format.group("1234567,891"), "1.234.567,891");
+        assert_eq!(format.ungroup("1.234,5"), "1234,5");
+    }
+
+    #[test]
+    fn handle_number_format_infer() {
+        let decimal_comma =
+            "2023-01-01 * \"Shop\"\n  Assets:Bank -1.234,56 EUR\n  Expenses:Food 10,50 EUR\n";
+        assert_eq!(
+            NumberFormat::infer(decimal_comma),
+            NumberFormat::DECIMAL_COMMA
+        );
+        let decimal_point =
+            "2023-01-01 * \"Shop\"\n  Assets:Bank -1,234.56 USD\n  Expenses:Food 1,000 USD\n";
+        assert_eq!(NumberFormat::infer(decimal_point), NumberFormat::default());
+        assert_eq!(NumberFormat::infer(""), NumberFormat::default());
+    }
+
+    #[test]
+    fn handle_decimal_evaluate() {
+        let format = NumberFormat::default();
+        assert_eq!(
+            Decimal::evaluate("(100.00 / 4)", format),
+            Some(Decimal::new(25, 0))
+        );
+        assert_eq!(
+            Decimal::evaluate("-2 * 5.5 + 1,000", format),
+            Some(Decimal::new(989, 0))
+        );
+        assert_eq!(
+            Decimal::evaluate("10 - 2 - 3", format),
+            Some(Decimal::new(5, 0))
+        );
+        assert_eq!(
+            Decimal::evaluate("100 / 3", format),
+            Some(Decimal::new(333333333333, 10))
+        );
+        assert_eq!(
+            Decimal::evaluate("2,5 * 2", NumberFormat::DECIMAL_COMMA),
+            Some(Decimal::new(5, 0))
+        );
+        assert_eq!(Decimal::evaluate("1 / 0", format), None);
+        assert_eq!(Decimal::evaluate("(1 + 2", format), None);
+        assert_eq!(Decimal::evaluate("1 2", format), None);
+    }
+
+    #[test]
+    fn handle_decimal_arithmetic() {
+        let a = Decimal::parse("10.50").unwrap();
+        let b = Decimal::parse("0.5").unwrap();
+        assert_eq!((a + b).to_string(), "11.00");
+        assert_eq!((b - a).to_string(), "-10.00");
+        assert_eq!((a * b).to_string(), "5.250");
+        assert_eq!(
+            Decimal::parse("100")
+                .unwrap()
+                .checked_div(Decimal::parse("3").unwrap(), 2),
+            Decimal::parse("33.33")
+        );
+        assert_eq!(
+            Decimal::parse("0.005").unwrap().with_scale(2).to_string(),
+            "0.01"
+        );
+        assert_eq!(Decimal::parse("-0.07").unwrap().to_string(), "-0.07");
+    }
+
+    #[test]
+    fn handle_amount_parse() {
+        assert_eq!(
+            Amount::parse("-10.00 USD"),
+            Some(Amount::new(Decimal::new(-1000, 2), "USD"))
+        );
+        assert_eq!(Amount::parse("USD"), None);
+    }
+}
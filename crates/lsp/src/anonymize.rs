@@ -0,0 +1,240 @@
+//! Scrambles a journal for sharing it in bug reports. Accounts, payees,
+//! narrations and numbers are rewritten while keeping their shape: every
+//! letter stays a letter of the same case, every digit a digit, and
+//! everything else, like separators, quotes and white space, is kept. The
+//! same name always becomes the same scrambled name, so the structure of the
+//! journal survives, but transactions with all amounts written out may no
+//! longer balance. Comments and other strings are kept as they are.
+
+use crate::providers::code_lens::percent_encode;
+use crate::providers::register::RegisterDocument;
+use crate::server::LspServerStateSnapshot;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::str::FromStr;
+use tracing::debug;
+
+/// The URI scheme of the anonymized documents.
+pub(crate) const ANONYMIZED_SCHEME: &str = "beancount-anonymized";
+
+/// Arguments of the `beancount.anonymize` command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnonymizeParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+}
+
+/// Consistent scrambling of words, without two words ever becoming the same.
+#[derive(Default)]
+struct Scrambler {
+    names: HashMap<String, String>,
+    used: HashSet<String>,
+    seed: u64,
+}
+
+impl Scrambler {
+    /// The scrambled form of `name`, the same for each occurrence.
+    fn name(&mut self, name: &str) -> String {
+        if let Some(scrambled) = self.names.get(name) {
+            return scrambled.clone();
+        }
+        let mut scrambled = self.scramble(name);
+        // a name without letters or digits, or one too short to find another
+        // form for, is kept
+        for _ in 0..100 {
+            if !self.used.contains(&scrambled) {
+                break;
+            }
+            scrambled = self.scramble(name);
+        }
+        self.used.insert(scrambled.clone());
+        self.names.insert(name.to_string(), scrambled.clone());
+        scrambled
+    }
+
+    /// Replaces each letter and digit of `text` with a random one of the same
+    /// kind, keeping a leading digit from becoming `0`.
+    fn scramble(&mut self, text: &str) -> String {
+        let mut scrambled = String::with_capacity(text.len());
+        let mut first_digit = true;
+        for c in text.chars() {
+            let next = self.next_random();
+            if c.is_uppercase() {
+                scrambled.push(char::from(b'A' + (next % 26) as u8));
+            } else if c.is_alphabetic() {
+                scrambled.push(char::from(b'a' + (next % 26) as u8));
+            } else if c.is_ascii_digit() {
+                let digit = if first_digit && c != '0' {
+                    1 + next % 9
+                } else {
+                    next % 10
+                };
+                scrambled.push(char::from(b'0' + digit as u8));
+                first_digit = false;
+            } else {
+                scrambled.push(c);
+                first_digit = !matches!(c, ',' | '.' | '\'' | ' ');
+            }
+        }
+        scrambled
+    }
+
+    /// The next number of a fixed pseudo-random sequence, so the output
+    /// only depends on the input.
+    fn next_random(&mut self) -> u64 {
+        self.seed = self
+            .seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.seed >> 33
+    }
+}
+
+/// The journal `text` with its accounts, payees, narrations and numbers
+/// scrambled.
+pub fn anonymize(text: &str) -> String {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_beancount::language())
+        .unwrap();
+    let Some(tree) = parser.parse(text, None) else {
+        return text.to_string();
+    };
+
+    let mut scrambler = Scrambler::default();
+    let mut edits: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+    let mut cursor = tree.walk();
+    let mut visited_children = false;
+    loop {
+        let node = cursor.node();
+        if !visited_children {
+            let range = node.byte_range();
+            match (node.kind(), cursor.field_name()) {
+                ("account", _) => {
+                    // the first segment is the type of the account
+                    let account = &text[range.clone()];
+                    let scrambled: Vec<String> = account
+                        .split(':')
+                        .enumerate()
+                        .map(|(index, segment)| match index {
+                            0 => segment.to_string(),
+                            _ => scrambler.name(segment),
+                        })
+                        .collect();
+                    edits.push((range, scrambled.join(":")));
+                }
+                ("string", Some("payee" | "narration")) => {
+                    let string = &text[range.clone()];
+                    let inner = string.trim_matches('"');
+                    edits.push((range, format!("\"{}\"", scrambler.name(inner))));
+                }
+                ("number", _) => {
+                    let number = &text[range.clone()];
+                    edits.push((range, scrambler.scramble(number)));
+                }
+                _ => {}
+            }
+        }
+        if !visited_children && cursor.goto_first_child() {
+            continue;
+        }
+        if cursor.goto_next_sibling() {
+            visited_children = false;
+        } else if cursor.goto_parent() {
+            visited_children = true;
+        } else {
+            break;
+        }
+    }
+
+    let mut anonymized = text.to_string();
+    for (range, replacement) in edits.into_iter().rev() {
+        anonymized.replace_range(range, &replacement);
+    }
+    anonymized
+}
+
+/// Handler of the `beancount.anonymize` command. Anonymizes the document,
+/// for the client to show as a read-only document the user can share.
+pub(crate) fn anonymize_document(
+    snapshot: LspServerStateSnapshot,
+    params: AnonymizeParams,
+) -> Result<RegisterDocument> {
+    debug!("anonymize {:?}", params.text_document.uri);
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let Some(content) = snapshot.document_content(&file) else {
+        anyhow::bail!("unknown document: {}", file.display());
+    };
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let uri = lsp_types::Uri::from_str(&format!("{ANONYMIZED_SCHEME}:{}", percent_encode(&name)))?;
+    Ok(RegisterDocument {
+        uri,
+        content: anonymize(&content.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestState;
+
+    const TEXT: &str = r#"2024-01-01 open Assets:Bank:Checking USD
+2024-01-02 * "Grocer" "Weekly shopping" #food
+    Assets:Bank:Checking  -1,234.50 USD
+    Expenses:Food
+2024-01-03 * "Grocer" "Milk"
+    Assets:Bank:Checking  -2.00 USD ; cash back
+    Expenses:Food
+"#;
+
+    #[test]
+    fn handle_anonymize() {
+        let anonymized = anonymize(TEXT);
+        assert_eq!(anonymized.lines().count(), TEXT.lines().count());
+        for secret in [
+            "Bank", "Checking", "Grocer", "Weekly", "Milk", "Food", "1,234.50",
+        ] {
+            assert!(!anonymized.contains(secret), "{secret} in {anonymized}");
+        }
+        // the structure is kept: account types, currencies, tags, comments
+        // and the width of every line
+        for kept in ["Assets:", "Expenses:", "USD", "#food", "; cash back"] {
+            assert!(anonymized.contains(kept), "{kept} missing in {anonymized}");
+        }
+        for (line, original) in anonymized.lines().zip(TEXT.lines()) {
+            assert_eq!(line.len(), original.len());
+        }
+        // the same payee and account always become the same
+        let lines: Vec<_> = anonymized.lines().collect();
+        assert_eq!(lines[1][13..21], lines[4][13..21]);
+        assert_eq!(lines[2][..26], lines[5][..26]);
+        assert_eq!(anonymized, anonymize(TEXT));
+    }
+
+    #[test]
+    fn handle_anonymize_command() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let document = anonymize_document(
+            test_state.snapshot,
+            AnonymizeParams {
+                text_document: lsp_types::TextDocumentIdentifier::new(
+                    lsp_types::Uri::from_str("file:///main.beancount").unwrap(),
+                ),
+            },
+        )
+        .unwrap();
+        assert_eq!(document.uri.as_str(), "beancount-anonymized:main.beancount");
+        assert!(document.content.starts_with("2024-01-01 open Assets:"));
+        assert!(!document.content.contains("Bank"));
+    }
+}
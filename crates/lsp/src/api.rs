@@ -0,0 +1,176 @@
+//! The providers as plain functions on the text of one document, for tools
+//! that want the formatter, the outline or the completions without running a
+//! language server, like pre-commit hooks or bindings for other languages.
+//! The text gets an in-memory workspace of its own, so nothing is read from
+//! disk and includes are not followed.
+
+use crate::beancount_data::BeancountData;
+use crate::checker::CheckOutput;
+use crate::config::Config;
+use crate::document::Document;
+use crate::providers::completion;
+use crate::providers::diagnostics;
+use crate::providers::document_symbols;
+use crate::providers::formatting;
+use crate::server::LspServerStateSnapshot;
+use crate::utils::apply_edits;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A workspace holding only `text`, with `config` as the options a client
+/// would send, and the URI of the document.
+fn snapshot(
+    text: &str,
+    config: serde_json::Value,
+) -> Result<(LspServerStateSnapshot, lsp_types::Uri)> {
+    let root = std::env::current_dir()?;
+    let path = root.join("untitled.beancount");
+    let url = url::Url::from_file_path(&path)
+        .map_err(|_| anyhow::anyhow!("invalid path: {}", path.display()))?;
+    let uri = lsp_types::Uri::from_str(url.as_str())?;
+
+    let mut options = Config::new(root);
+    options.update(config)?;
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+    let Some(tree) = parser.parse(text, None) else {
+        anyhow::bail!("could not parse the text");
+    };
+    let content = ropey::Rope::from_str(text);
+    let number_format = options.number_format.for_content(&content);
+    let data = BeancountData::new(&tree, &content, options.position_encoding, number_format);
+
+    let snapshot = LspServerStateSnapshot {
+        beancount_data: Arc::new(HashMap::from([(path.clone(), data)])),
+        config: Arc::new(options),
+        forest: Arc::new(HashMap::from([(path.clone(), tree)])),
+        journals: Arc::default(),
+        open_docs: Arc::new(HashMap::from([(
+            path,
+            Document {
+                content,
+                version: 0,
+            },
+        )])),
+        cancelled: Default::default(),
+        failed_checkers: Default::default(),
+        diagnostics: Arc::default(),
+        last_checks: Arc::default(),
+    };
+    Ok((snapshot, uri))
+}
+
+/// Formats `text` like `textDocument/formatting` does, with `config` as the
+/// options a client would send, like `{"formatting": {"currencyColumn": 60}}`.
+pub fn format_string(text: &str, config: serde_json::Value) -> Result<String> {
+    let (snapshot, uri) = snapshot(text, config)?;
+    let params = lsp_types::DocumentFormattingParams {
+        text_document: lsp_types::TextDocumentIdentifier::new(uri),
+        options: lsp_types::FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        },
+        work_done_progress_params: Default::default(),
+    };
+    let edits = formatting::formatting(snapshot, params)?.unwrap_or_default();
+    Ok(apply_edits(text, &edits))
+}
+
+/// The problems the server itself finds in `text`, like flagged entries or
+/// unknown custom directives, with `config` as the options a client would
+/// send. `bean-check` is not run, so errors only it reports are missing.
+pub fn lint(text: &str, config: serde_json::Value) -> Result<Vec<lsp_types::Diagnostic>> {
+    let (snapshot, _) = snapshot(text, config)?;
+    let check = CheckOutput {
+        success: true,
+        errors: String::new(),
+    };
+    let diagnostics = diagnostics::diagnostics(
+        &snapshot.beancount_data,
+        &snapshot.forest,
+        &snapshot.config,
+        &check,
+    );
+    Ok(diagnostics.into_values().flatten().collect())
+}
+
+/// The outline of `text`, as `textDocument/documentSymbol` returns it.
+pub fn parse_symbols(text: &str) -> Result<Vec<lsp_types::DocumentSymbol>> {
+    let (snapshot, uri) = snapshot(text, serde_json::Value::Null)?;
+    let params = lsp_types::DocumentSymbolParams {
+        text_document: lsp_types::TextDocumentIdentifier::new(uri),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    match document_symbols::document_symbols(snapshot, params)? {
+        Some(lsp_types::DocumentSymbolResponse::Nested(symbols)) => Ok(symbols),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// The completions at the zero-based `line` and UTF-16 column `col` of
+/// `text`, completing from the accounts, payees and so on of `text` itself.
+pub fn complete(text: &str, line: u32, col: u32) -> Result<Vec<lsp_types::CompletionItem>> {
+    let (snapshot, uri) = snapshot(text, serde_json::Value::Null)?;
+    // what a client would send as the trigger character, where `2` only
+    // triggers at the start of a line, like the handler of the server does
+    let trigger_character = text
+        .lines()
+        .nth(line as usize)
+        .and_then(|line| {
+            let before: Vec<u16> = line.encode_utf16().take(col as usize).collect();
+            String::from_utf16_lossy(&before).chars().last()
+        })
+        .filter(|c| completion::TRIGGER_CHARACTERS.contains(c))
+        .filter(|c| *c != '2' || col <= 1);
+    let cursor = lsp_types::TextDocumentPositionParams::new(
+        lsp_types::TextDocumentIdentifier::new(uri),
+        lsp_types::Position::new(line, col),
+    );
+    Ok(completion::completion(snapshot, trigger_character, cursor)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "2024-01-01 open Assets:Bank\n2024-01-01 open Expenses:Food\n\n2024-01-02 * \"Grocer\"\n  Assets:Bank -10.00 USD\n  Expenses:Food\n";
+
+    #[test]
+    fn handle_api_format_string() {
+        let formatted = format_string(TEXT, serde_json::Value::Null).unwrap();
+        assert_eq!(formatted.lines().count(), TEXT.lines().count());
+        assert!(formatted.contains("-10.00 USD"));
+        // formatting is idempotent
+        assert_eq!(
+            format_string(&formatted, serde_json::Value::Null).unwrap(),
+            formatted
+        );
+    }
+
+    #[test]
+    fn handle_api_lint() {
+        let text = TEXT.replace("* \"Grocer\"", "! \"Grocer\"");
+        let diagnostics = lint(&text, serde_json::Value::Null).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Flagged");
+        assert_eq!(diagnostics[0].range.start.line, 3);
+        assert!(lint(TEXT, serde_json::Value::Null).unwrap().is_empty());
+    }
+
+    #[test]
+    fn handle_api_parse_symbols() {
+        let symbols = parse_symbols(TEXT).unwrap();
+        assert!(!symbols.is_empty());
+    }
+
+    #[test]
+    fn handle_api_complete() {
+        let text = format!("{TEXT}2024-01-03 * \"Grocer\"\n  Ex");
+        let items = complete(&text, 7, 4).unwrap();
+        assert!(items.iter().any(|item| item.label == "Expenses:Food"));
+    }
+}
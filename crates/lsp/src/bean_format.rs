@@ -0,0 +1,140 @@
+//! The alignment of `bean-format`, ported line for line, for the
+//! `formatting.beanFormat` option. Files formatted by the server and by
+//! `bean-format` then stay the same instead of changing back and forth, which
+//! the alignment of the syntax tree cannot promise: `bean-format` reads lines
+//! with a regular expression, counts characters rather than display columns
+//! and also aligns directives like `price`.
+
+const CURRENCY: &str = r"[A-Z][A-Z0-9'._\-]{0,22}[A-Z0-9]";
+
+/// An account name, as `bean-format` matches it: a capitalized root followed
+/// by at least one component.
+const ACCOUNT: &str = r"\p{Lu}[\p{L}\p{Nd}\-]*(?::[\p{Lu}\p{Nd}][\p{L}\p{Nd}\-]*)+";
+
+/// A line split around its number, like `bean-format` does.
+struct Line<'a> {
+    prefix: String,
+    number: Option<&'a str>,
+    rest: &'a str,
+}
+
+/// Aligns `lines` like `bean-format`, or like `bean-format --currency-column`
+/// with `currency_column`. Returns the aligned lines.
+pub(crate) fn align(lines: &[&str], currency_column: Option<usize>) -> Vec<String> {
+    let amount = regex::Regex::new(&format!(
+        r#"^([^";]*?)\s+([-+]?\s*[\d,]+(?:\.\d*)?)\s+({CURRENCY}\b.*)"#
+    ))
+    .unwrap();
+    let mut lines: Vec<Line> = lines
+        .iter()
+        .map(|line| match amount.captures(line) {
+            Some(caps) => Line {
+                prefix: caps.get(1).unwrap().as_str().to_string(),
+                number: caps.get(2).map(|number| number.as_str()),
+                rest: caps.get(3).unwrap().as_str(),
+            },
+            None => Line {
+                prefix: line.to_string(),
+                number: None,
+                rest: "",
+            },
+        })
+        .collect();
+
+    // the widths are taken before the indents are normalized
+    let width = |text: &str| text.chars().count();
+    let numbered = lines.iter().filter(|line| line.number.is_some());
+    let prefix_width = numbered
+        .clone()
+        .map(|line| width(&line.prefix))
+        .max()
+        .unwrap_or_default();
+    let number_width = numbered
+        .map(|line| width(line.number.unwrap_or_default()))
+        .max()
+        .unwrap_or_default();
+
+    normalize_indent(&mut lines);
+
+    lines
+        .iter()
+        .map(|line| {
+            let Some(number) = line.number else {
+                return line.prefix.clone();
+            };
+            match currency_column {
+                Some(column) => {
+                    let spaces = column.saturating_sub(width(&line.prefix) + width(number) + 4);
+                    format!(
+                        "{}{}  {number} {}",
+                        line.prefix,
+                        " ".repeat(spaces),
+                        line.rest
+                    )
+                }
+                None => format!(
+                    "{:<prefix_width$}  {number:>number_width$} {}",
+                    line.prefix.trim_end(),
+                    line.rest
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Indents the lines starting with an indented account by the indent most of
+/// them have, the first one seen winning a tie. Like in `bean-format`, lines
+/// without an indent or with a capitalized word that is not an account keep
+/// theirs.
+fn normalize_indent(lines: &mut [Line]) {
+    let posting = regex::Regex::new(&format!(r"^([ \t]+)({ACCOUNT}.*)")).unwrap();
+    let mut counts: Vec<(usize, usize)> = Vec::new();
+    for line in lines.iter() {
+        if let Some(caps) = posting.captures(&line.prefix) {
+            let indent = caps[1].chars().count();
+            match counts.iter_mut().find(|(width, _)| *width == indent) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((indent, 1)),
+            }
+        }
+    }
+    let Some(max_count) = counts.iter().map(|(_, count)| *count).max() else {
+        return;
+    };
+    let (indent_width, _) = counts
+        .iter()
+        .find(|(_, count)| *count == max_count)
+        .copied()
+        .unwrap_or_default();
+
+    for line in lines.iter_mut() {
+        if let Some(caps) = posting.captures(&line.prefix) {
+            line.prefix = format!("{}{}", " ".repeat(indent_width), &caps[2]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bean_format::align;
+
+    #[test]
+    fn handle_indent_of_unindented_accounts() {
+        // only indented accounts count towards the indent, and are moved to it
+        let lines = [
+            "Assets:Cash  1 USD",
+            "Equity:Opening  2 USD",
+            "   Expenses:Food  3 USD",
+            "  Notes",
+        ];
+        assert_eq!(
+            align(&lines, Some(30)),
+            [
+                "Assets:Cash                1 USD",
+                "Equity:Opening             2 USD",
+                "   Expenses:Food           3 USD",
+                "  Notes",
+            ]
+        );
+    }
+}
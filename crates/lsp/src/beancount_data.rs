@@ -1,5 +1,21 @@
+use crate::amount::amount_for_tree_sitter_node;
+use crate::amount::number_for_tree_sitter_node;
+use crate::amount::Amount;
+use crate::amount::Decimal;
+use crate::amount::NumberFormat;
+#[cfg(feature = "spellcheck")]
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::entry_for_tree_sitter_node;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
 use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::PositionEncoding;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::ops::Deref;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub struct FlaggedEntry {
@@ -13,20 +29,348 @@ pub struct FlaggedEntry {
 //    }
 //}
 
+/// A posting of a transaction, with the date of its transaction.
 #[derive(Clone, Debug)]
-pub struct BeancountData {
+pub struct PostingEntry {
+    pub date: chrono::NaiveDate,
+    pub account: String,
+    /// The amount as written, `None` if it is left for beancount to infer.
+    pub amount: Option<Amount>,
+    /// The per-unit cost from a `{...}` cost specification.
+    pub cost: Option<Amount>,
+    /// The lot date from the cost specification, e.g. `{10 USD, 2024-01-05}`.
+    pub cost_date: Option<chrono::NaiveDate>,
+    /// The lot label from the cost specification, without its quotes.
+    pub cost_label: Option<String>,
+    /// Whether the posting has a cost specification, even an empty `{}`.
+    pub at_cost: bool,
+    /// The per-unit price from an `@` or `@@` price annotation.
+    pub price: Option<Amount>,
+    /// The amounts the posting changes the balance of the account by. For a
+    /// posting without an amount these are inferred from the other postings.
+    pub units: Vec<Amount>,
+    /// The tags of the transaction, with their `#`.
+    pub tags: Vec<String>,
+    pub line: u32,
+}
+
+impl PostingEntry {
+    /// The amount the posting contributes to the balance of its transaction.
+    pub fn weight(&self) -> Option<Amount> {
+        let amount = self.amount.as_ref()?;
+        match self.cost.as_ref().or(self.price.as_ref()) {
+            Some(per_unit) => Some(Amount::new(
+                amount.number * per_unit.number,
+                per_unit.currency.clone(),
+            )),
+            None => Some(amount.clone()),
+        }
+    }
+}
+
+/// A `custom "budget"` directive.
+#[derive(Clone, Debug)]
+pub struct BudgetEntry {
+    pub date: chrono::NaiveDate,
+    pub account: String,
+    /// The budget period as written, e.g. `monthly`.
+    pub period: String,
+    pub amount: Amount,
+}
+
+/// A `custom` directive, with its values as written.
+#[derive(Clone, Debug)]
+pub struct CustomEntry {
+    /// The name without its quotes.
+    pub name: String,
+    pub name_range: lsp_types::Range,
+    pub values: Vec<CustomValue>,
+}
+
+/// A value of a `custom` directive.
+#[derive(Clone, Debug)]
+pub struct CustomValue {
+    /// The kind of the node, like `account`, `string`, `amount`, `date` or
+    /// `bool`, and `number` for any number expression.
+    pub kind: String,
+    pub text: String,
+    pub range: lsp_types::Range,
+}
+
+/// A `query` directive.
+#[derive(Clone, Debug)]
+pub struct QueryEntry {
+    pub name: String,
+    /// The query without its quotes.
+    pub query: String,
+    /// The range of the query string.
+    pub range: lsp_types::Range,
+}
+
+/// A `close` directive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloseEntry {
+    pub date: chrono::NaiveDate,
+    pub account: String,
+    pub line: u32,
+}
+
+/// The path of a `document` directive, or the directory of a `documents`
+/// option with `directory`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentEntry {
+    /// The path without its quotes.
+    pub path: String,
+    /// The range of the path string.
+    pub range: lsp_types::Range,
+    pub directory: bool,
+}
+
+impl DocumentEntry {
+    /// The path on disk, where relative paths are relative to the directory
+    /// of `file`, the file of the entry.
+    pub fn resolve(&self, file: &Path) -> PathBuf {
+        match file.parent() {
+            Some(parent) => parent.join(&self.path),
+            None => PathBuf::from(&self.path),
+        }
+    }
+}
+
+/// The path of an `include` directive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncludeEntry {
+    /// The path without its quotes.
+    pub path: String,
+    /// The range of the path string.
+    pub range: lsp_types::Range,
+}
+
+impl IncludeEntry {
+    /// The path on disk, where relative paths are relative to the directory
+    /// of `file`, the file of the entry.
+    pub fn resolve(&self, file: &Path) -> PathBuf {
+        match file.parent() {
+            Some(parent) => parent.join(&self.path),
+            None => PathBuf::from(&self.path),
+        }
+    }
+
+    pub fn is_glob(&self) -> bool {
+        self.path.contains(['*', '?', '['])
+    }
+}
+
+/// A `price` directive: the price of one unit of `currency` on `date`.
+#[derive(Clone, Debug)]
+pub struct PriceEntry {
+    pub date: chrono::NaiveDate,
+    pub currency: String,
+    pub price: Amount,
+    pub line: u32,
+}
+
+/// A forecast entry: a transaction tagged `#forecast` or a
+/// `custom "forecast"` directive.
+#[derive(Clone, Debug)]
+pub struct ForecastEntry {
+    pub start_line: u32,
+    /// The last line of the entry.
+    pub end_line: u32,
+}
+
+impl ForecastEntry {
+    fn new(node: &tree_sitter::Node) -> Self {
+        let end = node.end_position();
+        Self {
+            start_line: node.start_position().row as u32,
+            end_line: if end.column == 0 && end.row > node.start_position().row {
+                end.row as u32 - 1
+            } else {
+                end.row as u32
+            },
+        }
+    }
+
+    pub fn contains_line(&self, line: u32) -> bool {
+        self.start_line <= line && line <= self.end_line
+    }
+}
+
+/// A use of a currency outside of a `commodity` directive.
+#[derive(Clone, Debug)]
+pub struct CurrencyUsage {
+    pub currency: String,
+    /// The date of the entry the currency is used in.
+    pub date: Option<chrono::NaiveDate>,
+    pub range: lsp_types::Range,
+}
+
+/// An account written in a posting or an `open`, `close`, `balance` or `pad`
+/// directive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountReference {
+    pub range: lsp_types::Range,
+    /// Whether the account is opened there.
+    pub declaration: bool,
+}
+
+/// The human-friendly names given to an account in the metadata of its
+/// `open` directive.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountDetails {
+    /// The `description:` metadata.
+    pub description: Option<String>,
+    /// The `alias:` metadata.
+    pub alias: Option<String>,
+}
+
+/// A word of a payee or narration, for the spell checker.
+#[cfg(feature = "spellcheck")]
+#[derive(Clone, Debug)]
+pub struct DescriptionWord {
+    pub word: String,
+    pub range: lsp_types::Range,
+}
+
+/// A payee or narration string with the spaces inside its quotes trimmed.
+fn trim_description(text: &str) -> String {
+    let text = text.trim();
+    match text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    {
+        Some(inner) => format!("\"{}\"", inner.trim()),
+        None => text.to_string(),
+    }
+}
+
+/// What the forms of a payee or narration that differ only in case and
+/// spacing, like `"AMAZON"` and `"Amazon "`, have in common.
+pub fn description_key(text: &str) -> String {
+    text.trim()
+        .trim_matches('"')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The currencies of the `operating_currency` options of all files, in the
+/// order of the options, the files being taken by path.
+pub fn operating_currencies(data: &HashMap<PathBuf, BeancountData>) -> Vec<String> {
+    let mut files: Vec<_> = data.iter().collect();
+    files.sort_by_key(|(path, _)| *path);
+    let mut currencies: Vec<String> = Vec::new();
+    for currency in files
+        .into_iter()
+        .flat_map(|(_, data)| data.get_operating_currencies())
+    {
+        if !currencies.contains(currency) {
+            currencies.push(currency.clone());
+        }
+    }
+    currencies
+}
+
+/// The form each payee or narration is shown in, by its `description_key`:
+/// the one used most often, or the first in order on a tie. The counts of
+/// several files are added up.
+pub fn canonical_descriptions<'a>(
+    counts: impl IntoIterator<Item = (&'a String, &'a usize)>,
+) -> HashMap<String, String> {
+    let mut totals: HashMap<&String, usize> = HashMap::new();
+    for (form, count) in counts {
+        *totals.entry(form).or_default() += count;
+    }
+    let mut canonical: HashMap<String, (usize, &String)> = HashMap::new();
+    for (form, count) in totals {
+        let best = canonical
+            .entry(description_key(form))
+            .or_insert((count, form));
+        if count > best.0 || (count == best.0 && form < best.1) {
+            *best = (count, form);
+        }
+    }
+    canonical
+        .into_iter()
+        .map(|(key, (_, form))| (key, form.clone()))
+        .collect()
+}
+
+/// The entries of a file the providers work with. It is shared rather than
+/// copied, as every snapshot of the server state holds the data of all files.
+#[derive(Clone, Debug)]
+pub struct BeancountData(Arc<FileData>);
+
+impl Deref for BeancountData {
+    type Target = FileData;
+
+    fn deref(&self) -> &FileData {
+        &self.0
+    }
+}
+
+#[derive(Debug)]
+pub struct FileData {
     accounts: Vec<String>,
-    narration: Vec<String>,
+    account_details: HashMap<String, AccountDetails>,
+    /// How often each narration is used, as written.
+    narrations: HashMap<String, usize>,
+    /// How often each payee is used, as written.
+    payees: HashMap<String, usize>,
+    /// The narrations used with each payee, by the `description_key` of the
+    /// payee.
+    payee_narrations: HashMap<String, Vec<String>>,
+    account_pairs: HashMap<String, HashMap<String, usize>>,
+    postings: Vec<PostingEntry>,
+    budgets: Vec<BudgetEntry>,
+    forecasts: Vec<ForecastEntry>,
+    customs: Vec<CustomEntry>,
+    queries: Vec<QueryEntry>,
+    closes: Vec<CloseEntry>,
+    documents: Vec<DocumentEntry>,
     pub flagged_entries: Vec<FlaggedEntry>,
     tags: Vec<String>,
     links: Vec<String>,
+    commodities: Vec<String>,
+    declared_commodities: Vec<String>,
+    currency_usages: Vec<CurrencyUsage>,
+    /// Where each account is written, in the order of the file.
+    account_references: HashMap<String, Vec<AccountReference>>,
+    #[cfg(feature = "spellcheck")]
+    description_words: Vec<DescriptionWord>,
+    includes: Vec<String>,
+    include_entries: Vec<IncludeEntry>,
+    prices: Vec<PriceEntry>,
+    operating_currencies: Vec<String>,
+    /// The value of the `booking_method` option.
+    booking_method: Option<String>,
+    /// The booking methods of the `open` directives that have one, by account.
+    booking_methods: HashMap<String, String>,
+    /// The values of the `event` directives, by event type.
+    events: HashMap<String, Vec<String>>,
+    /// The descriptions of the `note` directives, by account.
+    notes: HashMap<String, Vec<String>>,
 }
 
 impl BeancountData {
-    pub fn new(tree: &tree_sitter::Tree, content: &ropey::Rope) -> Self {
+    pub fn new(
+        tree: &tree_sitter::Tree,
+        content: &ropey::Rope,
+        encoding: PositionEncoding,
+        format: NumberFormat,
+    ) -> Self {
         let mut accounts = vec![];
-        let mut narration = vec![];
+        let mut account_details = HashMap::new();
+        let mut booking_methods = HashMap::new();
+        let mut narrations: HashMap<String, usize> = HashMap::new();
+        let mut payees: HashMap<String, usize> = HashMap::new();
+        let mut payee_narrations: HashMap<String, Vec<String>> = HashMap::new();
+        let mut account_pairs: HashMap<String, HashMap<String, usize>> = HashMap::new();
         let mut flagged_entries = vec![];
+        #[cfg(feature = "spellcheck")]
+        let mut description_words = vec![];
 
         let mut cursor = tree.root_node().walk();
 
@@ -43,13 +387,28 @@ impl BeancountData {
                     .children(&mut node_cursor)
                     .find(|c| c.kind() == "account")?;
                 let account = text_for_tree_sitter_node(content, &account_node);
-                Some(account)
+                Some((account, node))
             });
 
         tracing::debug!("beancount_data:: update accounts");
         accounts.clear();
 
-        for account in account_strings {
+        for (account, node) in account_strings {
+            let mut details = AccountDetails::default();
+            for (key, value) in metadata_for_tree_sitter_node(content, node) {
+                match key.as_str() {
+                    "description" => details.description = Some(value),
+                    "alias" => details.alias = Some(value),
+                    _ => {}
+                }
+            }
+            if details != AccountDetails::default() {
+                account_details.insert(account.clone(), details);
+            }
+            if let Some(booking) = node.child_by_field_name("opt_booking") {
+                let booking = text_for_tree_sitter_node(content, &booking);
+                booking_methods.insert(account.clone(), booking.trim_matches('"').to_string());
+            }
             accounts.push(account);
         }
 
@@ -62,24 +421,46 @@ impl BeancountData {
             .filter(|c| c.kind() == "transaction")
             .collect::<Vec<_>>();
 
-        //TODO: consider doing something silimar with others around
-        let mut txn_string_strings: HashSet<String> = HashSet::new();
         for transaction in transactions {
+            #[cfg(feature = "spellcheck")]
+            for field in ["payee", "narration"] {
+                if let Some(node) = transaction.child_by_field_name(field) {
+                    description_words.extend(words_for_tree_sitter_node(content, &node, encoding));
+                }
+            }
+            // forecasts are not suggested until they are materialized
+            if is_forecast_transaction(content, &transaction) {
+                continue;
+            }
             if let Some(narration) = transaction.child_by_field_name("narration") {
-                txn_string_strings.insert(
-                    text_for_tree_sitter_node(content, &narration)
-                        .trim()
-                        .to_string(),
-                );
+                let narration = trim_description(&text_for_tree_sitter_node(content, &narration));
+                if let Some(payee) = transaction.child_by_field_name("payee") {
+                    let payee = trim_description(&text_for_tree_sitter_node(content, &payee));
+                    let payee_narrations =
+                        payee_narrations.entry(description_key(&payee)).or_default();
+                    if !payee_narrations.contains(&narration) {
+                        payee_narrations.push(narration.clone());
+                    }
+                    *payees.entry(payee).or_default() += 1;
+                }
+                *narrations.entry(narration).or_default() += 1;
             }
-        }
-
-        tracing::debug!("beancount_data:: update narration");
-        narration.clear();
 
-        for txn_string in txn_string_strings {
-            if !narration.contains(&txn_string) {
-                narration.push(txn_string);
+            let mut posting_cursor = transaction.walk();
+            let posting_accounts = transaction
+                .children(&mut posting_cursor)
+                .filter(|c| c.kind() == "posting")
+                .filter_map(|posting| posting.child_by_field_name("account"))
+                .map(|account| text_for_tree_sitter_node(content, &account))
+                .collect::<HashSet<_>>();
+            for account in posting_accounts.iter() {
+                for other in posting_accounts.iter().filter(|other| *other != account) {
+                    *account_pairs
+                        .entry(account.clone())
+                        .or_default()
+                        .entry(other.clone())
+                        .or_default() += 1;
+                }
             }
         }
 
@@ -158,21 +539,445 @@ impl BeancountData {
         links.sort();
         links.dedup();
 
-        Self {
+        // Update postings
+        tracing::debug!("beancount_data:: get postings");
+        let mut postings = vec![];
+        let mut forecasts = vec![];
+        let query_string = r#"
+        (transaction) @transaction
+        "#;
+        let query = tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
+            .unwrap_or_else(|_| panic!("get_position_by_query invalid query {query_string}"));
+        let mut cursor_qry = tree_sitter::QueryCursor::new();
+        let binding = content.clone().to_string();
+        let matches = cursor_qry.matches(&query, tree.root_node(), binding.as_bytes());
+        for capture in matches.into_iter().flat_map(|m| m.captures) {
+            let transaction = capture.node;
+            let Some(date) = transaction
+                .child_by_field_name("date")
+                .and_then(|date| parse_date(&text_for_tree_sitter_node(content, &date)))
+            else {
+                continue;
+            };
+            // forecasts do not affect balances until they are materialized
+            if is_forecast_transaction(content, &transaction) {
+                forecasts.push(ForecastEntry::new(&transaction));
+                continue;
+            }
+            let tags = transaction_tags(content, &transaction);
+            let mut transaction_postings = vec![];
+            let mut posting_cursor = transaction.walk();
+            for posting in transaction
+                .children(&mut posting_cursor)
+                .filter(|c| c.kind() == "posting")
+            {
+                let Some(account) = posting.child_by_field_name("account") else {
+                    continue;
+                };
+                let amount = posting
+                    .child_by_field_name("amount")
+                    .and_then(|amount| amount_for_tree_sitter_node(content, &amount, format));
+                let units_number = amount.as_ref().map(|amount| amount.number);
+                let (cost_date, cost_label) = posting
+                    .child_by_field_name("cost_spec")
+                    .map(|cost| lot_for_tree_sitter_node(content, &cost))
+                    .unwrap_or_default();
+                transaction_postings.push(PostingEntry {
+                    date,
+                    account: text_for_tree_sitter_node(content, &account),
+                    cost: posting.child_by_field_name("cost_spec").and_then(|cost| {
+                        cost_for_tree_sitter_node(content, &cost, units_number, format)
+                    }),
+                    cost_date,
+                    cost_label,
+                    at_cost: posting.child_by_field_name("cost_spec").is_some(),
+                    price: posting
+                        .child_by_field_name("price_annotation")
+                        .and_then(|price| {
+                            price_for_tree_sitter_node(content, &price, units_number, format)
+                        }),
+                    units: amount.iter().cloned().collect(),
+                    amount,
+                    tags: tags.clone(),
+                    line: posting.start_position().row as u32,
+                });
+            }
+            interpolate_missing_units(&mut transaction_postings);
+            postings.extend(transaction_postings);
+        }
+
+        // Update budgets
+        tracing::debug!("beancount_data:: get budgets");
+        let query_string = r#"
+        (custom) @custom
+        "#;
+        let query = tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
+            .unwrap_or_else(|_| panic!("get_position_by_query invalid query {query_string}"));
+        let mut cursor_qry = tree_sitter::QueryCursor::new();
+        let binding = content.clone().to_string();
+        let matches = cursor_qry.matches(&query, tree.root_node(), binding.as_bytes());
+        let mut budgets = vec![];
+        let mut customs = vec![];
+        for capture in matches.into_iter().flat_map(|m| m.captures) {
+            customs.extend(custom_entry_for_tree_sitter_node(
+                content,
+                &capture.node,
+                encoding,
+            ));
+            if let Some(budget) = budget_for_tree_sitter_node(content, &capture.node, format) {
+                budgets.push(budget);
+            } else if forecast_date_for_custom_node(content, &capture.node).is_some() {
+                forecasts.push(ForecastEntry::new(&capture.node));
+            }
+        }
+
+        // Update commodities
+        tracing::debug!("beancount_data:: get commodities");
+        let query_string = r#"
+        (currency) @currency
+        "#;
+        let query = tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
+            .unwrap_or_else(|_| panic!("get_position_by_query invalid query {query_string}"));
+        let mut cursor_qry = tree_sitter::QueryCursor::new();
+        let binding = content.clone().to_string();
+        let matches = cursor_qry.matches(&query, tree.root_node(), binding.as_bytes());
+        let mut commodities = vec![];
+        let mut declared_commodities = vec![];
+        let mut currency_usages = vec![];
+        for capture in matches.into_iter().flat_map(|m| m.captures) {
+            let currency = text_for_tree_sitter_node(content, &capture.node);
+            match entry_for_tree_sitter_node(capture.node) {
+                Some(entry) if entry.kind() == "commodity" => {
+                    declared_commodities.push(currency.clone());
+                }
+                Some(entry) if entry.kind() != "ERROR" => currency_usages.push(CurrencyUsage {
+                    currency: currency.clone(),
+                    date: entry
+                        .child_by_field_name("date")
+                        .and_then(|date| parse_date(&text_for_tree_sitter_node(content, &date))),
+                    range: lsp_range_for_tree_sitter_node(content, &capture.node, encoding),
+                }),
+                _ => {}
+            }
+            commodities.push(currency);
+        }
+        commodities.sort();
+        commodities.dedup();
+
+        // Update account references
+        tracing::debug!("beancount_data:: get account references");
+        let query_string = r#"
+        (account) @account
+        "#;
+        let query = tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
+            .unwrap_or_else(|_| panic!("get_position_by_query invalid query {query_string}"));
+        let mut cursor_qry = tree_sitter::QueryCursor::new();
+        let binding = content.clone().to_string();
+        let matches = cursor_qry.matches(&query, tree.root_node(), binding.as_bytes());
+        let mut account_references: HashMap<String, Vec<AccountReference>> = HashMap::new();
+        for capture in matches.into_iter().flat_map(|m| m.captures) {
+            let Some(entry) = entry_for_tree_sitter_node(capture.node).filter(|entry| {
+                matches!(
+                    entry.kind(),
+                    "transaction" | "open" | "close" | "balance" | "pad"
+                )
+            }) else {
+                continue;
+            };
+            account_references
+                .entry(text_for_tree_sitter_node(content, &capture.node))
+                .or_default()
+                .push(AccountReference {
+                    range: lsp_range_for_tree_sitter_node(content, &capture.node, encoding),
+                    declaration: entry.kind() == "open",
+                });
+        }
+
+        // Update prices and options
+        tracing::debug!("beancount_data:: get prices");
+        let query_string = r#"
+        (price) @price
+        (option) @option
+        "#;
+        let query = tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
+            .unwrap_or_else(|_| panic!("get_position_by_query invalid query {query_string}"));
+        let mut cursor_qry = tree_sitter::QueryCursor::new();
+        let binding = content.clone().to_string();
+        let matches = cursor_qry.matches(&query, tree.root_node(), binding.as_bytes());
+        let mut prices = vec![];
+        let mut operating_currencies = vec![];
+        let mut booking_method = None;
+        let mut documents = vec![];
+        for capture in matches.into_iter().flat_map(|m| m.captures) {
+            let node = capture.node;
+            if node.kind() == "price" {
+                if let Some(price) = price_entry_for_tree_sitter_node(content, &node, format) {
+                    prices.push(price);
+                }
+                continue;
+            }
+            let mut option_cursor = node.walk();
+            let string_nodes: Vec<_> = node
+                .children(&mut option_cursor)
+                .filter(|c| c.kind() == "string")
+                .collect();
+            let strings: Vec<_> = string_nodes
+                .iter()
+                .map(|c| {
+                    text_for_tree_sitter_node(content, c)
+                        .trim_matches('"')
+                        .to_string()
+                })
+                .collect();
+            if let [key, value] = strings.as_slice() {
+                match key.as_str() {
+                    "operating_currency" => operating_currencies.push(value.clone()),
+                    "booking_method" => booking_method = Some(value.clone()),
+                    "documents" => documents.push(DocumentEntry {
+                        path: value.clone(),
+                        range: lsp_range_for_tree_sitter_node(content, &string_nodes[1], encoding),
+                        directory: true,
+                    }),
+                    _ => {}
+                }
+            }
+        }
+
+        // Update queries
+        tracing::debug!("beancount_data:: get queries");
+        let queries = tree
+            .root_node()
+            .children(&mut cursor)
+            .filter(|c| c.kind() == "query")
+            .filter_map(|node| {
+                let mut node_cursor = node.walk();
+                let strings: Vec<_> = node
+                    .children(&mut node_cursor)
+                    .filter(|c| c.kind() == "string")
+                    .collect();
+                let [name, query] = strings.as_slice() else {
+                    return None;
+                };
+                Some(QueryEntry {
+                    name: text_for_tree_sitter_node(content, name)
+                        .trim_matches('"')
+                        .to_string(),
+                    query: unquote(&text_for_tree_sitter_node(content, query)),
+                    range: lsp_range_for_tree_sitter_node(content, query, encoding),
+                })
+            })
+            .collect();
+
+        // Update closes
+        tracing::debug!("beancount_data:: get closes");
+        let closes = tree
+            .root_node()
+            .children(&mut cursor)
+            .filter(|c| c.kind() == "close")
+            .filter_map(|node| {
+                let mut node_cursor = node.walk();
+                let account = node
+                    .children(&mut node_cursor)
+                    .find(|c| c.kind() == "account")?;
+                Some(CloseEntry {
+                    date: parse_date(&text_for_tree_sitter_node(
+                        content,
+                        &node.child_by_field_name("date")?,
+                    ))?,
+                    account: text_for_tree_sitter_node(content, &account),
+                    line: node.start_position().row as u32,
+                })
+            })
+            .collect();
+
+        // Update documents
+        tracing::debug!("beancount_data:: get documents");
+        documents.extend(
+            tree.root_node()
+                .children(&mut cursor)
+                .filter(|c| c.kind() == "document")
+                .filter_map(|node| {
+                    let mut node_cursor = node.walk();
+                    let filename = node
+                        .children(&mut node_cursor)
+                        .find(|c| c.kind() == "string")?;
+                    Some(DocumentEntry {
+                        path: unquote(&text_for_tree_sitter_node(content, &filename)),
+                        range: lsp_range_for_tree_sitter_node(content, &filename, encoding),
+                        directory: false,
+                    })
+                }),
+        );
+
+        // Update includes
+        tracing::debug!("beancount_data:: get includes");
+        let include_entries: Vec<IncludeEntry> = tree
+            .root_node()
+            .children(&mut cursor)
+            .filter(|c| c.kind() == "include")
+            .filter_map(|include| {
+                let mut include_cursor = include.walk();
+                let filename = include
+                    .children(&mut include_cursor)
+                    .find(|c| c.kind() == "string")?;
+                Some(IncludeEntry {
+                    path: text_for_tree_sitter_node(content, &filename)
+                        .trim_matches('"')
+                        .to_string(),
+                    range: lsp_range_for_tree_sitter_node(content, &filename, encoding),
+                })
+            })
+            .collect();
+        let includes = include_entries
+            .iter()
+            .map(|include| include.path.clone())
+            .collect();
+
+        // Update events and notes
+        tracing::debug!("beancount_data:: get events and notes");
+        let mut events: HashMap<String, Vec<String>> = HashMap::new();
+        let mut notes: HashMap<String, Vec<String>> = HashMap::new();
+        for node in tree
+            .root_node()
+            .children(&mut cursor)
+            .filter(|c| matches!(c.kind(), "event" | "note"))
+        {
+            let mut node_cursor = node.walk();
+            let mut key = None;
+            let mut strings = vec![];
+            for child in node.children(&mut node_cursor) {
+                match child.kind() {
+                    "account" => key = Some(text_for_tree_sitter_node(content, &child)),
+                    "string" => strings.push(text_for_tree_sitter_node(content, &child)),
+                    _ => {}
+                }
+            }
+            let (map, key, value) = match (node.kind(), key, strings.as_slice()) {
+                ("event", _, [event_type, value]) => (&mut events, event_type.clone(), value),
+                ("note", Some(account), [description]) => (&mut notes, account, description),
+                _ => continue,
+            };
+            let values = map.entry(key).or_default();
+            if !values.contains(value) {
+                values.push(value.clone());
+            }
+        }
+
+        Self(Arc::new(FileData {
             accounts,
-            narration,
+            account_details,
+            narrations,
+            payees,
+            payee_narrations,
+            account_pairs,
+            postings,
+            budgets,
+            forecasts,
+            customs,
+            queries,
+            closes,
+            documents,
             flagged_entries,
             tags,
             links,
-        }
+            commodities,
+            declared_commodities,
+            currency_usages,
+            account_references,
+            #[cfg(feature = "spellcheck")]
+            description_words,
+            includes,
+            include_entries,
+            prices,
+            operating_currencies,
+            booking_method,
+            booking_methods,
+            events,
+            notes,
+        }))
     }
+}
 
+impl FileData {
     pub fn get_accounts(&self) -> Vec<String> {
         self.accounts.clone()
     }
 
-    pub fn get_narration(&self) -> Vec<String> {
-        self.narration.clone()
+    /// The description and alias of an account opened in this file.
+    pub fn get_account_details(&self, account: &str) -> Option<&AccountDetails> {
+        self.account_details.get(account)
+    }
+
+    /// How often each narration is used, as written.
+    pub fn get_narration_counts(&self) -> &HashMap<String, usize> {
+        &self.narrations
+    }
+
+    /// How often each payee is used, as written.
+    pub fn get_payee_counts(&self) -> &HashMap<String, usize> {
+        &self.payees
+    }
+
+    /// Narrations previously used together with `payee`, or with another
+    /// form of it.
+    pub fn get_payee_narrations(&self, payee: &str) -> Vec<String> {
+        self.payee_narrations
+            .get(&description_key(payee))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The types of the `event` directives, in no particular order.
+    pub fn get_event_types(&self) -> Vec<String> {
+        self.events.keys().cloned().collect()
+    }
+
+    /// The values previously given to events of `event_type`.
+    pub fn get_event_values(&self, event_type: &str) -> Vec<String> {
+        self.events.get(event_type).cloned().unwrap_or_default()
+    }
+
+    /// The descriptions of the `note` directives, by account.
+    pub fn get_notes(&self) -> &HashMap<String, Vec<String>> {
+        &self.notes
+    }
+
+    /// Number of transactions in which `account` and `other` both have a posting.
+    pub fn get_account_pair_count(&self, account: &str, other: &str) -> usize {
+        self.account_pairs
+            .get(account)
+            .and_then(|pairs| pairs.get(other))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn get_postings(&self) -> &[PostingEntry] {
+        &self.postings
+    }
+
+    pub fn get_budgets(&self) -> &[BudgetEntry] {
+        &self.budgets
+    }
+
+    pub fn get_forecasts(&self) -> &[ForecastEntry] {
+        &self.forecasts
+    }
+
+    pub fn get_customs(&self) -> &[CustomEntry] {
+        &self.customs
+    }
+
+    pub fn get_queries(&self) -> &[QueryEntry] {
+        &self.queries
+    }
+
+    pub fn get_closes(&self) -> &[CloseEntry] {
+        &self.closes
+    }
+
+    /// The paths of the `document` directives and `documents` options.
+    pub fn get_documents(&self) -> &[DocumentEntry] {
+        &self.documents
     }
 
     pub fn get_tags(&self) -> Vec<String> {
@@ -182,4 +987,394 @@ impl BeancountData {
     pub fn get_links(&self) -> Vec<String> {
         self.links.clone()
     }
+
+    pub fn get_commodities(&self) -> Vec<String> {
+        self.commodities.clone()
+    }
+
+    /// Currencies declared with a `commodity` directive.
+    pub fn get_declared_commodities(&self) -> &[String] {
+        &self.declared_commodities
+    }
+
+    /// The paths of the `include` directives, as written.
+    pub fn get_includes(&self) -> &[String] {
+        &self.includes
+    }
+
+    pub fn get_include_entries(&self) -> &[IncludeEntry] {
+        &self.include_entries
+    }
+
+    pub fn get_currency_usages(&self) -> &[CurrencyUsage] {
+        &self.currency_usages
+    }
+
+    pub fn get_account_references(&self, account: &str) -> &[AccountReference] {
+        self.account_references
+            .get(account)
+            .map_or(&[], |references| references.as_slice())
+    }
+
+    /// The references to `account` and to the accounts below it, by account.
+    pub fn get_account_tree_references<'a>(
+        &'a self,
+        account: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a [AccountReference])> + 'a {
+        self.account_references
+            .iter()
+            .filter(move |(name, _)| {
+                name.strip_prefix(account)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+            })
+            .map(|(name, references)| (name.as_str(), references.as_slice()))
+    }
+
+    /// The words of the payees and narrations of the transactions.
+    #[cfg(feature = "spellcheck")]
+    pub fn get_description_words(&self) -> &[DescriptionWord] {
+        &self.description_words
+    }
+
+    pub fn get_prices(&self) -> &[PriceEntry] {
+        &self.prices
+    }
+
+    /// The currencies of the `operating_currency` options.
+    pub fn get_operating_currencies(&self) -> &[String] {
+        &self.operating_currencies
+    }
+
+    /// The value of the `booking_method` option, if set in this file.
+    pub fn get_booking_method(&self) -> Option<&str> {
+        self.booking_method.as_deref()
+    }
+
+    /// The booking method given in the `open` directive of an account opened
+    /// in this file.
+    pub fn get_account_booking_method(&self, account: &str) -> Option<&str> {
+        self.booking_methods.get(account).map(String::as_str)
+    }
+}
+
+/// Parses a beancount date, which may use either `-` or `/` as separator.
+pub fn parse_date(text: &str) -> Option<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+        .or_else(|_| chrono::NaiveDate::parse_from_str(text.trim(), "%Y/%m/%d"))
+        .ok()
+}
+
+/// Reads the per-unit cost of a `cost_spec` node. Total costs (`{{...}}` or
+/// the part after `#`) are spread over the `units` of the posting.
+fn cost_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    units: Option<Decimal>,
+    format: NumberFormat,
+) -> Option<Amount> {
+    let is_total = node.child(0).is_some_and(|open| open.kind() == "{{");
+    let mut cursor = node.walk();
+    let compound = node
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "cost_comp")
+        .find_map(|comp| {
+            comp.named_child(0)
+                .filter(|c| c.kind() == "compound_amount")
+        })?;
+    let currency = text_for_tree_sitter_node(content, &compound.child_by_field_name("currency")?);
+    let per = compound
+        .child_by_field_name("per")
+        .and_then(|per| number_for_tree_sitter_node(content, &per, format));
+    let total = compound
+        .child_by_field_name("total")
+        .and_then(|total| number_for_tree_sitter_node(content, &total, format));
+    let (per, total) = if is_total { (None, per) } else { (per, total) };
+    let mut number = per.unwrap_or_default();
+    if let Some(total) = total {
+        number += total.checked_div(units?.abs(), PRECISION)?;
+    }
+    Some(Amount::new(number, currency))
+}
+
+/// Reads the lot date and label of a `cost_spec` node.
+fn lot_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+) -> (Option<chrono::NaiveDate>, Option<String>) {
+    let mut date = None;
+    let mut label = None;
+    let mut cursor = node.walk();
+    for comp in node
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "cost_comp")
+        .filter_map(|comp| comp.named_child(0))
+    {
+        match comp.kind() {
+            "date" => date = parse_date(&text_for_tree_sitter_node(content, &comp)),
+            "string" => {
+                label = Some(
+                    text_for_tree_sitter_node(content, &comp)
+                        .trim_matches('"')
+                        .to_string(),
+                )
+            }
+            _ => {}
+        }
+    }
+    (date, label)
+}
+
+/// Reads the per-unit price of a `price_annotation` node. Total prices (`@@`)
+/// are spread over the `units` of the posting.
+fn price_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    units: Option<Decimal>,
+    format: NumberFormat,
+) -> Option<Amount> {
+    let is_total = node.prev_sibling().is_some_and(|at| at.kind() == "atat");
+    let price = amount_for_tree_sitter_node(content, &node.named_child(0)?, format)?;
+    if is_total {
+        let number = price.number.checked_div(units?.abs(), PRECISION)?;
+        Some(Amount::new(number, price.currency))
+    } else {
+        Some(price)
+    }
+}
+
+/// Reads a `price` directive.
+fn price_entry_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    format: NumberFormat,
+) -> Option<PriceEntry> {
+    let date = parse_date(&text_for_tree_sitter_node(
+        content,
+        &node.child_by_field_name("date")?,
+    ))?;
+    let mut cursor = node.walk();
+    let mut currency = None;
+    let mut price = None;
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "currency" if currency.is_none() => {
+                currency = Some(text_for_tree_sitter_node(content, &child))
+            }
+            "amount" => price = amount_for_tree_sitter_node(content, &child, format),
+            _ => {}
+        }
+    }
+    Some(PriceEntry {
+        date,
+        currency: currency?,
+        price: price?,
+        line: node.start_position().row as u32,
+    })
+}
+
+/// Number of fractional digits kept when dividing amounts.
+pub(crate) const PRECISION: u32 = 10;
+
+/// Infers the units of the posting without an amount, if the transaction has
+/// exactly one, from the weights of the other postings.
+fn interpolate_missing_units(postings: &mut [PostingEntry]) {
+    let mut missing = postings.iter().filter(|posting| posting.amount.is_none());
+    if missing.next().is_none() || missing.next().is_some() {
+        return;
+    }
+    let mut residual: BTreeMap<String, Decimal> = BTreeMap::new();
+    for weight in postings.iter().filter_map(PostingEntry::weight) {
+        *residual.entry(weight.currency).or_default() += weight.number;
+    }
+    let units = residual
+        .into_iter()
+        .filter(|(_, number)| !number.is_zero())
+        .map(|(currency, number)| Amount::new(-number, currency))
+        .collect();
+    if let Some(posting) = postings.iter_mut().find(|posting| posting.amount.is_none()) {
+        posting.units = units;
+    }
+}
+
+/// Reads a `custom "budget" Account "period" Amount` directive. Fava's
+/// `custom "fava-budget"` spelling is accepted as well.
+fn budget_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    format: NumberFormat,
+) -> Option<BudgetEntry> {
+    let name = text_for_tree_sitter_node(content, &node.child_by_field_name("name")?);
+    if !matches!(name.trim_matches('"'), "budget" | "fava-budget") {
+        return None;
+    }
+    let date = parse_date(&text_for_tree_sitter_node(
+        content,
+        &node.child_by_field_name("date")?,
+    ))?;
+    let mut cursor = node.walk();
+    let values: Vec<_> = node
+        .children_by_field_name("custom_value_list", &mut cursor)
+        .filter_map(|value| value.named_child(0))
+        .collect();
+    match values.as_slice() {
+        [account, period, amount]
+            if account.kind() == "account"
+                && period.kind() == "string"
+                && amount.kind() == "amount" =>
+        {
+            Some(BudgetEntry {
+                date,
+                account: text_for_tree_sitter_node(content, account),
+                period: text_for_tree_sitter_node(content, period)
+                    .trim_matches('"')
+                    .to_string(),
+                amount: amount_for_tree_sitter_node(content, amount, format)?,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Reads the name and values of any `custom` directive.
+fn custom_entry_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    encoding: PositionEncoding,
+) -> Option<CustomEntry> {
+    let name = node.child_by_field_name("name")?;
+    let mut cursor = node.walk();
+    let values = node
+        .children_by_field_name("custom_value_list", &mut cursor)
+        .filter_map(|value| value.named_child(0))
+        .map(|value| CustomValue {
+            kind: match value.kind() {
+                kind @ ("account" | "string" | "amount" | "date" | "bool") => kind.to_string(),
+                _ => String::from("number"),
+            },
+            text: text_for_tree_sitter_node(content, &value),
+            range: lsp_range_for_tree_sitter_node(content, &value, encoding),
+        })
+        .collect();
+    Some(CustomEntry {
+        name: text_for_tree_sitter_node(content, &name)
+            .trim_matches('"')
+            .to_string(),
+        name_range: lsp_range_for_tree_sitter_node(content, &name, encoding),
+        values,
+    })
+}
+
+/// The text of a string node without its quotes and escapes.
+fn unquote(text: &str) -> String {
+    let text = text.strip_prefix('"').unwrap_or(text);
+    let text = text.strip_suffix('"').unwrap_or(text);
+    text.replace("\\\"", "\"").replace("\\\\", "\\")
+}
+
+/// The tag marking transactions that have not happened yet.
+pub const FORECAST_TAG: &str = "#forecast";
+
+/// The `key: value` metadata lines of an entry, with quotes around string
+/// values removed.
+fn metadata_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: tree_sitter::Node,
+) -> Vec<(String, String)> {
+    let mut metadata = vec![];
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() != "key_value" {
+            metadata.extend(metadata_for_tree_sitter_node(content, child));
+            continue;
+        }
+        let text = text_for_tree_sitter_node(content, &child);
+        if let Some((key, value)) = text.split_once(':') {
+            metadata.push((
+                key.trim().to_string(),
+                value.trim().trim_matches('"').to_string(),
+            ));
+        }
+    }
+    metadata
+}
+
+/// The tags on the first line of a `transaction` node.
+pub(crate) fn transaction_tags(content: &ropey::Rope, node: &tree_sitter::Node) -> Vec<String> {
+    let Some(tags_links) = node.child_by_field_name("tags_links") else {
+        return vec![];
+    };
+    let mut cursor = tags_links.walk();
+    let tags = tags_links
+        .children(&mut cursor)
+        .filter(|tag| tag.kind() == "tag")
+        .map(|tag| text_for_tree_sitter_node(content, &tag))
+        .collect();
+    tags
+}
+
+/// The words of a string node, leaving out acronyms like `ATM`.
+#[cfg(feature = "spellcheck")]
+fn words_for_tree_sitter_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    encoding: PositionEncoding,
+) -> Vec<DescriptionWord> {
+    let text = text_for_tree_sitter_node(content, node);
+    let mut words = vec![];
+    let mut start = None;
+    // a trailing space ends the last word
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        let in_word = c.is_alphabetic()
+            || (c == '\'' && start.is_some() && text[i + 1..].starts_with(char::is_alphabetic));
+        match (start, in_word) {
+            (None, true) => start = Some(i),
+            (Some(word_start), false) => {
+                start = None;
+                let word = &text[word_start..i];
+                if word.chars().count() < 2 || !word.chars().any(char::is_lowercase) {
+                    continue;
+                }
+                let byte = node.start_byte() + word_start;
+                words.push(DescriptionWord {
+                    word: word.to_string(),
+                    range: lsp_types::Range::new(
+                        byte_to_lsp_position(content, byte, encoding),
+                        byte_to_lsp_position(content, byte + word.len(), encoding),
+                    ),
+                });
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Whether the `transaction` node is tagged `#forecast`.
+pub fn is_forecast_transaction(content: &ropey::Rope, node: &tree_sitter::Node) -> bool {
+    node.child_by_field_name("tags_links")
+        .is_some_and(|tags_links| {
+            let mut cursor = tags_links.walk();
+            let is_forecast = tags_links.children(&mut cursor).any(|tag| {
+                tag.kind() == "tag" && text_for_tree_sitter_node(content, &tag) == FORECAST_TAG
+            });
+            is_forecast
+        })
+}
+
+/// The date of a `custom "forecast"` directive.
+pub fn forecast_date_for_custom_node(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+) -> Option<chrono::NaiveDate> {
+    let name = text_for_tree_sitter_node(content, &node.child_by_field_name("name")?);
+    if name.trim_matches('"') != "forecast" {
+        return None;
+    }
+    parse_date(&text_for_tree_sitter_node(
+        content,
+        &node.child_by_field_name("date")?,
+    ))
 }
@@ -0,0 +1,138 @@
+//! A lexer for the Beancount Query Language of `query` directives, enough to
+//! highlight the queries. Whether a query is valid is left to `beanquery`.
+
+/// What a token of a query is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BqlTokenKind {
+    Keyword,
+    /// A name followed by `(`, like `sum` or `year`.
+    Function,
+    /// Any other name, like `account` or `position`.
+    Column,
+    String,
+    /// Numbers and dates.
+    Number,
+    Operator,
+}
+
+/// A token of a query, with its byte range in the query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BqlToken {
+    pub kind: BqlTokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+const KEYWORDS: &[&str] = &[
+    "AND", "AS", "ASC", "AT", "BALANCES", "BY", "CLEAR", "CLOSE", "DESC", "DISTINCT", "FALSE",
+    "FROM", "GROUP", "HAVING", "IN", "IS", "JOURNAL", "LIMIT", "NOT", "NULL", "ON", "OPEN", "OR",
+    "ORDER", "PIVOT", "PRINT", "SELECT", "TRUE", "WHERE",
+];
+
+/// Splits `query` into tokens, skipping white space and punctuation like
+/// commas and parentheses. An unterminated string runs to the end.
+pub fn tokens(query: &str) -> Vec<BqlToken> {
+    let bytes = query.as_bytes();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let start = index;
+        let byte = bytes[index];
+        let kind = match byte {
+            b'\'' | b'"' => {
+                index += 1;
+                while index < bytes.len() && bytes[index] != byte {
+                    index += if bytes[index] == b'\\' { 2 } else { 1 };
+                }
+                index = (index + 1).min(bytes.len());
+                BqlTokenKind::String
+            }
+            b'0'..=b'9' => {
+                while index < bytes.len()
+                    && (bytes[index].is_ascii_digit()
+                        || bytes[index] == b'.'
+                        || (bytes[index] == b'-'
+                            && bytes.get(index + 1).is_some_and(u8::is_ascii_digit)))
+                {
+                    index += 1;
+                }
+                BqlTokenKind::Number
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                while index < bytes.len()
+                    && (bytes[index].is_ascii_alphanumeric() || matches!(bytes[index], b'_' | b'.'))
+                {
+                    index += 1;
+                }
+                let word = &query[start..index];
+                let next = query[index..].trim_start();
+                if KEYWORDS.contains(&word.to_uppercase().as_str()) {
+                    BqlTokenKind::Keyword
+                } else if next.starts_with('(') {
+                    BqlTokenKind::Function
+                } else {
+                    BqlTokenKind::Column
+                }
+            }
+            b'=' | b'<' | b'>' | b'!' | b'~' | b'+' | b'-' | b'*' | b'/' => {
+                while index < bytes.len() && matches!(bytes[index], b'=' | b'<' | b'>' | b'!') {
+                    index += 1;
+                }
+                index = index.max(start + 1);
+                BqlTokenKind::Operator
+            }
+            _ => {
+                // other characters, multibyte ones included, are skipped whole
+                index += query[index..].chars().next().map_or(1, char::len_utf8);
+                continue;
+            }
+        };
+        tokens.push(BqlToken {
+            kind,
+            start,
+            end: index.min(bytes.len()),
+        });
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_bql_tokens() {
+        let query = "SELECT account, sum(position) WHERE account ~ 'Expenses:.*' AND date >= 2024-01-01 GROUP BY 1";
+        let tokens: Vec<_> = tokens(query)
+            .into_iter()
+            .map(|token| (token.kind, &query[token.start..token.end]))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (BqlTokenKind::Keyword, "SELECT"),
+                (BqlTokenKind::Column, "account"),
+                (BqlTokenKind::Function, "sum"),
+                (BqlTokenKind::Column, "position"),
+                (BqlTokenKind::Keyword, "WHERE"),
+                (BqlTokenKind::Column, "account"),
+                (BqlTokenKind::Operator, "~"),
+                (BqlTokenKind::String, "'Expenses:.*'"),
+                (BqlTokenKind::Keyword, "AND"),
+                (BqlTokenKind::Column, "date"),
+                (BqlTokenKind::Operator, ">="),
+                (BqlTokenKind::Number, "2024-01-01"),
+                (BqlTokenKind::Keyword, "GROUP"),
+                (BqlTokenKind::Keyword, "BY"),
+                (BqlTokenKind::Number, "1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_bql_unterminated_string() {
+        let tokens = tokens("WHERE payee = 'Groc");
+        assert_eq!(tokens.last().unwrap().kind, BqlTokenKind::String);
+        assert_eq!(tokens.last().unwrap().end, 19);
+    }
+}
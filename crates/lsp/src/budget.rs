@@ -0,0 +1,208 @@
+//! Tracks spending against the budgets declared with `custom "budget"`
+//! directives.
+
+use crate::amount::Amount;
+use crate::amount::Decimal;
+use crate::beancount_data::BeancountData;
+use crate::beancount_data::BudgetEntry;
+use chrono::Datelike;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Daily,
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Period {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "daily" => Some(Period::Daily),
+            "weekly" => Some(Period::Weekly),
+            "monthly" => Some(Period::Monthly),
+            "quarterly" => Some(Period::Quarterly),
+            "yearly" => Some(Period::Yearly),
+            _ => None,
+        }
+    }
+
+    /// A label naming the period that contains `date`, e.g. `2024-01`.
+    pub fn label(self, date: chrono::NaiveDate) -> String {
+        match self {
+            Period::Daily => date.format("%Y-%m-%d").to_string(),
+            Period::Weekly => date.format("%G-W%V").to_string(),
+            Period::Monthly => date.format("%Y-%m").to_string(),
+            Period::Quarterly => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+            Period::Yearly => date.format("%Y").to_string(),
+        }
+    }
+}
+
+/// The state of a budget after a posting to a budgeted account.
+#[derive(Clone, Debug)]
+pub struct PostingBudget {
+    pub file: PathBuf,
+    pub line: u32,
+    /// The account the budget is declared for.
+    pub account: String,
+    /// The label of the budget period the posting falls in.
+    pub period: String,
+    pub budget: Amount,
+    /// The amount spent in the period, including this posting.
+    pub spent: Decimal,
+    /// Whether this posting is the one that exceeded the budget.
+    pub exceeded_here: bool,
+}
+
+impl PostingBudget {
+    pub fn remaining(&self) -> Decimal {
+        self.budget.number - self.spent
+    }
+}
+
+/// Computes the budget state after every posting to a budgeted account. A
+/// budget covers its account and all sub-accounts; postings are matched to the
+/// most specific budget in effect at their date.
+pub fn posting_budgets(data: &HashMap<PathBuf, BeancountData>) -> Vec<PostingBudget> {
+    let mut budgets: Vec<&BudgetEntry> = data.values().flat_map(|d| d.get_budgets()).collect();
+    if budgets.is_empty() {
+        return vec![];
+    }
+    budgets.sort_by_key(|budget| budget.date);
+
+    let mut postings: Vec<_> = data
+        .iter()
+        .flat_map(|(file, d)| d.get_postings().iter().map(move |p| (file, p)))
+        .collect();
+    postings.sort_by(|(a_file, a), (b_file, b)| {
+        (a.date, a_file, a.line).cmp(&(b.date, b_file, b.line))
+    });
+
+    let mut spent: HashMap<(String, String), Decimal> = HashMap::new();
+    let mut result = vec![];
+    for (file, posting) in postings {
+        let budget = budgets
+            .iter()
+            .filter(|budget| budget.date <= posting.date)
+            .filter(|budget| {
+                posting.account == budget.account
+                    || posting
+                        .account
+                        .strip_prefix(budget.account.as_str())
+                        .is_some_and(|rest| rest.starts_with(':'))
+            })
+            // later budgets replace earlier ones, specific accounts win over parents
+            .max_by_key(|budget| (budget.account.len(), budget.date));
+        let Some(budget) = budget else {
+            continue;
+        };
+        let Some(period) = Period::parse(&budget.period) else {
+            continue;
+        };
+        let units: Decimal = posting
+            .units
+            .iter()
+            .filter(|units| units.currency == budget.amount.currency)
+            .fold(Decimal::ZERO, |sum, units| sum + units.number);
+        if units.is_zero() {
+            continue;
+        }
+
+        let label = period.label(posting.date);
+        let total = spent
+            .entry((budget.account.clone(), label.clone()))
+            .or_default();
+        let before = *total;
+        *total += units;
+        result.push(PostingBudget {
+            file: file.clone(),
+            line: posting.line,
+            account: budget.account.clone(),
+            period: label,
+            budget: budget.amount.clone(),
+            spent: *total,
+            exceeded_here: before <= budget.amount.number && *total > budget.amount.number,
+        });
+    }
+    result
+}
+
+/// Warnings for postings that exceed their budget.
+pub fn diagnostics(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for status in posting_budgets(data)
+        .into_iter()
+        .filter(|status| status.exceeded_here)
+    {
+        let position = lsp_types::Position {
+            line: status.line,
+            character: 0,
+        };
+        diagnostics
+            .entry(status.file.clone())
+            .or_default()
+            .push(lsp_types::Diagnostic {
+                range: lsp_types::Range {
+                    start: position,
+                    end: position,
+                },
+                message: format!(
+                    "Over budget: spent {} {} of {} for {} in {}",
+                    status.spent,
+                    status.budget.currency,
+                    status.budget,
+                    status.account,
+                    status.period
+                ),
+                severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                ..lsp_types::Diagnostic::default()
+            });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestState;
+
+    #[test]
+    fn handle_over_budget() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 custom "budget" Expenses:Food "monthly" 100.00 USD
+2024-01-02 txn "Grocer" "Food"
+    Assets:Bank -60.00 USD
+    Expenses:Food
+2024-01-20 txn "Grocer" "Food"
+    Assets:Bank -50.00 USD
+    Expenses:Food:Snacks 50.00 USD
+2024-01-21 txn "Grocer" "Food"
+    Assets:Bank -5.00 USD
+    Expenses:Food 5.00 USD
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let diags = diagnostics(&test_state.snapshot.beancount_data);
+        let diags = &diags[&PathBuf::from("/main.beancount")];
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].range.start.line, 6);
+        assert_eq!(
+            diags[0].message,
+            "Over budget: spent 110.00 USD of 100.00 USD for Expenses:Food in 2024-01"
+        );
+    }
+
+    #[test]
+    fn handle_period_label() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 5, 3).unwrap();
+        assert_eq!(Period::Quarterly.label(date), "2024-Q2");
+        assert_eq!(Period::Weekly.label(date), "2024-W18");
+        assert_eq!(Period::parse("fortnightly"), None);
+    }
+}
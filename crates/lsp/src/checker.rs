@@ -0,0 +1,655 @@
+//! Runs the checker configured with `checker.method` on a journal, falling
+//! back to the next method when one is not available or fails.
+
+use crate::config::CheckerMethod;
+use crate::config::CheckerOptions;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::debug;
+
+/// The Python program behind `pythonEmbedded`. It prints the errors like
+/// `bean-check` does, so both outputs are read the same way.
+const PYTHON_CHECK: &str = "\
+import sys
+from beancount import loader
+from beancount.parser import printer
+_, errors, _ = loader.load_file(sys.argv[1])
+printer.print_errors(errors, file=sys.stderr)
+sys.exit(1 if errors else 0)
+";
+
+const PYTHON_CMD: &str = "python3";
+
+const BEAN_PRICE_CMD: &str = "bean-price";
+
+/// How long `bean-price` may take, as a price source that does not answer
+/// would otherwise keep it running forever.
+const FETCH_PRICES_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The Python program that compiles the queries given after the journal with
+/// `beanquery`, printing `index<TAB>message` for each that does not compile.
+/// It exits with 3 when `beanquery` is not installed.
+const PYTHON_QUERY_CHECK: &str = "\
+import sys
+try:
+    import beanquery
+    from beanquery import compiler, parser
+except ImportError:
+    sys.exit(3)
+connection = beanquery.connect('beancount:' + sys.argv[1])
+for index, query in enumerate(sys.argv[2:]):
+    try:
+        compiler.compile(connection, parser.parse(query))
+    except Exception as error:
+        message = ' '.join(str(error).split())
+        print(f'{index}\\t{message}', file=sys.stderr)
+";
+
+/// The Python program that runs the query given after the journal with
+/// `beanquery` and prints the result as a table. The table goes to stderr,
+/// the only output `run` reads, like the errors of the checks.
+const PYTHON_QUERY_RUN: &str = "\
+import sys
+import beanquery
+connection = beanquery.connect('beancount:' + sys.argv[1])
+cursor = connection.execute(sys.argv[2])
+names = [column.name for column in cursor.description]
+rows = [['' if value is None else str(value) for value in row] for row in cursor.fetchall()]
+widths = [max([len(name)] + [len(row[i]) for row in rows]) for i, name in enumerate(names)]
+lines = ['  '.join(name.ljust(width) for name, width in zip(names, widths))]
+lines.append('  '.join('-' * width for width in widths))
+lines += ['  '.join(value.ljust(width) for value, width in zip(row, widths)) for row in rows]
+sys.stderr.write('\\n'.join(line.rstrip() for line in lines) + '\\n')
+";
+
+/// The program of the `systemCall` method when `checker.beanCheckCmd` is unset.
+pub(crate) const BEAN_CHECK_CMD: &str = "bean-check";
+
+/// What a checker found in the journal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct CheckOutput {
+    pub success: bool,
+    /// The errors, as `file:line: message` lines among others.
+    pub errors: String,
+}
+
+/// The methods that failed since the configuration last changed, shared by
+/// all snapshots so that later checks skip them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FailedCheckers(Arc<Mutex<HashSet<CheckerMethod>>>);
+
+impl FailedCheckers {
+    fn contains(&self, method: CheckerMethod) -> bool {
+        self.0.lock().unwrap().contains(&method)
+    }
+
+    fn insert(&self, method: CheckerMethod) {
+        self.0.lock().unwrap().insert(method);
+    }
+
+    /// Gives all methods another chance, after the configuration changed.
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+impl CheckerMethod {
+    /// The name of the method in the configuration.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CheckerMethod::PythonEmbedded => "pythonEmbedded",
+            CheckerMethod::SystemCall => "systemCall",
+            CheckerMethod::Native => "native",
+        }
+    }
+
+    /// Whether the program the method runs can be found. Whether it works is
+    /// only known once it ran.
+    fn is_available(self, bean_check_cmd: &Path) -> bool {
+        match self {
+            CheckerMethod::PythonEmbedded => find_executable(Path::new(PYTHON_CMD)),
+            CheckerMethod::SystemCall => find_executable(bean_check_cmd),
+            CheckerMethod::Native => true,
+        }
+    }
+
+    /// Checks `root_journal_file`, returning `None` if `cancelled` was set
+    /// before the check finished.
+    fn check(
+        self,
+        options: &CheckerOptions,
+        bean_check_cmd: &Path,
+        root_journal_file: &Path,
+        cancelled: &AtomicBool,
+    ) -> anyhow::Result<Option<CheckOutput>> {
+        let mut command = match self {
+            CheckerMethod::PythonEmbedded => {
+                let mut command = Command::new(PYTHON_CMD);
+                command.arg("-c").arg(PYTHON_CHECK);
+                command
+            }
+            CheckerMethod::SystemCall => {
+                let mut command = Command::new(bean_check_cmd);
+                command.args(&options.extra_args);
+                command
+            }
+            CheckerMethod::Native => {
+                return Ok(Some(CheckOutput {
+                    success: true,
+                    errors: String::new(),
+                }))
+            }
+        };
+        command.arg(root_journal_file);
+        apply_env(&mut command, &options.env);
+        let Some(output) = run(command, cancelled)? else {
+            return Ok(None);
+        };
+        // a checker that exits with an error without reporting any, like a
+        // Python traceback, is broken rather than the journal
+        if !output.success && !output.errors.lines().any(is_error_line) {
+            anyhow::bail!(
+                "exited without reporting errors: {}",
+                output.errors.lines().last().unwrap_or_default()
+            );
+        }
+        Ok(Some(output))
+    }
+}
+
+/// The first of `methods` that is available and has not failed, or `native`
+/// when none is left.
+pub(crate) fn select(
+    methods: &[CheckerMethod],
+    failed: &FailedCheckers,
+    bean_check_cmd: &Path,
+) -> CheckerMethod {
+    methods
+        .iter()
+        .copied()
+        .find(|method| !failed.contains(*method) && method.is_available(bean_check_cmd))
+        .unwrap_or(CheckerMethod::Native)
+}
+
+/// Checks `root_journal_file` with the method selected from `options`. When
+/// it fails, it is remembered in `failed`, `on_fallback` is told why and the
+/// next method takes over. Returns the method that checked the journal and its output, or
+/// `None` if `cancelled` was set.
+pub(crate) fn check(
+    options: &CheckerOptions,
+    failed: &FailedCheckers,
+    bean_check_cmd: &Path,
+    root_journal_file: &Path,
+    cancelled: &AtomicBool,
+    mut on_fallback: impl FnMut(CheckerMethod, &anyhow::Error),
+) -> Option<(CheckerMethod, CheckOutput)> {
+    loop {
+        let method = select(&options.method, failed, bean_check_cmd);
+        debug!("checking with {}", method.name());
+        match method.check(options, bean_check_cmd, root_journal_file, cancelled) {
+            Ok(output) => return output.map(|output| (method, output)),
+            Err(err) => {
+                failed.insert(method);
+                on_fallback(method, &err);
+            }
+        }
+    }
+}
+
+/// Compiles `queries` against `root_journal_file` with `beanquery`, returning
+/// the error of each query that does not compile by its index. Returns
+/// `None` when `beanquery` is not available, the program failed otherwise or
+/// `cancelled` was set.
+pub(crate) fn check_queries(
+    options: &CheckerOptions,
+    root_journal_file: &Path,
+    queries: &[String],
+    cancelled: &AtomicBool,
+) -> Option<Vec<(usize, String)>> {
+    if !find_executable(Path::new(PYTHON_CMD)) {
+        return None;
+    }
+    let mut command = Command::new(PYTHON_CMD);
+    command
+        .arg("-c")
+        .arg(PYTHON_QUERY_CHECK)
+        .arg(root_journal_file)
+        .args(queries);
+    apply_env(&mut command, &options.env);
+    let output = match run(command, cancelled) {
+        Ok(Some(output)) if output.success => output,
+        Ok(Some(output)) => {
+            debug!("query check failed: {}", output.errors);
+            return None;
+        }
+        Ok(None) => return None,
+        Err(err) => {
+            debug!("query check failed: {}", err);
+            return None;
+        }
+    };
+    Some(parse_query_errors(&output.errors))
+}
+
+/// Runs `query` on `root_journal_file` with `beanquery`, returning the result
+/// as a text table.
+pub(crate) fn run_query(
+    options: &CheckerOptions,
+    root_journal_file: &Path,
+    query: &str,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<String> {
+    if !find_executable(Path::new(PYTHON_CMD)) {
+        anyhow::bail!("{} is not available", PYTHON_CMD);
+    }
+    let mut command = Command::new(PYTHON_CMD);
+    command
+        .arg("-c")
+        .arg(PYTHON_QUERY_RUN)
+        .arg(root_journal_file)
+        .arg(query);
+    apply_env(&mut command, &options.env);
+    let Some(output) = run(command, cancelled)? else {
+        anyhow::bail!("the query was cancelled");
+    };
+    if !output.success {
+        anyhow::bail!(
+            "the query failed: {}",
+            output.errors.lines().last().unwrap_or_default()
+        );
+    }
+    Ok(output.errors)
+}
+
+/// Fetches the latest prices of the commodities of `root_journal_file` with
+/// `bean-price`, returning what it prints: a `price` directive per line.
+/// It gives up after `FETCH_PRICES_TIMEOUT` or as soon as `cancelled` is set.
+pub(crate) fn fetch_prices(
+    options: &CheckerOptions,
+    root_journal_file: &Path,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<String> {
+    if !find_executable(Path::new(BEAN_PRICE_CMD)) {
+        anyhow::bail!("{} is not available", BEAN_PRICE_CMD);
+    }
+    let mut command = Command::new(BEAN_PRICE_CMD);
+    command.arg(root_journal_file);
+    apply_env(&mut command, &options.env);
+    let Some(output) = run_with_timeout(command, cancelled, Some(FETCH_PRICES_TIMEOUT))? else {
+        anyhow::bail!("fetching prices was cancelled");
+    };
+    if !output.status.success() {
+        anyhow::bail!(
+            "fetching prices failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .last()
+                .unwrap_or_default()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads the `index<TAB>message` lines of the query check.
+fn parse_query_errors(output: &str) -> Vec<(usize, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (index, message) = line.split_once('\t')?;
+            Some((index.parse().ok()?, message.to_string()))
+        })
+        .collect()
+}
+
+/// The program of the `systemCall` method. A configured name without a
+/// directory is looked up on `PATH`. A configured path is expanded, resolved
+/// against the workspace root and canonicalized, and a relative one has to
+/// stay inside the workspace unless `checker.allowOutsideWorkspace` is set.
+/// A refused path is logged and replaced by an empty one, which is never
+/// available, so the next method takes over.
+pub(crate) fn bean_check_cmd(options: &CheckerOptions, workspace_root: &Path) -> PathBuf {
+    let Some(configured) = options.bean_check_cmd.as_deref() else {
+        return PathBuf::from(BEAN_CHECK_CMD);
+    };
+    match resolve_executable(configured, workspace_root, options.allow_outside_workspace) {
+        Ok(path) => path,
+        Err(err) => {
+            tracing::warn!("refusing checker.beanCheckCmd {}: {}", configured, err);
+            PathBuf::new()
+        }
+    }
+}
+
+fn resolve_executable(
+    configured: &str,
+    workspace_root: &Path,
+    allow_outside_workspace: bool,
+) -> anyhow::Result<PathBuf> {
+    let path = PathBuf::from(shellexpand::full(configured)?.into_owned());
+    if path.is_relative() && path.components().count() == 1 {
+        return Ok(path);
+    }
+    let root = workspace_root.canonicalize()?;
+    let resolved = root.join(&path).canonicalize()?;
+    if path.is_relative() && !resolved.starts_with(&root) && !allow_outside_workspace {
+        anyhow::bail!("{} is outside the workspace", resolved.display());
+    }
+    if !resolved.is_file() {
+        anyhow::bail!("{} is not a file", resolved.display());
+    }
+    Ok(resolved)
+}
+
+/// Logs an external process about to be spawned with its full command line,
+/// for auditing what the server runs.
+pub(crate) fn audit_spawn(command: &Command) {
+    let argv: Vec<_> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect();
+    tracing::info!(target: "audit", "spawning {:?}", argv);
+}
+
+/// Whether `line` is an error of a checker, like `main.beancount:12: ...`.
+fn is_error_line(line: &str) -> bool {
+    error_line_regex().is_match(line)
+}
+
+pub(crate) fn error_line_regex() -> regex::Regex {
+    regex::Regex::new(r"^([^:]+):(\d+):\s*(.*)$").unwrap()
+}
+
+/// Sets the variables of `env` for `command`, expanding `~` and environment
+/// variables in their values, or only `~` when a variable is not set.
+fn apply_env(command: &mut Command, env: &HashMap<String, String>) {
+    for (name, value) in env {
+        let value = shellexpand::full(value)
+            .unwrap_or_else(|_| shellexpand::tilde(value))
+            .into_owned();
+        command.env(name, value);
+    }
+}
+
+/// Whether `command` is a path to a file or the name of a program on `PATH`.
+fn find_executable(command: &Path) -> bool {
+    if command.components().count() > 1 {
+        return command.is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+}
+
+/// Runs `command`, returning whether it succeeded and its error output. The
+/// process is killed and `None` returned as soon as `cancelled` is set.
+fn run(command: Command, cancelled: &AtomicBool) -> anyhow::Result<Option<CheckOutput>> {
+    let Some(output) = run_with_timeout(command, cancelled, None)? else {
+        return Ok(None);
+    };
+    Ok(Some(CheckOutput {
+        success: output.status.success(),
+        errors: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }))
+}
+
+/// Runs `command` like `run`, returning all its output, and kills it with an
+/// error once it ran longer than `timeout`.
+fn run_with_timeout(
+    mut command: Command,
+    cancelled: &AtomicBool,
+    timeout: Option<Duration>,
+) -> anyhow::Result<Option<Output>> {
+    audit_spawn(&command);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // read in the background so the program never blocks on a full pipe
+    let stdout = read_in_background(child.stdout.take().unwrap());
+    let stderr = read_in_background(child.stderr.take().unwrap());
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancelled.load(Ordering::Relaxed) {
+            debug!("{} cancelled", command.get_program().to_string_lossy());
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        if let Some(timeout) = timeout.filter(|timeout| started.elapsed() >= *timeout) {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!(
+                "{} timed out after {}s",
+                command.get_program().to_string_lossy(),
+                timeout.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let read = |reader: std::thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+        reader
+            .join()
+            .map_err(|_| anyhow::anyhow!("could not read the output"))
+    };
+    Ok(Some(Output {
+        status,
+        stdout: read(stdout)??,
+        stderr: read(stderr)??,
+    }))
+}
+
+fn read_in_background(
+    mut pipe: impl Read + Send + 'static,
+) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        pipe.read_to_end(&mut output).map(|_| output)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checker::bean_check_cmd;
+    use crate::checker::check;
+    use crate::checker::parse_query_errors;
+    use crate::checker::run_with_timeout;
+    use crate::checker::select;
+    use crate::checker::FailedCheckers;
+    use crate::config::CheckerMethod;
+    use crate::config::CheckerOptions;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_select_first_available() {
+        let failed = FailedCheckers::default();
+        let methods = [CheckerMethod::SystemCall, CheckerMethod::Native];
+        assert_eq!(
+            select(&methods, &failed, Path::new("true")),
+            CheckerMethod::SystemCall
+        );
+        assert_eq!(
+            select(&methods, &failed, Path::new("/does/not/exist")),
+            CheckerMethod::Native
+        );
+        assert_eq!(
+            select(&[], &failed, Path::new("true")),
+            CheckerMethod::Native
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_fallback_on_failure() {
+        let failed = FailedCheckers::default();
+        let methods = [CheckerMethod::SystemCall, CheckerMethod::Native];
+        let options = CheckerOptions {
+            method: methods.to_vec(),
+            ..Default::default()
+        };
+        let mut fallbacks = vec![];
+        // `false` stands in for a checker that exits without reporting errors
+        let (method, output) = check(
+            &options,
+            &failed,
+            Path::new("false"),
+            Path::new("/main.beancount"),
+            &AtomicBool::new(false),
+            |method, _| fallbacks.push(method),
+        )
+        .unwrap();
+        assert_eq!(method, CheckerMethod::Native);
+        assert!(output.success);
+        assert_eq!(fallbacks, [CheckerMethod::SystemCall]);
+        assert_eq!(
+            select(&methods, &failed, Path::new("false")),
+            CheckerMethod::Native
+        );
+        failed.clear();
+        assert_eq!(
+            select(&methods, &failed, Path::new("false")),
+            CheckerMethod::SystemCall
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_cancelled_check() {
+        let cancelled = AtomicBool::new(true);
+        let result = check(
+            &CheckerOptions::default(),
+            &FailedCheckers::default(),
+            Path::new("sleep"),
+            Path::new("10"),
+            &cancelled,
+            |_, _| {},
+        );
+        assert!(result.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_timed_out_program() {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("echo 2024-01-01 price EUR 1.1 USD; sleep 10");
+        let result = run_with_timeout(
+            command,
+            &AtomicBool::new(false),
+            Some(Duration::from_millis(200)),
+        );
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo 2024-01-01 price EUR 1.1 USD");
+        let output = run_with_timeout(
+            command,
+            &AtomicBool::new(false),
+            Some(Duration::from_secs(10)),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(output.stdout, b"2024-01-01 price EUR 1.1 USD\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_extra_args_and_env() {
+        // the script gets the journal file as `$0`
+        let options = CheckerOptions {
+            extra_args: vec![
+                String::from("-c"),
+                String::from("echo \"$0:3: $PLUGIN_ERROR\" >&2; exit 1"),
+            ],
+            env: [(String::from("PLUGIN_ERROR"), String::from("unknown plugin"))].into(),
+            ..Default::default()
+        };
+        let (method, output) = check(
+            &options,
+            &FailedCheckers::default(),
+            Path::new("sh"),
+            Path::new("/main.beancount"),
+            &AtomicBool::new(false),
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(method, CheckerMethod::SystemCall);
+        assert!(!output.success);
+        assert_eq!(output.errors, "/main.beancount:3: unknown plugin\n");
+    }
+
+    #[test]
+    fn handle_bean_check_cmd_paths() {
+        let workspace =
+            std::env::temp_dir().join(format!("beancount-checker-{}", std::process::id()));
+        std::fs::create_dir_all(workspace.join("bin")).unwrap();
+        std::fs::write(workspace.join("bin/check"), "").unwrap();
+        let outside = workspace
+            .parent()
+            .unwrap()
+            .join(format!("beancount-checker-outside-{}", std::process::id()));
+        std::fs::write(&outside, "").unwrap();
+        let relative_outside = format!("../{}", outside.file_name().unwrap().to_str().unwrap());
+        let options = |cmd: &str, allow_outside_workspace| CheckerOptions {
+            bean_check_cmd: Some(cmd.to_string()),
+            allow_outside_workspace,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            bean_check_cmd(&CheckerOptions::default(), &workspace),
+            PathBuf::from("bean-check")
+        );
+        assert_eq!(
+            bean_check_cmd(&options("bean-check-3", false), &workspace),
+            PathBuf::from("bean-check-3")
+        );
+        assert_eq!(
+            bean_check_cmd(&options("bin/check", false), &workspace),
+            workspace.join("bin/check").canonicalize().unwrap()
+        );
+        assert_eq!(
+            bean_check_cmd(&options(&relative_outside, false), &workspace),
+            PathBuf::new()
+        );
+        assert_eq!(
+            bean_check_cmd(&options(&relative_outside, true), &workspace),
+            outside.canonicalize().unwrap()
+        );
+        assert_eq!(
+            bean_check_cmd(&options("bin/missing", false), &workspace),
+            PathBuf::new()
+        );
+        std::fs::remove_file(outside).unwrap();
+    }
+
+    #[test]
+    fn handle_query_errors() {
+        let output = "Traceback noise\n1\tInvalid column name 'acount' in SELECT\n";
+        assert_eq!(
+            parse_query_errors(output),
+            [(1, String::from("Invalid column name 'acount' in SELECT"))]
+        );
+    }
+}
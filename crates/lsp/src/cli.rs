@@ -0,0 +1,216 @@
+//! The `fmt` and `lint` subcommands, for pre-commit hooks and CI. They run
+//! the formatter and the checks of the server on files, and report through
+//! their output and exit code. `format` is a filter like `bean-format`.
+
+use crate::api;
+use anyhow::Result;
+use std::fmt::Write;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The lines of context around the changes of a diff.
+const CONTEXT: usize = 3;
+
+/// Formats `files` in place or, with `check`, prints a diff of the changes
+/// instead. Returns whether all files were formatted already.
+pub fn fmt(files: &[PathBuf], config: &serde_json::Value, check: bool) -> Result<bool> {
+    let mut formatted = true;
+    for file in files {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+        let new_text = api::format_string(&text, config.clone())?;
+        if new_text == text {
+            continue;
+        }
+        formatted = false;
+        if check {
+            print!("{}", unified_diff(file, &text, &new_text));
+        } else {
+            std::fs::write(file, new_text)
+                .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+        }
+    }
+    Ok(formatted)
+}
+
+/// The formatted text of `file`, or of stdin when `file` is `-`.
+pub fn format(file: &Path, config: &serde_json::Value) -> Result<String> {
+    let text = if file == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?
+    };
+    api::format_string(&text, config.clone())
+}
+
+/// Prints the problems in `files` as `file:line:column: severity: message`.
+/// Returns whether there were no errors or warnings, as hints and
+/// information do not fail a check.
+pub fn lint(files: &[PathBuf], config: &serde_json::Value) -> Result<bool> {
+    let mut clean = true;
+    for file in files {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+        let mut diagnostics = api::lint(&text, config.clone())?;
+        diagnostics.sort_by_key(|diag| (diag.range.start.line, diag.range.start.character));
+        for diag in diagnostics {
+            let severity = match diag.severity {
+                Some(lsp_types::DiagnosticSeverity::ERROR) => "error",
+                Some(lsp_types::DiagnosticSeverity::WARNING) => "warning",
+                Some(lsp_types::DiagnosticSeverity::INFORMATION) => "info",
+                _ => "hint",
+            };
+            clean &= !matches!(severity, "error" | "warning");
+            println!(
+                "{}:{}:{}: {}: {}",
+                file.display(),
+                diag.range.start.line + 1,
+                diag.range.start.character + 1,
+                severity,
+                diag.message
+            );
+        }
+    }
+    Ok(clean)
+}
+
+/// The changed ranges of old and new lines, as end-exclusive line indices.
+type Change = ((usize, usize), (usize, usize));
+
+/// A unified diff of `old` and `new`. The formatter keeps the lines, so
+/// lines are compared in pairs; when the number of lines differs, everything
+/// between the common start and end is one change.
+fn unified_diff(file: &Path, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let mut changes: Vec<Change> = Vec::new();
+    if old_lines.len() == new_lines.len() {
+        for (index, (old_line, new_line)) in old_lines.iter().zip(&new_lines).enumerate() {
+            if old_line == new_line {
+                continue;
+            }
+            match changes.last_mut() {
+                Some((old_range, new_range)) if old_range.1 == index => {
+                    old_range.1 = index + 1;
+                    new_range.1 = index + 1;
+                }
+                _ => changes.push(((index, index + 1), (index, index + 1))),
+            }
+        }
+    } else {
+        let prefix = old_lines
+            .iter()
+            .zip(&new_lines)
+            .take_while(|(old_line, new_line)| old_line == new_line)
+            .count();
+        let suffix = old_lines[prefix..]
+            .iter()
+            .rev()
+            .zip(new_lines[prefix..].iter().rev())
+            .take_while(|(old_line, new_line)| old_line == new_line)
+            .count();
+        changes.push((
+            (prefix, old_lines.len() - suffix),
+            (prefix, new_lines.len() - suffix),
+        ));
+    }
+
+    // changes closer than twice the context share a hunk
+    let mut hunks: Vec<Vec<Change>> = Vec::new();
+    for change in changes {
+        match hunks.last_mut() {
+            Some(hunk) if change.0 .0 <= hunk.last().unwrap().0 .1 + 2 * CONTEXT => {
+                hunk.push(change)
+            }
+            _ => hunks.push(vec![change]),
+        }
+    }
+
+    let mut diff = format!("--- {0}\n+++ {0}\n", file.display());
+    for hunk in hunks {
+        let (first_old, first_new) = hunk[0];
+        let (last_old, last_new) = *hunk.last().unwrap();
+        let old_start = first_old.0.saturating_sub(CONTEXT);
+        let old_end = (last_old.1 + CONTEXT).min(old_lines.len());
+        // the context is the same on both sides
+        let new_start = first_new.0 - (first_old.0 - old_start);
+        let new_end = last_new.1 + (old_end - last_old.1);
+        writeln!(
+            diff,
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_end - old_start,
+            new_start + 1,
+            new_end - new_start
+        )
+        .unwrap();
+        let mut line = old_start;
+        for (old_range, new_range) in hunk {
+            for context in &old_lines[line..old_range.0] {
+                writeln!(diff, " {context}").unwrap();
+            }
+            for removed in &old_lines[old_range.0..old_range.1] {
+                writeln!(diff, "-{removed}").unwrap();
+            }
+            for added in &new_lines[new_range.0..new_range.1] {
+                writeln!(diff, "+{added}").unwrap();
+            }
+            line = old_range.1;
+        }
+        for context in &old_lines[line..old_end] {
+            writeln!(diff, " {context}").unwrap();
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nM\n";
+        assert_eq!(
+            unified_diff(Path::new("main.beancount"), old, new),
+            "--- main.beancount\n+++ main.beancount\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,4 +10,4 @@\n j\n k\n l\n-m\n+M\n"
+        );
+    }
+
+    #[test]
+    fn handle_unified_diff_lines_added() {
+        let diff = unified_diff(Path::new("main.beancount"), "a\nb\n", "a\nx\nb\n");
+        assert_eq!(
+            diff,
+            "--- main.beancount\n+++ main.beancount\n@@ -1,2 +1,3 @@\n a\n+x\n b\n"
+        );
+    }
+
+    #[test]
+    fn handle_fmt_check() {
+        let file =
+            std::env::temp_dir().join(format!("beancount-cli-{}.beancount", std::process::id()));
+        let text = "2024-01-02 * \"Grocer\"\n  Assets:Bank -10.00 USD\n  Expenses:Food:Groceries 10.00 USD\n";
+        std::fs::write(&file, text).unwrap();
+        let files = vec![file.clone()];
+        let config = serde_json::Value::Null;
+
+        // checking leaves the misaligned amounts alone, formatting fixes them
+        assert!(!fmt(&files, &config, true).unwrap());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), text);
+        assert!(!fmt(&files, &config, false).unwrap());
+        assert_ne!(std::fs::read_to_string(&file).unwrap(), text);
+        assert!(fmt(&files, &config, true).unwrap());
+        assert_eq!(
+            format(&file, &config).unwrap(),
+            std::fs::read_to_string(&file).unwrap()
+        );
+        std::fs::remove_file(&file).unwrap();
+    }
+}
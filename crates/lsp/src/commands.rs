@@ -0,0 +1,35 @@
+//! Commands the server handles through `workspace/executeCommand`.
+
+pub(crate) const ADD_DOCUMENTS: &str = "beancount.addDocuments";
+pub(crate) const ANONYMIZE: &str = "beancount.anonymize";
+pub(crate) const EXTRACT_TO_FILE: &str = "beancount.extractToFile";
+pub(crate) const FETCH_PRICES: &str = "beancount.fetchPrices";
+pub(crate) const GOTO_NEXT_ERROR: &str = "beancount.gotoNextError";
+pub(crate) const HOLDINGS: &str = "beancount.holdings";
+pub(crate) const INSERT_BALANCE_ASSERTION: &str = "beancount.insertBalanceAssertion";
+pub(crate) const MATERIALIZE_FORECASTS: &str = "beancount.materializeForecasts";
+pub(crate) const NORMALIZE_PAYEE: &str = "beancount.normalizePayee";
+pub(crate) const RELOAD_WORKSPACE: &str = "beancount.reloadWorkspace";
+pub(crate) const REPORT: &str = "beancount.report";
+pub(crate) const RUN_QUERY: &str = "beancount.runQuery";
+pub(crate) const SHOW_REGISTER: &str = "beancount.showRegister";
+
+/// All commands advertised to the client.
+pub(crate) const COMMANDS: &[&str] = &[
+    ADD_DOCUMENTS,
+    ANONYMIZE,
+    EXTRACT_TO_FILE,
+    FETCH_PRICES,
+    GOTO_NEXT_ERROR,
+    HOLDINGS,
+    INSERT_BALANCE_ASSERTION,
+    MATERIALIZE_FORECASTS,
+    NORMALIZE_PAYEE,
+    RELOAD_WORKSPACE,
+    REPORT,
+    RUN_QUERY,
+    SHOW_REGISTER,
+];
+
+/// The commands running an external program, handled on the thread pool.
+pub(crate) const BACKGROUND_COMMANDS: &[&str] = &[RUN_QUERY];
@@ -0,0 +1,204 @@
+//! Transactions commented out with `;`, which the parser only sees as
+//! comments, so they are found in the text: a commented transaction header
+//! followed by the commented lines indented deeper than it.
+
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::PositionEncoding;
+
+/// A transaction whose lines are all commented out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CommentedTransaction {
+    /// The line of the header.
+    pub start_line: usize,
+    /// The last line of the postings.
+    pub end_line: usize,
+    /// The header without the comment.
+    pub header: String,
+}
+
+impl CommentedTransaction {
+    /// The range from the start of the header to the end of the last line.
+    pub(crate) fn range(
+        &self,
+        content: &ropey::Rope,
+        encoding: PositionEncoding,
+    ) -> lsp_types::Range {
+        lines_range(content, self.start_line, self.end_line, encoding)
+    }
+
+    /// The edit that removes the `;` of each line, with the space after them
+    /// when every line has one, so the postings keep their indent.
+    pub(crate) fn uncomment_edit(
+        &self,
+        content: &ropey::Rope,
+        encoding: PositionEncoding,
+    ) -> lsp_types::TextEdit {
+        let comment = regex::Regex::new(r"^([ \t]*);+( ?)").unwrap();
+        let with_space = (self.start_line..=self.end_line).all(|line| {
+            comment
+                .captures(&content.line(line).to_string())
+                .is_some_and(|caps| !caps[2].is_empty())
+        });
+        let replacement = if with_space { "$1" } else { "$1$2" };
+        edit_lines(content, self.start_line, self.end_line, encoding, |line| {
+            comment.replace(line, replacement).into_owned()
+        })
+    }
+}
+
+/// The edit that comments out the lines from `start_line` to `end_line`,
+/// like the lines of a transaction, with `; `.
+pub(crate) fn comment_edit(
+    content: &ropey::Rope,
+    start_line: usize,
+    end_line: usize,
+    encoding: PositionEncoding,
+) -> lsp_types::TextEdit {
+    edit_lines(content, start_line, end_line, encoding, |line| {
+        format!("; {line}")
+    })
+}
+
+/// The range from the start of `start_line` to the end of `end_line`,
+/// without its line break.
+fn lines_range(
+    content: &ropey::Rope,
+    start_line: usize,
+    end_line: usize,
+    encoding: PositionEncoding,
+) -> lsp_types::Range {
+    let end_text = content.line(end_line).to_string();
+    let end = content.line_to_byte(end_line) + end_text.trim_end_matches(['\r', '\n']).len();
+    lsp_types::Range::new(
+        byte_to_lsp_position(content, content.line_to_byte(start_line), encoding),
+        byte_to_lsp_position(content, end, encoding),
+    )
+}
+
+/// One edit that rewrites each of the lines from `start_line` to `end_line`
+/// with `rewrite`, keeping their line breaks.
+fn edit_lines(
+    content: &ropey::Rope,
+    start_line: usize,
+    end_line: usize,
+    encoding: PositionEncoding,
+    rewrite: impl Fn(&str) -> String,
+) -> lsp_types::TextEdit {
+    let mut new_text = String::new();
+    for line in start_line..=end_line {
+        let text = content.line(line).to_string();
+        let trimmed = text.trim_end_matches(['\r', '\n']);
+        new_text.push_str(&rewrite(trimmed));
+        if line < end_line {
+            new_text.push_str(&text[trimmed.len()..]);
+        }
+    }
+    lsp_types::TextEdit {
+        range: lines_range(content, start_line, end_line, encoding),
+        new_text,
+    }
+}
+
+/// The commented transactions of a file that have at least one posting.
+pub(crate) fn commented_transactions(content: &ropey::Rope) -> Vec<CommentedTransaction> {
+    let header = regex::Regex::new(
+        r"^[ \t]*;+([ \t]*)(\d{4}[-/]\d{2}[-/]\d{2}[ \t]+(?:txn|[*!&#?%PSTCURM])(?:[ \t].*)?)$",
+    )
+    .unwrap();
+    let posting = regex::Regex::new(r"^[ \t]*;+([ \t]*)\S").unwrap();
+
+    let lines: Vec<String> = content
+        .lines()
+        .map(|line| line.to_string().trim_end_matches(['\r', '\n']).to_string())
+        .collect();
+    let mut transactions = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let Some(caps) = header.captures(&lines[index]) else {
+            index += 1;
+            continue;
+        };
+        let indent = caps[1].len();
+        let mut end_line = index;
+        while let Some(caps) = lines
+            .get(end_line + 1)
+            .and_then(|line| posting.captures(line))
+        {
+            if caps[1].len() <= indent {
+                break;
+            }
+            end_line += 1;
+        }
+        if end_line > index {
+            transactions.push(CommentedTransaction {
+                start_line: index,
+                end_line,
+                header: caps[2].trim_end().to_string(),
+            });
+        }
+        index = end_line + 1;
+    }
+    transactions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commented_out::comment_edit;
+    use crate::commented_out::commented_transactions;
+    use crate::test_utils::apply_edits;
+    use crate::treesitter_utils::PositionEncoding;
+    use test_log::test;
+
+    const TEXT: &str = r#"; 2024-01-02 * "Grocer" "Food"
+;     Assets:Bank -10.00 USD
+;     Expenses:Food
+; just a note
+2024-01-03 txn "Bakery" "Bread"
+    Assets:Bank -3.00 USD
+    Expenses:Food
+;2024-01-04 ! "Lonely header"
+"#;
+
+    #[test]
+    fn handle_commented_transactions() {
+        let content = ropey::Rope::from_str(TEXT);
+        let transactions = commented_transactions(&content);
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            (transactions[0].start_line, transactions[0].end_line),
+            (0, 2)
+        );
+        assert_eq!(transactions[0].header, "2024-01-02 * \"Grocer\" \"Food\"");
+    }
+
+    #[test]
+    fn handle_uncomment_edit() {
+        let content = ropey::Rope::from_str(TEXT);
+        let transactions = commented_transactions(&content);
+        let edit = transactions[0].uncomment_edit(&content, PositionEncoding::default());
+        let text = apply_edits(TEXT, &[edit]);
+        assert!(text.starts_with(
+            "2024-01-02 * \"Grocer\" \"Food\"\n    Assets:Bank -10.00 USD\n    Expenses:Food\n; just a note\n"
+        ));
+    }
+
+    #[test]
+    fn handle_comment_edit_round_trip() {
+        let text = "2024-01-03 txn \"Bakery\" \"Bread\"\r\n    Assets:Bank -3.00 USD\r\n    ; paid in cash\r\n    Expenses:Food\r\n";
+        let content = ropey::Rope::from_str(text);
+        let commented = apply_edits(
+            text,
+            &[comment_edit(&content, 0, 3, PositionEncoding::default())],
+        );
+        assert_eq!(
+            commented,
+            "; 2024-01-03 txn \"Bakery\" \"Bread\"\r\n;     Assets:Bank -3.00 USD\r\n;     ; paid in cash\r\n;     Expenses:Food\r\n"
+        );
+
+        let content = ropey::Rope::from_str(&commented);
+        let transactions = commented_transactions(&content);
+        assert_eq!(transactions.len(), 1);
+        let edit = transactions[0].uncomment_edit(&content, PositionEncoding::default());
+        assert_eq!(apply_edits(&commented, &[edit]), text);
+    }
+}
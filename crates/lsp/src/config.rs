@@ -1,40 +1,672 @@
+use crate::amount::NumberFormat;
+use crate::treesitter_utils::PositionEncoding;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub root_file: PathBuf,
-    pub journal_root: Option<PathBuf>,
+    /// The main files of the ledgers in the workspace, from `journal_file`
+    /// and `journal_files`.
+    pub journal_roots: Vec<PathBuf>,
+    pub formatting: FormattingOptions,
+    pub budget: BudgetOptions,
+    pub lint: LintOptions,
+    pub completion: CompletionOptions,
+    pub files: FilesOptions,
+    pub document_symbols: DocumentSymbolsOptions,
+    pub number_format: NumberFormatOptions,
+    pub checker: CheckerOptions,
+    pub spellcheck: SpellcheckOptions,
+    pub inlay_hints: InlayHintsOptions,
+    /// The arguments of `custom` directives by their name, the built-in ones
+    /// for budgets and forecasts extended by those of the user.
+    pub custom_directives: BTreeMap<String, CustomDirectiveSchema>,
+    pub telemetry: TelemetryOptions,
+    pub templates: TemplatesOptions,
+    /// How the columns of LSP positions are counted, as negotiated with the
+    /// client.
+    pub position_encoding: PositionEncoding,
+    /// Whether the client lets the server register file watchers after
+    /// initialization, as negotiated with the client.
+    pub dynamic_file_watchers: bool,
 }
 
 impl Config {
     pub fn new(root_file: PathBuf) -> Self {
         Self {
             root_file,
-            journal_root: None,
+            journal_roots: Vec::new(),
+            formatting: FormattingOptions::default(),
+            budget: BudgetOptions::default(),
+            lint: LintOptions::default(),
+            completion: CompletionOptions::default(),
+            files: FilesOptions::default(),
+            document_symbols: DocumentSymbolsOptions::default(),
+            number_format: NumberFormatOptions::default(),
+            checker: CheckerOptions::default(),
+            spellcheck: SpellcheckOptions::default(),
+            inlay_hints: InlayHintsOptions::default(),
+            custom_directives: default_custom_directives(),
+            telemetry: TelemetryOptions::default(),
+            templates: TemplatesOptions::default(),
+            position_encoding: PositionEncoding::default(),
+            dynamic_file_watchers: false,
         }
     }
     pub fn update(&mut self, json: serde_json::Value) -> Result<()> {
-        // Check explicitly for Ok() here to avoid panicking on invalid input.
         // Gracefully ignore non-BeancountLspOptions inputs here.
         // Example: "[]" is sent by nvim-lspconfig if no initialization options are specified in
         // Lua.
-        if let Ok(beancount_lsp_settings) = serde_json::from_value::<BeancountLspOptions>(json) {
-            if beancount_lsp_settings.journal_file.is_some() {
-                self.journal_root = Some(PathBuf::from(
-                    shellexpand::tilde(&beancount_lsp_settings.journal_file.unwrap()).as_ref(),
-                ));
+        if let Some(beancount_lsp_settings) = parse_settings(json) {
+            let mut journal_roots: Vec<PathBuf> = vec![];
+            for journal_file in beancount_lsp_settings
+                .journal_file
+                .into_iter()
+                .chain(beancount_lsp_settings.journal_files.into_iter().flatten())
+            {
+                let journal_root = self.resolve_path(&journal_file);
+                if !journal_roots.contains(&journal_root) {
+                    journal_roots.push(journal_root);
+                }
+            }
+            if !journal_roots.is_empty() {
+                self.journal_roots = journal_roots;
+            }
+            if let Some(formatting) = beancount_lsp_settings.formatting {
+                self.formatting = formatting;
+            }
+            if let Some(budget) = beancount_lsp_settings.budget {
+                self.budget = budget;
+            }
+            if let Some(lint) = beancount_lsp_settings.lint {
+                self.lint = lint;
+            }
+            if let Some(completion) = beancount_lsp_settings.completion {
+                self.completion = completion;
+            }
+            if let Some(files) = beancount_lsp_settings.files {
+                self.files = files;
+            }
+            if let Some(document_symbols) = beancount_lsp_settings.document_symbols {
+                self.document_symbols = document_symbols;
+            }
+            if let Some(number_format) = beancount_lsp_settings.number_format {
+                self.number_format = number_format;
+            }
+            if let Some(checker) = beancount_lsp_settings.checker {
+                self.checker = checker;
+            }
+            if let Some(spellcheck) = beancount_lsp_settings.spellcheck {
+                self.spellcheck = spellcheck;
+            }
+            if let Some(inlay_hints) = beancount_lsp_settings.inlay_hints {
+                self.inlay_hints = inlay_hints;
+            }
+            if let Some(custom_directives) = beancount_lsp_settings.custom_directives {
+                self.custom_directives = default_custom_directives();
+                self.custom_directives.extend(custom_directives);
+            }
+            if let Some(telemetry) = beancount_lsp_settings.telemetry {
+                self.telemetry = telemetry;
+            }
+            if let Some(templates) = beancount_lsp_settings.templates {
+                self.templates = templates;
             }
         }
 
         Ok(())
     }
+
+    /// The file to write usage statistics to, when the user opted in.
+    pub fn telemetry_path(&self) -> Option<PathBuf> {
+        self.telemetry
+            .local_path
+            .as_deref()
+            .map(|path| self.resolve_path(path))
+    }
+
+    /// The directory with the templates for new files, if configured.
+    pub fn templates_directory(&self) -> Option<PathBuf> {
+        self.templates
+            .directory
+            .as_deref()
+            .map(|path| self.resolve_path(path))
+    }
+
+    /// Expands `~` and environment variables in a configured path, and
+    /// resolves it relative to the workspace root.
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        let expanded = shellexpand::full(path)
+            .unwrap_or_else(|_| shellexpand::tilde(path))
+            .into_owned();
+        self.root_file.join(expanded)
+    }
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct BeancountLspOptions {
     pub journal_file: Option<String>,
+    /// Several independent ledgers in one workspace, like `personal.bean` and
+    /// `business.bean`.
+    pub journal_files: Option<Vec<String>>,
+    pub formatting: Option<FormattingOptions>,
+    pub budget: Option<BudgetOptions>,
+    pub lint: Option<LintOptions>,
+    pub completion: Option<CompletionOptions>,
+    pub files: Option<FilesOptions>,
+    #[serde(alias = "documentSymbols")]
+    pub document_symbols: Option<DocumentSymbolsOptions>,
+    #[serde(alias = "numberFormat")]
+    pub number_format: Option<NumberFormatOptions>,
+    pub checker: Option<CheckerOptions>,
+    pub spellcheck: Option<SpellcheckOptions>,
+    #[serde(alias = "inlayHints")]
+    pub inlay_hints: Option<InlayHintsOptions>,
+    #[serde(alias = "customDirectives")]
+    pub custom_directives: Option<BTreeMap<String, CustomDirectiveSchema>>,
+    pub telemetry: Option<TelemetryOptions>,
+    pub templates: Option<TemplatesOptions>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FormattingOptions {
+    /// Width of a tab when computing alignment columns. Falls back to the
+    /// client's `tabSize` formatting option when unset.
+    pub tab_size: Option<u32>,
+    /// Aligns amounts exactly like `bean-format` does, for ledgers that are
+    /// also formatted with it.
+    #[serde(default)]
+    pub bean_format: bool,
+    /// With `beanFormat`, the column the currencies are aligned at, like the
+    /// `--currency-column` option of `bean-format`.
+    pub currency_column: Option<usize>,
+}
+
+/// How the numbers in the ledger are written.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberFormatOptions {
+    /// The decimal separator, `.` or `,`. Inferred from each file when unset.
+    pub decimal_separator: Option<char>,
+    /// The thousands separator. Defaults to `,` with a decimal point and `.`
+    /// with a decimal comma.
+    pub grouping_separator: Option<char>,
+}
+
+impl NumberFormatOptions {
+    /// The format to read the numbers of a file with `content` in.
+    pub fn for_content(&self, content: &ropey::Rope) -> NumberFormat {
+        let mut format = match self.decimal_separator {
+            None => NumberFormat::infer(&content.to_string()),
+            Some(',') => NumberFormat::DECIMAL_COMMA,
+            Some(decimal_separator) => NumberFormat {
+                decimal_separator,
+                ..NumberFormat::default()
+            },
+        };
+        if self.grouping_separator.is_some() {
+            format.grouping_separator = self.grouping_separator;
+        }
+        format
+    }
+}
+
+/// How the journal is checked for errors.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckerOptions {
+    /// The checkers to try, in order. The first one that is available is
+    /// used, and the next one takes over when it fails. A single method may
+    /// be given without a list.
+    #[serde(default = "default_checker_methods", deserialize_with = "one_or_many")]
+    pub method: Vec<CheckerMethod>,
+    /// Arguments passed to `bean-check` before the journal file.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    /// Environment variables for the checker, like a `PYTHONPATH` to find
+    /// plugins kept next to the ledger. `~` and variables in the values are
+    /// expanded.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// The `bean-check` program of the `systemCall` method, a name looked up
+    /// on `PATH` or a path relative to the workspace root.
+    pub bean_check_cmd: Option<String>,
+    /// Allows a relative `beanCheckCmd` to lead out of the workspace.
+    #[serde(default)]
+    pub allow_outside_workspace: bool,
+}
+
+impl Default for CheckerOptions {
+    fn default() -> Self {
+        Self {
+            method: default_checker_methods(),
+            extra_args: Vec::new(),
+            env: HashMap::new(),
+            bean_check_cmd: None,
+            allow_outside_workspace: false,
+        }
+    }
+}
+
+/// The settings in `json`. An object with invalid options keeps its valid
+/// ones, so a mistake in one option does not throw away the others, and
+/// the invalid ones are logged.
+fn parse_settings(json: serde_json::Value) -> Option<BeancountLspOptions> {
+    let error = match serde_json::from_value::<BeancountLspOptions>(json.clone()) {
+        Ok(settings) => return Some(settings),
+        Err(error) => error,
+    };
+    let serde_json::Value::Object(options) = json else {
+        return None;
+    };
+    tracing::warn!("invalid settings: {}", error);
+    let valid = options
+        .into_iter()
+        .filter(|(name, value)| {
+            let option = serde_json::json!({ name: value });
+            let valid = serde_json::from_value::<BeancountLspOptions>(option).is_ok();
+            if !valid {
+                tracing::warn!("ignoring the invalid option {}: {}", name, value);
+            }
+            valid
+        })
+        .collect();
+    serde_json::from_value(serde_json::Value::Object(valid)).ok()
+}
+
+/// Deserializes a list, which may also be given as its only item.
+fn one_or_many<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(item) => vec![item],
+        OneOrMany::Many(items) => items,
+    })
+}
+
+fn default_checker_methods() -> Vec<CheckerMethod> {
+    vec![CheckerMethod::SystemCall]
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckerMethod {
+    /// Loads the journal with the `beancount` Python package.
+    #[serde(alias = "python_embedded")]
+    PythonEmbedded,
+    /// Runs `bean-check`.
+    #[serde(alias = "system_call")]
+    SystemCall,
+    /// Only the checks of the language server itself.
+    Native,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetOptions {
+    /// Track `custom "budget"` directives, warning about overspending and
+    /// showing the remaining budget as inlay hints.
+    #[serde(default)]
+    pub enable: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintsOptions {
+    /// Shows the number of errors, the time of the last check and the date
+    /// of the newest transaction at the end of each journal root.
+    #[serde(default)]
+    pub file_summary: bool,
+    /// Shows the value of postings in other currencies in the operating
+    /// currency, at the price on the date of their transaction.
+    #[serde(default)]
+    pub operating_currency_equivalent: bool,
+}
+
+/// Spell checking of payees and narrations, when built with the `spellcheck`
+/// feature.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SpellcheckOptions {
+    /// Show hints about misspelled words.
+    #[serde(default)]
+    pub enable: bool,
+    /// The Hunspell dictionary, as the path of its `.aff` and `.dic` files
+    /// without the extension.
+    #[serde(default = "default_spellcheck_dictionary")]
+    pub dictionary: String,
+    /// Files with more words to accept, one per line, like the names of
+    /// shops and people.
+    #[serde(default)]
+    pub user_dictionaries: Vec<String>,
+    /// More words to accept.
+    #[serde(default)]
+    pub words: Vec<String>,
+}
+
+impl Default for SpellcheckOptions {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            dictionary: default_spellcheck_dictionary(),
+            user_dictionaries: Vec::new(),
+            words: Vec::new(),
+        }
+    }
+}
+
+fn default_spellcheck_dictionary() -> String {
+    String::from("/usr/share/hunspell/en_US")
+}
+
+/// Usage statistics for profiling the server on a ledger. Only the names of
+/// the requests and how long they took are recorded, and only to a local file.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryOptions {
+    /// The JSON file to write the statistics to. Nothing is recorded without.
+    pub local_path: Option<String>,
+}
+
+/// Starter content offered as completions in empty files.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplatesOptions {
+    /// A directory of `.beancount` files offered besides the built-in
+    /// template, by their file name. They are inserted as snippets, so `$1`
+    /// and `${1:default}` are placeholders and a literal `$` is written `\$`.
+    pub directory: Option<String>,
+}
+
+/// Optional checks that are stricter than `bean-check`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LintOptions {
+    /// Warn about currencies used without a `commodity` directive.
+    #[serde(default)]
+    pub undeclared_commodities: bool,
+    /// Warn about held commodities whose latest `price` directive is older
+    /// than this many days.
+    pub stale_price_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionOptions {
+    /// Accounts to leave out of completion and workspace symbols, as globs
+    /// like `Equity:*` or, when starting with `^`, regular expressions.
+    #[serde(default)]
+    pub hidden_account_patterns: Vec<String>,
+    /// An executable contributing extra completion items.
+    pub external_provider: Option<ExternalCompletionProvider>,
+    /// The most items returned at once. When there are more, the best ranked
+    /// are returned as an incomplete list, which clients query again as the
+    /// user types.
+    pub max_items: Option<usize>,
+    /// How long completion may take before it skips the external provider
+    /// and returns an incomplete list.
+    pub time_budget_ms: Option<u64>,
+}
+
+/// An executable run on every completion request. It gets the completion
+/// context as JSON on stdin and prints a JSON array of completion items,
+/// which are added to the native ones.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalCompletionProvider {
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// How long to wait for the items before completing without them.
+    #[serde(default = "default_external_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_external_timeout_ms() -> u64 {
+    500
+}
+
+impl CompletionOptions {
+    pub fn hidden_accounts(&self) -> HiddenAccounts {
+        let mut matchers = vec![];
+        for pattern in self.hidden_account_patterns.iter() {
+            let matcher = if pattern.starts_with('^') {
+                regex::Regex::new(pattern).map(AccountMatcher::Regex).ok()
+            } else {
+                glob::Pattern::new(pattern).map(AccountMatcher::Glob).ok()
+            };
+            match matcher {
+                Some(matcher) => matchers.push(matcher),
+                None => tracing::warn!("invalid hidden account pattern: {}", pattern),
+            }
+        }
+        HiddenAccounts(matchers)
+    }
+}
+
+/// The compiled `hiddenAccountPatterns`.
+#[derive(Debug)]
+pub struct HiddenAccounts(Vec<AccountMatcher>);
+
+#[derive(Debug)]
+enum AccountMatcher {
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+}
+
+impl HiddenAccounts {
+    pub fn contains(&self, account: &str) -> bool {
+        self.0.iter().any(|matcher| match matcher {
+            AccountMatcher::Glob(pattern) => pattern.matches(account),
+            AccountMatcher::Regex(regex) => regex.is_match(account),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesOptions {
+    /// Files and directories that glob includes never pick up, as globs
+    /// matched against each path component like `node_modules`, or against
+    /// the whole path when they contain a `/`.
+    #[serde(default = "default_ignore_globs")]
+    pub ignore_globs: Vec<String>,
+}
+
+impl Default for FilesOptions {
+    fn default() -> Self {
+        Self {
+            ignore_globs: default_ignore_globs(),
+        }
+    }
+}
+
+fn default_ignore_globs() -> Vec<String> {
+    [".git", "venv", "node_modules"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+impl FilesOptions {
+    pub fn ignore_globs(&self) -> IgnoreGlobs {
+        let mut patterns = vec![];
+        for glob in self.ignore_globs.iter() {
+            match glob::Pattern::new(glob) {
+                Ok(pattern) => patterns.push(pattern),
+                Err(_) => tracing::warn!("invalid ignore glob: {}", glob),
+            }
+        }
+        IgnoreGlobs(patterns)
+    }
+}
+
+/// The compiled `ignoreGlobs`.
+#[derive(Debug, Default)]
+pub struct IgnoreGlobs(Vec<glob::Pattern>);
+
+impl IgnoreGlobs {
+    /// Whether `path`, relative to the directory that was searched, is
+    /// ignored.
+    pub fn matches(&self, path: &Path) -> bool {
+        self.0.iter().any(|pattern| {
+            if pattern.as_str().contains('/') {
+                return pattern.matches_path(path);
+            }
+            path.components()
+                .filter_map(|component| component.as_os_str().to_str())
+                .any(|component| pattern.matches(component))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbolsOptions {
+    /// The kinds of entries shown in the outline, like `transactions`,
+    /// `opens`, `balances`, `sections` or `metadata` for the org-mode title
+    /// and property drawers, to trim it for huge files.
+    #[serde(default = "default_document_symbols")]
+    pub include: Vec<String>,
+}
+
+impl Default for DocumentSymbolsOptions {
+    fn default() -> Self {
+        Self {
+            include: default_document_symbols(),
+        }
+    }
+}
+
+fn default_document_symbols() -> Vec<String> {
+    [
+        "sections",
+        "transactions",
+        "opens",
+        "closes",
+        "balances",
+        "pads",
+        "prices",
+        "commodities",
+        "notes",
+        "events",
+        "documents",
+        "customs",
+        "metadata",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+/// The arguments a `custom "name"` directive takes.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomDirectiveSchema {
+    /// Shown with the signature of the directive.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<CustomArgument>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomArgument {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: CustomArgumentType,
+    /// The values a string argument is limited to, like the periods of a
+    /// budget.
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Whether the argument, and all after it, may be left out.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl CustomArgument {
+    fn new(name: &str, kind: CustomArgumentType) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            values: Vec::new(),
+            optional: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomArgumentType {
+    Account,
+    String,
+    Amount,
+    Number,
+    Date,
+    Bool,
+}
+
+impl CustomArgumentType {
+    /// The name of the type as shown to the user.
+    pub fn name(self) -> &'static str {
+        match self {
+            CustomArgumentType::Account => "account",
+            CustomArgumentType::String => "string",
+            CustomArgumentType::Amount => "amount",
+            CustomArgumentType::Number => "number",
+            CustomArgumentType::Date => "date",
+            CustomArgumentType::Bool => "bool",
+        }
+    }
+}
+
+/// The directives the language server itself reads: budgets, also in the
+/// spelling of fava, and forecasts.
+fn default_custom_directives() -> BTreeMap<String, CustomDirectiveSchema> {
+    let mut period = CustomArgument::new("period", CustomArgumentType::String);
+    period.values = ["daily", "weekly", "monthly", "quarterly", "yearly"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let budget = CustomDirectiveSchema {
+        description: Some(String::from("The budget of an account for each period.")),
+        arguments: vec![
+            CustomArgument::new("account", CustomArgumentType::Account),
+            period,
+            CustomArgument::new("amount", CustomArgumentType::Amount),
+        ],
+    };
+    let forecast = CustomDirectiveSchema {
+        description: Some(String::from(
+            "A transaction that has not happened yet, between two accounts.",
+        )),
+        arguments: vec![
+            CustomArgument::new("narration", CustomArgumentType::String),
+            CustomArgument::new("account", CustomArgumentType::Account),
+            CustomArgument::new("amount", CustomArgumentType::Amount),
+            CustomArgument::new("other_account", CustomArgumentType::Account),
+        ],
+    };
+    BTreeMap::from([
+        (String::from("budget"), budget.clone()),
+        (String::from("fava-budget"), budget),
+        (String::from("forecast"), forecast),
+    ])
 }
 
 #[cfg(test)]
@@ -45,7 +677,7 @@ mod tests {
     fn test_no_journal() {
         let mut config = Config::new(PathBuf::new());
         config.update(serde_json::from_str("[]").unwrap()).unwrap();
-        assert_eq!(config.journal_root, None);
+        assert!(config.journal_roots.is_empty());
     }
 
     #[test]
@@ -54,7 +686,7 @@ mod tests {
         config
             .update(serde_json::from_str("{\"journal_file\": null}").unwrap())
             .unwrap();
-        assert_eq!(config.journal_root, None);
+        assert!(config.journal_roots.is_empty());
     }
 
     #[test]
@@ -63,6 +695,377 @@ mod tests {
         config
             .update(serde_json::from_str("{\"journal_file\": \"mypath\"}").unwrap())
             .unwrap();
-        assert_eq!(config.journal_root, Some("mypath".into()));
+        assert_eq!(config.journal_roots, [PathBuf::from("mypath")]);
+    }
+
+    #[test]
+    fn test_relative_journal() {
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        config
+            .update(serde_json::from_str("{\"journal_file\": \"books/main.beancount\"}").unwrap())
+            .unwrap();
+        assert_eq!(
+            config.journal_roots,
+            [PathBuf::from("/workspace/books/main.beancount")]
+        );
+        config
+            .update(serde_json::from_str("{\"journal_file\": \"/ledger/main.beancount\"}").unwrap())
+            .unwrap();
+        assert_eq!(
+            config.journal_roots,
+            [PathBuf::from("/ledger/main.beancount")]
+        );
+    }
+
+    #[test]
+    fn test_journal_files() {
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"journal_file\": \"personal.bean\", \"journal_files\": [\"business.bean\", \"personal.bean\"]}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.journal_roots,
+            [
+                PathBuf::from("/workspace/personal.bean"),
+                PathBuf::from("/workspace/business.bean")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_journal_env_expansion() {
+        std::env::set_var("BEANCOUNT_LSP_TEST_LEDGER", "/ledger");
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"journal_file\": \"$BEANCOUNT_LSP_TEST_LEDGER/main.beancount\"}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.journal_roots,
+            [PathBuf::from("/ledger/main.beancount")]
+        );
+    }
+
+    #[test]
+    fn test_formatting_tab_size() {
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(serde_json::from_str("{\"formatting\": {\"tabSize\": 8}}").unwrap())
+            .unwrap();
+        assert_eq!(config.formatting.tab_size, Some(8));
+    }
+
+    #[test]
+    fn test_formatting_bean_format() {
+        let mut config = Config::new(PathBuf::new());
+        assert!(!config.formatting.bean_format);
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"formatting\": {\"beanFormat\": true, \"currencyColumn\": 60}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert!(config.formatting.bean_format);
+        assert_eq!(config.formatting.currency_column, Some(60));
+    }
+
+    #[test]
+    fn test_budget_enable() {
+        let mut config = Config::new(PathBuf::new());
+        assert!(!config.budget.enable);
+        config
+            .update(serde_json::from_str("{\"budget\": {\"enable\": true}}").unwrap())
+            .unwrap();
+        assert!(config.budget.enable);
+    }
+
+    #[test]
+    fn test_inlay_hints_file_summary() {
+        let mut config = Config::new(PathBuf::new());
+        assert!(!config.inlay_hints.file_summary);
+        config
+            .update(serde_json::from_str("{\"inlayHints\": {\"fileSummary\": true}}").unwrap())
+            .unwrap();
+        assert!(config.inlay_hints.file_summary);
+    }
+
+    #[test]
+    fn test_telemetry_local_path() {
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        assert_eq!(config.telemetry_path(), None);
+        config
+            .update(
+                serde_json::from_str("{\"telemetry\": {\"localPath\": \"stats.json\"}}").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.telemetry_path(),
+            Some(PathBuf::from("/workspace/stats.json"))
+        );
+    }
+
+    #[test]
+    fn test_templates_directory() {
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        assert_eq!(config.templates_directory(), None);
+        config
+            .update(
+                serde_json::from_str("{\"templates\": {\"directory\": \"templates\"}}").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.templates_directory(),
+            Some(PathBuf::from("/workspace/templates"))
+        );
+    }
+
+    #[test]
+    fn test_lint_undeclared_commodities() {
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(serde_json::from_str("{\"lint\": {\"undeclaredCommodities\": true}}").unwrap())
+            .unwrap();
+        assert!(config.lint.undeclared_commodities);
+    }
+
+    #[test]
+    fn test_ignore_globs() {
+        let mut config = Config::new(PathBuf::new());
+        let ignore = config.files.ignore_globs();
+        assert!(ignore.matches(Path::new("venv/lib/main.beancount")));
+        assert!(ignore.matches(Path::new("web/node_modules/a.beancount")));
+        assert!(!ignore.matches(Path::new("accounts/bank.beancount")));
+
+        config
+            .update(
+                serde_json::from_str("{\"files\": {\"ignoreGlobs\": [\"archive/*.beancount\"]}}")
+                    .unwrap(),
+            )
+            .unwrap();
+        let ignore = config.files.ignore_globs();
+        assert!(ignore.matches(Path::new("archive/2019.beancount")));
+        assert!(!ignore.matches(Path::new("venv/main.beancount")));
+    }
+
+    #[test]
+    fn test_document_symbols_include() {
+        let mut config = Config::new(PathBuf::new());
+        assert!(config
+            .document_symbols
+            .include
+            .contains(&String::from("transactions")));
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"documentSymbols\": {\"include\": [\"sections\", \"balances\"]}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(config.document_symbols.include, ["sections", "balances"]);
+    }
+
+    #[test]
+    fn test_hidden_account_patterns() {
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"completion\": {\"hiddenAccountPatterns\": [\"Equity:*\", \"^Assets:Old(:|$)\"]}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let hidden = config.completion.hidden_accounts();
+        assert!(hidden.contains("Equity:Opening-Balances"));
+        assert!(hidden.contains("Assets:Old"));
+        assert!(hidden.contains("Assets:Old:Bank"));
+        assert!(!hidden.contains("Assets:Older"));
+        assert!(!hidden.contains("Expenses:Equity"));
+    }
+
+    #[test]
+    fn test_external_completion_provider() {
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"completion\": {\"externalProvider\": {\"command\": \"cost-centers\"}}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let provider = config.completion.external_provider.unwrap();
+        assert_eq!(provider.command, PathBuf::from("cost-centers"));
+        assert!(provider.args.is_empty());
+        assert_eq!(provider.timeout_ms, 500);
+    }
+
+    #[test]
+    fn test_completion_budget() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.completion.max_items, None);
+        config
+            .update(
+                serde_json::from_str("{\"completion\": {\"maxItems\": 200, \"timeBudgetMs\": 50}}")
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(config.completion.max_items, Some(200));
+        assert_eq!(config.completion.time_budget_ms, Some(50));
+    }
+
+    #[test]
+    fn test_number_format() {
+        let content = ropey::Rope::from_str("2023-01-01 price EUR 1,10 USD\n");
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(
+            config.number_format.for_content(&content),
+            NumberFormat::DECIMAL_COMMA
+        );
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"numberFormat\": {\"decimalSeparator\": \".\", \"groupingSeparator\": \"'\"}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.number_format.for_content(&content),
+            NumberFormat {
+                decimal_separator: '.',
+                grouping_separator: Some('\''),
+            }
+        );
+    }
+
+    #[test]
+    fn test_checker_method() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.checker.method, [CheckerMethod::SystemCall]);
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"checker\": {\"method\": [\"pythonEmbedded\", \"systemCall\", \"native\"]}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.checker.method,
+            [
+                CheckerMethod::PythonEmbedded,
+                CheckerMethod::SystemCall,
+                CheckerMethod::Native
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checker_single_method() {
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(serde_json::from_str("{\"checker\": {\"method\": \"system_call\"}}").unwrap())
+            .unwrap();
+        assert_eq!(config.checker.method, [CheckerMethod::SystemCall]);
+    }
+
+    #[test]
+    fn test_invalid_option_keeps_the_others() {
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"journal_file\": \"/main.beancount\", \"checker\": {\"method\": 3}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(config.journal_roots, [PathBuf::from("/main.beancount")]);
+        assert_eq!(config.checker, CheckerOptions::default());
+    }
+
+    #[test]
+    fn test_checker_extra_args_and_env() {
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"checker\": {\"extraArgs\": [\"--no-cache\"], \"env\": {\"PYTHONPATH\": \"~/plugins\"}}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(config.checker.method, [CheckerMethod::SystemCall]);
+        assert_eq!(config.checker.extra_args, ["--no-cache"]);
+        assert_eq!(config.checker.env["PYTHONPATH"], "~/plugins");
+    }
+
+    #[test]
+    fn test_checker_bean_check_cmd() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.checker.bean_check_cmd, None);
+        assert!(!config.checker.allow_outside_workspace);
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"checker\": {\"beanCheckCmd\": \"../venv/bin/bean-check\", \"allowOutsideWorkspace\": true}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.checker.bean_check_cmd.as_deref(),
+            Some("../venv/bin/bean-check")
+        );
+        assert!(config.checker.allow_outside_workspace);
+    }
+
+    #[test]
+    fn test_spellcheck() {
+        let mut config = Config::new(PathBuf::new());
+        assert!(!config.spellcheck.enable);
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"spellcheck\": {\"enable\": true, \"userDictionaries\": [\"~/payees.dic\"]}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert!(config.spellcheck.enable);
+        assert_eq!(config.spellcheck.dictionary, "/usr/share/hunspell/en_US");
+        assert_eq!(config.spellcheck.user_dictionaries, ["~/payees.dic"]);
+    }
+
+    #[test]
+    fn test_custom_directives() {
+        let mut config = Config::new(PathBuf::new());
+        assert!(config.custom_directives.contains_key("budget"));
+        config
+            .update(
+                serde_json::from_str(
+                    "{\"customDirectives\": {\"autobean.xcheck\": {\"arguments\": [{\"name\": \"file\", \"type\": \"string\"}, {\"name\": \"strict\", \"type\": \"bool\", \"optional\": true}]}}}",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert!(config.custom_directives.contains_key("budget"));
+        let schema = &config.custom_directives["autobean.xcheck"];
+        assert_eq!(schema.arguments.len(), 2);
+        assert_eq!(schema.arguments[1].kind, CustomArgumentType::Bool);
+        assert!(schema.arguments[1].optional);
     }
 }
@@ -0,0 +1,142 @@
+//! Tests for documents with Windows line endings, whose `\r` must not shift
+//! columns or rows.
+
+use crate::providers::completion;
+use crate::providers::formatting;
+use crate::providers::inlay_hints;
+use crate::test_utils::apply_edits;
+use crate::test_utils::Fixture;
+use crate::test_utils::TestDocument;
+use crate::test_utils::TestState;
+use crate::treesitter_utils::lsp_textdocchange_to_ts_inputedit;
+use crate::treesitter_utils::PositionEncoding;
+use std::str::FromStr;
+use std::sync::Arc;
+use test_log::test;
+
+/// A test state for `text`, which keeps its line endings, unlike a fixture.
+fn state(text: &str) -> TestState {
+    TestState::from_fixture(Fixture {
+        documents: vec![TestDocument {
+            path: "/main.beancount".to_string(),
+            text: text.to_string(),
+            cursor: None,
+        }],
+    })
+    .unwrap()
+}
+
+fn document() -> lsp_types::TextDocumentIdentifier {
+    lsp_types::TextDocumentIdentifier::new(
+        lsp_types::Uri::from_str("file:///main.beancount").unwrap(),
+    )
+}
+
+fn parse(text: &str) -> tree_sitter::Tree {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_beancount::language())
+        .unwrap();
+    parser.parse(text, None).unwrap()
+}
+
+#[test]
+fn handle_crlf_formatting() {
+    let text = "2023-10-01 txn \"Test Co\"\r\n    Assets:Test 1.00 USD\r\n    Expenses:Test:Long  -1.00 USD\r\n";
+    let edits = formatting::formatting(
+        state(text).snapshot,
+        lsp_types::DocumentFormattingParams {
+            text_document: document(),
+            options: lsp_types::FormattingOptions {
+                tab_size: 4,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        },
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        apply_edits(text, &edits),
+        "2023-10-01 txn \"Test Co\"\r\n    Assets:Test          1.00 USD\r\n    Expenses:Test:Long  -1.00 USD\r\n"
+    );
+}
+
+#[test]
+fn handle_crlf_inlay_hints() {
+    let text = "2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 100.00 USD\r\n2024-01-02 txn \"Grocer\" \"Food\"\r\n    Assets:Bank -60.00 USD\r\n    Expenses:Food\r\n";
+    let mut test_state = state(text);
+    Arc::make_mut(&mut test_state.snapshot.config).budget.enable = true;
+    let hints = inlay_hints::inlay_hints(
+        test_state.snapshot,
+        lsp_types::InlayHintParams {
+            text_document: document(),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(0, 0),
+                lsp_types::Position::new(100, 0),
+            ),
+            work_done_progress_params: Default::default(),
+        },
+    )
+    .unwrap()
+    .unwrap();
+    let positions: Vec<_> = hints.iter().map(|hint| hint.position).collect();
+    assert_eq!(positions, [lsp_types::Position::new(3, 17)]);
+}
+
+#[test]
+fn handle_crlf_completion() {
+    let text = "2023-10-01 open Assets:Test USD\r\n2023-10-01 t\r\n";
+    let items = completion::completion(
+        state(text).snapshot,
+        None,
+        lsp_types::TextDocumentPositionParams::new(document(), lsp_types::Position::new(1, 12)),
+    )
+    .unwrap()
+    .unwrap_or_default();
+    let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+    assert_eq!(labels, ["txn", "balance", "open", "close"]);
+}
+
+#[test]
+fn handle_crlf_incremental_parse() {
+    let text = "2023-10-01 open Assets:Bank USD\r\n2023-10-02 txn \"Shop\"\r\n";
+    let change = lsp_types::TextDocumentContentChangeEvent {
+        range: Some(lsp_types::Range::new(
+            lsp_types::Position::new(1, 21),
+            lsp_types::Position::new(1, 21),
+        )),
+        range_length: None,
+        text: "\r\n    Assets:Bank -1.00 USD\r\n    Expenses:Food".to_string(),
+    };
+    let new_text = "2023-10-01 open Assets:Bank USD\r\n2023-10-02 txn \"Shop\"\r\n    Assets:Bank -1.00 USD\r\n    Expenses:Food\r\n";
+
+    let source = ropey::Rope::from_str(text);
+    let edit =
+        lsp_textdocchange_to_ts_inputedit(&source, &change, PositionEncoding::Utf16).unwrap();
+    assert_eq!(edit.start_position, tree_sitter::Point::new(1, 21));
+    assert_eq!(edit.new_end_position, tree_sitter::Point::new(3, 17));
+
+    let mut tree = parse(text);
+    tree.edit(&edit);
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_beancount::language())
+        .unwrap();
+    let reparsed = parser.parse(new_text, Some(&tree)).unwrap();
+    assert_eq!(
+        reparsed.root_node().to_sexp(),
+        parse(new_text).root_node().to_sexp()
+    );
+}
+
+#[test]
+fn handle_unicode_line_separator() {
+    // U+2028 is not a line break to tree-sitter or LSP, so it must not be one
+    // for the rope either
+    let text = "2023-10-01 txn \"Shop\u{2028}Co\"\r\n    Assets:Test 1.00 USD\r\n    Expenses:Test:Long  -1.00 USD\r\n";
+    let content = ropey::Rope::from_str(text);
+    assert_eq!(content.len_lines(), 4);
+    let tree = parse(text);
+    assert_eq!(tree.root_node().end_position().row, 3);
+}
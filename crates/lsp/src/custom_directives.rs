@@ -0,0 +1,286 @@
+//! The arguments of `custom` directives, checked and completed from the
+//! schemas of `customDirectives`.
+
+use crate::beancount_data::BeancountData;
+use crate::config::CustomArgument;
+use crate::config::CustomArgumentType;
+use crate::config::CustomDirectiveSchema;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The part of a `custom` directive the cursor is at.
+#[derive(Debug, PartialEq)]
+pub enum CustomSlot {
+    /// The name, after its opening quote.
+    Name,
+    /// The argument of the `custom "name"` directive at `index`, which may be
+    /// past the arguments of its schema. `currency` is set after the number
+    /// of an amount, and `typing` while the argument is being typed.
+    Argument {
+        name: String,
+        index: usize,
+        currency: bool,
+        typing: bool,
+    },
+}
+
+impl CustomSlot {
+    /// The argument of the schema the cursor is at.
+    pub fn argument<'a>(
+        &self,
+        schemas: &'a BTreeMap<String, CustomDirectiveSchema>,
+    ) -> Option<&'a CustomArgument> {
+        match self {
+            CustomSlot::Name => None,
+            CustomSlot::Argument { name, index, .. } => schemas.get(name)?.arguments.get(*index),
+        }
+    }
+}
+
+/// Analyses the line up to the cursor as a `custom` directive. With a schema
+/// for the directive an amount counts as one argument, without one every
+/// value does.
+pub fn custom_slot(
+    line_prefix: &str,
+    schemas: &BTreeMap<String, CustomDirectiveSchema>,
+) -> Option<CustomSlot> {
+    let custom =
+        regex::Regex::new(r#"^\d{4}[-/]\d{2}[-/]\d{2}\s+custom\s+"([^"]*)(?:"(.*))?$"#).unwrap();
+    let caps = custom.captures(line_prefix)?;
+    let Some(rest) = caps.get(2).map(|rest| rest.as_str()) else {
+        return Some(CustomSlot::Name);
+    };
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let value = regex::Regex::new(r#""(?:[^"\\]|\\.)*"?|[^\s"]+"#).unwrap();
+    let values: Vec<&str> = value.find_iter(rest).map(|value| value.as_str()).collect();
+    // the value at the cursor is still being typed, a string until it is closed
+    let typing = !rest.ends_with(char::is_whitespace)
+        || values
+            .last()
+            .is_some_and(|value| value.starts_with('"') && !is_closed_string(value));
+    let mut left = values.len() - usize::from(typing);
+
+    let name = caps[1].to_string();
+    let arguments = schemas
+        .get(&name)
+        .map(|schema| schema.arguments.as_slice())
+        .unwrap_or_default();
+    let mut index = 0;
+    // inside an amount when its number has been typed but not its currency
+    let currency = loop {
+        let width = match arguments.get(index) {
+            Some(argument) if argument.kind == CustomArgumentType::Amount => 2,
+            _ => 1,
+        };
+        if left < width {
+            break left == 1;
+        }
+        left -= width;
+        index += 1;
+    };
+    Some(CustomSlot::Argument {
+        name,
+        index,
+        currency,
+        typing,
+    })
+}
+
+fn is_closed_string(value: &str) -> bool {
+    value.len() > 1 && value.ends_with('"') && !value.ends_with("\\\"")
+}
+
+/// Warnings for `custom` directives that do not match their schema: missing
+/// or extra arguments, arguments of the wrong type, and strings that are not
+/// one of the allowed values. Directives without a schema are not checked.
+pub fn diagnostics(
+    data: &HashMap<PathBuf, BeancountData>,
+    schemas: &BTreeMap<String, CustomDirectiveSchema>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (file, data) in data.iter() {
+        for custom in data.get_customs() {
+            let Some(schema) = schemas.get(&custom.name) else {
+                continue;
+            };
+            let mut warn = |range, message| {
+                diagnostics
+                    .entry(file.clone())
+                    .or_default()
+                    .push(lsp_types::Diagnostic {
+                        range,
+                        message,
+                        severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                        ..lsp_types::Diagnostic::default()
+                    });
+            };
+            for (value, argument) in custom.values.iter().zip(&schema.arguments) {
+                let expected = argument.kind.name();
+                if value.kind != expected {
+                    warn(
+                        value.range,
+                        format!(
+                            "`{}` of custom \"{}\" should be {} {}, not {} {}",
+                            argument.name,
+                            custom.name,
+                            article(expected),
+                            expected,
+                            article(&value.kind),
+                            value.kind
+                        ),
+                    );
+                } else if !argument.values.is_empty()
+                    && !argument
+                        .values
+                        .iter()
+                        .any(|allowed| *allowed == value.text.trim_matches('"'))
+                {
+                    warn(
+                        value.range,
+                        format!(
+                            "`{}` of custom \"{}\" should be one of {}",
+                            argument.name,
+                            custom.name,
+                            argument.values.join(", ")
+                        ),
+                    );
+                }
+            }
+            if let Some(extra) = custom.values.get(schema.arguments.len()) {
+                let last = custom.values.last().unwrap_or(extra);
+                warn(
+                    lsp_types::Range::new(extra.range.start, last.range.end),
+                    format!(
+                        "custom \"{}\" takes {} arguments",
+                        custom.name,
+                        schema.arguments.len()
+                    ),
+                );
+            }
+            let missing: Vec<_> = schema
+                .arguments
+                .iter()
+                .skip(custom.values.len())
+                .take_while(|argument| !argument.optional)
+                .map(|argument| format!("`{}`", argument.name))
+                .collect();
+            if !missing.is_empty() {
+                warn(
+                    custom.name_range,
+                    format!(
+                        "custom \"{}\" is missing {}",
+                        custom.name,
+                        missing.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+fn article(kind: &str) -> &'static str {
+    if kind.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_utils::TestState;
+
+    fn schemas() -> BTreeMap<String, CustomDirectiveSchema> {
+        Config::new(PathBuf::new()).custom_directives
+    }
+
+    #[test]
+    fn handle_custom_slot() {
+        let schemas = schemas();
+        let slot = |line_prefix| custom_slot(line_prefix, &schemas);
+        assert_eq!(slot("2024-01-01 custom \"bud"), Some(CustomSlot::Name));
+        assert_eq!(slot("2024-01-01 custom \"budget\""), None);
+        let argument = |index, currency, typing| {
+            Some(CustomSlot::Argument {
+                name: String::from("budget"),
+                index,
+                currency,
+                typing,
+            })
+        };
+        assert_eq!(
+            slot("2024-01-01 custom \"budget\" "),
+            argument(0, false, false)
+        );
+        assert_eq!(
+            slot("2024-01-01 custom \"budget\" Expenses:Fo"),
+            argument(0, false, true)
+        );
+        assert_eq!(
+            slot("2024-01-01 custom \"budget\" Expenses:Food \"mon"),
+            argument(1, false, true)
+        );
+        assert_eq!(
+            slot("2024-01-01 custom \"budget\" Expenses:Food \"per month "),
+            argument(1, false, true)
+        );
+        assert_eq!(
+            slot("2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 400.00 "),
+            argument(2, true, false)
+        );
+        assert_eq!(
+            slot("2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 400.00 USD "),
+            argument(3, false, false)
+        );
+        // without a schema every value is an argument
+        assert_eq!(
+            slot("2024-01-01 custom \"other\" 400.00 USD "),
+            Some(CustomSlot::Argument {
+                name: String::from("other"),
+                index: 2,
+                currency: false,
+                typing: false,
+            })
+        );
+    }
+
+    #[test]
+    fn handle_custom_diagnostics() {
+        let fixture = r#"
+%! /main.beancount
+2024-01-01 custom "budget" Expenses:Food "monthly" 400.00 USD
+2024-01-01 custom "budget" "Expenses:Food" "monthly" 400.00 USD
+2024-01-01 custom "budget" Expenses:Food "fortnightly" 400.00 USD
+2024-01-01 custom "budget" Expenses:Food
+2024-01-01 custom "forecast" "Rent" Assets:Bank -800.00 USD Expenses:Rent TRUE
+2024-01-01 custom "fava-option" "language" "en"
+"#;
+        let state = TestState::new(fixture).unwrap();
+        let diagnostics = diagnostics(&state.snapshot.beancount_data, &schemas());
+        let messages: Vec<(u32, &str)> = diagnostics[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    1,
+                    "`account` of custom \"budget\" should be an account, not a string"
+                ),
+                (
+                    2,
+                    "`period` of custom \"budget\" should be one of daily, weekly, monthly, quarterly, yearly"
+                ),
+                (3, "custom \"budget\" is missing `period`, `amount`"),
+                (4, "custom \"forecast\" takes 4 arguments"),
+            ]
+        );
+    }
+}
@@ -2,9 +2,33 @@ use crate::from_json;
 use crate::server::LspServerState;
 use crate::server::LspServerStateSnapshot;
 use crate::server::Task;
+use crate::utils::ToFilePath;
 use anyhow::Result;
+use lsp_types::request::Request;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The prefixes of the methods the server does not handle on purpose: the
+/// optional `$/` ones, and those of notebooks which the server does not
+/// advertise but some clients send anyway. They are dropped or answered
+/// with `MethodNotFound` without a warning.
+const UNSUPPORTED_METHOD_PREFIXES: [&str; 2] = ["$/", "notebookDocument/"];
+
+/// Logs a method the server does not handle, quietly for the expected ones.
+/// Only the method is logged, as the params may hold document text.
+fn log_unhandled(kind: &str, method: &str) {
+    if UNSUPPORTED_METHOD_PREFIXES
+        .iter()
+        .any(|prefix| method.starts_with(prefix))
+    {
+        tracing::debug!("unsupported {}: {}", kind, method);
+    } else {
+        tracing::warn!("unknown {}: {}", kind, method);
+    }
+}
 
 fn result_to_response<R>(
     id: lsp_server::RequestId,
@@ -25,6 +49,39 @@ where
     }
 }
 
+/// Runs the handler `f`, turning a panic into an error so that a bug in one
+/// request does not take down the whole server.
+fn catch_panic<T>(method: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            tracing::error!("handler for {} panicked: {}", method, message);
+            Err(anyhow::anyhow!(
+                "handler for {} panicked: {}",
+                method,
+                message
+            ))
+        }
+    }
+}
+
+/// The documents a request is about, from the `textDocument` of its params.
+/// Only changes to them make its response outdated, not edits elsewhere.
+fn target_documents(params: &serde_json::Value) -> Vec<PathBuf> {
+    params
+        .pointer("/textDocument/uri")
+        .and_then(|uri| uri.as_str())
+        .and_then(|uri| lsp_types::Uri::from_str(uri).ok())
+        .and_then(|uri| uri.to_file_path().ok())
+        .into_iter()
+        .collect()
+}
+
 // A helper struct to  dispatch LSP requests to functions.
 #[must_use = "RequestDispatcher::finish not called"]
 pub(crate) struct RequestDispatcher<'a> {
@@ -79,7 +136,8 @@ impl<'a> RequestDispatcher<'a> {
             Some(it) => it,
             None => return Ok(self),
         };
-        let result = f(self.state, params);
+        let state = &mut *self.state;
+        let result = catch_panic(R::METHOD, || f(state, params));
         let response = result_to_response::<R>(id, result);
         self.state.respond(response);
         Ok(self)
@@ -95,6 +153,10 @@ impl<'a> RequestDispatcher<'a> {
         R::Params: DeserializeOwned + 'static + Send,
         R::Result: Serialize + 'static,
     {
+        let targets = match &self.request {
+            Some(req) if req.method == R::METHOD => target_documents(&req.params),
+            _ => Vec::new(),
+        };
         let (id, params) = match self.parse::<R>() {
             Some(it) => it,
             None => return Ok(self),
@@ -102,12 +164,16 @@ impl<'a> RequestDispatcher<'a> {
 
         self.state.thread_pool.execute({
             let snapshot = self.state.snapshot();
+            let versions = snapshot.document_versions(&targets);
             let sender = self.state.task_sender.clone();
 
             move || {
-                let result = f(snapshot, params);
+                let result = catch_panic(R::METHOD, || f(snapshot, params));
                 sender
-                    .send(Task::Response(result_to_response::<R>(id, result)))
+                    .send(Task::Response(
+                        result_to_response::<R>(id, result),
+                        versions,
+                    ))
                     .unwrap();
             }
         });
@@ -115,14 +181,55 @@ impl<'a> RequestDispatcher<'a> {
         Ok(self)
     }
 
+    /// Dispatches a `workspace/executeCommand` request running one of
+    /// `commands` on the thread pool, as those run external programs which
+    /// would block the main loop. The other commands are left to `on_sync`.
+    pub fn on_command(
+        &mut self,
+        commands: &[&str],
+        f: fn(
+            LspServerStateSnapshot,
+            lsp_types::ExecuteCommandParams,
+        ) -> Result<Option<serde_json::Value>>,
+    ) -> Result<&mut Self> {
+        let runs_command = self.request.as_ref().is_some_and(|req| {
+            req.method == lsp_types::request::ExecuteCommand::METHOD
+                && req
+                    .params
+                    .get("command")
+                    .and_then(|command| command.as_str())
+                    .is_some_and(|command| commands.contains(&command))
+        });
+        if !runs_command {
+            return Ok(self);
+        }
+        self.on::<lsp_types::request::ExecuteCommand>(f)
+    }
+
+    /// Answers the given Request type with an empty result, for methods the
+    /// server advertises or clients expect without it having anything to do.
+    pub fn stub<R>(&mut self) -> &mut Self
+    where
+        R: lsp_types::request::Request + 'static,
+        R::Params: DeserializeOwned + 'static,
+        R::Result: Default + Serialize + 'static,
+    {
+        if let Some((id, _)) = self.parse::<R>() {
+            tracing::debug!("stubbed request: {}", R::METHOD);
+            let response = result_to_response::<R>(id, Ok(R::Result::default()));
+            self.state.respond(response);
+        }
+        self
+    }
+
     // If the request was not handled, report back that this is an unknown request.
     pub fn finish(&mut self) {
         if let Some(req) = self.request.take() {
-            tracing::error!("unknown request: {:?}", req);
+            log_unhandled("request", &req.method);
             let response = lsp_server::Response::new_err(
                 req.id,
                 lsp_server::ErrorCode::MethodNotFound as i32,
-                "unknown request".to_string(),
+                format!("unknown request: {}", req.method),
             );
             self.state.respond(response);
         }
@@ -161,7 +268,8 @@ impl<'a> NotificationDispatcher<'a> {
         let params = match notification.extract::<N::Params>(N::METHOD) {
             Ok(it) => it,
             Err(lsp_server::ExtractError::JsonError { method, error }) => {
-                panic!("Invalid request\nMethod: {method}\n error: {error}",)
+                tracing::warn!("invalid params of {}: {}", method, error);
+                return Ok(self);
             }
             Err(lsp_server::ExtractError::MethodMismatch(notification)) => {
                 self.notification = Some(notification);
@@ -172,12 +280,132 @@ impl<'a> NotificationDispatcher<'a> {
         Ok(self)
     }
 
-    /// Wraps-up the dispatcher. If the notification was not handled, log an error.
+    /// Drops the given Notification type, for methods the server advertises
+    /// or clients send without it having anything to do.
+    pub fn ignore<N>(&mut self) -> &mut Self
+    where
+        N: lsp_types::notification::Notification + 'static,
+    {
+        if self
+            .notification
+            .as_ref()
+            .is_some_and(|notification| notification.method == N::METHOD)
+        {
+            tracing::debug!("ignored notification: {}", N::METHOD);
+            self.notification = None;
+        }
+        self
+    }
+
+    /// Wraps-up the dispatcher. If the notification was not handled, log it.
     pub fn finish(&mut self) {
-        if let Some(notification) = &self.notification {
-            if !notification.method.starts_with("$/") {
-                tracing::error!("unhandled notification: {:?}", notification);
-            }
+        if let Some(notification) = self.notification.take() {
+            log_unhandled("notification", &notification.method);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::document::Document;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn handle_panicking_request() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::new()));
+        let request = lsp_server::Request::new(
+            lsp_server::RequestId::from(1),
+            lsp_types::request::Shutdown::METHOD.to_string(),
+            serde_json::Value::Null,
+        );
+        state
+            .req_queue
+            .incoming
+            .register(request.id.clone(), (request.method.clone(), Instant::now()));
+        RequestDispatcher::new(&mut state, request)
+            .on_sync::<lsp_types::request::Shutdown>(|_, _| panic!("unexpected tree"))
+            .unwrap()
+            .finish();
+
+        let Ok(lsp_server::Message::Response(response)) = receiver.try_recv() else {
+            panic!("expected a response");
+        };
+        let error = response.error.unwrap();
+        assert_eq!(error.code, lsp_server::ErrorCode::InternalError as i32);
+        assert!(error.message.contains("unexpected tree"));
+    }
+
+    #[test]
+    fn handle_versions_of_target_documents() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::new()));
+        for path in ["/main.beancount", "/other.beancount"] {
+            Arc::make_mut(&mut state.open_docs).insert(
+                PathBuf::from(path),
+                Document {
+                    content: ropey::Rope::new(),
+                    version: 3,
+                },
+            );
         }
+        let request = lsp_server::Request::new(
+            lsp_server::RequestId::from(1),
+            lsp_types::request::HoverRequest::METHOD.to_string(),
+            serde_json::json!({
+                "textDocument": { "uri": "file:///main.beancount" },
+                "position": { "line": 0, "character": 0 },
+            }),
+        );
+        RequestDispatcher::new(&mut state, request)
+            .on::<lsp_types::request::HoverRequest>(|_, _| Ok(None))
+            .unwrap()
+            .finish();
+
+        let Ok(Task::Response(_, versions)) = state.task_receiver.recv() else {
+            panic!("expected a response");
+        };
+        // an edit of the other document leaves the hover up to date
+        assert_eq!(versions, [(PathBuf::from("/main.beancount"), 3)].into());
+    }
+
+    #[test]
+    fn handle_background_commands() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::new()));
+        for (id, command) in [(1, "slow"), (2, "fast")] {
+            let request = lsp_server::Request::new(
+                lsp_server::RequestId::from(id),
+                lsp_types::request::ExecuteCommand::METHOD.to_string(),
+                serde_json::json!({ "command": command, "arguments": [] }),
+            );
+            state
+                .req_queue
+                .incoming
+                .register(request.id.clone(), (request.method.clone(), Instant::now()));
+            RequestDispatcher::new(&mut state, request)
+                .on_command(&["slow"], |_, _| Ok(Some(serde_json::json!("pool"))))
+                .unwrap()
+                .on_sync::<lsp_types::request::ExecuteCommand>(|_, _| {
+                    Ok(Some(serde_json::json!("main")))
+                })
+                .unwrap()
+                .finish();
+        }
+
+        let Ok(Task::Response(response, _)) = state.task_receiver.recv() else {
+            panic!("expected a response from the thread pool");
+        };
+        assert_eq!(response.id, lsp_server::RequestId::from(1));
+        assert_eq!(response.result, Some(serde_json::json!("pool")));
+        let Ok(lsp_server::Message::Response(response)) = receiver.try_recv() else {
+            panic!("expected a response from the main loop");
+        };
+        assert_eq!(response.id, lsp_server::RequestId::from(2));
+        assert_eq!(response.result, Some(serde_json::json!("main")));
     }
 }
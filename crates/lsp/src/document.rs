@@ -2,12 +2,17 @@
 pub struct Document {
     /// The textual content of the document.
     pub content: ropey::Rope,
+    /// The version of the content, as numbered by the client.
+    pub version: i32,
 }
 
 impl Document {
     pub fn open(params: lsp_types::DidOpenTextDocumentParams) -> Self {
         let content = ropey::Rope::from(params.text_document.text);
-        Self { content }
+        Self {
+            content,
+            version: params.text_document.version,
+        }
     }
 
     pub fn text(&self) -> ropey::Rope {
@@ -1,36 +1,41 @@
 use crate::beancount_data::BeancountData;
+use crate::config::IgnoreGlobs;
 use crate::server::LspServerStateSnapshot;
 use crate::server::ProgressMsg;
 use crate::server::Task;
 use crate::utils::ToFilePath;
 use crossbeam_channel::Sender;
-use glob::glob;
 use std::collections::linked_list::LinkedList;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
 use std::path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use tracing::error;
+use walkdir::WalkDir;
 
 // Issus to look at if running into issues with this
 // https://github.com/silvanshade/lspower/issues/8
 pub(crate) fn parse_initial_forest(
     snapshot: LspServerStateSnapshot,
-    root_url: PathBuf,
+    root_urls: Vec<PathBuf>,
     sender: Sender<Task>,
 ) -> anyhow::Result<bool, anyhow::Error> {
+    let ignore = snapshot.config.files.ignore_globs();
     let mut seen_files = LinkedList::new();
     // let root_pathbuf: String = self.root_journal_path.into_inner().unwrap().as_ref().as_os_str();
     // let temp = self.root_journal_path.read().await;
     // let root_url = lsp::Url::from_file_path(temp.clone().unwrap()).unwrap();
-    seen_files.push_back(root_url.clone());
+    seen_files.extend(root_urls.iter().cloned());
     let mut done = false;
 
     let mut to_processs = LinkedList::new();
     let mut new_to_processs = LinkedList::new();
-    to_processs.push_back(root_url);
+    let mut total = root_urls.len();
+    to_processs.extend(root_urls);
     let mut processed = 0;
-    let mut total = 1;
 
     sender
         .send(Task::Progress(ProgressMsg::ForestInit {
@@ -46,6 +51,9 @@ pub(crate) fn parse_initial_forest(
 
         while iter.peek().is_some() {
             let file = iter.next().unwrap();
+            if snapshot.cancelled.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
             tracing::info!("processing {:#?}", file);
             //session
             //    .client
@@ -54,16 +62,25 @@ pub(crate) fn parse_initial_forest(
 
             processed += 1;
 
-            let text = fs::read_to_string(file.clone())?;
-            let bytes = text.as_bytes();
+            // an open document is newer than the file on disk, and its
+            // includes are followed like those of the other files
+            let text = match snapshot.open_docs.get(file) {
+                Some(document) => document.content.to_string(),
+                None => fs::read_to_string(file.clone())?,
+            };
 
             let mut parser = tree_sitter::Parser::new();
             parser.set_language(&tree_sitter_beancount::language())?;
             let tree = parser.parse(&text, None).unwrap();
-            let mut cursor = tree.root_node().walk();
 
             let content = ropey::Rope::from_str(text.as_str());
-            let beancount_data = BeancountData::new(&tree, &content);
+            let beancount_data = BeancountData::new(
+                &tree,
+                &content,
+                snapshot.config.position_encoding,
+                snapshot.config.number_format.for_content(&content),
+            );
+            let include_filenames = beancount_data.get_includes().to_vec();
 
             sender
                 .send(Task::Progress(ProgressMsg::ForestInit {
@@ -75,60 +92,16 @@ pub(crate) fn parse_initial_forest(
 
             //snapshot.forest.insert(file.clone(), tree.clone());
 
-            let include_filenames = tree
-                .root_node()
-                .children(&mut cursor)
-                .filter(|c| c.kind() == "include")
-                .filter_map(|include_node| {
-                    let mut node_cursor = include_node.walk();
-                    let node = include_node
-                        .children(&mut node_cursor)
-                        .find(|c| c.kind() == "string")?;
-
-                    let filename = node
-                        .utf8_text(bytes)
-                        .unwrap()
-                        .trim_start_matches('"')
-                        .trim_end_matches('"');
-
-                    let path = path::Path::new(filename);
-
-                    let path = if path.is_absolute() {
-                        path.to_path_buf()
-                    } else if file.is_absolute() {
-                        file.parent().unwrap().join(path)
-                    } else {
-                        path.to_path_buf()
-                    };
-                    let path_url = lsp_types::Uri::from_str(
-                        format!("file://{}", path.to_str().unwrap()).as_str(),
-                    )
-                    .unwrap();
-
-                    Some(path_url)
-                });
-
-            // This could get in an infinite loop if there is a loop wtth the include files
-            // TODO see if I can prevent this
-            for include_url in include_filenames {
-                for entry in glob(include_url.to_file_path().unwrap().to_str().unwrap())
-                    .expect("Failed to read glob")
-                {
-                    match entry {
-                        Ok(path) => {
-                            let url = lsp_types::Uri::from_str(
-                                format!("file://{}", path.to_str().unwrap()).as_str(),
-                            )
-                            .unwrap()
-                            .to_file_path()
-                            .unwrap();
-                            if !snapshot.forest.contains_key(&url) {
-                                total += 1;
-                                new_to_processs.push_back(url);
-                            }
-                        }
-                        Err(e) => error!("{:?}", e),
-                    }
+            // files shared by several journals, or included in a loop, are
+            // only parsed once. Open documents are in the forest already, but
+            // what they include may not be.
+            for path in resolve_includes(file, &include_filenames, &ignore) {
+                let parsed =
+                    snapshot.forest.contains_key(&path) && !snapshot.open_docs.contains_key(&path);
+                if !parsed && !seen_files.contains(&path) {
+                    seen_files.push_back(path.clone());
+                    total += 1;
+                    new_to_processs.push_back(path);
                 }
             }
         }
@@ -152,3 +125,186 @@ pub(crate) fn parse_initial_forest(
 
     Ok(true)
 }
+
+/// Resolves the `include` paths of `file` to the files they refer to. Relative
+/// paths are relative to the including file, globs are expanded and paths that
+/// do not exist are left out. Glob matches under `ignore` are left out too,
+/// and ignored directories are not searched at all.
+pub(crate) fn resolve_includes(
+    file: &path::Path,
+    includes: &[String],
+    ignore: &IgnoreGlobs,
+) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    for filename in includes {
+        let path = path::Path::new(filename);
+        let path = if path.is_absolute() {
+            path.to_path_buf()
+        } else if file.is_absolute() {
+            file.parent().unwrap().join(path)
+        } else {
+            path.to_path_buf()
+        };
+        let Some(pattern) =
+            lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+        else {
+            continue;
+        };
+        if filename.contains(['*', '?', '[']) {
+            let dir = file.parent().unwrap_or(path::Path::new(""));
+            paths.extend(expand_glob(&pattern, dir, ignore));
+        } else if pattern.exists() {
+            paths.push(pattern);
+        }
+    }
+    paths
+}
+
+/// The files matching the glob `pattern`, searched for from the directory
+/// before its first wildcard. Entries under `ignore`, relative to `dir`, are
+/// pruned before their directories are read.
+fn expand_glob(pattern: &path::Path, dir: &path::Path, ignore: &IgnoreGlobs) -> Vec<PathBuf> {
+    let matcher = match glob::Pattern::new(pattern.to_str().unwrap()) {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            error!("{:?}", e);
+            return vec![];
+        }
+    };
+    // like `glob`, a wildcard other than `**` stays within one component
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    let base: PathBuf = pattern
+        .components()
+        .take_while(|component| {
+            !component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '['])
+        })
+        .collect();
+    let mut walker = WalkDir::new(&base).follow_links(true).sort_by_file_name();
+    if !matcher.as_str().contains("**") {
+        walker = walker.max_depth(pattern.components().count() - base.components().count());
+    }
+    walker
+        .into_iter()
+        .filter_entry(|entry| {
+            let ignored = ignore.matches(entry.path().strip_prefix(dir).unwrap_or(entry.path()));
+            if ignored {
+                tracing::debug!("ignoring {:#?}", entry.path());
+            }
+            !ignored
+        })
+        .filter_map(|entry| entry.map_err(|e| error!("{:?}", e)).ok())
+        .filter(|entry| {
+            entry.file_type().is_file() && matcher.matches_path_with(entry.path(), options)
+        })
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// The files that can be reached from `roots` by following includes.
+pub(crate) fn reachable_files(
+    roots: impl IntoIterator<Item = PathBuf>,
+    beancount_data: &HashMap<PathBuf, BeancountData>,
+    ignore: &IgnoreGlobs,
+) -> HashSet<PathBuf> {
+    let mut reachable = HashSet::new();
+    let mut to_process: Vec<PathBuf> = roots.into_iter().collect();
+    while let Some(file) = to_process.pop() {
+        if !reachable.insert(file.clone()) {
+            continue;
+        }
+        if let Some(data) = beancount_data.get(&file) {
+            to_process.extend(resolve_includes(&file, data.get_includes(), ignore));
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(path: &path::Path) -> BeancountData {
+        let text = fs::read_to_string(path).unwrap();
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(&text, None).unwrap();
+        BeancountData::new(
+            &tree,
+            &ropey::Rope::from_str(&text),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn handle_reachable_files() {
+        let dir = std::env::temp_dir().join(format!("beancount-forest-{}", std::process::id()));
+        fs::create_dir_all(dir.join("accounts")).unwrap();
+        let main = dir.join("main.beancount");
+        let bank = dir.join("accounts/bank.beancount");
+        let card = dir.join("accounts/card.beancount");
+        let old = dir.join("old.beancount");
+        fs::write(&main, "include \"accounts/*.beancount\"\n").unwrap();
+        fs::write(&bank, "2023-01-01 open Assets:Bank\n").unwrap();
+        fs::write(&card, "2023-01-01 open Liabilities:Card\n").unwrap();
+        fs::write(&old, "2023-01-01 open Assets:Old\n").unwrap();
+
+        let data: HashMap<PathBuf, BeancountData> = [&main, &bank, &card, &old]
+            .into_iter()
+            .map(|path| (path.clone(), parse(path)))
+            .collect();
+        let ignore = IgnoreGlobs::default();
+        let reachable = reachable_files([main.clone()], &data, &ignore);
+        assert_eq!(
+            reachable,
+            HashSet::from([main.clone(), bank.clone(), card.clone()])
+        );
+
+        let ignore = crate::config::FilesOptions {
+            ignore_globs: vec![String::from("card.*")],
+        }
+        .ignore_globs();
+        let reachable = reachable_files([main.clone()], &data, &ignore);
+        assert_eq!(reachable, HashSet::from([main.clone(), bank.clone()]));
+
+        fs::remove_file(&card).unwrap();
+        let reachable = reachable_files([main.clone()], &data, &IgnoreGlobs::default());
+        assert_eq!(reachable, HashSet::from([main.clone(), bank.clone()]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn handle_ignored_directories() {
+        let dir =
+            std::env::temp_dir().join(format!("beancount-forest-ignore-{}", std::process::id()));
+        fs::create_dir_all(dir.join("2024/archive")).unwrap();
+        let main = dir.join("main.beancount");
+        let january = dir.join("2024/01.beancount");
+        let archived = dir.join("2024/archive/01.beancount");
+        fs::write(&january, "").unwrap();
+        fs::write(&archived, "").unwrap();
+        let includes = [String::from("**/*.beancount")];
+
+        let found = resolve_includes(&main, &includes, &IgnoreGlobs::default());
+        assert_eq!(found, [january.clone(), archived]);
+
+        let ignore = crate::config::FilesOptions {
+            ignore_globs: vec![String::from("archive")],
+        }
+        .ignore_globs();
+        assert_eq!(resolve_includes(&main, &includes, &ignore), [january]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! Property tests that run the providers over mutated versions of the ledgers
+//! in `tests/corpus`, at random positions, checking that they neither panic
+//! nor return ranges outside of the document.
+//!
+//! The cases are generated by proptest, which shrinks a failing one to a
+//! small mutation and keeps its seed in `proptest-regressions` to run it
+//! first from then on. The number of cases defaults to 64 and can be set with
+//! the `PROPTEST_CASES` environment variable.
+
+use crate::providers::code_actions;
+use crate::providers::completion;
+use crate::providers::formatting;
+use crate::providers::inlay_hints;
+use crate::providers::rename;
+use crate::providers::workspace_symbols;
+use crate::test_utils::Fixture;
+use crate::test_utils::TestDocument;
+use crate::test_utils::TestState;
+use proptest::prelude::*;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const PATH: &str = "/fuzz.beancount";
+
+/// Fragments inserted by mutations, chosen to hit the interesting parts of the
+/// grammar.
+const FRAGMENTS: &[&str] = &[
+    "\"",
+    "#",
+    "^",
+    "{",
+    "}",
+    "{{",
+    "@",
+    "@@",
+    " ",
+    "  ",
+    "\t",
+    "\n",
+    "\r\n",
+    "2024-",
+    "2024-01-01",
+    " txn ",
+    " * ",
+    " ! ",
+    "Assets:",
+    "Expenses:Food",
+    " USD",
+    "-",
+    "1,000.00",
+    "(1 + 2)",
+    ":",
+    "é",
+    "€",
+    "📚",
+    "日本",
+    ";",
+    "include \"",
+    "pushtag ",
+    "custom \"budget\" ",
+    "#forecast",
+];
+
+/// An edit of a ledger, at a character offset taken modulo its length.
+#[derive(Debug, Clone)]
+enum Mutation {
+    /// Inserts a fragment.
+    Insert(usize, &'static str),
+    /// Deletes a few characters.
+    Delete(usize, usize),
+    /// Duplicates a stretch of text.
+    Duplicate(usize, usize),
+}
+
+fn mutation() -> impl Strategy<Value = Mutation> {
+    prop_oneof![
+        2 => (any::<usize>(), prop::sample::select(FRAGMENTS))
+            .prop_map(|(at, fragment)| Mutation::Insert(at, fragment)),
+        1 => (any::<usize>(), 1..=8usize).prop_map(|(at, len)| Mutation::Delete(at, len)),
+        1 => (any::<usize>(), 1..=40usize).prop_map(|(at, len)| Mutation::Duplicate(at, len)),
+    ]
+}
+
+/// Where the providers that work at a position are asked, with the line and
+/// character taken modulo the number of lines and the length of the line
+/// plus two, so it is usually valid but sometimes past the end of the line.
+#[derive(Debug, Clone)]
+struct Probe {
+    line: usize,
+    character: usize,
+    trigger: Option<char>,
+}
+
+fn probe() -> impl Strategy<Value = Probe> {
+    (
+        any::<usize>(),
+        any::<usize>(),
+        prop::option::of(prop::sample::select(
+            completion::TRIGGER_CHARACTERS.to_vec(),
+        )),
+    )
+        .prop_map(|(line, character, trigger)| Probe {
+            line,
+            character,
+            trigger,
+        })
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn corpus() -> Vec<(PathBuf, String)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut ledgers: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "beancount"))
+        .map(|path| {
+            let text = std::fs::read_to_string(&path).unwrap();
+            (path, text)
+        })
+        .collect();
+    ledgers.sort();
+    ledgers
+}
+
+/// Applies `mutation` to `text`.
+fn mutate(text: &str, mutation: &Mutation) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let (Mutation::Insert(at, _) | Mutation::Delete(at, _) | Mutation::Duplicate(at, _)) =
+        *mutation;
+    let at = at % (chars.len() + 1);
+    let mut result: String = chars[..at].iter().collect();
+    let rest: String = chars[at..].iter().collect();
+    match *mutation {
+        Mutation::Insert(_, fragment) => {
+            result.push_str(fragment);
+            result.push_str(&rest);
+        }
+        Mutation::Delete(_, len) => result.extend(rest.chars().skip(len)),
+        Mutation::Duplicate(_, len) => {
+            let stretch: String = rest.chars().take(len).collect();
+            result.push_str(&stretch);
+            result.push_str(&rest);
+        }
+    }
+    result
+}
+
+fn state(text: &str) -> TestState {
+    TestState::from_fixture(Fixture {
+        documents: vec![TestDocument {
+            path: PATH.to_string(),
+            text: text.to_string(),
+            cursor: None,
+        }],
+    })
+    .unwrap()
+}
+
+/// The position `probe` is at in `content`.
+fn probe_position(probe: &Probe, content: &ropey::Rope) -> lsp_types::Position {
+    let line = probe.line % content.len_lines();
+    let len = content.line(line).len_utf16_cu();
+    lsp_types::Position::new(line as u32, (probe.character % (len + 2)) as u32)
+}
+
+fn assert_valid_position(content: &ropey::Rope, position: lsp_types::Position, what: &str) {
+    let line = position.line as usize;
+    assert!(
+        line < content.len_lines(),
+        "{what}: line {line} out of range"
+    );
+    let len = content.line(line).len_utf16_cu();
+    assert!(
+        position.character as usize <= len,
+        "{what}: character {} past the end of line {line}",
+        position.character
+    );
+}
+
+fn assert_valid_range(content: &ropey::Rope, range: lsp_types::Range, what: &str) {
+    assert_valid_position(content, range.start, what);
+    assert_valid_position(content, range.end, what);
+    assert!(
+        range.start <= range.end,
+        "{what}: range {range:?} is reversed"
+    );
+}
+
+/// Runs every provider over `text`, those that work at a position once for
+/// each of `probes`.
+fn check_providers(text: &str, tab_size: u32, probes: &[Probe]) {
+    let content = ropey::Rope::from_str(text);
+    let uri = lsp_types::Uri::from_str(format!("file://{PATH}").as_str()).unwrap();
+    let document = lsp_types::TextDocumentIdentifier::new(uri);
+    let whole = lsp_types::Range::new(
+        lsp_types::Position::new(0, 0),
+        lsp_types::Position::new(content.len_lines() as u32, 0),
+    );
+
+    let edits = formatting::formatting(
+        state(text).snapshot,
+        lsp_types::DocumentFormattingParams {
+            text_document: document.clone(),
+            options: lsp_types::FormattingOptions {
+                tab_size,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        },
+    )
+    .unwrap();
+    for edit in edits.unwrap_or_default() {
+        assert_valid_range(&content, edit.range, "formatting");
+    }
+
+    let mut snapshot = state(text).snapshot;
+    Arc::make_mut(&mut snapshot.config).budget.enable = true;
+    let hints = inlay_hints::inlay_hints(
+        snapshot,
+        lsp_types::InlayHintParams {
+            text_document: document.clone(),
+            range: whole,
+            work_done_progress_params: Default::default(),
+        },
+    )
+    .unwrap();
+    for hint in hints.unwrap_or_default() {
+        assert_valid_position(&content, hint.position, "inlay hint");
+    }
+
+    let symbols = workspace_symbols::workspace_symbols(
+        state(text).snapshot,
+        lsp_types::WorkspaceSymbolParams {
+            query: String::new(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        },
+    )
+    .unwrap();
+    if let Some(lsp_types::WorkspaceSymbolResponse::Nested(symbols)) = symbols {
+        for symbol in symbols {
+            if let lsp_types::OneOf::Left(location) = symbol.location {
+                assert_valid_range(&content, location.range, "workspace symbol");
+            }
+        }
+    }
+
+    for probe in probes {
+        let position = probe_position(probe, &content);
+        let _ = completion::completion(
+            state(text).snapshot,
+            probe.trigger,
+            lsp_types::TextDocumentPositionParams::new(document.clone(), position),
+        );
+
+        if let Some(lsp_types::PrepareRenameResponse::RangeWithPlaceholder { range, .. }) =
+            rename::prepare_rename(
+                state(text).snapshot,
+                lsp_types::TextDocumentPositionParams::new(document.clone(), position),
+            )
+            .unwrap()
+        {
+            assert_valid_range(&content, range, "prepare rename");
+        }
+
+        let actions = code_actions::code_actions(
+            state(text).snapshot,
+            lsp_types::CodeActionParams {
+                text_document: document.clone(),
+                range: lsp_types::Range::new(position, position),
+                context: Default::default(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        )
+        .unwrap();
+        for action in actions.unwrap_or_default() {
+            let lsp_types::CodeActionOrCommand::CodeAction(action) = action else {
+                continue;
+            };
+            for edits in action
+                .edit
+                .and_then(|edit| edit.changes)
+                .unwrap_or_default()
+                .values()
+            {
+                for edit in edits {
+                    assert_valid_range(&content, edit.range, "code action");
+                }
+            }
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(env_or("PROPTEST_CASES", 64)))]
+
+    #[test]
+    fn fuzz_providers(
+        (_, ledger) in prop::sample::select(corpus()),
+        mutations in prop::collection::vec(mutation(), 1..4),
+        tab_size in 1..=8u32,
+        probes in prop::collection::vec(probe(), 3),
+    ) {
+        let text = mutations
+            .iter()
+            .fold(ledger, |text, mutation| mutate(&text, mutation));
+        check_providers(&text, tab_size, &probes);
+    }
+}
+
+#[test]
+fn corpus_parses_cleanly() {
+    for (path, ledger) in corpus() {
+        let state = state(&ledger);
+        let tree = state.snapshot.forest.values().next().unwrap();
+        assert!(
+            !tree.root_node().has_error(),
+            "{} does not parse",
+            path.display()
+        );
+        let probe = Probe {
+            line: 0,
+            character: 0,
+            trigger: None,
+        };
+        check_providers(&ledger, 4, &[probe]);
+    }
+}
@@ -0,0 +1,241 @@
+//! Golden tests that run the providers over the fixtures in `tests/golden` and
+//! compare their results with the JSON snapshots in `tests/golden/snapshots`.
+//!
+//! A fixture is written like the fixtures of the unit tests: documents start
+//! with a `%! /path` line, and a line of spaces with a `|` marks the cursor on
+//! the line above it. The document wide providers run on every document, the
+//! ones that need a position only if the fixture has a cursor.
+//!
+//! After a change in behavior, review and accept the new snapshots with
+//! `cargo insta review`, or record them with `INSTA_UPDATE=always cargo test`.
+//!
+//! The files in `tests/bean_format` are formatted with `formatting.beanFormat`
+//! and compared byte for byte with the output of `bean-format`, which
+//! `name.formatted` holds and `name.cNN.formatted` with `--currency-column NN`.
+
+use crate::providers::code_actions;
+use crate::providers::completion;
+use crate::providers::document_symbols;
+use crate::providers::formatting;
+use crate::providers::hover;
+use crate::providers::inlay_hints;
+use crate::test_utils::apply_edits;
+use crate::test_utils::TestState;
+use crate::utils::ToFilePath;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+fn fixtures() -> Vec<(String, String)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut fixtures: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "beancount"))
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let text = std::fs::read_to_string(&path).unwrap();
+            (name, text)
+        })
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+fn document(path: &str) -> lsp_types::TextDocumentIdentifier {
+    lsp_types::TextDocumentIdentifier::new(
+        lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap(),
+    )
+}
+
+/// Runs `provider` on every document of the fixture, keyed by its path.
+fn per_document<T: Serialize>(
+    fixture: &str,
+    provider: impl Fn(TestState, lsp_types::TextDocumentIdentifier) -> T,
+) -> BTreeMap<String, T> {
+    let paths: Vec<_> = TestState::new(fixture)
+        .unwrap()
+        .fixture
+        .documents
+        .iter()
+        .map(|document| document.path.clone())
+        .collect();
+    paths
+        .into_iter()
+        .map(|path| {
+            let result = provider(TestState::new(fixture).unwrap(), document(&path));
+            (path, result)
+        })
+        .collect()
+}
+
+fn run_document_providers(name: &str, fixture: &str) {
+    let symbols = per_document(fixture, |state, text_document| {
+        document_symbols::document_symbols(
+            state.snapshot,
+            lsp_types::DocumentSymbolParams {
+                text_document,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        )
+        .unwrap()
+    });
+    insta::assert_json_snapshot!(format!("{name}@document_symbols"), symbols);
+
+    let edits = per_document(fixture, |state, text_document| {
+        formatting::formatting(
+            state.snapshot,
+            lsp_types::DocumentFormattingParams {
+                text_document,
+                options: lsp_types::FormattingOptions {
+                    tab_size: 4,
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+            },
+        )
+        .unwrap()
+    });
+    insta::assert_json_snapshot!(format!("{name}@formatting"), edits);
+
+    let hints = per_document(fixture, |state, text_document| {
+        let mut snapshot = state.snapshot;
+        Arc::make_mut(&mut snapshot.config).budget.enable = true;
+        let file = text_document.uri.to_file_path().unwrap();
+        let lines = snapshot.document_content(&file).unwrap().len_lines() as u32;
+        inlay_hints::inlay_hints(
+            snapshot,
+            lsp_types::InlayHintParams {
+                text_document,
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(0, 0),
+                    lsp_types::Position::new(lines, 0),
+                ),
+                work_done_progress_params: Default::default(),
+            },
+        )
+        .unwrap()
+    });
+    insta::assert_json_snapshot!(format!("{name}@inlay_hints"), hints);
+}
+
+fn run_cursor_providers(name: &str, fixture: &str) {
+    let state = TestState::new(fixture).unwrap();
+    let Some(cursor) = state.cursor() else {
+        return;
+    };
+    // complete as if the character before the cursor had just been typed
+    let trigger_character = state
+        .fixture
+        .documents
+        .iter()
+        .find(|document| document.cursor.is_some())
+        .and_then(|document| {
+            let line = document.text.lines().nth(cursor.position.line as usize)?;
+            line.chars().take(cursor.position.character as usize).last()
+        })
+        .filter(|c| completion::TRIGGER_CHARACTERS.contains(c));
+
+    let mut items =
+        completion::completion(state.snapshot, trigger_character, cursor.clone()).unwrap();
+    // the order of the items is up to the client, only what is offered matters
+    if let Some(items) = items.as_mut() {
+        items.sort_by(|a, b| (&a.sort_text, &a.label).cmp(&(&b.sort_text, &b.label)));
+    }
+    insta::assert_json_snapshot!(format!("{name}@completion"), items);
+
+    let hover = hover::hover(
+        TestState::new(fixture).unwrap().snapshot,
+        lsp_types::HoverParams {
+            text_document_position_params: cursor.clone(),
+            work_done_progress_params: Default::default(),
+        },
+    )
+    .unwrap();
+    insta::assert_json_snapshot!(format!("{name}@hover"), hover);
+
+    let actions = code_actions::code_actions(
+        TestState::new(fixture).unwrap().snapshot,
+        lsp_types::CodeActionParams {
+            text_document: cursor.text_document,
+            range: lsp_types::Range::new(cursor.position, cursor.position),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        },
+    )
+    .unwrap();
+    insta::assert_json_snapshot!(format!("{name}@code_actions"), actions);
+}
+
+#[test]
+fn golden_providers() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    for (name, fixture) in fixtures() {
+        let mut settings = insta::Settings::clone_current();
+        settings.set_snapshot_path(dir.join("snapshots"));
+        settings.set_prepend_module_to_snapshot(false);
+        settings.set_input_file(dir.join(format!("{name}.beancount")));
+        settings.bind(|| {
+            run_document_providers(&name, &fixture);
+            run_cursor_providers(&name, &fixture);
+        });
+    }
+}
+
+#[test]
+fn bean_format_corpus() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/bean_format");
+    // `regenerate.sh` writes the expected files with the real `bean-format`
+    let version = std::fs::read_to_string(dir.join("BEANCOUNT_VERSION"))
+        .map_or(String::from("unknown"), |version| {
+            version.trim().to_string()
+        });
+    let mut checked = 0;
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some((input, currency_column)) = name.strip_suffix(".formatted").map(|name| match name
+            .rsplit_once(".c")
+        {
+            Some((input, column)) => (input, column.parse::<usize>().ok()),
+            None => (name, None),
+        }) else {
+            continue;
+        };
+        let text = std::fs::read_to_string(dir.join(format!("{input}.beancount"))).unwrap();
+        let expected = std::fs::read_to_string(&path).unwrap();
+
+        let mut state = TestState::new(&format!("%! /main.beancount\n{text}")).unwrap();
+        Arc::make_mut(&mut state.snapshot.config)
+            .formatting
+            .bean_format = true;
+        Arc::make_mut(&mut state.snapshot.config)
+            .formatting
+            .currency_column = currency_column;
+        let text = state.fixture.documents[0].text.clone();
+        let edits = formatting::formatting(
+            state.snapshot,
+            lsp_types::DocumentFormattingParams {
+                text_document: document("/main.beancount"),
+                options: Default::default(),
+                work_done_progress_params: Default::default(),
+            },
+        )
+        .unwrap()
+        .unwrap_or_default();
+        // the fixture drops the last newline, which `bean-format` always ends with
+        assert_eq!(
+            format!("{}\n", apply_edits(&text, &edits)),
+            expected,
+            "{name}, from bean-format of beancount {version}"
+        );
+        checked += 1;
+    }
+    assert!(checked > 0);
+}
@@ -1,21 +1,38 @@
 pub mod text_document {
     use crate::beancount_data::BeancountData;
+    use crate::beancount_data::QueryEntry;
+    use crate::checker;
+    use crate::config::CheckerMethod;
     use crate::document::Document;
+    use crate::providers::code_actions;
+    use crate::providers::code_lens;
     use crate::providers::completion;
     use crate::providers::diagnostics;
+    use crate::providers::document_links;
+    use crate::providers::document_symbols;
+    use crate::providers::folding_ranges;
     use crate::providers::formatting;
+    use crate::providers::hover;
+    use crate::providers::inlay_hints;
+    use crate::providers::on_type_formatting;
+    use crate::providers::references;
+    use crate::providers::rename;
+    use crate::providers::semantic_tokens;
+    use crate::providers::signature_help;
     use crate::server::LspServerState;
     use crate::server::LspServerStateSnapshot;
     use crate::server::ProgressMsg;
     use crate::server::Task;
     use crate::to_json;
+    use crate::treesitter_utils::lsp_position_to_char;
     use crate::treesitter_utils::lsp_textdocchange_to_ts_inputedit;
     use crate::utils::ToFilePath;
     use anyhow::Result;
     use crossbeam_channel::Sender;
     use lsp_types::notification::Notification;
+    use std::collections::BTreeMap;
     use std::path::PathBuf;
-    use std::str::FromStr;
+    use std::sync::Arc;
     use tracing::debug;
 
     /// handler for `textDocument/didOpen`.
@@ -29,7 +46,7 @@ pub mod text_document {
         let document = Document::open(params.clone());
         //let tree = document.tree.clone();
         tracing::debug!("handlers::did_open - adding {:#?}", &uri);
-        state.open_docs.insert(uri.clone(), document);
+        Arc::make_mut(&mut state.open_docs).insert(uri.clone(), document);
 
         state.parsers.entry(uri.clone()).or_insert_with(|| {
             let mut parser = tree_sitter::Parser::new();
@@ -40,15 +57,21 @@ pub mod text_document {
         });
         let parser = state.parsers.get_mut(&uri).unwrap();
 
-        state
-            .forest
+        Arc::make_mut(&mut state.forest)
             .entry(uri.clone())
             .or_insert_with(|| parser.parse(&params.text_document.text, None).unwrap());
 
-        state.beancount_data.entry(uri.clone()).or_insert_with(|| {
-            let content = ropey::Rope::from_str(&params.text_document.text);
-            BeancountData::new(state.forest.get(&uri).unwrap(), &content)
-        });
+        Arc::make_mut(&mut state.beancount_data)
+            .entry(uri.clone())
+            .or_insert_with(|| {
+                let content = ropey::Rope::from_str(&params.text_document.text);
+                BeancountData::new(
+                    state.forest.get(&uri).unwrap(),
+                    &content,
+                    state.config.position_encoding,
+                    state.config.number_format.for_content(&content),
+                )
+            });
 
         let snapshot = state.snapshot();
         let task_sender = state.task_sender.clone();
@@ -65,6 +88,7 @@ pub mod text_document {
         params: lsp_types::DidSaveTextDocumentParams,
     ) -> Result<()> {
         tracing::debug!("handlers::did_save");
+        state.prune_forest();
 
         let snapshot = state.snapshot();
         let task_sender = state.task_sender.clone();
@@ -82,7 +106,8 @@ pub mod text_document {
     ) -> Result<()> {
         tracing::debug!("handlers::did_close");
         let uri = params.text_document.uri.to_file_path().unwrap();
-        state.open_docs.remove(&uri);
+        Arc::make_mut(&mut state.open_docs).remove(&uri);
+        state.prune_forest();
         // let version = Default::default();
         Ok(())
     }
@@ -95,43 +120,43 @@ pub mod text_document {
         tracing::debug!("handlers::did_change");
         let uri = &params.text_document.uri.to_file_path().unwrap();
         tracing::debug!("handlers::did_change - requesting {:#?}", uri);
-        let doc = state.open_docs.get_mut(uri).unwrap();
-
-        tracing::debug!("handlers::did_change - convert edits");
-        let edits = params
-            .content_changes
-            .iter()
-            .map(|change| lsp_textdocchange_to_ts_inputedit(&doc.content, change))
-            .collect::<Result<Vec<_>, _>>()?;
+        let doc = Arc::make_mut(&mut state.open_docs).get_mut(uri).unwrap();
+
+        // the edits of a change older than the content we have would land in
+        // the wrong place
+        if params.text_document.version <= doc.version {
+            tracing::warn!(
+                "handlers::did_change - dropping stale version {} of {:#?} (at {})",
+                params.text_document.version,
+                uri,
+                doc.version
+            );
+            return Ok(());
+        }
+        doc.version = params.text_document.version;
 
         tracing::debug!("handlers::did_change - apply edits - document");
+        let encoding = state.config.position_encoding;
+        let mut edits = Vec::with_capacity(params.content_changes.len());
         for change in &params.content_changes {
-            let text = change.text.as_str();
-            let text_bytes = text.as_bytes();
-            let text_end_byte_idx = text_bytes.len();
-
-            let range = if let Some(range) = change.range {
-                range
-            } else {
-                let start_line_idx = doc.content.byte_to_line(0);
-                let end_line_idx = doc.content.byte_to_line(text_end_byte_idx);
-
-                let start = lsp_types::Position::new(start_line_idx as u32, 0);
-                let end = lsp_types::Position::new(end_line_idx as u32, 0);
-                lsp_types::Range { start, end }
+            // each change is relative to the content left by the previous one
+            edits.push(lsp_textdocchange_to_ts_inputedit(
+                &doc.content,
+                change,
+                encoding,
+            )?);
+
+            let (start_char_idx, end_char_idx) = match change.range {
+                Some(range) => (
+                    lsp_position_to_char(&doc.content, range.start, encoding),
+                    lsp_position_to_char(&doc.content, range.end, encoding),
+                ),
+                None => (0, doc.content.len_chars()),
             };
-
-            let start_row_char_idx = doc.content.line_to_char(range.start.line as usize);
-            let start_col_char_idx = doc.content.utf16_cu_to_char(range.start.character as usize);
-            let end_row_char_idx = doc.content.line_to_char(range.end.line as usize);
-            let end_col_char_idx = doc.content.utf16_cu_to_char(range.end.character as usize);
-
-            let start_char_idx = start_row_char_idx + start_col_char_idx;
-            let end_char_idx = end_row_char_idx + end_col_char_idx;
             doc.content.remove(start_char_idx..end_char_idx);
 
             if !change.text.is_empty() {
-                doc.content.insert(start_char_idx, text);
+                doc.content.insert(start_char_idx, &change.text);
             }
         }
 
@@ -140,7 +165,7 @@ pub mod text_document {
             let parser = state.parsers.get_mut(uri).unwrap();
             //let mut parser = parser.lock();
 
-            let old_tree = state.forest.get_mut(uri).unwrap();
+            let old_tree = Arc::make_mut(&mut state.forest).get_mut(uri).unwrap();
             //let mut old_tree = old_tree.lock().await;
 
             for edit in &edits {
@@ -152,8 +177,11 @@ pub mod text_document {
 
         debug!("handlers::did_change - save tree");
         if let Some(tree) = result {
-            *state.forest.get_mut(uri).unwrap() = tree.clone();
-            *state.beancount_data.get_mut(uri).unwrap() = BeancountData::new(&tree, &doc.content);
+            *Arc::make_mut(&mut state.forest).get_mut(uri).unwrap() = tree.clone();
+            let number_format = state.config.number_format.for_content(&doc.content);
+            *Arc::make_mut(&mut state.beancount_data)
+                .get_mut(uri)
+                .unwrap() = BeancountData::new(&tree, &doc.content, encoding, number_format);
             /*.unwrap().update_data(
                 uri.clone(),
                 &tree,
@@ -161,6 +189,8 @@ pub mod text_document {
             );*/
         }
 
+        state.mark_diagnostics_stale(uri);
+
         debug!("handlers::did_close - done");
         Ok(())
     }
@@ -186,12 +216,24 @@ pub mod text_document {
             },
             None => None,
         };
+        let options = snapshot.config.completion.clone();
+        let started = std::time::Instant::now();
         let Some(items) =
             completion::completion(snapshot, trigger_char, params.text_document_position)?
         else {
             return Ok(None);
         };
-        Ok(Some(lsp_types::CompletionResponse::Array(items)))
+        // a list rather than an array, so the server decides when the client
+        // has to ask again
+        let list = completion::apply_budget(items, &options, started.elapsed());
+        Ok(Some(lsp_types::CompletionResponse::List(list)))
+    }
+
+    pub(crate) fn document_symbol(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::DocumentSymbolParams,
+    ) -> Result<Option<lsp_types::DocumentSymbolResponse>> {
+        document_symbols::document_symbols(snapshot, params)
     }
 
     pub(crate) fn formatting(
@@ -201,52 +243,460 @@ pub mod text_document {
         formatting::formatting(snapshot, params)
     }
 
+    pub(crate) fn on_type_formatting(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<lsp_types::TextEdit>>> {
+        on_type_formatting::on_type_formatting(snapshot, params)
+    }
+
+    pub(crate) fn hover(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::HoverParams,
+    ) -> Result<Option<lsp_types::Hover>> {
+        hover::hover(snapshot, params)
+    }
+
+    pub(crate) fn semantic_tokens_full(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::SemanticTokensParams,
+    ) -> Result<Option<lsp_types::SemanticTokensResult>> {
+        semantic_tokens::semantic_tokens_full(snapshot, params)
+    }
+
+    pub(crate) fn signature_help(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::SignatureHelpParams,
+    ) -> Result<Option<lsp_types::SignatureHelp>> {
+        signature_help::signature_help(snapshot, params)
+    }
+
+    pub(crate) fn inlay_hint(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::InlayHintParams,
+    ) -> Result<Option<Vec<lsp_types::InlayHint>>> {
+        inlay_hints::inlay_hints(snapshot, params)
+    }
+
+    pub(crate) fn prepare_rename(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::TextDocumentPositionParams,
+    ) -> Result<Option<lsp_types::PrepareRenameResponse>> {
+        rename::prepare_rename(snapshot, params)
+    }
+
+    pub(crate) fn rename(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::RenameParams,
+    ) -> Result<Option<lsp_types::WorkspaceEdit>> {
+        rename::rename(snapshot, params)
+    }
+
+    pub(crate) fn folding_range(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::FoldingRangeParams,
+    ) -> Result<Option<Vec<lsp_types::FoldingRange>>> {
+        folding_ranges::folding_ranges(snapshot, params)
+    }
+
+    pub(crate) fn references(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::ReferenceParams,
+    ) -> Result<Option<Vec<lsp_types::Location>>> {
+        references::references(snapshot, params)
+    }
+
+    pub(crate) fn code_action(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::CodeActionParams,
+    ) -> Result<Option<lsp_types::CodeActionResponse>> {
+        code_actions::code_actions(snapshot, params)
+    }
+
+    pub(crate) fn code_lens(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::CodeLensParams,
+    ) -> Result<Option<Vec<lsp_types::CodeLens>>> {
+        code_lens::code_lens(snapshot, params)
+    }
+
+    pub(crate) fn document_link(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::DocumentLinkParams,
+    ) -> Result<Option<Vec<lsp_types::DocumentLink>>> {
+        document_links::document_links(snapshot, params)
+    }
+
     fn handle_diagnostics(
         snapshot: LspServerStateSnapshot,
         sender: Sender<Task>,
         uri: lsp_types::Uri,
     ) -> Result<()> {
         tracing::debug!("handlers::check_beancount");
-        let bean_check_cmd = &PathBuf::from("bean-check");
+        let bean_check_cmd =
+            &checker::bean_check_cmd(&snapshot.config.checker, &snapshot.config.root_file);
 
         sender
             .send(Task::Progress(ProgressMsg::BeanCheck { done: 0, total: 1 }))
             .unwrap();
 
-        let root_journal_path = if snapshot.config.journal_root.is_some() {
-            snapshot.config.journal_root.unwrap()
-        } else {
-            PathBuf::from(uri.to_string().replace("file://", ""))
+        let file = PathBuf::from(uri.to_string().replace("file://", ""));
+        let journal = snapshot.journal_for(&file);
+        let root_journal_path = match (journal, snapshot.config.journal_roots.first()) {
+            (Some(journal), _) => journal.root.clone(),
+            (None, Some(journal_root)) => journal_root.clone(),
+            (None, None) => file.clone(),
         };
+        let journal_files = journal.map(|journal| journal.files.clone());
 
-        let diags =
-            diagnostics::diagnostics(snapshot.beancount_data, bean_check_cmd, &root_journal_path);
+        let data = snapshot.journal_data(&file);
+        let queries: Vec<(PathBuf, QueryEntry)> = data
+            .iter()
+            .flat_map(|(file, data)| {
+                data.get_queries()
+                    .iter()
+                    .map(|query| (file.clone(), query.clone()))
+            })
+            .collect();
+
+        let Some((method, check)) = checker::check(
+            &snapshot.config.checker,
+            &snapshot.failed_checkers,
+            bean_check_cmd,
+            &root_journal_path,
+            &snapshot.cancelled,
+            |method, err| {
+                tracing::warn!("checker {} failed: {}", method.name(), err);
+                let fallback = checker::select(
+                    &snapshot.config.checker.method,
+                    &snapshot.failed_checkers,
+                    bean_check_cmd,
+                );
+                sender
+                    .send(Task::Notify(lsp_server::Notification {
+                        method: lsp_types::notification::ShowMessage::METHOD.to_owned(),
+                        params: to_json(lsp_types::ShowMessageParams {
+                            typ: lsp_types::MessageType::WARNING,
+                            message: format!(
+                                "checker {} failed ({}), falling back to {}",
+                                method.name(),
+                                err,
+                                fallback.name()
+                            ),
+                        })
+                        .unwrap(),
+                    }))
+                    .unwrap();
+            },
+        ) else {
+            return Ok(());
+        };
+        let mut diags = diagnostics::diagnostics(&data, &snapshot.forest, &snapshot.config, &check);
+        // the queries are compiled when beanquery is there, unless only the
+        // native checks are wanted
+        if !queries.is_empty() && method != CheckerMethod::Native {
+            let strings: Vec<String> = queries
+                .iter()
+                .map(|(_, query)| query.query.clone())
+                .collect();
+            if let Some(errors) = checker::check_queries(
+                &snapshot.config.checker,
+                &root_journal_path,
+                &strings,
+                &snapshot.cancelled,
+            ) {
+                for (file, diag) in diagnostics::query_diagnostics(&queries, errors) {
+                    diags.entry(file).or_default().push(diag);
+                }
+            }
+        }
 
         sender
             .send(Task::Progress(ProgressMsg::BeanCheck { done: 1, total: 1 }))
             .unwrap();
 
-        for file in snapshot.forest.keys() {
-            let diagnostics = if diags.contains_key(file) {
-                diags.get(file).unwrap().clone()
-            } else {
-                vec![]
-            };
-            sender
-                .send(Task::Notify(lsp_server::Notification {
-                    method: lsp_types::notification::PublishDiagnostics::METHOD.to_owned(),
-                    params: to_json(lsp_types::PublishDiagnosticsParams {
-                        uri: lsp_types::Uri::from_str(
-                            format!("file://{}", file.to_str().unwrap()).as_str(),
-                        )
-                        .unwrap(),
-                        diagnostics,
-                        version: None,
-                    })
-                    .unwrap(),
-                }))
-                .unwrap()
+        if journal.is_none() && !snapshot.journals.is_empty() && snapshot.forest.contains_key(&file)
+        {
+            // at the start of the file, so the diagnostics stay in order
+            diags
+                .entry(file.clone())
+                .or_default()
+                .insert(0, diagnostics::orphan_diagnostic());
         }
+
+        // with several journals, the other ones keep their diagnostics
+        let files = snapshot.forest.keys().filter(|file| match &journal_files {
+            Some(journal_files) => journal_files.contains(*file),
+            None => true,
+        });
+        // published together once the check is done, so the previous
+        // diagnostics stay until they are replaced
+        let diagnostics: BTreeMap<PathBuf, Vec<lsp_types::Diagnostic>> = files
+            .map(|file| (file.clone(), diags.remove(file).unwrap_or_default()))
+            .collect();
+        sender
+            .send(Task::Diagnostics(root_journal_path, diagnostics))
+            .unwrap();
         Ok(())
     }
 }
+
+pub mod workspace {
+    use crate::anonymize;
+    use crate::commands;
+    use crate::from_json;
+    use crate::providers::balance;
+    use crate::providers::code_lens;
+    use crate::providers::diagnostics;
+    use crate::providers::documents;
+    use crate::providers::extract;
+    use crate::providers::forecast;
+    use crate::providers::holdings;
+    use crate::providers::payee;
+    use crate::providers::prices;
+    use crate::providers::register;
+    use crate::providers::report;
+    use crate::providers::workspace_symbols;
+    use crate::server::LspServerState;
+    use crate::server::LspServerStateSnapshot;
+    use crate::server::Task;
+    use crate::to_json;
+    use anyhow::Result;
+    use lsp_types::notification::Notification;
+    use std::sync::Arc;
+
+    /// handler for `workspace/executeCommand`.
+    pub(crate) fn execute_command(
+        state: &mut LspServerState,
+        params: lsp_types::ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        tracing::debug!("handlers::execute_command {}", params.command);
+        let argument = params
+            .arguments
+            .into_iter()
+            .next()
+            .unwrap_or(serde_json::Value::Null);
+        match params.command.as_str() {
+            commands::ADD_DOCUMENTS => {
+                let params = from_json(commands::ADD_DOCUMENTS, argument)?;
+                let edit = documents::add_documents(state.snapshot(), params)?;
+                apply_edit(state, "Add documents", edit);
+                Ok(None)
+            }
+            commands::ANONYMIZE => {
+                let params = from_json(commands::ANONYMIZE, argument)?;
+                let document = anonymize::anonymize_document(state.snapshot(), params)?;
+                Ok(Some(to_json(document)?))
+            }
+            commands::EXTRACT_TO_FILE => {
+                let params = from_json(commands::EXTRACT_TO_FILE, argument)?;
+                let edit = extract::extract_to_file(state.snapshot(), params)?;
+                apply_edit(state, "Extract to file", edit);
+                Ok(None)
+            }
+            commands::FETCH_PRICES => {
+                let params = from_json(commands::FETCH_PRICES, argument)?;
+                // bean-price asks online sources, so the edit is applied
+                // from the thread pool once they answered
+                let snapshot = state.snapshot();
+                let task_sender = state.task_sender.clone();
+                state.thread_pool.execute(move || {
+                    let task = match prices::fetch_prices(snapshot, params) {
+                        Ok(edit) => Task::ApplyEdit(String::from("Fetch prices"), edit),
+                        Err(err) => Task::Notify(lsp_server::Notification {
+                            method: lsp_types::notification::ShowMessage::METHOD.to_owned(),
+                            params: to_json(lsp_types::ShowMessageParams {
+                                typ: lsp_types::MessageType::ERROR,
+                                message: format!("could not fetch prices: {}", err),
+                            })
+                            .unwrap(),
+                        }),
+                    };
+                    task_sender.send(task).unwrap();
+                });
+                Ok(None)
+            }
+            commands::GOTO_NEXT_ERROR => {
+                let params = from_json(commands::GOTO_NEXT_ERROR, argument)?;
+                let location = diagnostics::next_error(&state.diagnostics, params)?;
+                Ok(Some(to_json(location)?))
+            }
+            commands::HOLDINGS => {
+                let params: Option<_> = from_json(commands::HOLDINGS, argument)?;
+                let holdings = holdings::holdings(state.snapshot(), params.unwrap_or_default())?;
+                Ok(Some(to_json(holdings)?))
+            }
+            commands::INSERT_BALANCE_ASSERTION => {
+                let params = from_json(commands::INSERT_BALANCE_ASSERTION, argument)?;
+                let edit = balance::insert_balance_assertion(state.snapshot(), params)?;
+                apply_edit(state, "Insert balance assertion", edit);
+                Ok(None)
+            }
+            commands::MATERIALIZE_FORECASTS => {
+                let params: Option<_> = from_json(commands::MATERIALIZE_FORECASTS, argument)?;
+                let edit =
+                    forecast::materialize_forecasts(state.snapshot(), params.unwrap_or_default())?;
+                apply_edit(state, "Materialize forecasts", edit);
+                Ok(None)
+            }
+            commands::NORMALIZE_PAYEE => {
+                let params = from_json(commands::NORMALIZE_PAYEE, argument)?;
+                let edit = payee::normalize_payee(state.snapshot(), params)?;
+                apply_edit(state, "Normalize payee", edit);
+                Ok(None)
+            }
+            commands::RELOAD_WORKSPACE => {
+                state.reload_workspace();
+                Ok(None)
+            }
+            commands::REPORT => {
+                let params = from_json(commands::REPORT, argument)?;
+                let markdown = report::report(state.snapshot(), params)?;
+                Ok(Some(serde_json::Value::String(markdown)))
+            }
+            commands::SHOW_REGISTER => {
+                let params = from_json(commands::SHOW_REGISTER, argument)?;
+                let document = register::register(state.snapshot(), params)?;
+                Ok(Some(to_json(document)?))
+            }
+            command => Err(anyhow::anyhow!("unknown command: {}", command)),
+        }
+    }
+
+    /// handler for the `workspace/executeCommand` requests of
+    /// `commands::BACKGROUND_COMMANDS`, run on the thread pool.
+    pub(crate) fn execute_background_command(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        tracing::debug!("handlers::execute_background_command {}", params.command);
+        let argument = params
+            .arguments
+            .into_iter()
+            .next()
+            .unwrap_or(serde_json::Value::Null);
+        match params.command.as_str() {
+            commands::RUN_QUERY => {
+                let params = from_json(commands::RUN_QUERY, argument)?;
+                let document = code_lens::run_query(snapshot, params)?;
+                Ok(Some(to_json(document)?))
+            }
+            command => Err(anyhow::anyhow!("unknown command: {}", command)),
+        }
+    }
+
+    /// handler for `workspace/didChangeWatchedFiles`.
+    pub(crate) fn did_change_watched_files(
+        state: &mut LspServerState,
+        params: lsp_types::DidChangeWatchedFilesParams,
+    ) -> Result<()> {
+        tracing::debug!("handlers::did_change_watched_files");
+        if params
+            .changes
+            .iter()
+            .any(|change| change.typ == lsp_types::FileChangeType::DELETED)
+        {
+            state.prune_forest();
+        }
+        Ok(())
+    }
+
+    /// handler for `workspace/didChangeConfiguration`. Checker methods that
+    /// failed get another chance with the new settings, and the client is
+    /// told which one is selected now.
+    pub(crate) fn did_change_configuration(
+        state: &mut LspServerState,
+        params: lsp_types::DidChangeConfigurationParams,
+    ) -> Result<()> {
+        tracing::debug!("handlers::did_change_configuration");
+        Arc::make_mut(&mut state.config).update(params.settings)?;
+        state.failed_checkers.clear();
+        state.send_journal_resolved();
+        Ok(())
+    }
+
+    /// handler for `workspace/symbol`.
+    pub(crate) fn symbol(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::WorkspaceSymbolParams,
+    ) -> Result<Option<lsp_types::WorkspaceSymbolResponse>> {
+        workspace_symbols::workspace_symbols(snapshot, params)
+    }
+
+    /// Asks the client to apply `edit` to the workspace.
+    pub(crate) fn apply_edit(
+        state: &mut LspServerState,
+        label: &str,
+        edit: lsp_types::WorkspaceEdit,
+    ) {
+        state.send_request::<lsp_types::request::ApplyWorkspaceEdit>(
+            lsp_types::ApplyWorkspaceEditParams {
+                label: Some(label.to_string()),
+                edit,
+            },
+            |_, response| {
+                if let Some(error) = response.error {
+                    tracing::error!("failed to apply workspace edit: {:?}", error);
+                }
+            },
+        );
+    }
+}
+
+pub mod beancount {
+    use crate::lsp_ext;
+    use crate::providers::activity;
+    use crate::providers::ast_dump;
+    use crate::providers::rename;
+    use crate::providers::tags;
+    use crate::server::LspServerStateSnapshot;
+    use anyhow::Result;
+
+    /// handler for `beancount/accountActivity`.
+    pub(crate) fn account_activity(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_ext::AccountActivityParams,
+    ) -> Result<Vec<lsp_ext::MonthlyActivity>> {
+        activity::account_activity(snapshot, params)
+    }
+
+    /// handler for `beancount/balanceAt`.
+    pub(crate) fn balance_at(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_ext::BalanceAtParams,
+    ) -> Result<lsp_ext::BalanceAtResult> {
+        activity::balance_at(snapshot, params)
+    }
+
+    /// handler for `beancount/renamePreview`.
+    pub(crate) fn rename_preview(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::RenameParams,
+    ) -> Result<Option<lsp_ext::RenamePreviewResult>> {
+        rename::rename_preview(snapshot, params)
+    }
+
+    /// handler for `beancount/inlineBalances`.
+    pub(crate) fn inline_balances(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_ext::InlineBalancesParams,
+    ) -> Result<Vec<lsp_ext::InlineBalance>> {
+        activity::inline_balances(snapshot, params)
+    }
+
+    /// handler for `beancount/transactionsByTag`.
+    pub(crate) fn transactions_by_tag(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_ext::TransactionsByTagParams,
+    ) -> Result<Vec<lsp_ext::TaggedTransaction>> {
+        tags::transactions_by_tag(snapshot, params)
+    }
+
+    /// handler for `beancount/astDump`.
+    pub(crate) fn ast_dump(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_ext::AstDumpParams,
+    ) -> Result<Option<lsp_ext::AstDumpResult>> {
+        ast_dump::ast_dump(snapshot, params)
+    }
+}
@@ -0,0 +1,392 @@
+//! A small native ledger engine computing account balances from the postings
+//! collected in [`BeancountData`](crate::beancount_data::BeancountData).
+
+use crate::amount::Amount;
+use crate::amount::Decimal;
+use crate::beancount_data::BeancountData;
+use crate::beancount_data::PostingEntry;
+use crate::beancount_data::PriceEntry;
+use crate::beancount_data::PRECISION;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The balance of an account, by currency.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Inventory(BTreeMap<String, Decimal>);
+
+impl Inventory {
+    pub fn add(&mut self, amount: &Amount) {
+        *self.0.entry(amount.currency.clone()).or_default() += amount.number;
+    }
+
+    pub fn merge(&mut self, other: &Inventory) {
+        for (currency, number) in other.0.iter() {
+            *self.0.entry(currency.clone()).or_default() += *number;
+        }
+    }
+
+    pub fn negate(&self) -> Inventory {
+        Inventory(
+            self.0
+                .iter()
+                .map(|(currency, number)| (currency.clone(), -*number))
+                .collect(),
+        )
+    }
+
+    /// The non-zero positions of the inventory.
+    pub fn amounts(&self) -> impl Iterator<Item = Amount> + '_ {
+        self.0
+            .iter()
+            .filter(|(_, number)| !number.is_zero())
+            .map(|(currency, number)| Amount::new(*number, currency.clone()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amounts().next().is_none()
+    }
+
+    /// Formats the inventory like its `Display`, but with the currencies of
+    /// `first` first, in their order.
+    pub fn format_with_first(&self, first: &[String]) -> String {
+        let mut amounts: Vec<_> = self.amounts().collect();
+        amounts.sort_by_key(|amount| {
+            first
+                .iter()
+                .position(|currency| *currency == amount.currency)
+                .unwrap_or(first.len())
+        });
+        let amounts: Vec<_> = amounts.iter().map(|amount| amount.to_string()).collect();
+        amounts.join(", ")
+    }
+}
+
+impl std::fmt::Display for Inventory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let amounts: Vec<_> = self.amounts().map(|amount| amount.to_string()).collect();
+        write!(f, "{}", amounts.join(", "))
+    }
+}
+
+/// Restricts the postings a report is computed from to a period and a tag.
+/// Balances and lots still carry over the postings before the period.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostingFilter {
+    /// The first day of the period.
+    pub from: Option<chrono::NaiveDate>,
+    /// The day after the period, like the end date of `bean-query`.
+    pub to: Option<chrono::NaiveDate>,
+    /// A tag the transaction must have, with or without its `#`.
+    pub tag_filter: Option<String>,
+}
+
+impl PostingFilter {
+    /// Whether `posting` is in the period and has the tag.
+    pub fn matches(&self, posting: &PostingEntry) -> bool {
+        self.from.is_none_or(|from| posting.date >= from) && self.books(posting)
+    }
+
+    /// Whether `posting` counts towards the balances at the end of the
+    /// period: like [`PostingFilter::matches`], but also before `from`.
+    pub fn books(&self, posting: &PostingEntry) -> bool {
+        self.to.is_none_or(|to| posting.date < to)
+            && self.tag_filter.as_deref().is_none_or(|tag| {
+                let tag = tag.trim_start_matches('#');
+                posting
+                    .tags
+                    .iter()
+                    .any(|posting_tag| posting_tag.trim_start_matches('#') == tag)
+            })
+    }
+}
+
+/// The balances of all accounts touched by `postings`.
+pub fn balances<'a>(
+    postings: impl Iterator<Item = &'a PostingEntry>,
+) -> BTreeMap<String, Inventory> {
+    let mut balances: BTreeMap<String, Inventory> = BTreeMap::new();
+    for posting in postings {
+        let inventory = balances.entry(posting.account.clone()).or_default();
+        for units in posting.units.iter() {
+            inventory.add(units);
+        }
+    }
+    balances
+}
+
+/// The postings to `account`, and to its sub-accounts with
+/// `include_subaccounts`, that match `filter` in date order, each with the
+/// balance of the account after it. The balances include the postings before
+/// `filter.from`. Postings on the same date keep their order in the files.
+pub fn running_balances<'a>(
+    data: &'a HashMap<PathBuf, BeancountData>,
+    account: &str,
+    include_subaccounts: bool,
+    filter: &PostingFilter,
+) -> Vec<(&'a Path, &'a PostingEntry, Inventory)> {
+    let subaccount_prefix = format!("{account}:");
+    let mut postings: Vec<(&Path, &PostingEntry)> = data
+        .iter()
+        .flat_map(|(file, data)| {
+            data.get_postings()
+                .iter()
+                .map(move |posting| (file.as_path(), posting))
+        })
+        .filter(|(_, posting)| {
+            posting.account == account
+                || (include_subaccounts && posting.account.starts_with(&subaccount_prefix))
+        })
+        .filter(|(_, posting)| filter.books(posting))
+        .collect();
+    postings.sort_by_key(|(file, posting)| (posting.date, *file, posting.line));
+
+    let mut balance = Inventory::default();
+    postings
+        .into_iter()
+        .map(|(file, posting)| {
+            for units in posting.units.iter() {
+                balance.add(units);
+            }
+            (file, posting, balance.clone())
+        })
+        .filter(|(_, posting, _)| filter.from.is_none_or(|from| posting.date >= from))
+        .collect()
+}
+
+/// The balance of `account`, and of its sub-accounts with
+/// `include_subaccounts`, from the postings dated before `to`, which is the
+/// balance a `balance` directive on `to` is checked against.
+pub fn balance_before(
+    data: &HashMap<PathBuf, BeancountData>,
+    account: &str,
+    include_subaccounts: bool,
+    to: chrono::NaiveDate,
+) -> Inventory {
+    let filter = PostingFilter {
+        to: Some(to),
+        ..Default::default()
+    };
+    running_balances(data, account, include_subaccounts, &filter)
+        .pop()
+        .map(|(_, _, balance)| balance)
+        .unwrap_or_default()
+}
+
+/// Units of a commodity held in an account, acquired at the same cost.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lot {
+    pub account: String,
+    pub units: Amount,
+    /// The per-unit cost, for units held at cost.
+    pub cost: Option<Amount>,
+    /// The acquisition date, for units held at cost: the date of the cost
+    /// specification or else of the transaction.
+    pub date: Option<chrono::NaiveDate>,
+    /// The label of the cost specification.
+    pub label: Option<String>,
+}
+
+/// The lots held in asset and liability accounts after all `postings`.
+/// Reductions are booked against the oldest lots matching the cost, date and
+/// label the posting specifies, and against the oldest lots if it has none.
+pub fn lots<'a>(postings: impl Iterator<Item = &'a PostingEntry>) -> Vec<Lot> {
+    book(postings.map(|posting| ((), posting))).0
+}
+
+/// Why a reduction of the lots of an account cannot be booked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BookingError {
+    /// No held lot matches the cost specification of the posting.
+    NoMatchingLot,
+    /// The matching lots hold fewer units than the posting reduces.
+    NotEnoughUnits { held: Amount },
+}
+
+/// The reductions among `postings` that bean-check would reject, booking them
+/// like [`lots`]. Only postings with a cost specification reducing lots held
+/// at cost are checked. Each posting comes with a `T`, like its file.
+pub fn booking_errors<'a, T: Copy>(
+    postings: impl Iterator<Item = (T, &'a PostingEntry)>,
+) -> Vec<(T, &'a PostingEntry, BookingError)> {
+    book(postings).1
+}
+
+#[allow(clippy::type_complexity)]
+fn book<'a, T: Copy>(
+    postings: impl Iterator<Item = (T, &'a PostingEntry)>,
+) -> (Vec<Lot>, Vec<(T, &'a PostingEntry, BookingError)>) {
+    let mut postings: Vec<(T, &PostingEntry)> = postings
+        .filter(|(_, posting)| matches!(account_root(&posting.account), "Assets" | "Liabilities"))
+        .collect();
+    postings.sort_by_key(|(_, posting)| posting.date);
+
+    let mut lots: Vec<Lot> = Vec::new();
+    let mut errors = Vec::new();
+    for (tag, posting) in postings {
+        for units in posting.units.iter() {
+            let cost = posting.cost.clone();
+            let date = cost
+                .as_ref()
+                .map(|_| posting.cost_date.unwrap_or(posting.date));
+            let label = posting.cost_label.clone();
+            let held = |lot: &&mut Lot| {
+                lot.account == posting.account
+                    && lot.units.currency == units.currency
+                    && lot.units.number.is_negative() != units.number.is_negative()
+                    && !lot.units.number.is_zero()
+            };
+            let specified = |lot: &&mut Lot| {
+                cost.as_ref()
+                    .is_none_or(|cost| lot.cost.as_ref() == Some(cost))
+                    && posting.cost_date.is_none_or(|date| lot.date == Some(date))
+                    && label
+                        .as_ref()
+                        .is_none_or(|label| lot.label.as_ref() == Some(label))
+            };
+            if posting.at_cost && lots.iter_mut().filter(held).any(|lot| lot.cost.is_some()) {
+                let matching = lots
+                    .iter_mut()
+                    .filter(held)
+                    .filter(specified)
+                    .fold(None::<Decimal>, |total, lot| {
+                        Some(total.unwrap_or_default() + lot.units.number)
+                    });
+                let error = match matching {
+                    None => Some(BookingError::NoMatchingLot),
+                    Some(total) if total.abs() < units.number.abs() => {
+                        Some(BookingError::NotEnoughUnits {
+                            held: Amount::new(total.abs(), units.currency.clone()),
+                        })
+                    }
+                    Some(_) => None,
+                };
+                errors.extend(error.map(|error| (tag, posting, error)));
+            }
+            let mut remaining = units.number;
+            for lot in lots.iter_mut().filter(held).filter(specified) {
+                remaining = reduce(lot, remaining);
+                if remaining.is_zero() {
+                    break;
+                }
+            }
+            if remaining.is_zero() {
+                continue;
+            }
+            match lots.iter_mut().find(|lot| {
+                lot.account == posting.account
+                    && lot.units.currency == units.currency
+                    && lot.cost == cost
+                    && lot.date == date
+                    && lot.label == label
+            }) {
+                Some(lot) => lot.units.number += remaining,
+                None => lots.push(Lot {
+                    account: posting.account.clone(),
+                    units: Amount::new(remaining, units.currency.clone()),
+                    cost,
+                    date,
+                    label,
+                }),
+            }
+        }
+    }
+    lots.retain(|lot| !lot.units.number.is_zero());
+    (lots, errors)
+}
+
+/// Takes as much of `number` as `lot` can absorb and returns the rest.
+fn reduce(lot: &mut Lot, number: Decimal) -> Decimal {
+    if number.abs() <= lot.units.number.abs() {
+        lot.units.number += number;
+        Decimal::default()
+    } else {
+        let rest = number + lot.units.number;
+        lot.units.number = Decimal::default();
+        rest
+    }
+}
+
+/// The prices from `price` directives, to convert between currencies.
+#[derive(Clone, Debug, Default)]
+pub struct PriceDatabase(BTreeMap<(String, String), Vec<(chrono::NaiveDate, Decimal)>>);
+
+impl PriceDatabase {
+    pub fn new<'a>(prices: impl Iterator<Item = &'a PriceEntry>) -> Self {
+        let mut database: BTreeMap<(String, String), Vec<(chrono::NaiveDate, Decimal)>> =
+            BTreeMap::new();
+        for price in prices {
+            database
+                .entry((price.currency.clone(), price.price.currency.clone()))
+                .or_default()
+                .push((price.date, price.price.number));
+        }
+        for rates in database.values_mut() {
+            rates.sort_by_key(|(date, _)| *date);
+        }
+        Self(database)
+    }
+
+    /// The latest price of one unit of `currency` in `quote`, using the
+    /// inverse of the price of `quote` in `currency` if needed.
+    pub fn latest(&self, currency: &str, quote: &str) -> Option<Decimal> {
+        self.rate(currency, quote, None)
+    }
+
+    /// The price of one unit of `currency` in `quote` on `date`: the latest
+    /// one on or before it, using the inverse like [`PriceDatabase::latest`].
+    pub fn on(&self, currency: &str, quote: &str, date: chrono::NaiveDate) -> Option<Decimal> {
+        self.rate(currency, quote, Some(date))
+    }
+
+    fn rate(
+        &self,
+        currency: &str,
+        quote: &str,
+        date: Option<chrono::NaiveDate>,
+    ) -> Option<Decimal> {
+        if currency == quote {
+            return Some(Decimal::new(1, 0));
+        }
+        let latest = |base: &str, quote: &str| {
+            self.0
+                .get(&(base.to_string(), quote.to_string()))
+                .and_then(|rates| {
+                    rates
+                        .iter()
+                        .rev()
+                        .find(|(day, _)| date.is_none_or(|date| *day <= date))
+                })
+                .map(|(_, rate)| *rate)
+        };
+        latest(currency, quote).or_else(|| {
+            let inverse = latest(quote, currency)?;
+            Decimal::new(1, 0).checked_div(inverse, PRECISION)
+        })
+    }
+
+    /// `amount` converted to `quote`.
+    pub fn convert(&self, amount: &Amount, quote: &str) -> Option<Amount> {
+        let rate = self.latest(&amount.currency, quote)?;
+        Some(Amount::new(amount.number * rate, quote))
+    }
+
+    /// `amount` converted to `quote` at the price on `date`.
+    pub fn convert_on(
+        &self,
+        amount: &Amount,
+        quote: &str,
+        date: chrono::NaiveDate,
+    ) -> Option<Amount> {
+        let rate = self.on(&amount.currency, quote, date)?;
+        Some(Amount::new(amount.number * rate, quote))
+    }
+}
+
+/// The root of an account name, e.g. `Assets` for `Assets:Bank:Checking`.
+pub fn account_root(account: &str) -> &str {
+    account.split(':').next().unwrap_or(account)
+}
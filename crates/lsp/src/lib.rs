@@ -1,17 +1,48 @@
+// `lsp_types::Uri` caches its parsed form internally, which clippy mistakes for a
+// mutable key in `WorkspaceEdit::changes` style maps.
+#![allow(clippy::mutable_key_type)]
+
+mod amount;
+pub mod anonymize;
+pub mod api;
+mod bean_format;
 mod beancount_data;
+mod bql;
+mod budget;
 mod capabilities;
+mod checker;
+pub mod cli;
+mod commands;
+mod commented_out;
 mod config;
+#[cfg(test)]
+mod crlf_tests;
+mod custom_directives;
 mod dispatcher;
 pub mod document;
 //pub mod error;
 pub mod forest;
+#[cfg(test)]
+mod fuzz_tests;
+#[cfg(test)]
+mod golden_tests;
 pub mod handlers;
+mod ledger;
+mod lint;
+pub mod lsp_ext;
 pub mod progress;
 pub mod providers;
 pub mod server;
 //pub mod session;
+#[cfg(feature = "spellcheck")]
+mod spellcheck;
+mod telemetry;
+#[cfg(test)]
+mod test_utils;
+pub mod traffic_log;
 mod treesitter_utils;
 mod utils;
+mod workspace_edit;
 
 use crate::config::Config;
 use crate::server::LspServerState;
@@ -19,27 +50,50 @@ use anyhow::Result;
 use lsp_server::Connection;
 use lsp_types::InitializeParams;
 use serde::{de::DeserializeOwned, Serialize};
+use treesitter_utils::PositionEncoding;
 use utils::ToFilePath;
 
-pub fn run_server() -> Result<()> {
+/// Runs the server over stdio, recording the messages as described by
+/// `traffic_log` if given.
+pub fn run_server(traffic_log: Option<traffic_log::TrafficLogOptions>) -> Result<()> {
     tracing::info!("beancount-language-server started");
 
     //Setup IO connections
-    let (connection, io_threads) = lsp_server::Connection::stdio();
+    let (mut connection, io_threads) = lsp_server::Connection::stdio();
+    if let Some(options) = traffic_log {
+        tracing::info!("recording lsp traffic to {}", options.path.display());
+        connection = traffic_log::record(connection, traffic_log::TrafficLog::new(options));
+    }
+
+    serve(connection)?;
+
+    io_threads.join()?;
 
+    Ok(())
+}
+
+/// Runs the server over `connection`, from the `initialize` handshake until the
+/// client sends `exit`.
+pub fn serve(connection: Connection) -> Result<()> {
     //wait for client to connection
     let (request_id, initialize_params) = connection.initialize_start()?;
     tracing::info!("initialize params: {}", initialize_params);
 
     let initialize_params = serde_json::from_value::<InitializeParams>(initialize_params)?;
 
-    let server_capabilities = capabilities::server_capabilities();
+    let position_encoding = PositionEncoding::negotiate(&initialize_params.capabilities);
+    let server_capabilities = capabilities::server_capabilities(position_encoding);
 
     let initialize_result = lsp_types::InitializeResult {
         capabilities: server_capabilities,
         server_info: Some(lsp_types::ServerInfo {
             name: String::from("beancount-language-server"),
-            version: Some(String::from(env!("CARGO_PKG_VERSION"))),
+            // the grammar goes in the build metadata to keep the version semver
+            version: Some(format!(
+                "{}+grammar.{}",
+                env!("CARGO_PKG_VERSION"),
+                treesitter_utils::grammar_version()
+            )),
         }),
     };
 
@@ -56,25 +110,28 @@ pub fn run_server() -> Result<()> {
     }
 
     let config = {
-        let root_file = match initialize_params
-            .root_uri
-            .and_then(|it| it.to_file_path().ok())
-        {
+        #[allow(deprecated)]
+        let root_uri = initialize_params.root_uri;
+        let root_file = match root_uri.and_then(|it| it.to_file_path().ok()) {
             Some(it) => it,
             None => std::env::current_dir()?,
         };
         let mut config = Config::new(root_file);
+        config.position_encoding = position_encoding;
+        config.dynamic_file_watchers = initialize_params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false);
         if let Some(json) = initialize_params.initialization_options {
             config.update(json).unwrap();
         }
         config
     };
 
-    main_loop(connection, config)?;
-
-    io_threads.join()?;
-
-    Ok(())
+    main_loop(connection, config)
 }
 
 pub fn main_loop(connection: Connection, config: Config) -> Result<()> {
@@ -0,0 +1,588 @@
+//! Checks that are stricter than `bean-check`, most of them optional.
+
+use crate::beancount_data::BeancountData;
+use crate::beancount_data::PriceEntry;
+use crate::ledger;
+use crate::ledger::BookingError;
+use crate::treesitter_utils::grammar_version;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Warnings for currencies used without a `commodity` directive.
+pub fn undeclared_commodities(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let declared: HashSet<&String> = data
+        .values()
+        .flat_map(|data| data.get_declared_commodities())
+        .collect();
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (file, data) in data.iter() {
+        for usage in data.get_currency_usages() {
+            if declared.contains(&usage.currency) {
+                continue;
+            }
+            diagnostics
+                .entry(file.clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: usage.range,
+                    message: format!("Undeclared commodity {}", usage.currency),
+                    severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+    diagnostics
+}
+
+/// Warnings for `price` directives that quote a commodity in itself, like
+/// `price USD 1 USD`.
+pub fn self_quoted_prices(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (file, data) in data.iter() {
+        for price in data.get_prices() {
+            if price.currency != price.price.currency {
+                continue;
+            }
+            let position = lsp_types::Position::new(price.line, 0);
+            diagnostics
+                .entry(file.clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: lsp_types::Range::new(position, position),
+                    message: format!("Price of {} quoted in itself", price.currency),
+                    severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+    diagnostics
+}
+
+/// Warnings for the latest `price` directive of each commodity still held in
+/// an asset or liability account when it is more than `max_age_days` older
+/// than `today`. Commodities without any price are not checked.
+pub fn stale_prices(
+    data: &HashMap<PathBuf, BeancountData>,
+    today: chrono::NaiveDate,
+    max_age_days: u32,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let held: HashSet<String> = ledger::lots(data.values().flat_map(|data| data.get_postings()))
+        .into_iter()
+        .map(|lot| lot.units.currency)
+        .collect();
+    let mut latest: HashMap<&str, (&PathBuf, &PriceEntry)> = HashMap::new();
+    for (file, data) in data.iter() {
+        for price in data.get_prices() {
+            let entry = latest
+                .entry(price.currency.as_str())
+                .or_insert((file, price));
+            if price.date > entry.1.date {
+                *entry = (file, price);
+            }
+        }
+    }
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (currency, (file, price)) in latest {
+        let age = (today - price.date).num_days();
+        if !held.contains(currency) || age <= i64::from(max_age_days) {
+            continue;
+        }
+        let position = lsp_types::Position::new(price.line, 0);
+        diagnostics
+            .entry(file.clone())
+            .or_default()
+            .push(lsp_types::Diagnostic {
+                range: lsp_types::Range::new(position, position),
+                message: format!(
+                    "Latest price of {} is from {}, {} days ago",
+                    currency, price.date, age
+                ),
+                severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                ..lsp_types::Diagnostic::default()
+            });
+    }
+    for diags in diagnostics.values_mut() {
+        diags.sort_by_key(|diag| diag.range.start.line);
+    }
+    diagnostics
+}
+
+/// Warnings for `document` directives and `documents` options naming files
+/// or directories that do not exist.
+pub fn missing_documents(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (file, data) in data.iter() {
+        for document in data.get_documents() {
+            if document.resolve(file).exists() {
+                continue;
+            }
+            let what = if document.directory {
+                "Documents directory"
+            } else {
+                "Document"
+            };
+            diagnostics
+                .entry(file.clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: document.range,
+                    message: format!("{what} {} not found", document.path),
+                    severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+    diagnostics
+}
+
+/// Errors for `include` directives naming files that do not exist, neither
+/// on disk nor among the parsed ones. Globs are left out, matching no file is
+/// not an error for them.
+pub fn missing_includes(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (file, file_data) in data.iter() {
+        for include in file_data.get_include_entries() {
+            let path = include.resolve(file);
+            if include.is_glob() || data.contains_key(&path) || path.exists() {
+                continue;
+            }
+            diagnostics
+                .entry(file.clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: include.range,
+                    message: format!("Included file {} not found", include.path),
+                    severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+    diagnostics
+}
+
+/// Documents with fewer entries are not checked for unsupported syntax.
+const MIN_ENTRIES_FOR_GRAMMAR_CHECK: usize = 4;
+
+/// The share of entries that do not parse above which a document probably
+/// uses syntax the bundled grammar does not know.
+const MAX_ERROR_RATIO: f64 = 0.25;
+
+/// A single information per document in which an unusually high share of
+/// the entries does not parse, which hints at syntax the bundled
+/// tree-sitter-beancount grammar does not support rather than at typos.
+pub fn unsupported_syntax(
+    forest: &HashMap<PathBuf, tree_sitter::Tree>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics = HashMap::new();
+    for (file, tree) in forest {
+        let mut entries = 0;
+        let mut errors = 0;
+        count_entries(tree.root_node(), &mut entries, &mut errors);
+        if entries < MIN_ENTRIES_FOR_GRAMMAR_CHECK
+            || (errors as f64) <= entries as f64 * MAX_ERROR_RATIO
+        {
+            continue;
+        }
+        let start = lsp_types::Position::new(0, 0);
+        diagnostics.insert(
+            file.clone(),
+            vec![lsp_types::Diagnostic {
+                range: lsp_types::Range::new(start, start),
+                message: format!(
+                    "{errors} of {entries} entries do not parse, the file may use syntax \
+                     that the bundled tree-sitter-beancount grammar (ABI {}) does not support",
+                    grammar_version()
+                ),
+                severity: Some(lsp_types::DiagnosticSeverity::INFORMATION),
+                ..lsp_types::Diagnostic::default()
+            }],
+        );
+    }
+    diagnostics
+}
+
+/// Counts the entries under `node`, looking into org-mode sections, and
+/// those of them with parse errors.
+fn count_entries(node: tree_sitter::Node, entries: &mut usize, errors: &mut usize) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "section" => count_entries(child, entries, errors),
+            "comment" => {}
+            _ => {
+                *entries += 1;
+                if child.has_error() {
+                    *errors += 1;
+                }
+            }
+        }
+    }
+}
+
+/// The date of the last posting to each account.
+pub fn last_posting_dates(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<String, chrono::NaiveDate> {
+    let mut dates: HashMap<String, chrono::NaiveDate> = HashMap::new();
+    for posting in data.values().flat_map(|data| data.get_postings()) {
+        let date = dates.entry(posting.account.clone()).or_insert(posting.date);
+        *date = (*date).max(posting.date);
+    }
+    dates
+}
+
+/// The date to close `account` on: the day after its last posting.
+pub fn suggested_close_date(
+    last_postings: &HashMap<String, chrono::NaiveDate>,
+    account: &str,
+) -> Option<chrono::NaiveDate> {
+    last_postings.get(account)?.succ_opt()
+}
+
+/// Warnings for `close` directives of accounts with postings after the date
+/// they are closed on, which `bean-check` rejects on the postings.
+pub fn postings_after_close(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let last_postings = last_posting_dates(data);
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (file, data) in data.iter() {
+        for close in data.get_closes() {
+            let Some(last) = last_postings.get(&close.account) else {
+                continue;
+            };
+            if *last <= close.date {
+                continue;
+            }
+            let position = lsp_types::Position::new(close.line, 0);
+            diagnostics
+                .entry(file.clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: lsp_types::Range::new(position, position),
+                    message: format!(
+                        "{} has postings after it is closed, the last on {}",
+                        close.account, last
+                    ),
+                    severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+    diagnostics
+}
+
+/// Errors for reductions of lots that bean-check rejects: those matching no
+/// held lot, and those reducing more units than the matching lots hold. Like
+/// beancount, accounts booked with the `NONE` method are not checked.
+pub fn lot_reductions(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let default_method = data
+        .values()
+        .find_map(|data| data.get_booking_method())
+        .unwrap_or("STRICT");
+    let postings = data.iter().flat_map(|(file, data)| {
+        data.get_postings()
+            .iter()
+            .map(move |posting| (file, posting))
+    });
+    let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    for (file, posting, error) in ledger::booking_errors(postings) {
+        let method = data
+            .values()
+            .find_map(|data| data.get_account_booking_method(&posting.account))
+            .unwrap_or(default_method);
+        if method == "NONE" {
+            continue;
+        }
+        let units = posting.amount.as_ref().map(|amount| amount.number.abs());
+        let message = match error {
+            BookingError::NoMatchingLot => format!(
+                "No lot held in {} matches the cost specification",
+                posting.account
+            ),
+            BookingError::NotEnoughUnits { held } => format!(
+                "Not enough lots to reduce {} {} from {}, which holds {} at this cost",
+                units.unwrap_or_default(),
+                held.currency,
+                posting.account,
+                held
+            ),
+        };
+        let position = lsp_types::Position::new(posting.line, 0);
+        diagnostics
+            .entry(file.clone())
+            .or_default()
+            .push(lsp_types::Diagnostic {
+                range: lsp_types::Range::new(position, position),
+                message,
+                severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                ..lsp_types::Diagnostic::default()
+            });
+    }
+    diagnostics
+}
+
+/// The date of the first entry using `currency`.
+pub fn first_usage_date(
+    data: &HashMap<PathBuf, BeancountData>,
+    currency: &str,
+) -> Option<chrono::NaiveDate> {
+    data.values()
+        .flat_map(|data| data.get_currency_usages())
+        .filter(|usage| usage.currency == currency)
+        .filter_map(|usage| usage.date)
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestState;
+
+    #[test]
+    fn handle_undeclared_commodities() {
+        let fixure = r#"
+%! /main.beancount
+2020-01-01 commodity USD
+2020-01-01 open Assets:Bank USD
+2021-03-01 price HOOL 10 USD
+2021-01-02 balance Assets:Bank 10 HOOL
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let data = &test_state.snapshot.beancount_data;
+        let diags = undeclared_commodities(data);
+        let messages: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (2, "Undeclared commodity HOOL"),
+                (3, "Undeclared commodity HOOL")
+            ]
+        );
+        assert_eq!(
+            first_usage_date(data, "HOOL"),
+            chrono::NaiveDate::from_ymd_opt(2021, 1, 2)
+        );
+    }
+
+    #[test]
+    fn handle_self_quoted_prices() {
+        let fixure = r#"
+%! /main.beancount
+2021-03-01 price HOOL 10 USD
+2021-03-01 price USD 1 USD
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let diags = self_quoted_prices(&test_state.snapshot.beancount_data);
+        let messages: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(messages, [(1, "Price of USD quoted in itself")]);
+    }
+
+    #[test]
+    fn handle_stale_prices() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 open Assets:Broker
+2024-01-01 open Assets:Cash
+2024-01-02 * "Buy"
+    Assets:Broker 10 HOOL {100 USD}
+    Assets:Broker 5 GOOG {50 USD}
+    Assets:Cash
+2024-01-03 * "Sell"
+    Assets:Broker -5 GOOG {50 USD}
+    Assets:Cash 250 USD
+2024-01-02 price HOOL 100 USD
+2024-02-01 price HOOL 110 USD
+2024-01-02 price GOOG 50 USD
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let data = &test_state.snapshot.beancount_data;
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let diags = stale_prices(data, today, 30);
+        let messages: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [(10, "Latest price of HOOL is from 2024-02-01, 43 days ago")]
+        );
+        assert!(stale_prices(data, today, 60).is_empty());
+    }
+
+    #[test]
+    fn handle_missing_documents() {
+        let dir = std::env::temp_dir().join(format!("beancount-lint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("invoice.pdf"), "").unwrap();
+        let fixure = format!(
+            r#"
+%! {0}/main.beancount
+option "documents" "receipts"
+2024-01-01 document Assets:Bank "invoice.pdf"
+2024-01-02 document Assets:Bank "missing.pdf"
+"#,
+            dir.display()
+        );
+        let test_state = TestState::new(&fixure).unwrap();
+        let diags = missing_documents(&test_state.snapshot.beancount_data);
+        let messages: Vec<_> = diags[&dir.join("main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (0, "Documents directory receipts not found"),
+                (2, "Document missing.pdf not found")
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn handle_missing_includes() {
+        let fixure = r#"
+%! /main.beancount
+include "accounts.beancount"
+include "2024/*.beancount"
+include "2025.beancount"
+%! /accounts.beancount
+2024-01-01 open Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let diags = missing_includes(&test_state.snapshot.beancount_data);
+        let messages: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(messages, [(2, "Included file 2025.beancount not found")]);
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn handle_postings_after_close() {
+        let fixure = r#"
+%! /main.beancount
+2020-01-01 open Assets:Bank
+2020-01-01 open Assets:Cash
+2020-01-01 open Expenses:Food
+2021-03-01 * "Grocer"
+    Assets:Bank -10 USD
+    Expenses:Food
+2021-03-05 * "Grocer"
+    Assets:Cash -10 USD
+    Expenses:Food
+2021-03-01 close Assets:Bank
+2021-03-04 close Assets:Cash
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let data = &test_state.snapshot.beancount_data;
+        let diags = postings_after_close(data);
+        let messages: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [(
+                10,
+                "Assets:Cash has postings after it is closed, the last on 2021-03-05"
+            )]
+        );
+        assert_eq!(
+            suggested_close_date(&last_posting_dates(data), "Assets:Cash"),
+            chrono::NaiveDate::from_ymd_opt(2021, 3, 6)
+        );
+    }
+
+    #[test]
+    fn handle_lot_reductions() {
+        let fixure = r#"
+%! /main.beancount
+2020-01-01 open Assets:Cash
+2020-01-01 open Assets:Stock
+2020-01-01 open Assets:Fund FUND "NONE"
+2021-01-02 * "Buy"
+    Assets:Stock 10 STOCK {10 USD}
+    Assets:Fund 10 FUND {10 USD}
+    Assets:Cash
+2021-02-02 * "Sell"
+    Assets:Stock -4 STOCK {10 USD, 2021-01-02}
+    Assets:Cash 40 USD
+2021-03-02 * "Sell at the wrong cost"
+    Assets:Stock -2 STOCK {12 USD}
+    Assets:Cash 24 USD
+2021-04-02 * "Sell too many"
+    Assets:Stock -12 STOCK {}
+    Assets:Fund -12 FUND {}
+    Assets:Cash 240 USD
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let diags = lot_reductions(&test_state.snapshot.beancount_data);
+        let messages: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (11, "No lot held in Assets:Stock matches the cost specification"),
+                (
+                    14,
+                    "Not enough lots to reduce 12 STOCK from Assets:Stock, which holds 6 STOCK at this cost"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_unsupported_syntax() {
+        let fixure = r#"
+%! /main.beancount
+2020-01-01 open Assets:Bank
+2020-01-01 open Assets:Cash
+2021-01-02 balance Assets:Bank 10 USD
+2021-01-03 open Assets:Broker
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        assert!(unsupported_syntax(&test_state.snapshot.forest).is_empty());
+
+        let fixure = r#"
+%! /main.beancount
+2020-01-01 open Assets:Bank
+2020-01-01 frobnicate Assets:Cash
+2020-01-01 open Assets:Cash
+2021-01-02 frobnicate Assets:Bank 10 USD
+2021-01-03 open Assets:Broker
+2021-01-04 frobnicate Assets:Broker
+2021-01-05 open Assets:Savings
+2021-01-06 frobnicate Assets:Savings
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let diags = unsupported_syntax(&test_state.snapshot.forest);
+        let diags = &diags[&PathBuf::from("/main.beancount")];
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].severity,
+            Some(lsp_types::DiagnosticSeverity::INFORMATION)
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! Beancount specific extensions to the LSP protocol.
+
+use crate::amount::Decimal;
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub enum AccountActivity {}
+
+impl Request for AccountActivity {
+    type Params = AccountActivityParams;
+    type Result = Vec<MonthlyActivity>;
+    const METHOD: &'static str = "beancount/accountActivity";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountActivityParams {
+    pub account: String,
+    /// Also count postings to sub-accounts of `account`.
+    #[serde(default)]
+    pub include_subaccounts: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlyActivity {
+    /// The month, formatted as `YYYY-MM`.
+    pub month: String,
+    pub postings: usize,
+    /// The sum of the posted amounts, by currency.
+    pub totals: BTreeMap<String, Decimal>,
+}
+
+/// The running balance of an account after each of its postings in a file, to
+/// show a register-like balance column as virtual text.
+pub enum InlineBalances {}
+
+impl Request for InlineBalances {
+    type Params = InlineBalancesParams;
+    type Result = Vec<InlineBalance>;
+    const METHOD: &'static str = "beancount/inlineBalances";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineBalancesParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    pub account: String,
+    /// Also count postings to sub-accounts of `account`.
+    #[serde(default)]
+    pub include_subaccounts: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineBalance {
+    /// The line of the posting.
+    pub line: u32,
+    /// The balance of the account after the posting, by currency.
+    pub balance: BTreeMap<String, Decimal>,
+    /// The balance formatted for display, like `90.00 USD, 2 EUR`.
+    pub label: String,
+}
+
+/// The balance of an account at the end of a day, for looking back at the
+/// balance on the date of an entry.
+pub enum BalanceAt {}
+
+impl Request for BalanceAt {
+    type Params = BalanceAtParams;
+    type Result = BalanceAtResult;
+    const METHOD: &'static str = "beancount/balanceAt";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceAtParams {
+    /// A document of the journal to compute the balance in, or all documents
+    /// of the workspace.
+    pub text_document: Option<lsp_types::TextDocumentIdentifier>,
+    pub account: String,
+    /// The balance includes the postings on this day.
+    pub date: chrono::NaiveDate,
+    /// Also count postings to sub-accounts of `account`.
+    #[serde(default)]
+    pub include_subaccounts: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceAtResult {
+    /// The balance of the account, by currency.
+    pub balance: BTreeMap<String, Decimal>,
+    /// The balance formatted for display, like `90.00 USD, 2 EUR`.
+    pub label: String,
+}
+
+/// What a rename would change, without changing anything, so clients can ask
+/// for confirmation before applying a large rename.
+pub enum RenamePreview {}
+
+impl Request for RenamePreview {
+    type Params = lsp_types::RenameParams;
+    type Result = Option<RenamePreviewResult>;
+    const METHOD: &'static str = "beancount/renamePreview";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamePreviewResult {
+    pub total_edits: usize,
+    /// The files with edits, by uri.
+    pub files: Vec<RenamePreviewFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamePreviewFile {
+    pub uri: lsp_types::Uri,
+    pub edits: usize,
+    /// The first few changed lines of the file.
+    pub samples: Vec<RenamePreviewSample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamePreviewSample {
+    pub line: u32,
+    pub text: String,
+    /// The line after the rename.
+    pub new_text: String,
+}
+
+/// The transactions carrying a tag, written on them or pushed onto them with
+/// `pushtag`, for tag-centric navigation like listing all the entries of a
+/// trip.
+pub enum TransactionsByTag {}
+
+impl Request for TransactionsByTag {
+    type Params = TransactionsByTagParams;
+    type Result = Vec<TaggedTransaction>;
+    const METHOD: &'static str = "beancount/transactionsByTag";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsByTagParams {
+    /// The tag, with or without its `#`.
+    pub tag: String,
+    /// A document of the journal to search, or all documents of the
+    /// workspace.
+    pub text_document: Option<lsp_types::TextDocumentIdentifier>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaggedTransaction {
+    pub location: lsp_types::Location,
+    pub date: chrono::NaiveDate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payee: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub narration: Option<String>,
+    /// Whether the tag comes from a `pushtag` directive rather than the
+    /// transaction itself.
+    pub pushed: bool,
+}
+
+/// The syntax tree of a document as parsed by the grammar the server bundles,
+/// for reporting parse issues and prototyping tree-sitter queries.
+pub enum AstDump {}
+
+impl Request for AstDump {
+    type Params = AstDumpParams;
+    type Result = Option<AstDumpResult>;
+    const METHOD: &'static str = "beancount/astDump";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AstDumpParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    /// Only the entries overlapping the range instead of the whole document.
+    pub range: Option<lsp_types::Range>,
+    #[serde(default)]
+    pub format: AstDumpFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AstDumpFormat {
+    /// The S-expression tree-sitter prints, like `(file (open ...))`.
+    #[default]
+    Sexp,
+    /// The named nodes as JSON objects.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AstDumpResult {
+    /// The ABI version of the bundled grammar.
+    pub language_version: usize,
+    /// The S-expression of each dumped node, one per line, with the `sexp`
+    /// format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sexp: Option<String>,
+    /// The dumped nodes, with the `json` format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<Vec<AstNode>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AstNode {
+    pub kind: String,
+    /// The name of the field the node is in its parent, like `date`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub range: lsp_types::Range,
+    /// Whether the node is an `ERROR` node or was inserted by the parser to
+    /// recover from an error.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub error: bool,
+    /// The text of a node without named children.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<AstNode>,
+}
+
+/// Sent once the main journal has been looked up and its includes parsed, so
+/// clients can show which journal is in use or warn that none was found.
+pub enum JournalResolved {}
+
+impl Notification for JournalResolved {
+    type Params = JournalResolvedParams;
+    const METHOD: &'static str = "beancount/journalResolved";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalResolvedParams {
+    /// The configured main journal file, if any.
+    pub journal_file: Option<PathBuf>,
+    /// Whether the journal file could be read.
+    pub found: bool,
+    /// The number of files included by the journal, directly or indirectly.
+    pub included_files: usize,
+    /// The method selected from `checker.method` to check the journal, like
+    /// `systemCall`.
+    pub checker: String,
+    /// The ABI version of the bundled tree-sitter-beancount grammar.
+    pub grammar_version: usize,
+}
+
+/// Sent after each check with the number of problems by severity across the
+/// workspace and in each file, so clients can show a status like "12 errors
+/// in 3 files" without counting the published diagnostics themselves.
+pub enum CheckSummary {}
+
+impl Notification for CheckSummary {
+    type Params = CheckSummaryParams;
+    const METHOD: &'static str = "beancount/checkSummary";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckSummaryParams {
+    #[serde(flatten)]
+    pub total: SeverityCounts,
+    /// The files with problems, by path.
+    pub files: Vec<FileCheckSummary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileCheckSummary {
+    pub uri: lsp_types::Uri,
+    #[serde(flatten)]
+    pub counts: SeverityCounts,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeverityCounts {
+    pub errors: usize,
+    pub warnings: usize,
+    pub information: usize,
+    pub hints: usize,
+}
+
+/// Metadata attached as `data` to every published diagnostic, so clients can
+/// group and filter diagnostics without matching on messages.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticMetadata {
+    pub category: DiagnosticCategory,
+    /// The kind of entry the diagnostic is on, e.g. `transaction` or `balance`.
+    pub entry_type: Option<String>,
+    pub checker: DiagnosticChecker,
+    /// Replacements offered as quick fixes, like the corrections of a
+    /// misspelled word.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticCategory {
+    Syntax,
+    Balance,
+    Unbalanced,
+    Booking,
+    Account,
+    Duplicate,
+    Plugin,
+    Commodity,
+    Price,
+    Document,
+    Include,
+    Flagged,
+    Budget,
+    Orphan,
+    Spelling,
+    Custom,
+    Query,
+    Other,
+}
+
+impl DiagnosticCategory {
+    /// Classifies a `bean-check` error message.
+    pub fn for_bean_check_message(message: &str) -> Self {
+        let message = message.to_lowercase();
+        if message.contains("syntax error") || message.contains("lexer error") {
+            DiagnosticCategory::Syntax
+        } else if message.starts_with("balance failed") {
+            DiagnosticCategory::Balance
+        } else if message.contains("does not balance") {
+            DiagnosticCategory::Unbalanced
+        } else if message.contains("account") && !message.contains("plugin") {
+            DiagnosticCategory::Account
+        } else if message.contains("duplicate") {
+            DiagnosticCategory::Duplicate
+        } else if message.contains("plugin") {
+            DiagnosticCategory::Plugin
+        } else {
+            DiagnosticCategory::Other
+        }
+    }
+}
+
+/// What produced a diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticChecker {
+    BeanCheck,
+    Flagged,
+    Budget,
+    Lint,
+    Spellcheck,
+    Beanquery,
+}
@@ -1,6 +1,8 @@
+use beancount_language_server::traffic_log::TrafficLogOptions;
 use clap::{arg, Command};
 use std::fs;
 use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::fmt::writer::BoxMakeWriter;
@@ -11,14 +13,119 @@ fn main() {
         .args(&[
             arg!(--stdio "specifies to use stdio to communicate with lsp"),
             arg!(--log "write log to file"),
+            arg!(--"log-lsp-traffic" <FILE> "record the lsp messages as JSON lines in FILE"),
+            arg!(--"log-lsp-traffic-text" "keep the document text in the recorded messages"),
+            arg!(--"log-lsp-traffic-max-size" <MB> "roll FILE over once it is this big")
+                .value_parser(clap::value_parser!(u64)),
         ])
+        .subcommand(
+            Command::new("anonymize")
+                .about("print a journal with scrambled accounts, payees, narrations and amounts")
+                .arg(arg!(<file> "the journal to anonymize")),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("format journals in place, or with --check print a diff and fail")
+                .args(&[
+                    arg!(--check "only check whether the files are formatted"),
+                    arg!(--config <FILE> "a JSON file with the initialization options"),
+                    arg!(<files> ... "the journals to format"),
+                ]),
+        )
+        .subcommand(
+            Command::new("format")
+                .about("print a formatted journal, reading it from stdin when FILE is -")
+                .args(&[
+                    arg!(--config <FILE> "a JSON file with the initialization options"),
+                    arg!(<file> "the journal to format, or - for stdin"),
+                ]),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("print the problems the server finds in journals, and fail on any")
+                .args(&[
+                    arg!(--config <FILE> "a JSON file with the initialization options"),
+                    arg!(<files> ... "the journals to check"),
+                ]),
+        )
         .get_matches();
 
+    // exit with 1 when there is something to fix and with 2 on errors
+    if let Some((command @ ("fmt" | "lint"), matches)) = matches.subcommand() {
+        let files: Vec<PathBuf> = matches
+            .get_many::<String>("files")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect();
+        let result =
+            cli_config(matches.get_one::<String>("config")).and_then(|config| match command {
+                "fmt" => {
+                    beancount_language_server::cli::fmt(&files, &config, matches.get_flag("check"))
+                }
+                _ => beancount_language_server::cli::lint(&files, &config),
+            });
+        match result {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("format") {
+        let file = PathBuf::from(matches.get_one::<String>("file").unwrap());
+        let result = cli_config(matches.get_one::<String>("config"))
+            .and_then(|config| beancount_language_server::cli::format(&file, &config));
+        match result {
+            Ok(formatted) => print!("{formatted}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("anonymize") {
+        let file = matches.get_one::<String>("file").unwrap();
+        match fs::read_to_string(file) {
+            Ok(text) => print!("{}", beancount_language_server::anonymize::anonymize(&text)),
+            Err(e) => {
+                eprintln!("{file}: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     setup_logging(matches.get_flag("log"));
 
-    beancount_language_server::run_server()
-        .map_err(|e| anyhow::anyhow!("{}", e))
-        .unwrap();
+    let traffic_log = matches.get_one::<String>("log-lsp-traffic").map(|file| {
+        let mut options = TrafficLogOptions::new(PathBuf::from(file));
+        options.redact = !matches.get_flag("log-lsp-traffic-text");
+        if let Some(mb) = matches.get_one::<u64>("log-lsp-traffic-max-size") {
+            options.max_bytes = mb * 1024 * 1024;
+        }
+        options
+    });
+
+    // exit with 1 when the client exits without requesting a shutdown first
+    if let Err(e) = beancount_language_server::run_server(traffic_log) {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// The options of the JSON file `file`, in the format of the initialization
+/// options of clients.
+fn cli_config(file: Option<&String>) -> anyhow::Result<serde_json::Value> {
+    let Some(file) = file else {
+        return Ok(serde_json::Value::Null);
+    };
+    let text = fs::read_to_string(file).map_err(|e| anyhow::anyhow!("{file}: {e}"))?;
+    serde_json::from_str(&text).map_err(|e| anyhow::anyhow!("{file}: {e}"))
 }
 
 fn setup_logging(file: bool) {
@@ -1,4 +1,31 @@
+pub mod activity;
+pub mod ast_dump;
+pub mod balance;
+pub mod code_actions;
+pub mod code_lens;
 pub mod completion;
 /// Provider definitions for LSP `textDocument/publishDiagnostics`.
 pub mod diagnostics;
+pub mod document_links;
+pub mod document_symbols;
+pub mod documents;
+pub mod external_completion;
+pub mod extract;
+pub mod folding_ranges;
+pub mod forecast;
 pub mod formatting;
+pub mod holdings;
+pub mod hover;
+pub mod inlay_hints;
+pub mod on_type_formatting;
+pub mod payee;
+pub mod prices;
+pub mod references;
+pub mod register;
+pub mod rename;
+pub mod report;
+pub mod semantic_tokens;
+pub mod signature_help;
+pub mod tags;
+pub mod templates;
+pub mod workspace_symbols;
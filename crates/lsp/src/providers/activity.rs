@@ -0,0 +1,226 @@
+use crate::ledger;
+use crate::lsp_ext::AccountActivityParams;
+use crate::lsp_ext::BalanceAtParams;
+use crate::lsp_ext::BalanceAtResult;
+use crate::lsp_ext::InlineBalance;
+use crate::lsp_ext::InlineBalancesParams;
+use crate::lsp_ext::MonthlyActivity;
+use crate::server::LspServerStateSnapshot;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+/// Provider function for `beancount/accountActivity`.
+pub(crate) fn account_activity(
+    snapshot: LspServerStateSnapshot,
+    params: AccountActivityParams,
+) -> Result<Vec<MonthlyActivity>> {
+    debug!("providers::activity::account_activity {}", params.account);
+    let subaccount_prefix = format!("{}:", params.account);
+
+    let mut months: BTreeMap<String, MonthlyActivity> = BTreeMap::new();
+    for data in snapshot.beancount_data.values() {
+        for posting in data.get_postings() {
+            let matches = posting.account == params.account
+                || (params.include_subaccounts && posting.account.starts_with(&subaccount_prefix));
+            if !matches {
+                continue;
+            }
+            let month = posting.date.format("%Y-%m").to_string();
+            let activity = months
+                .entry(month.clone())
+                .or_insert_with(|| MonthlyActivity {
+                    month,
+                    postings: 0,
+                    totals: BTreeMap::new(),
+                });
+            activity.postings += 1;
+            if let Some(amount) = &posting.amount {
+                *activity.totals.entry(amount.currency.clone()).or_default() += amount.number;
+            }
+        }
+    }
+    Ok(months.into_values().collect())
+}
+
+/// Provider function for `beancount/inlineBalances`. The balances run over
+/// the whole journal of the document, but only its own postings are returned.
+pub(crate) fn inline_balances(
+    snapshot: LspServerStateSnapshot,
+    params: InlineBalancesParams,
+) -> Result<Vec<InlineBalance>> {
+    debug!("providers::activity::inline_balances {}", params.account);
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let data = snapshot.journal_data(&file);
+    let balances = ledger::running_balances(
+        &data,
+        &params.account,
+        params.include_subaccounts,
+        &ledger::PostingFilter::default(),
+    )
+    .into_iter()
+    .filter(|(posting_file, _, _)| *posting_file == file)
+    .map(|(_, posting, balance)| InlineBalance {
+        line: posting.line,
+        balance: balance
+            .amounts()
+            .map(|amount| (amount.currency, amount.number))
+            .collect(),
+        label: balance.to_string(),
+    })
+    .collect();
+    Ok(balances)
+}
+
+/// Provider function for `beancount/balanceAt`.
+pub(crate) fn balance_at(
+    snapshot: LspServerStateSnapshot,
+    params: BalanceAtParams,
+) -> Result<BalanceAtResult> {
+    debug!("providers::activity::balance_at {}", params.account);
+    let data = match &params.text_document {
+        Some(document) => snapshot.journal_data(&document.uri.to_file_path().unwrap()),
+        None => snapshot.beancount_data.clone(),
+    };
+    let Some(to) = params.date.succ_opt() else {
+        anyhow::bail!("invalid date: {}", params.date);
+    };
+    let balance = ledger::balance_before(&data, &params.account, params.include_subaccounts, to);
+    Ok(BalanceAtResult {
+        balance: balance
+            .amounts()
+            .map(|amount| (amount.currency, amount.number))
+            .collect(),
+        label: balance.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Decimal;
+    use crate::lsp_ext::AccountActivityParams;
+    use crate::lsp_ext::BalanceAtParams;
+    use crate::lsp_ext::InlineBalancesParams;
+    use crate::providers::activity::account_activity;
+    use crate::providers::activity::balance_at;
+    use crate::providers::activity::inline_balances;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_account_activity() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -10.50 USD
+    Expenses:Food
+2023-10-15 txn "Grocer" "Food"
+    Assets:Bank -4.50 USD
+    Expenses:Food:Snacks
+2023-12-01 txn "Employer" "Salary"
+    Assets:Bank 1,000 USD
+    Income:Salary
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let params = AccountActivityParams {
+            account: String::from("Assets:Bank"),
+            include_subaccounts: false,
+        };
+        let activity = account_activity(test_state.snapshot, params).unwrap();
+        assert_eq!(activity.len(), 2);
+        assert_eq!(activity[0].month, "2023-10");
+        assert_eq!(activity[0].postings, 2);
+        assert_eq!(activity[0].totals["USD"], Decimal::new(-1500, 2));
+        assert_eq!(activity[1].month, "2023-12");
+        assert_eq!(activity[1].totals["USD"], Decimal::new(1000, 0));
+    }
+
+    #[test]
+    fn handle_account_activity_with_subaccounts() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -10.50 USD
+    Expenses:Food
+2023-10-15 txn "Grocer" "Food"
+    Assets:Bank -4.50 USD
+    Expenses:Food:Snacks
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let params = AccountActivityParams {
+            account: String::from("Expenses:Food"),
+            include_subaccounts: true,
+        };
+        let activity = account_activity(test_state.snapshot, params).unwrap();
+        assert_eq!(activity.len(), 1);
+        assert_eq!(activity[0].postings, 2);
+        assert!(activity[0].totals.is_empty());
+    }
+
+    #[test]
+    fn handle_inline_balances() {
+        let fixure = r#"
+%! /main.beancount
+include "2023.beancount"
+2023-12-01 txn "Employer" "Salary"
+    Assets:Bank 1,000 USD
+    Income:Salary
+2023-12-02 txn "Grocer" "Food"
+    Assets:Bank:Savings 100 USD
+    Assets:Bank
+%! /2023.beancount
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -10.50 USD
+    Expenses:Food
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = InlineBalancesParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            account: String::from("Assets:Bank"),
+            include_subaccounts: false,
+        };
+        let balances = inline_balances(test_state.snapshot, params).unwrap();
+        let labels: Vec<_> = balances
+            .iter()
+            .map(|balance| (balance.line, balance.label.as_str()))
+            .collect();
+        assert_eq!(labels, [(2, "989.50 USD"), (6, "889.50 USD")]);
+        assert_eq!(balances[0].balance["USD"], Decimal::new(98950, 2));
+    }
+
+    #[test]
+    fn handle_balance_at() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -10.50 USD
+    Expenses:Food
+2023-10-15 txn "Grocer" "Food"
+    Assets:Bank:Savings -4.50 USD
+    Expenses:Food
+2023-12-01 txn "Employer" "Salary"
+    Assets:Bank 1,000 USD
+    Income:Salary
+"#;
+        let snapshot = || TestState::new(fixure).unwrap().snapshot;
+        let params = |date: &str, include_subaccounts| BalanceAtParams {
+            text_document: None,
+            account: String::from("Assets:Bank"),
+            date: chrono::NaiveDate::from_str(date).unwrap(),
+            include_subaccounts,
+        };
+        let balance = |date, include_subaccounts| {
+            balance_at(snapshot(), params(date, include_subaccounts))
+                .unwrap()
+                .label
+        };
+        assert_eq!(balance("2023-09-30", false), "");
+        assert_eq!(balance("2023-10-01", false), "-10.50 USD");
+        assert_eq!(balance("2023-11-30", false), "-10.50 USD");
+        assert_eq!(balance("2023-11-30", true), "-15.00 USD");
+        assert_eq!(balance("2023-12-01", false), "989.50 USD");
+    }
+}
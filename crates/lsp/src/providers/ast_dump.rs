@@ -0,0 +1,185 @@
+use crate::lsp_ext::AstDumpFormat;
+use crate::lsp_ext::AstDumpParams;
+use crate::lsp_ext::AstDumpResult;
+use crate::lsp_ext::AstNode;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::grammar_version;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::tree_sitter_point_for_lsp_position;
+use crate::treesitter_utils::PositionEncoding;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use tracing::debug;
+
+/// Provider function for `beancount/astDump`. Dumps the whole tree of the
+/// document or, with a range, the entries overlapping it.
+pub(crate) fn ast_dump(
+    snapshot: LspServerStateSnapshot,
+    params: AstDumpParams,
+) -> Result<Option<AstDumpResult>> {
+    debug!("providers::ast_dump {:?}", params.format);
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) =
+        (snapshot.forest.get(&file), snapshot.document_content(&file))
+    else {
+        return Ok(None);
+    };
+    let encoding = snapshot.config.position_encoding;
+    let root = tree.root_node();
+    let nodes: Vec<tree_sitter::Node> = match params.range {
+        None => vec![root],
+        Some(range) => {
+            let start = tree_sitter_point_for_lsp_position(&content, range.start, encoding);
+            let end = tree_sitter_point_for_lsp_position(&content, range.end, encoding);
+            let mut entries = vec![];
+            overlapping_entries(root, start, end, &mut entries);
+            entries
+        }
+    };
+
+    let mut result = AstDumpResult {
+        language_version: grammar_version(),
+        sexp: None,
+        nodes: None,
+    };
+    match params.format {
+        AstDumpFormat::Sexp => {
+            let sexps: Vec<String> = nodes.iter().map(|node| node.to_sexp()).collect();
+            result.sexp = Some(sexps.join("\n"));
+        }
+        AstDumpFormat::Json => {
+            result.nodes = Some(
+                nodes
+                    .into_iter()
+                    .map(|node| ast_node(&content, node, None, encoding))
+                    .collect(),
+            );
+        }
+    }
+    Ok(Some(result))
+}
+
+/// The entries under `node` overlapping the range from `start` to `end`,
+/// looking into org-mode sections rather than dumping them whole.
+fn overlapping_entries<'a>(
+    node: tree_sitter::Node<'a>,
+    start: tree_sitter::Point,
+    end: tree_sitter::Point,
+    entries: &mut Vec<tree_sitter::Node<'a>>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.end_position() <= start || child.start_position() > end {
+            continue;
+        }
+        if child.kind() == "section" {
+            overlapping_entries(child, start, end, entries);
+        } else {
+            entries.push(child);
+        }
+    }
+}
+
+/// The named node with its named children, in the field `field` of its parent.
+fn ast_node(
+    content: &ropey::Rope,
+    node: tree_sitter::Node,
+    field: Option<&str>,
+    encoding: PositionEncoding,
+) -> AstNode {
+    let mut cursor = node.walk();
+    let mut children = vec![];
+    if cursor.goto_first_child() {
+        loop {
+            let child = cursor.node();
+            if child.is_named() || child.is_missing() {
+                children.push(ast_node(content, child, cursor.field_name(), encoding));
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+    AstNode {
+        kind: node.kind().to_string(),
+        field: field.map(String::from),
+        range: lsp_range_for_tree_sitter_node(content, &node, encoding),
+        error: node.is_error() || node.is_missing(),
+        text: children
+            .is_empty()
+            .then(|| text_for_tree_sitter_node(content, &node)),
+        children,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    const FIXTURE: &str = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+2024-01-02 * "Grocer"
+    Assets:Bank -10.00 USD
+    Expenses:Food
+"#;
+
+    fn dump(range: Option<lsp_types::Range>, format: AstDumpFormat) -> AstDumpResult {
+        let state = TestState::new(FIXTURE).unwrap();
+        ast_dump(
+            state.snapshot,
+            AstDumpParams {
+                text_document: lsp_types::TextDocumentIdentifier::new(
+                    lsp_types::Uri::from_str("file:///main.beancount").unwrap(),
+                ),
+                range,
+                format,
+            },
+        )
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn handle_ast_dump_sexp() {
+        let result = dump(None, AstDumpFormat::Sexp);
+        let sexp = result.sexp.unwrap();
+        assert!(sexp.starts_with("(file (open date: (date)"));
+        assert!(result.nodes.is_none());
+
+        // only the transaction overlaps the second line of its posting
+        let range = lsp_types::Range::new(
+            lsp_types::Position::new(3, 0),
+            lsp_types::Position::new(3, 4),
+        );
+        let sexp = dump(Some(range), AstDumpFormat::Sexp).sexp.unwrap();
+        assert!(sexp.starts_with("(transaction"));
+        assert_eq!(sexp.lines().count(), 1);
+    }
+
+    #[test]
+    fn handle_ast_dump_json() {
+        let range = lsp_types::Range::new(
+            lsp_types::Position::new(0, 0),
+            lsp_types::Position::new(0, 0),
+        );
+        let nodes = dump(Some(range), AstDumpFormat::Json).nodes.unwrap();
+        assert_eq!(nodes.len(), 1);
+        let open = &nodes[0];
+        assert_eq!(open.kind, "open");
+        let date = &open.children[0];
+        assert_eq!(date.field.as_deref(), Some("date"));
+        assert_eq!(date.text.as_deref(), Some("2024-01-01"));
+        let account = open
+            .children
+            .iter()
+            .find(|child| child.kind == "account")
+            .unwrap();
+        assert_eq!(account.text.as_deref(), Some("Assets:Bank"));
+        assert!(!open.error);
+    }
+}
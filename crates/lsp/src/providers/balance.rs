@@ -0,0 +1,152 @@
+use crate::beancount_data::parse_date;
+use crate::ledger;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::entry_for_tree_sitter_node;
+use crate::treesitter_utils::lsp_position_for_tree_sitter_point;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::tree_sitter_point_for_lsp_position;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Arguments of the `beancount.insertBalanceAssertion` command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertBalanceAssertionParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    /// The position of the account to assert the balance of.
+    pub position: lsp_types::Position,
+    /// The date of the assertions, defaults to today.
+    pub date: Option<String>,
+}
+
+/// Provider function for the `beancount.insertBalanceAssertion` command.
+/// Inserts a `balance` directive for each currency held in the account under
+/// the cursor after the entry it is in. Like beancount, the balance on a date
+/// is that at the start of the day, and it includes the sub-accounts.
+pub(crate) fn insert_balance_assertion(
+    snapshot: LspServerStateSnapshot,
+    params: InsertBalanceAssertionParams,
+) -> Result<lsp_types::WorkspaceEdit> {
+    debug!("providers::balance::insert_balance_assertion");
+    let date = match params.date {
+        Some(date) => parse_date(&date).ok_or_else(|| anyhow::anyhow!("invalid date: {}", date))?,
+        None => chrono::Local::now().date_naive(),
+    };
+    let uri = params.text_document.uri;
+    let file = uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) =
+        (snapshot.forest.get(&file), snapshot.document_content(&file))
+    else {
+        anyhow::bail!("unknown document: {}", uri.as_str());
+    };
+    let encoding = snapshot.config.position_encoding;
+    let point = tree_sitter_point_for_lsp_position(&content, params.position, encoding);
+    let node = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point);
+    let (Some(account), Some(entry)) = (
+        node.filter(|node| node.kind() == "account"),
+        node.and_then(entry_for_tree_sitter_node),
+    ) else {
+        anyhow::bail!("no account under the cursor");
+    };
+    let account = text_for_tree_sitter_node(&content, &account);
+
+    let balance = ledger::balance_before(&snapshot.journal_data(&file), &account, true, date);
+    if balance.is_empty() {
+        anyhow::bail!("{} holds nothing on {}", account, date.format("%Y-%m-%d"));
+    }
+
+    let format = snapshot.config.number_format.for_content(&content);
+    let directives: Vec<String> = balance
+        .amounts()
+        .map(|amount| {
+            format!(
+                "{} balance {} {} {}",
+                date.format("%Y-%m-%d"),
+                account,
+                format.display(amount.number),
+                amount.currency
+            )
+        })
+        .collect();
+    let end = entry.end_position();
+    let new_text = if end.column == 0 {
+        format!("{}\n", directives.join("\n"))
+    } else {
+        format!("\n{}", directives.join("\n"))
+    };
+    let position = lsp_position_for_tree_sitter_point(&content, end, encoding);
+    let edit = lsp_types::TextEdit {
+        range: lsp_types::Range::new(position, position),
+        new_text,
+    };
+    Ok(lsp_types::WorkspaceEdit {
+        changes: Some(HashMap::from([(uri, vec![edit])])),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::balance::insert_balance_assertion;
+    use crate::providers::balance::InsertBalanceAssertionParams;
+    use crate::test_utils::apply_edits;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    const FIXTURE: &str = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+2024-01-01 open Assets:Bank:Savings
+2024-01-01 open Income:Salary
+2024-01-05 * "Salary"
+    Assets:Bank 1000.00 USD
+    Income:Salary
+2024-01-10 * "Transfer"
+    Assets:Bank:Savings 200.00 EUR
+    Assets:Bank -200.00 USD
+2024-02-01 * "Salary"
+    Assets:Bank 1000.00 USD
+    Income:Salary
+"#;
+
+    fn params(line: u32, character: u32, date: &str) -> InsertBalanceAssertionParams {
+        InsertBalanceAssertionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(
+                lsp_types::Uri::from_str("file:///main.beancount").unwrap(),
+            ),
+            position: lsp_types::Position::new(line, character),
+            date: Some(String::from(date)),
+        }
+    }
+
+    #[test]
+    fn handle_insert_balance_assertion() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let edit =
+            insert_balance_assertion(test_state.snapshot, params(0, 20, "2024-02-01")).unwrap();
+        let inserted = apply_edits(&text, &edit.changes.unwrap()[&uri]);
+        assert!(inserted.starts_with(
+            "2024-01-01 open Assets:Bank\n\
+             2024-02-01 balance Assets:Bank 200.00 EUR\n\
+             2024-02-01 balance Assets:Bank 800.00 USD\n\
+             2024-01-01 open Assets:Bank:Savings\n"
+        ));
+    }
+
+    #[test]
+    fn handle_insert_balance_assertion_errors() {
+        let snapshot = || TestState::new(FIXTURE).unwrap().snapshot;
+        // not on an account
+        assert!(insert_balance_assertion(snapshot(), params(3, 14, "2024-02-01")).is_err());
+        // before the first posting
+        assert!(insert_balance_assertion(snapshot(), params(0, 20, "2024-01-05")).is_err());
+    }
+}
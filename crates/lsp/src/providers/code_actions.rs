@@ -0,0 +1,1632 @@
+use crate::amount::number_expr_for_tree_sitter_node;
+use crate::amount::Decimal;
+use crate::beancount_data::parse_date;
+use crate::commands;
+use crate::commented_out::comment_edit;
+use crate::commented_out::commented_transactions;
+use crate::lint;
+use crate::lsp_ext::DiagnosticCategory;
+use crate::lsp_ext::DiagnosticMetadata;
+use crate::providers::documents;
+use crate::providers::extract;
+use crate::providers::formatting::number_end;
+use crate::providers::prices;
+use crate::providers::templates;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::entry_for_tree_sitter_node;
+use crate::treesitter_utils::lsp_position_for_tree_sitter_point;
+use crate::treesitter_utils::lsp_position_to_char;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::tree_sitter_point_for_lsp_position;
+use crate::treesitter_utils::PositionEncoding;
+use crate::utils::ToFilePath;
+use crate::workspace_edit::WorkspaceEditBuilder;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Number of replacements offered for an unknown account.
+const MAX_ACCOUNT_SUGGESTIONS: usize = 3;
+
+/// Provider function for LSP `textDocument/codeAction`.
+pub(crate) fn code_actions(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::CodeActionParams,
+) -> Result<Option<lsp_types::CodeActionResponse>> {
+    debug!("providers::code_actions");
+    let uri = params.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) = (snapshot.forest.get(&uri), snapshot.document_content(&uri))
+    else {
+        return Ok(None);
+    };
+
+    let mut actions = unknown_account_actions(&snapshot, &params, tree, &content)?;
+    actions.extend(undeclared_commodity_actions(&snapshot, &params, &content)?);
+    actions.extend(spelling_actions(&params));
+    actions.extend(orphan_actions(&snapshot, &params, &uri)?);
+    actions.extend(evaluate_expression_actions(
+        &snapshot, &params, tree, &content,
+    ));
+    actions.extend(digit_grouping_actions(&snapshot, &params, tree, &content)?);
+    actions.extend(toggle_comment_actions(&snapshot, &params, tree, &content));
+    actions.extend(close_account_actions(
+        &snapshot, &params, tree, &content, &uri,
+    ));
+    actions.extend(close_date_actions(&snapshot, &params, &content, &uri));
+    actions.extend(add_documents_actions(&snapshot, &params, &uri)?);
+    actions.extend(fetch_prices_actions(&params)?);
+    actions.extend(create_include_actions(&snapshot, &params, &uri)?);
+    actions.extend(extract_to_file_actions(&params, tree, &content)?);
+    Ok(Some(actions))
+}
+
+/// Replacements for posting accounts in the range that have not been opened.
+fn unknown_account_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    let known_accounts: HashSet<String> = snapshot
+        .beancount_data
+        .values()
+        .flat_map(|data| data.get_accounts())
+        .collect();
+    let mut actions = Vec::new();
+    if known_accounts.is_empty() {
+        return Ok(actions);
+    }
+    let query = tree_sitter::Query::new(
+        &tree_sitter_beancount::language(),
+        "(posting account: (account) @account)",
+    )?;
+    let encoding = snapshot.config.position_encoding;
+    let mut query_cursor = tree_sitter::QueryCursor::new();
+    query_cursor.set_point_range(
+        tree_sitter_point_for_lsp_position(content, params.range.start, encoding)
+            ..tree_sitter_point_for_lsp_position(content, params.range.end, encoding),
+    );
+    let text = content.to_string();
+    for matched in query_cursor.matches(&query, tree.root_node(), text.as_bytes()) {
+        for capture in matched.captures {
+            let account = text_for_tree_sitter_node(content, &capture.node);
+            if known_accounts.contains(&account) {
+                continue;
+            }
+            let range = lsp_range_for_tree_sitter_node(content, &capture.node, encoding);
+            let diagnostics: Vec<_> = params
+                .context
+                .diagnostics
+                .iter()
+                .filter(|diag| diag.range.start.line == range.start.line)
+                .filter(|diag| diagnostic_category(diag) == Some(DiagnosticCategory::Account))
+                .cloned()
+                .collect();
+            for suggestion in closest_accounts(&account, &known_accounts) {
+                actions.push(quick_fix(
+                    format!("Change to `{suggestion}`"),
+                    diagnostics.clone(),
+                    params.text_document.uri.clone(),
+                    lsp_types::TextEdit {
+                        range,
+                        new_text: suggestion,
+                    },
+                ));
+            }
+        }
+    }
+    Ok(actions)
+}
+
+/// `commodity` directives for the undeclared commodities reported in the
+/// range. The directive is dated at the first use of the commodity and added
+/// after the last existing declaration, or at the top of the document if there
+/// is none.
+fn undeclared_commodity_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    content: &ropey::Rope,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    let encoding = snapshot.config.position_encoding;
+    let mut actions = Vec::new();
+    let mut seen = HashSet::new();
+    for diag in params.context.diagnostics.iter() {
+        if diagnostic_category(diag) != Some(DiagnosticCategory::Commodity) {
+            continue;
+        }
+        let start = lsp_position_to_char(content, diag.range.start, encoding);
+        let end = lsp_position_to_char(content, diag.range.end, encoding);
+        let currency = content.slice(start..end).to_string();
+        if !seen.insert(currency.clone()) {
+            continue;
+        }
+        let Some(date) = lint::first_usage_date(&snapshot.beancount_data, &currency) else {
+            continue;
+        };
+        let directive = format!("{} commodity {}", date.format("%Y-%m-%d"), currency);
+        let (uri, edit) = match last_commodity_declaration(snapshot)? {
+            Some((uri, end, content)) if end.column == 0 => (
+                uri,
+                lsp_types::TextEdit {
+                    range: point_range(&content, end, encoding),
+                    new_text: format!("{directive}\n"),
+                },
+            ),
+            Some((uri, end, content)) => (
+                uri,
+                lsp_types::TextEdit {
+                    range: point_range(&content, end, encoding),
+                    new_text: format!("\n{directive}"),
+                },
+            ),
+            None => (
+                params.text_document.uri.clone(),
+                lsp_types::TextEdit {
+                    range: lsp_types::Range::default(),
+                    new_text: format!("{directive}\n"),
+                },
+            ),
+        };
+        actions.push(quick_fix(
+            format!("Declare commodity `{currency}`"),
+            vec![diag.clone()],
+            uri,
+            edit,
+        ));
+    }
+    Ok(actions)
+}
+
+/// The corrections of the misspelled words the diagnostics in the range point
+/// at.
+fn spelling_actions(params: &lsp_types::CodeActionParams) -> Vec<lsp_types::CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    for diag in params.context.diagnostics.iter() {
+        let Some(metadata) = diagnostic_metadata(diag) else {
+            continue;
+        };
+        if metadata.category != DiagnosticCategory::Spelling {
+            continue;
+        }
+        for suggestion in metadata.suggestions {
+            actions.push(quick_fix(
+                format!("Change to `{suggestion}`"),
+                vec![diag.clone()],
+                params.text_document.uri.clone(),
+                lsp_types::TextEdit {
+                    range: diag.range,
+                    new_text: suggestion,
+                },
+            ));
+        }
+    }
+    actions
+}
+
+/// The end of the last `commodity` directive of the first file, by path, that
+/// has any.
+fn last_commodity_declaration(
+    snapshot: &LspServerStateSnapshot,
+) -> Result<Option<(lsp_types::Uri, tree_sitter::Point, ropey::Rope)>> {
+    let query =
+        tree_sitter::Query::new(&tree_sitter_beancount::language(), "(commodity) @commodity")?;
+    let mut paths: Vec<_> = snapshot.forest.keys().collect();
+    paths.sort();
+    for path in paths {
+        let Some(content) = snapshot.document_content(path) else {
+            continue;
+        };
+        let text = content.to_string();
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let last = query_cursor
+            .matches(&query, snapshot.forest[path].root_node(), text.as_bytes())
+            .flat_map(|matched| matched.captures)
+            .map(|capture| capture.node.end_position())
+            .max();
+        if let Some(end) = last {
+            let uri =
+                lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+            return Ok(Some((uri, end, content)));
+        }
+    }
+    Ok(None)
+}
+
+/// An `include` of `file` when it is reported as not included by any journal.
+/// The include is added after the last one of the file that includes the most
+/// siblings of `file`, so a new month file lands next to the other months.
+fn orphan_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    file: &Path,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    let mut actions = Vec::new();
+    let Some(diag) = params
+        .context
+        .diagnostics
+        .iter()
+        .find(|diag| diagnostic_category(diag) == Some(DiagnosticCategory::Orphan))
+    else {
+        return Ok(actions);
+    };
+    let Some(parent) = include_parent(snapshot, file) else {
+        return Ok(actions);
+    };
+    let (Some(tree), Some(content)) = (
+        snapshot.forest.get(&parent),
+        snapshot.document_content(&parent),
+    ) else {
+        return Ok(actions);
+    };
+    let include = parent
+        .parent()
+        .and_then(|dir| file.strip_prefix(dir).ok())
+        .unwrap_or(file);
+    let directive = format!("include \"{}\"", include.display());
+
+    let query = tree_sitter::Query::new(&tree_sitter_beancount::language(), "(include) @include")?;
+    let text = content.to_string();
+    let mut query_cursor = tree_sitter::QueryCursor::new();
+    let last = query_cursor
+        .matches(&query, tree.root_node(), text.as_bytes())
+        .flat_map(|matched| matched.captures)
+        .map(|capture| capture.node.end_position())
+        .max();
+    let encoding = snapshot.config.position_encoding;
+    let edit = match last {
+        Some(end) if end.column == 0 => lsp_types::TextEdit {
+            range: point_range(&content, end, encoding),
+            new_text: format!("{directive}\n"),
+        },
+        Some(end) => lsp_types::TextEdit {
+            range: point_range(&content, end, encoding),
+            new_text: format!("\n{directive}"),
+        },
+        None => lsp_types::TextEdit {
+            range: lsp_types::Range::default(),
+            new_text: format!("{directive}\n"),
+        },
+    };
+    let uri = lsp_types::Uri::from_str(format!("file://{}", parent.to_str().unwrap()).as_str())?;
+    let name = parent.file_name().unwrap_or_default().to_string_lossy();
+    actions.push(quick_fix(
+        format!("Include from `{name}`"),
+        vec![diag.clone()],
+        uri,
+        edit,
+    ));
+    Ok(actions)
+}
+
+/// The journal file whose includes point into the directory of `file` most
+/// often, or the first journal root when none does.
+fn include_parent(snapshot: &LspServerStateSnapshot, file: &Path) -> Option<PathBuf> {
+    let dir = file.parent()?;
+    let mut candidates: Vec<&PathBuf> = snapshot
+        .journals
+        .iter()
+        .flat_map(|journal| journal.files.iter())
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    let mut best: Option<(usize, &PathBuf)> = None;
+    for candidate in candidates {
+        let (Some(data), Some(base)) = (snapshot.beancount_data.get(candidate), candidate.parent())
+        else {
+            continue;
+        };
+        let siblings = data
+            .get_includes()
+            .iter()
+            .filter(|include| base.join(include).parent() == Some(dir))
+            .count();
+        if siblings > best.map_or(0, |(count, _)| count) {
+            best = Some((siblings, candidate));
+        }
+    }
+    best.map(|(_, parent)| parent.clone()).or_else(|| {
+        snapshot
+            .journals
+            .first()
+            .map(|journal| journal.root.clone())
+    })
+}
+
+/// Replaces the arithmetic expression at the start of the range, like
+/// `(100.00 / 3)`, with its value.
+fn evaluate_expression_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let encoding = snapshot.config.position_encoding;
+    let point = tree_sitter_point_for_lsp_position(content, params.range.start, encoding);
+    let Some(expression) = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point)
+        .and_then(number_expr_for_tree_sitter_node)
+        .filter(|expression| expression.kind() != "number")
+    else {
+        return vec![];
+    };
+    let format = snapshot.config.number_format.for_content(content);
+    let text = text_for_tree_sitter_node(content, &expression);
+    // a plain negative number is not worth evaluating
+    if Decimal::parse_with(&text.replace(' ', ""), format).is_some() {
+        return vec![];
+    }
+    let Some(value) = Decimal::evaluate_at_literal_scale(&text, format) else {
+        return vec![];
+    };
+    let edit = lsp_types::TextEdit {
+        range: lsp_range_for_tree_sitter_node(content, &expression, encoding),
+        new_text: format.display(value),
+    };
+    vec![lsp_types::CodeActionOrCommand::CodeAction(
+        lsp_types::CodeAction {
+            title: format!("Evaluate `{}`", text.trim()),
+            kind: Some(lsp_types::CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(lsp_types::WorkspaceEdit {
+                changes: Some(HashMap::from([(
+                    params.text_document.uri.clone(),
+                    vec![edit],
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )]
+}
+
+/// Rewrites of all numbers of the file with their integer digits grouped by
+/// the grouping separator, like `1,000,000.00`, or without it. Each is only
+/// offered if it changes some number.
+fn digit_grouping_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    let format = snapshot.config.number_format.for_content(content);
+    if format.grouping_separator.is_none() {
+        return Ok(vec![]);
+    }
+    let encoding = snapshot.config.position_encoding;
+    let query = tree_sitter::Query::new(&tree_sitter_beancount::language(), "(number) @number")?;
+    let mut query_cursor = tree_sitter::QueryCursor::new();
+    let text = content.to_string();
+    let mut grouped = vec![];
+    let mut ungrouped = vec![];
+    for matched in query_cursor.matches(&query, tree.root_node(), text.as_bytes()) {
+        for capture in matched.captures {
+            let start = capture.node.start_position();
+            let end = number_end(content, capture.node.end_position(), format);
+            let start_byte = content.line_to_byte(start.row) + start.column;
+            let end_byte = content.line_to_byte(end.row) + end.column;
+            let number = content.byte_slice(start_byte..end_byte).to_string();
+            let range = lsp_types::Range::new(
+                lsp_position_for_tree_sitter_point(content, start, encoding),
+                lsp_position_for_tree_sitter_point(content, end, encoding),
+            );
+            for (edits, new_text) in [
+                (&mut grouped, format.group(&number)),
+                (&mut ungrouped, format.ungroup(&number)),
+            ] {
+                if new_text != number {
+                    edits.push(lsp_types::TextEdit { range, new_text });
+                }
+            }
+        }
+    }
+
+    let mut actions = vec![];
+    for (title, edits) in [
+        ("Group the digits of all numbers", grouped),
+        ("Remove the digit grouping of all numbers", ungrouped),
+    ] {
+        if edits.is_empty() {
+            continue;
+        }
+        actions.push(lsp_types::CodeActionOrCommand::CodeAction(
+            lsp_types::CodeAction {
+                title: title.to_string(),
+                kind: Some(lsp_types::CodeActionKind::REFACTOR_REWRITE),
+                edit: Some(lsp_types::WorkspaceEdit {
+                    changes: Some(HashMap::from([(params.text_document.uri.clone(), edits)])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ));
+    }
+    Ok(actions)
+}
+
+/// Comments out the transaction at the start of the range with `;`, or
+/// uncomments the commented transaction there, in a single edit.
+fn toggle_comment_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let encoding = snapshot.config.position_encoding;
+    let line = params.range.start.line as usize;
+    let point = tree_sitter_point_for_lsp_position(content, params.range.start, encoding);
+    let transaction = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point)
+        .and_then(entry_for_tree_sitter_node)
+        .filter(|entry| entry.kind() == "transaction");
+    let (title, edit) = match transaction {
+        Some(transaction) => {
+            let start = transaction.start_position();
+            let end = transaction.end_position();
+            // the node ends at the start of the line after it
+            let end_line = if end.column == 0 && end.row > start.row {
+                end.row - 1
+            } else {
+                end.row
+            };
+            (
+                "Comment out transaction",
+                comment_edit(content, start.row, end_line, encoding),
+            )
+        }
+        None => {
+            let Some(commented) = commented_transactions(content)
+                .into_iter()
+                .find(|transaction| transaction.start_line <= line && line <= transaction.end_line)
+            else {
+                return vec![];
+            };
+            (
+                "Uncomment transaction",
+                commented.uncomment_edit(content, encoding),
+            )
+        }
+    };
+    vec![lsp_types::CodeActionOrCommand::CodeAction(
+        lsp_types::CodeAction {
+            title: title.to_string(),
+            kind: Some(lsp_types::CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(lsp_types::WorkspaceEdit {
+                changes: Some(HashMap::from([(
+                    params.text_document.uri.clone(),
+                    vec![edit],
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )]
+}
+
+/// Closing the account of the `open` directive at the cursor, on the day
+/// after its last posting, or on the day it is opened without any. The
+/// `close` directive goes right after the `open` one.
+fn close_account_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+    uri: &Path,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let encoding = snapshot.config.position_encoding;
+    let point = tree_sitter_point_for_lsp_position(content, params.range.start, encoding);
+    let Some(open) = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point)
+        .and_then(entry_for_tree_sitter_node)
+        .filter(|entry| entry.kind() == "open")
+    else {
+        return vec![];
+    };
+    let mut cursor = open.walk();
+    let (Some(date), Some(account)) = (
+        open.child_by_field_name("date")
+            .and_then(|date| parse_date(&text_for_tree_sitter_node(content, &date))),
+        open.children(&mut cursor)
+            .find(|child| child.kind() == "account")
+            .map(|account| text_for_tree_sitter_node(content, &account)),
+    ) else {
+        return vec![];
+    };
+    let data = snapshot.journal_data(uri);
+    if data
+        .values()
+        .flat_map(|data| data.get_closes())
+        .any(|close| close.account == account)
+    {
+        return vec![];
+    }
+    let close_date =
+        lint::suggested_close_date(&lint::last_posting_dates(&data), &account).unwrap_or(date);
+    let directive = format!("{} close {}", close_date.format("%Y-%m-%d"), account);
+    let end = open.end_position();
+    let new_text = if end.column == 0 {
+        format!("{directive}\n")
+    } else {
+        format!("\n{directive}")
+    };
+    let edit = lsp_types::TextEdit {
+        range: point_range(content, end, encoding),
+        new_text,
+    };
+    vec![lsp_types::CodeActionOrCommand::CodeAction(
+        lsp_types::CodeAction {
+            title: format!("Close `{account}` on {}", close_date.format("%Y-%m-%d")),
+            kind: Some(lsp_types::CodeActionKind::REFACTOR),
+            edit: Some(lsp_types::WorkspaceEdit {
+                changes: Some(HashMap::from([(
+                    params.text_document.uri.clone(),
+                    vec![edit],
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )]
+}
+
+/// Moving the date of a `close` directive with postings after it to the day
+/// after the last posting.
+fn close_date_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    content: &ropey::Rope,
+    uri: &Path,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let data = snapshot.journal_data(uri);
+    let last_postings = lint::last_posting_dates(&data);
+    let mut actions = Vec::new();
+    for diag in params.context.diagnostics.iter() {
+        if diagnostic_category(diag) != Some(DiagnosticCategory::Account) {
+            continue;
+        }
+        let Some(close) = data
+            .get(uri)
+            .into_iter()
+            .flat_map(|data| data.get_closes())
+            .find(|close| close.line == diag.range.start.line)
+        else {
+            continue;
+        };
+        let Some(close_date) = lint::suggested_close_date(&last_postings, &close.account)
+            .filter(|close_date| *close_date > close.date)
+        else {
+            continue;
+        };
+        // the date is ASCII, so its columns are the same in every encoding
+        let line = content.line(close.line as usize).to_string();
+        let Some(width) = line.find(char::is_whitespace) else {
+            continue;
+        };
+        let edit = lsp_types::TextEdit {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(close.line, 0),
+                lsp_types::Position::new(close.line, width as u32),
+            ),
+            new_text: close_date.format("%Y-%m-%d").to_string(),
+        };
+        actions.push(quick_fix(
+            format!("Close `{}` on {}", close.account, edit.new_text),
+            vec![diag.clone()],
+            params.text_document.uri.clone(),
+            edit,
+        ));
+    }
+    actions
+}
+
+/// Adding `document` directives for the files of the `documents` directory
+/// of the option at the cursor that have none, through the
+/// `beancount.addDocuments` command.
+fn add_documents_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    uri: &Path,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    let on_option = snapshot.beancount_data.get(uri).is_some_and(|data| {
+        data.get_documents().iter().any(|document| {
+            document.directory && document.range.start.line == params.range.start.line
+        })
+    });
+    if !on_option {
+        return Ok(vec![]);
+    }
+    let count: usize = documents::undocumented_files(&snapshot.journal_data(uri))
+        .values()
+        .map(Vec::len)
+        .sum();
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    let arguments = documents::AddDocumentsParams {
+        text_document: params.text_document.clone(),
+    };
+    let title = format!(
+        "Add {count} missing document directive{}",
+        if count == 1 { "" } else { "s" }
+    );
+    Ok(vec![lsp_types::CodeActionOrCommand::CodeAction(
+        lsp_types::CodeAction {
+            title: title.clone(),
+            kind: Some(lsp_types::CodeActionKind::SOURCE),
+            command: Some(lsp_types::Command {
+                title,
+                command: String::from(commands::ADD_DOCUMENTS),
+                arguments: Some(vec![serde_json::to_value(arguments)?]),
+            }),
+            ..Default::default()
+        },
+    )])
+}
+
+/// Creating the missing files of the `include` directives with errors in the
+/// range, empty or seeded with one of the templates of the templates
+/// directory.
+fn create_include_actions(
+    snapshot: &LspServerStateSnapshot,
+    params: &lsp_types::CodeActionParams,
+    file: &Path,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    let Some(data) = snapshot.beancount_data.get(file) else {
+        return Ok(vec![]);
+    };
+    let mut actions = Vec::new();
+    for diag in params
+        .context
+        .diagnostics
+        .iter()
+        .filter(|diag| diagnostic_category(diag) == Some(DiagnosticCategory::Include))
+    {
+        let Some(include) = data
+            .get_include_entries()
+            .iter()
+            .find(|include| include.range == diag.range)
+        else {
+            continue;
+        };
+        let path = include.resolve(file);
+        let uri = lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+        let action = |title: String, edit: &WorkspaceEditBuilder| {
+            lsp_types::CodeActionOrCommand::CodeAction(lsp_types::CodeAction {
+                title,
+                kind: Some(lsp_types::CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diag.clone()]),
+                edit: Some(edit.build()),
+                ..Default::default()
+            })
+        };
+        let mut create = WorkspaceEditBuilder::new();
+        create.create_file(uri.clone(), true);
+        actions.push(action(format!("Create `{}`", include.path), &create));
+        for template in templates::file_templates(&snapshot.config) {
+            let mut edit = WorkspaceEditBuilder::new();
+            edit.create_file(uri.clone(), true).edit(
+                uri.clone(),
+                lsp_types::TextEdit {
+                    range: lsp_types::Range::default(),
+                    new_text: template.text(),
+                },
+            );
+            actions.push(action(
+                format!(
+                    "Create `{}` from template `{}`",
+                    include.path, template.name
+                ),
+                &edit,
+            ));
+        }
+    }
+    Ok(actions)
+}
+
+/// Moving the entries of a selection into a new file through the
+/// `beancount.extractToFile` command, named after their first month.
+fn extract_to_file_actions(
+    params: &lsp_types::CodeActionParams,
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    if params.range.start == params.range.end {
+        return Ok(vec![]);
+    }
+    let entries = extract::selected_entries(tree, params.range);
+    if entries.is_empty() {
+        return Ok(vec![]);
+    }
+    let arguments = extract::ExtractToFileParams {
+        text_document: params.text_document.clone(),
+        range: params.range,
+        path: extract::default_path(content, &entries),
+    };
+    let title = format!(
+        "Extract {} {} to `{}`",
+        entries.len(),
+        if entries.len() == 1 {
+            "entry"
+        } else {
+            "entries"
+        },
+        arguments.path
+    );
+    Ok(vec![lsp_types::CodeActionOrCommand::CodeAction(
+        lsp_types::CodeAction {
+            title: title.clone(),
+            kind: Some(lsp_types::CodeActionKind::REFACTOR_EXTRACT),
+            command: Some(lsp_types::Command {
+                title,
+                command: String::from(commands::EXTRACT_TO_FILE),
+                arguments: Some(vec![serde_json::to_value(arguments)?]),
+            }),
+            ..Default::default()
+        },
+    )])
+}
+
+/// Fetching the latest prices into the document through the
+/// `beancount.fetchPrices` command, for stale prices in the range.
+fn fetch_prices_actions(
+    params: &lsp_types::CodeActionParams,
+) -> Result<Vec<lsp_types::CodeActionOrCommand>> {
+    let diagnostics: Vec<_> = params
+        .context
+        .diagnostics
+        .iter()
+        .filter(|diag| diagnostic_category(diag) == Some(DiagnosticCategory::Price))
+        .cloned()
+        .collect();
+    if diagnostics.is_empty() {
+        return Ok(vec![]);
+    }
+    let arguments = prices::FetchPricesParams {
+        text_document: params.text_document.clone(),
+    };
+    let title = String::from("Fetch the latest prices");
+    Ok(vec![lsp_types::CodeActionOrCommand::CodeAction(
+        lsp_types::CodeAction {
+            title: title.clone(),
+            kind: Some(lsp_types::CodeActionKind::QUICKFIX),
+            diagnostics: Some(diagnostics),
+            command: Some(lsp_types::Command {
+                title,
+                command: String::from(commands::FETCH_PRICES),
+                arguments: Some(vec![serde_json::to_value(arguments)?]),
+            }),
+            ..Default::default()
+        },
+    )])
+}
+
+/// An empty range at a tree-sitter point.
+fn point_range(
+    content: &ropey::Rope,
+    point: tree_sitter::Point,
+    encoding: PositionEncoding,
+) -> lsp_types::Range {
+    let position = lsp_position_for_tree_sitter_point(content, point, encoding);
+    lsp_types::Range::new(position, position)
+}
+
+fn quick_fix(
+    title: String,
+    diagnostics: Vec<lsp_types::Diagnostic>,
+    uri: lsp_types::Uri,
+    edit: lsp_types::TextEdit,
+) -> lsp_types::CodeActionOrCommand {
+    lsp_types::CodeActionOrCommand::CodeAction(lsp_types::CodeAction {
+        title,
+        kind: Some(lsp_types::CodeActionKind::QUICKFIX),
+        diagnostics: Some(diagnostics),
+        edit: Some(lsp_types::WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, vec![edit])])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// The category from the metadata of `diag`.
+fn diagnostic_category(diag: &lsp_types::Diagnostic) -> Option<DiagnosticCategory> {
+    diagnostic_metadata(diag).map(|metadata| metadata.category)
+}
+
+fn diagnostic_metadata(diag: &lsp_types::Diagnostic) -> Option<DiagnosticMetadata> {
+    diag.data
+        .clone()
+        .and_then(|data| serde_json::from_value::<DiagnosticMetadata>(data).ok())
+}
+
+/// The known accounts with the smallest edit distance to `account`.
+fn closest_accounts(account: &str, known_accounts: &HashSet<String>) -> Vec<String> {
+    let mut candidates: Vec<_> = known_accounts
+        .iter()
+        .map(|known| (strsim::levenshtein(account, known), known))
+        .collect();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .take(MAX_ACCOUNT_SUGGESTIONS)
+        .map(|(_, known)| known.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp_ext::DiagnosticCategory;
+    use crate::lsp_ext::DiagnosticChecker;
+    use crate::lsp_ext::DiagnosticMetadata;
+    use crate::providers::code_actions::code_actions;
+    use crate::server::Journal;
+    use crate::test_utils::apply_edits;
+    use crate::test_utils::TestState;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use test_log::test;
+
+    #[test]
+    fn handle_unknown_account_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Assets:Bank
+2023-01-01 open Expenses:Food:Groceries
+2023-01-01 open Expenses:Food:Restaurants
+2023-01-01 open Expenses:Rent
+2023-01-01 open Income:Salary
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -1 USD
+    Expenses:Food:Grocerys
+          |
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: cursor.text_document,
+            range: lsp_types::Range::new(cursor.position, cursor.position),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let titles: Vec<_> = actions
+            .iter()
+            .filter_map(|action| match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action)
+                    if action.kind == Some(lsp_types::CodeActionKind::QUICKFIX) =>
+                {
+                    Some(action.title.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                "Change to `Expenses:Food:Groceries`",
+                "Change to `Expenses:Food:Restaurants`",
+                "Change to `Expenses:Rent`",
+            ]
+        );
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        let edits = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        let edit = &edits.values().next().unwrap()[0];
+        assert_eq!(
+            edit.range,
+            lsp_types::Range::new(
+                lsp_types::Position::new(7, 4),
+                lsp_types::Position::new(7, 26)
+            )
+        );
+    }
+
+    #[test]
+    fn handle_known_account_no_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Assets:Bank
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -1 USD
+          |
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: cursor.text_document,
+            range: lsp_types::Range::new(cursor.position, cursor.position),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        assert!(!actions.iter().any(|action| matches!(
+            action,
+            lsp_types::CodeActionOrCommand::CodeAction(action)
+                if action.kind == Some(lsp_types::CodeActionKind::QUICKFIX)
+        )));
+    }
+
+    #[test]
+    fn handle_declare_commodity_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+include "commodities.beancount"
+2021-03-01 price HOOL 10 USD
+2021-01-02 balance Assets:Bank 10 HOOL
+%! /commodities.beancount
+2020-01-01 commodity USD
+  name: "Dollar"
+2020-01-01 commodity EUR
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .lint
+            .undeclared_commodities = true;
+        let check = crate::checker::CheckOutput {
+            success: true,
+            errors: String::new(),
+        };
+        let diags = crate::providers::diagnostics::diagnostics(
+            &test_state.snapshot.beancount_data,
+            &test_state.snapshot.forest,
+            &test_state.snapshot.config,
+            &check,
+        );
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(1, 0),
+                lsp_types::Position::new(3, 0),
+            ),
+            context: lsp_types::CodeActionContext {
+                diagnostics: diags[&std::path::PathBuf::from("/main.beancount")].clone(),
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        assert_eq!(actions.len(), 1);
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Declare commodity `HOOL`");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        let uri = lsp_types::Uri::from_str("file:///commodities.beancount").unwrap();
+        let text = &test_state.fixture.documents[1].text;
+        assert_eq!(
+            apply_edits(text, &changes[&uri]),
+            "2020-01-01 commodity USD\n  name: \"Dollar\"\n2020-01-01 commodity EUR\n2021-01-02 commodity HOOL"
+        );
+    }
+
+    #[test]
+    fn handle_spelling_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 * "Grocer" "Weekly shoping"
+    Expenses:Food 42.10 USD
+    Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let metadata = DiagnosticMetadata {
+            category: DiagnosticCategory::Spelling,
+            entry_type: Some(String::from("transaction")),
+            checker: DiagnosticChecker::Spellcheck,
+            suggestions: vec![String::from("shopping"), String::from("hoping")],
+        };
+        let diag = lsp_types::Diagnostic {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(0, 30),
+                lsp_types::Position::new(0, 37),
+            ),
+            message: String::from("Unknown word `shoping`"),
+            data: serde_json::to_value(metadata).ok(),
+            ..Default::default()
+        };
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            range: diag.range,
+            context: lsp_types::CodeActionContext {
+                diagnostics: vec![diag],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let titles: Vec<_> = actions
+            .iter()
+            .filter_map(|action| match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action)
+                    if action.kind == Some(lsp_types::CodeActionKind::QUICKFIX) =>
+                {
+                    Some(action.title.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(titles, ["Change to `shopping`", "Change to `hoping`"]);
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert_eq!(
+            apply_edits(&test_state.fixture.documents[0].text, &changes[&uri]),
+            "2023-10-01 * \"Grocer\" \"Weekly shopping\"\n    Expenses:Food 42.10 USD\n    Assets:Bank"
+        );
+    }
+
+    #[test]
+    fn handle_orphan_include_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+include "accounts.beancount"
+include "2024/01.beancount"
+%! /accounts.beancount
+2024-01-01 open Assets:Bank
+%! /2024/01.beancount
+2024-01-05 balance Assets:Bank 10 USD
+%! /2024/02.beancount
+2024-02-05 balance Assets:Bank 10 USD
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        let files = [
+            "/main.beancount",
+            "/accounts.beancount",
+            "/2024/01.beancount",
+        ];
+        test_state.snapshot.journals = Arc::new(vec![Journal {
+            root: PathBuf::from("/main.beancount"),
+            files: files.into_iter().map(PathBuf::from).collect(),
+        }]);
+        let uri = lsp_types::Uri::from_str("file:///2024/02.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::default(),
+            context: lsp_types::CodeActionContext {
+                diagnostics: vec![crate::providers::diagnostics::orphan_diagnostic()],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        assert_eq!(actions.len(), 1);
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Include from `main.beancount`");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let text = &test_state.fixture.documents[0].text;
+        assert_eq!(
+            apply_edits(text, &changes[&uri]),
+            "include \"accounts.beancount\"\ninclude \"2024/01.beancount\"\ninclude \"2024/02.beancount\""
+        );
+    }
+
+    fn digit_grouping_edits(fixture: &str) -> Vec<(String, String)> {
+        let test_state = TestState::new(fixture).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            range: lsp_types::Range::default(),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        actions
+            .into_iter()
+            .filter_map(|action| {
+                let lsp_types::CodeActionOrCommand::CodeAction(action) = action else {
+                    unreachable!();
+                };
+                // the transaction the numbers are in can be commented out too
+                (action.title != "Comment out transaction").then_some(action)
+            })
+            .map(|action| {
+                assert_eq!(
+                    action.kind,
+                    Some(lsp_types::CodeActionKind::REFACTOR_REWRITE)
+                );
+                let changes = action.edit.unwrap().changes.unwrap();
+                (action.title, apply_edits(&text, &changes[&uri]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn handle_digit_grouping_actions() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Employer" "Bonus"
+    Assets:Bank 1000000.00 USD
+    Income:Bonus -1,000,000.00 USD
+"#;
+        assert_eq!(
+            digit_grouping_edits(fixure),
+            [
+                (
+                    String::from("Group the digits of all numbers"),
+                    String::from("2023-10-01 txn \"Employer\" \"Bonus\"\n    Assets:Bank 1,000,000.00 USD\n    Income:Bonus -1,000,000.00 USD")
+                ),
+                (
+                    String::from("Remove the digit grouping of all numbers"),
+                    String::from("2023-10-01 txn \"Employer\" \"Bonus\"\n    Assets:Bank 1000000.00 USD\n    Income:Bonus -1000000.00 USD")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_digit_grouping_nothing_to_group() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -12.50 USD
+    Expenses:Food
+"#;
+        assert!(digit_grouping_edits(fixure).is_empty());
+    }
+
+    #[test]
+    fn handle_evaluate_expression() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Friends" "Dinner"
+    Assets:Receivable (100.00 / 4) USD
+                        |
+    Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: cursor.text_document.clone(),
+            range: lsp_types::Range::new(cursor.position, cursor.position),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Evaluate `(100.00 / 4)`");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert_eq!(
+            apply_edits(&text, &changes[&cursor.text_document.uri]),
+            "2023-10-01 txn \"Friends\" \"Dinner\"\n    Assets:Receivable 25.00 USD\n    Assets:Bank"
+        );
+    }
+
+    #[test]
+    fn handle_close_account_action() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Assets:Bank
+     |
+2023-01-01 open Expenses:Food
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -1 USD
+    Expenses:Food
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: cursor.text_document.clone(),
+            range: lsp_types::Range::new(cursor.position, cursor.position),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        assert_eq!(actions.len(), 1);
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Close `Assets:Bank` on 2023-10-02");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert_eq!(
+            apply_edits(&text, &changes[&cursor.text_document.uri]),
+            "2023-01-01 open Assets:Bank\n2023-10-02 close Assets:Bank\n2023-01-01 open Expenses:Food\n2023-10-01 txn \"Grocer\" \"Food\"\n    Assets:Bank -1 USD\n    Expenses:Food"
+        );
+    }
+
+    #[test]
+    fn handle_add_documents_action() {
+        let dir = std::env::temp_dir().join(format!("beancount-actions-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("docs/Assets/Bank")).unwrap();
+        std::fs::write(dir.join("docs/Assets/Bank/2024-01-31.pdf"), "").unwrap();
+        let fixure = format!(
+            r#"
+%! {0}/main.beancount
+option "documents" "docs"
+2024-01-01 open Assets:Bank
+"#,
+            dir.display()
+        );
+        let snapshot = || TestState::new(&fixure).unwrap().snapshot;
+        let uri =
+            lsp_types::Uri::from_str(&format!("file://{}/main.beancount", dir.display())).unwrap();
+        let params = |line| lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(line, 0),
+                lsp_types::Position::new(line, 0),
+            ),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(snapshot(), params(0)).unwrap().unwrap();
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Add 1 missing document directive");
+        assert_eq!(
+            action.command.as_ref().unwrap().command,
+            crate::commands::ADD_DOCUMENTS
+        );
+        // only offered on the option
+        let actions = code_actions(snapshot(), params(1)).unwrap().unwrap();
+        assert!(actions.iter().all(|action| !matches!(
+            action,
+            lsp_types::CodeActionOrCommand::CodeAction(action) if action.command.is_some()
+        )));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn handle_close_date_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Assets:Bank
+2023-01-01 open Expenses:Food
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -1 USD
+    Expenses:Food
+2023-09-30 close Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let check = crate::checker::CheckOutput {
+            success: true,
+            errors: String::new(),
+        };
+        let diags = crate::providers::diagnostics::diagnostics(
+            &test_state.snapshot.beancount_data,
+            &test_state.snapshot.forest,
+            &test_state.snapshot.config,
+            &check,
+        );
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(5, 0),
+                lsp_types::Position::new(5, 0),
+            ),
+            context: lsp_types::CodeActionContext {
+                diagnostics: diags[&PathBuf::from("/main.beancount")].clone(),
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Close `Assets:Bank` on 2023-10-02");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert!(apply_edits(&text, &changes[&uri]).ends_with("\n2023-10-02 close Assets:Bank"));
+    }
+
+    #[test]
+    fn handle_fetch_prices_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+2020-01-01 open Assets:Broker
+2020-01-01 open Assets:Cash
+2020-01-02 * "Buy"
+    Assets:Broker 10 HOOL {100 USD}
+    Assets:Cash
+2020-01-02 price HOOL 100 USD
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .lint
+            .stale_price_days = Some(30);
+        let check = crate::checker::CheckOutput {
+            success: true,
+            errors: String::new(),
+        };
+        let diags = crate::providers::diagnostics::diagnostics(
+            &test_state.snapshot.beancount_data,
+            &test_state.snapshot.forest,
+            &test_state.snapshot.config,
+            &check,
+        );
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(5, 0),
+                lsp_types::Position::new(5, 0),
+            ),
+            context: lsp_types::CodeActionContext {
+                diagnostics: diags[&PathBuf::from("/main.beancount")].clone(),
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let action = actions
+            .iter()
+            .find_map(|action| match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action)
+                    if action.command.as_ref().is_some_and(|command| {
+                        command.command == crate::commands::FETCH_PRICES
+                    }) =>
+                {
+                    Some(action)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(action.title, "Fetch the latest prices");
+        assert_eq!(action.diagnostics.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn handle_extract_to_file_action() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+2024-02-10 * "Grocer"
+    Assets:Bank -10 USD
+    Expenses:Food
+2024-02-01 * "Landlord"
+    Assets:Bank -100 USD
+    Expenses:Rent
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(1, 0),
+                lsp_types::Position::new(6, 0),
+            ),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let titles: Vec<_> = actions
+            .iter()
+            .filter_map(|action| match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action)
+                    if action.kind == Some(lsp_types::CodeActionKind::REFACTOR_EXTRACT) =>
+                {
+                    Some(action.title.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(titles, ["Extract 2 entries to `2024-02.beancount`"]);
+    }
+
+    #[test]
+    fn handle_create_include_quick_fix() {
+        let fixure = r#"
+%! /main.beancount
+include "2024/01.beancount"
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let diags = crate::lint::missing_includes(&test_state.snapshot.beancount_data);
+        let diag = lsp_types::Diagnostic {
+            data: serde_json::to_value(DiagnosticMetadata {
+                category: DiagnosticCategory::Include,
+                entry_type: None,
+                checker: DiagnosticChecker::Lint,
+                suggestions: Vec::new(),
+            })
+            .ok(),
+            ..diags[&PathBuf::from("/main.beancount")][0].clone()
+        };
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: diag.range,
+            context: lsp_types::CodeActionContext {
+                diagnostics: vec![diag],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let action = actions
+            .iter()
+            .find_map(|action| match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action)
+                    if action.title == "Create `2024/01.beancount`" =>
+                {
+                    Some(action)
+                }
+                _ => None,
+            })
+            .unwrap();
+        let Some(lsp_types::DocumentChanges::Operations(operations)) = action
+            .edit
+            .as_ref()
+            .and_then(|edit| edit.document_changes.as_ref())
+        else {
+            panic!("expected a file operation");
+        };
+        assert!(matches!(
+            operations.as_slice(),
+            [lsp_types::DocumentChangeOperation::Op(lsp_types::ResourceOp::Create(create))]
+                if create.uri.as_str() == "file:///2024/01.beancount"
+        ));
+    }
+
+    #[test]
+    fn handle_create_include_from_template_quick_fix() {
+        let dir = std::env::temp_dir().join(format!("beancount-month-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("month.beancount"), "; ${1:Month}\n$0").unwrap();
+        let fixure = r#"
+%! /main.beancount
+include "2024/01.beancount"
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .update(serde_json::json!({"templates": {"directory": dir.to_str().unwrap()}}))
+            .unwrap();
+        let diags = crate::lint::missing_includes(&test_state.snapshot.beancount_data);
+        let diag = lsp_types::Diagnostic {
+            data: serde_json::to_value(DiagnosticMetadata {
+                category: DiagnosticCategory::Include,
+                entry_type: None,
+                checker: DiagnosticChecker::Lint,
+                suggestions: Vec::new(),
+            })
+            .ok(),
+            ..diags[&PathBuf::from("/main.beancount")][0].clone()
+        };
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: diag.range,
+            context: lsp_types::CodeActionContext {
+                diagnostics: vec![diag],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        let action = actions
+            .iter()
+            .find_map(|action| match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action)
+                    if action.title == "Create `2024/01.beancount` from template `month`" =>
+                {
+                    Some(action)
+                }
+                _ => None,
+            })
+            .unwrap();
+        let Some(lsp_types::DocumentChanges::Operations(operations)) = action
+            .edit
+            .as_ref()
+            .and_then(|edit| edit.document_changes.as_ref())
+        else {
+            panic!("expected file operations");
+        };
+        let [lsp_types::DocumentChangeOperation::Op(lsp_types::ResourceOp::Create(_)), lsp_types::DocumentChangeOperation::Edit(insert)] =
+            operations.as_slice()
+        else {
+            panic!("expected a creation and an edit");
+        };
+        assert_eq!(
+            insert.text_document.uri.as_str(),
+            "file:///2024/01.beancount"
+        );
+        assert_eq!(
+            insert.edits,
+            [lsp_types::OneOf::Left(lsp_types::TextEdit {
+                range: lsp_types::Range::default(),
+                new_text: String::from("; Month\n"),
+            })]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn handle_uncomment_transaction() {
+        let fixure = r#"
+%! /main.beancount
+; 2023-10-01 txn "Friends" "Dinner"
+;     Assets:Receivable 25.00 USD
+        |
+;     Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: cursor.text_document.clone(),
+            range: lsp_types::Range::new(cursor.position, cursor.position),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        assert_eq!(actions.len(), 1);
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Uncomment transaction");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert_eq!(
+            apply_edits(&text, &changes[&cursor.text_document.uri]),
+            "2023-10-01 txn \"Friends\" \"Dinner\"\n    Assets:Receivable 25.00 USD\n    Assets:Bank"
+        );
+    }
+
+    #[test]
+    fn handle_comment_out_transaction() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Friends" "Dinner"
+    Assets:Receivable 25.00 USD
+      |
+    Assets:Bank
+2023-10-02 balance Assets:Bank 0 USD
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: cursor.text_document.clone(),
+            range: lsp_types::Range::new(cursor.position, cursor.position),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = code_actions(test_state.snapshot, params).unwrap().unwrap();
+        assert_eq!(actions.len(), 1);
+        let lsp_types::CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.title, "Comment out transaction");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        let edits = &changes[&cursor.text_document.uri];
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            apply_edits(&text, edits),
+            "; 2023-10-01 txn \"Friends\" \"Dinner\"\n;     Assets:Receivable 25.00 USD\n;     Assets:Bank\n2023-10-02 balance Assets:Bank 0 USD"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::checker;
+use crate::commands;
+use crate::providers::register::RegisterDocument;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::str::FromStr;
+use tracing::debug;
+
+/// The URI scheme of the documents with the results of queries.
+pub(crate) const QUERY_SCHEME: &str = "beancount-query";
+
+/// Arguments of the `beancount.runQuery` command.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunQueryParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    /// The name of the `query` directive.
+    pub name: String,
+}
+
+/// Provider function for LSP `textDocument/codeLens`. Puts a "Run query"
+/// lens above each `query` directive.
+pub(crate) fn code_lens(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::CodeLensParams,
+) -> Result<Option<Vec<lsp_types::CodeLens>>> {
+    debug!("providers::code_lens");
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) =
+        (snapshot.forest.get(&file), snapshot.document_content(&file))
+    else {
+        return Ok(None);
+    };
+    let mut lenses = vec![];
+    let mut cursor = tree.root_node().walk();
+    for query in tree
+        .root_node()
+        .children(&mut cursor)
+        .filter(|node| node.kind() == "query")
+    {
+        let mut query_cursor = query.walk();
+        let Some(name) = query
+            .children(&mut query_cursor)
+            .find(|child| child.kind() == "string")
+        else {
+            continue;
+        };
+        let name = text_for_tree_sitter_node(&content, &name)
+            .trim_matches('"')
+            .to_string();
+        let line = query.start_position().row as u32;
+        let arguments = RunQueryParams {
+            text_document: params.text_document.clone(),
+            name,
+        };
+        lenses.push(lsp_types::CodeLens {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(line, 0),
+                lsp_types::Position::new(line, 0),
+            ),
+            command: Some(lsp_types::Command {
+                title: String::from("Run query"),
+                command: String::from(commands::RUN_QUERY),
+                arguments: Some(vec![serde_json::to_value(arguments)?]),
+            }),
+            data: None,
+        });
+    }
+    Ok(Some(lenses))
+}
+
+/// Provider function for the `beancount.runQuery` command. Runs the query on
+/// the journal of the document with `beanquery`, for the client to show the
+/// result as a read-only document.
+pub(crate) fn run_query(
+    snapshot: LspServerStateSnapshot,
+    params: RunQueryParams,
+) -> Result<RegisterDocument> {
+    debug!("providers::code_lens::run_query {}", params.name);
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let Some(query) = snapshot
+        .journal_data(&file)
+        .values()
+        .flat_map(|data| data.get_queries())
+        .find(|query| query.name == params.name)
+        .cloned()
+    else {
+        anyhow::bail!("unknown query: {}", params.name);
+    };
+    let root = snapshot
+        .journal_for(&file)
+        .map(|journal| journal.root.clone())
+        .or_else(|| snapshot.config.journal_roots.first().cloned())
+        .unwrap_or(file);
+    let result = checker::run_query(
+        &snapshot.config.checker,
+        &root,
+        &query.query,
+        &snapshot.cancelled,
+    )?;
+
+    let content = format!("{}\n\n{}\n\n{}", query.name, query.query, result);
+    let uri = lsp_types::Uri::from_str(&format!("{QUERY_SCHEME}:{}", percent_encode(&query.name)))?;
+    Ok(RegisterDocument { uri, content })
+}
+
+/// Escapes everything but letters, digits and `-_.` for the path of a URI.
+pub(crate) fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-_.".contains(&byte) {
+                char::from(byte).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    #[test]
+    fn handle_query_code_lens() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+2024-01-01 query "cash balance" "SELECT sum(position) WHERE account ~ 'Assets'"
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let text_document = lsp_types::TextDocumentIdentifier::new(
+            lsp_types::Uri::from_str("file:///main.beancount").unwrap(),
+        );
+        let lenses = code_lens(
+            test_state.snapshot,
+            lsp_types::CodeLensParams {
+                text_document,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(lenses.len(), 1);
+        assert_eq!(lenses[0].range.start.line, 1);
+        let command = lenses[0].command.as_ref().unwrap();
+        assert_eq!(command.command, commands::RUN_QUERY);
+        assert_eq!(
+            command.arguments.as_ref().unwrap()[0],
+            serde_json::json!({
+                "textDocument": {"uri": "file:///main.beancount"},
+                "name": "cash balance",
+            })
+        );
+    }
+
+    #[test]
+    fn handle_query_uri() {
+        assert_eq!(percent_encode("cash balance"), "cash%20balance");
+    }
+}
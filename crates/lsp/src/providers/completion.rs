@@ -1,13 +1,39 @@
+use crate::amount::NumberFormat;
+use crate::beancount_data::canonical_descriptions;
+use crate::beancount_data::description_key;
+use crate::beancount_data::operating_currencies;
+use crate::beancount_data::parse_date;
 use crate::beancount_data::BeancountData;
+use crate::config::CompletionOptions;
+use crate::config::CustomArgumentType;
+use crate::config::CustomDirectiveSchema;
+use crate::config::HiddenAccounts;
+use crate::custom_directives::custom_slot;
+use crate::custom_directives::CustomSlot;
+use crate::ledger;
+use crate::lint;
+use crate::providers::external_completion::external_completion;
+use crate::providers::external_completion::CompletionContext;
+use crate::providers::templates;
 use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
 use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::tree_sitter_point_for_lsp_position;
+use crate::treesitter_utils::PositionEncoding;
 use crate::utils::ToFilePath;
 use anyhow::Result;
 use chrono::Datelike;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
 use tracing::debug;
 
+/// Characters registered with the client as completion triggers.
+pub(crate) const TRIGGER_CHARACTERS: [char; 7] = ['2', '"', '#', '^', '@', '{', '/'];
+
 /// Provider function for LSP ``.
 pub(crate) fn completion(
     snapshot: LspServerStateSnapshot,
@@ -15,6 +41,7 @@ pub(crate) fn completion(
     cursor: lsp_types::TextDocumentPositionParams,
 ) -> Result<Option<Vec<lsp_types::CompletionItem>>> {
     debug!("providers::completion");
+    let started = Instant::now();
 
     let uri = &cursor.text_document.uri.to_file_path().unwrap();
     let line = &cursor.position.line;
@@ -24,22 +51,28 @@ pub(crate) fn completion(
     let tree = snapshot.forest.get(uri).unwrap();
     let doc = snapshot.open_docs.get(uri).unwrap();
     let content = doc.clone().content;
+    // with several journals, only the one including this file is completed from
+    let beancount_data = snapshot.journal_data(uri);
 
-    let start = tree_sitter::Point {
-        row: *line as usize,
-        column: if *char == 0 {
-            *char as usize
-        } else {
-            *char as usize - 1
-        },
-    };
-    let end = tree_sitter::Point {
-        row: *line as usize,
-        column: *char as usize,
+    // the node is looked up from the character before the cursor up to it
+    let end = tree_sitter_point_for_lsp_position(
+        &content,
+        cursor.position,
+        snapshot.config.position_encoding,
+    );
+    let start = if end.column == 0 {
+        end
+    } else {
+        let line = content.line(end.row);
+        let before = line.char_to_byte(line.byte_to_char(end.column) - 1);
+        tree_sitter::Point::new(end.row, before)
     };
     let node = tree
         .root_node()
         .named_descendant_for_point_range(start, end);
+    let line_prefix = content.line(end.row).byte_slice(..end.column).to_string();
+    let header = transaction_header(&line_prefix);
+    debug!("providers::completion - transaction header {:?}", header);
 
     let prev_sibling_node = match node {
         Some(node) => node.prev_sibling(),
@@ -65,24 +98,119 @@ pub(crate) fn completion(
     };
     debug!("providers::completion - parent node {:?}", parent_node);
 
-    if let Some(char) = trigger_character {
+    let native = if trigger_character.is_none() && templates::is_new_file(&content, end.row) {
+        let year = chrono::Local::now().year();
+        Ok(Some(templates::completion_items(
+            templates::templates(&snapshot.config, year),
+            &content,
+            end.row,
+            end.column,
+            snapshot.config.position_encoding,
+        )))
+    } else if let Some(char) = trigger_character {
         debug!(
             "providers::completion - handle trigger_character {:?}",
             trigger_character
         );
         match char {
-            '2' => complete_date(),
-            '"' => {
-                if prev_sibling_node.is_some() && prev_sibling_node.unwrap().kind() == "txn" {
-                    complete_narration(snapshot.beancount_data)
-                } else {
-                    Ok(None)
+            '2' => match partial_date(&line_prefix) {
+                Some(partial) => complete_partial_date(&partial, cursor.position),
+                None => complete_date(),
+            },
+            '"' => match header {
+                Some(header) => complete_transaction_header(
+                    &beancount_data,
+                    &header,
+                    &content,
+                    &line_prefix,
+                    cursor.position,
+                    snapshot.config.position_encoding,
+                ),
+                None => {
+                    if let Some(typed) = include_path(&line_prefix) {
+                        complete_include(&snapshot, uri, typed, cursor.position)
+                    } else if let Some(slot) = directive_string(&line_prefix) {
+                        complete_directive_string(&beancount_data, slot)
+                    } else if let Some(slot) =
+                        custom_slot(&line_prefix, &snapshot.config.custom_directives)
+                    {
+                        complete_custom(
+                            &beancount_data,
+                            slot,
+                            &snapshot.config.custom_directives,
+                            &snapshot.config.completion.hidden_accounts(),
+                        )
+                    } else {
+                        Ok(None)
+                    }
                 }
-            }
-            '#' => complete_tag(snapshot.beancount_data),
-            '^' => complete_link(snapshot.beancount_data),
+            },
+            '#' => complete_tag(&beancount_data),
+            '^' => complete_link(&beancount_data),
+            '@' => match node.and_then(enclosing_posting) {
+                Some(posting) => {
+                    let currency = posting_currency(posting, &content);
+                    complete_price_currency(&beancount_data, currency.as_deref())
+                }
+                None => Ok(None),
+            },
+            '/' => match (include_path(&line_prefix), partial_date(&line_prefix)) {
+                (Some(typed), _) => complete_include(&snapshot, uri, typed, cursor.position),
+                (None, Some(partial)) => complete_partial_date(&partial, cursor.position),
+                (None, None) => Ok(None),
+            },
+            '{' => match node.and_then(enclosing_posting) {
+                Some(posting) => {
+                    let currency = posting_currency(posting, &content);
+                    let format = snapshot.config.number_format.for_content(&content);
+                    match complete_lot(&beancount_data, uri, posting, &content, format) {
+                        Some(lots) => Ok(Some(lots)),
+                        None => complete_cost(&beancount_data, currency.as_deref()),
+                    }
+                }
+                None => Ok(None),
+            },
             _ => Ok(None),
         }
+    } else if let Some(header) = header {
+        complete_transaction_header(
+            &beancount_data,
+            &header,
+            &content,
+            &line_prefix,
+            cursor.position,
+            snapshot.config.position_encoding,
+        )
+    } else if let Some(slot) = directive_string(&line_prefix) {
+        complete_directive_string(&beancount_data, slot)
+    } else if let Some(slot) = price_slot(&line_prefix) {
+        complete_price(&beancount_data, slot)
+    } else if let Some(slot) = balance_slot(&line_prefix) {
+        complete_balance(
+            &beancount_data,
+            slot,
+            &snapshot.config.completion.hidden_accounts(),
+        )
+    } else if let Some(date) = close_slot(&line_prefix) {
+        complete_close(
+            &beancount_data,
+            date,
+            cursor.position.line,
+            &snapshot.config.completion.hidden_accounts(),
+        )
+    } else if let Some(typed) = include_path(&line_prefix) {
+        complete_include(&snapshot, uri, typed, cursor.position)
+    } else if let Some(partial) = partial_date(&line_prefix) {
+        complete_partial_date(&partial, cursor.position)
+    } else if let Some(slot) = custom_slot(&line_prefix, &snapshot.config.custom_directives)
+        .filter(|slot| !is_typed_account(slot, &snapshot.config.custom_directives))
+    {
+        complete_custom(
+            &beancount_data,
+            slot,
+            &snapshot.config.custom_directives,
+            &snapshot.config.completion.hidden_accounts(),
+        )
     } else {
         debug!("providers::completion - handle node {:?}", node);
         match node {
@@ -122,18 +250,37 @@ pub(crate) fn completion(
                         } else {
                             // if parent_parent_node.is_some() && parent_parent_node.unwrap().kind() ==
                             // "posting_or_kv_list" {
-                            complete_account(snapshot.beancount_data)
+                            let context_account = first_posting_account(node, &content);
+                            complete_account(
+                                &beancount_data,
+                                context_account.as_deref(),
+                                &snapshot.config.completion.hidden_accounts(),
+                            )
                             //} else {
                             //    Ok(None)
                         }
                     }
+                    "account" => {
+                        debug!("providers::completion - handle node - handle account");
+                        complete_account_segment(
+                            &beancount_data,
+                            &content,
+                            node,
+                            end,
+                            snapshot.config.position_encoding,
+                            &snapshot.config.completion.hidden_accounts(),
+                        )
+                    }
                     "narration" => {
                         debug!("providers::completion - handle node - handle narration");
-                        complete_narration(snapshot.beancount_data)
+                        let payee = parent_node
+                            .and_then(|parent| parent.child_by_field_name("payee"))
+                            .map(|payee| text_for_tree_sitter_node(&content, &payee));
+                        complete_narration(&beancount_data, payee.as_deref().map(str::trim))
                     }
                     "payee" => {
                         debug!("providers::completion - handle node - handle payee");
-                        complete_narration(snapshot.beancount_data)
+                        complete_narration(&beancount_data, None)
                     }
                     _ => Ok(None),
                 }
@@ -141,6 +288,60 @@ pub(crate) fn completion(
             }
             None => Ok(None),
         }
+    };
+
+    let Some(provider) = snapshot.config.completion.external_provider.as_ref() else {
+        return native;
+    };
+    if over_budget(&snapshot.config.completion, started.elapsed()) {
+        debug!("providers::completion - over the time budget, skipping the external provider");
+        return native;
+    }
+    let context = CompletionContext {
+        uri: cursor.text_document.uri.clone(),
+        position: cursor.position,
+        line_prefix,
+        node_kind: node.map(|node| node.kind().to_string()),
+        trigger_character,
+    };
+    let external = external_completion(provider, &context);
+    if external.is_empty() {
+        return native;
+    }
+    let mut items = native?.unwrap_or_default();
+    items.extend(external);
+    Ok(Some(items))
+}
+
+/// Whether completion took longer than `completion.timeBudgetMs`.
+fn over_budget(options: &CompletionOptions, elapsed: Duration) -> bool {
+    options
+        .time_budget_ms
+        .is_some_and(|budget| elapsed > Duration::from_millis(budget))
+}
+
+/// Applies `completion.maxItems` and `completion.timeBudgetMs` to the items of
+/// a completion that took `elapsed`. Over either, the list is marked
+/// incomplete, keeping the items the client would list first, so that the
+/// client asks again as the user types instead of filtering what it has.
+pub(crate) fn apply_budget(
+    mut items: Vec<lsp_types::CompletionItem>,
+    options: &CompletionOptions,
+    elapsed: Duration,
+) -> lsp_types::CompletionList {
+    let truncated = options.max_items.is_some_and(|max| items.len() > max);
+    if let Some(max) = options.max_items.filter(|_| truncated) {
+        // clients order items by their sort text, falling back to the label
+        items.sort_by(|a, b| {
+            let a_key = a.sort_text.as_deref().unwrap_or(&a.label);
+            let b_key = b.sort_text.as_deref().unwrap_or(&b.label);
+            a_key.cmp(b_key)
+        });
+        items.truncate(max);
+    }
+    lsp_types::CompletionList {
+        is_incomplete: truncated || over_budget(options, elapsed),
+        items,
     }
 }
 
@@ -184,6 +385,206 @@ fn complete_date() -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
     Ok(Some(items))
 }
 
+/// The path typed so far in the string of an `include` directive.
+fn include_path(line_prefix: &str) -> Option<&str> {
+    let include = regex::Regex::new(r#"^include\s+"([^"]*)$"#).unwrap();
+    let caps = include.captures(line_prefix)?;
+    Some(caps.get(1)?.as_str())
+}
+
+/// Completes the files and directories in the directory of the path `typed`
+/// in an `include` string, relative to `file`, except `file` itself. With
+/// nothing typed, those next to the journal root are offered too when it is
+/// in a directory above. Each replaces the whole typed path.
+fn complete_include(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    typed: &str,
+    position: lsp_types::Position,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::include {}", typed);
+    let Some(dir) = file.parent() else {
+        return Ok(None);
+    };
+    // the directory part of the typed path, with its `/`
+    let typed_dir = &typed[..typed.rfind('/').map_or(0, |index| index + 1)];
+    let mut prefixes = vec![typed_dir.to_string()];
+    if typed.is_empty() {
+        let root_dir = snapshot
+            .journal_for(file)
+            .and_then(|journal| journal.root.parent().map(Path::to_path_buf));
+        if let Some(depth) = root_dir
+            .and_then(|root_dir| {
+                dir.strip_prefix(root_dir)
+                    .ok()
+                    .map(|sub| sub.iter().count())
+            })
+            .filter(|depth| *depth > 0)
+        {
+            prefixes.push("../".repeat(depth));
+        }
+    }
+
+    // the length of the typed path in the columns of the client
+    let typed_len = match snapshot.config.position_encoding {
+        PositionEncoding::Utf8 => typed.len(),
+        PositionEncoding::Utf16 => typed.encode_utf16().count(),
+        PositionEncoding::Utf32 => typed.chars().count(),
+    } as u32;
+    let range = lsp_types::Range::new(
+        lsp_types::Position::new(position.line, position.character - typed_len),
+        position,
+    );
+    let mut items = Vec::new();
+    for prefix in prefixes {
+        let Ok(entries) = std::fs::read_dir(dir.join(&prefix)) else {
+            continue;
+        };
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            let Some(name) = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+            else {
+                continue;
+            };
+            if name.starts_with('.') || path == file {
+                continue;
+            }
+            let (text, kind, rank) = if path.is_dir() {
+                (
+                    format!("{prefix}{name}/"),
+                    lsp_types::CompletionItemKind::FOLDER,
+                    0,
+                )
+            } else {
+                (
+                    format!("{prefix}{name}"),
+                    lsp_types::CompletionItemKind::FILE,
+                    1,
+                )
+            };
+            items.push(lsp_types::CompletionItem {
+                label: text.clone(),
+                kind: Some(kind),
+                detail: Some(String::from("Beancount Include")),
+                sort_text: Some(format!("{rank}{text}")),
+                filter_text: Some(text.clone()),
+                text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                    range,
+                    new_text: text,
+                })),
+                ..Default::default()
+            });
+        }
+    }
+    items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    Ok(Some(items))
+}
+
+/// A date being typed at the start of a line, with its year and separator
+/// typed and its month or day not yet complete.
+#[derive(Debug, PartialEq, Eq)]
+struct PartialDate {
+    year: i32,
+    separator: char,
+    /// The complete month, once its day is being typed.
+    month: Option<u32>,
+    /// The digits typed of the month or day being completed.
+    typed: String,
+}
+
+fn partial_date(line_prefix: &str) -> Option<PartialDate> {
+    let partial =
+        regex::Regex::new(r"^(\d{4})([-/])(?:(\d{0,2})|(\d{2})([-/])(\d{0,2}))$").unwrap();
+    let caps = partial.captures(line_prefix)?;
+    let year = caps[1].parse().ok()?;
+    let separator = caps[2].chars().next()?;
+    if let Some(typed) = caps.get(3) {
+        return Some(PartialDate {
+            year,
+            separator,
+            month: None,
+            typed: typed.as_str().to_string(),
+        });
+    }
+    // the separators of a date are the same
+    if caps[5] != caps[2] {
+        return None;
+    }
+    let month = caps[4]
+        .parse()
+        .ok()
+        .filter(|month| (1..=12).contains(month))?;
+    Some(PartialDate {
+        year,
+        separator,
+        month: Some(month),
+        typed: caps[6].to_string(),
+    })
+}
+
+/// Completes the month of a partial date, then the days of its month,
+/// replacing what is typed of the date.
+fn complete_partial_date(
+    partial: &PartialDate,
+    position: lsp_types::Position,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::partial_date {:?}", partial);
+    let PartialDate {
+        year,
+        separator,
+        month,
+        typed,
+    } = partial;
+    let dates: Vec<(String, String)> = match month {
+        None => (1..=12)
+            .filter_map(|month| chrono::NaiveDate::from_ymd_opt(*year, month, 1))
+            .map(|first| {
+                (
+                    format!("{year}{separator}{:02}{separator}", first.month()),
+                    first.format("%B %Y").to_string(),
+                )
+            })
+            .collect(),
+        Some(month) => (1..=31)
+            .filter_map(|day| chrono::NaiveDate::from_ymd_opt(*year, *month, day))
+            .map(|date| {
+                (
+                    format!("{year}{separator}{month:02}{separator}{:02}", date.day()),
+                    date.format("%A").to_string(),
+                )
+            })
+            .collect(),
+    };
+    // the date starts the line, so the edit starts at its first column
+    let range = lsp_types::Range::new(lsp_types::Position::new(position.line, 0), position);
+    let prefix = format!("{year}{separator}");
+    let items = dates
+        .into_iter()
+        .filter(|(text, _)| match month {
+            None => text[prefix.len()..].starts_with(typed.as_str()),
+            Some(_) => text[prefix.len() + 3..].starts_with(typed.as_str()),
+        })
+        .enumerate()
+        .map(|(index, (text, detail))| lsp_types::CompletionItem {
+            label: text.clone(),
+            detail: Some(detail),
+            kind: Some(lsp_types::CompletionItemKind::TEXT),
+            sort_text: Some(format!("{index:02}")),
+            filter_text: Some(text.clone()),
+            text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range,
+                new_text: text,
+            })),
+            ..Default::default()
+        })
+        .collect();
+    Ok(Some(items))
+}
+
 fn complete_kind() -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
     debug!("providers::completion::kind");
     let items = vec![
@@ -235,13 +636,496 @@ pub fn sub_one_month(date: chrono::NaiveDate) -> chrono::NaiveDate {
     chrono::NaiveDate::from_ymd_opt(year, month, 1).expect("valid date")
 }
 
+/// Where the cursor is in the header of a transaction, like in
+/// `2024-01-01 txn "Payee" |`.
+#[derive(Debug, PartialEq)]
+struct TransactionHeader {
+    /// The byte range of the flag, or of `txn`, in the line.
+    flag: std::ops::Range<usize>,
+    /// The string the cursor is at.
+    slot: HeaderSlot,
+    /// Whether the cursor is in a string that is still being typed.
+    in_string: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum HeaderSlot {
+    /// The first string, which is the payee if a narration follows it and the
+    /// narration otherwise.
+    Payee,
+    /// The string after `payee`.
+    Narration { payee: String },
+}
+
+/// Analyses the line up to the cursor as the header of a transaction. Returns
+/// `None` when the cursor is not where a payee or narration goes.
+///
+/// The line is read as text rather than from the syntax tree, because the
+/// parser represents the header differently depending on its flag, whether
+/// `txn`, `*`, `!` or a letter like `P`, and a header that is still being
+/// typed is often an `ERROR` node.
+fn transaction_header(line_prefix: &str) -> Option<TransactionHeader> {
+    let header = regex::Regex::new(
+        r#"^\d{4}[-/]\d{2}[-/]\d{2}\s+(txn|[*!&#?%PSTCURM])\s+((?:"(?:[^"\\]|\\.)*"\s+)*)("(?:[^"\\]|\\.)*)?$"#,
+    )
+    .unwrap();
+    let caps = header.captures(line_prefix)?;
+    let string = regex::Regex::new(r#""(?:[^"\\]|\\.)*""#).unwrap();
+    let strings: Vec<&str> = string
+        .find_iter(&caps[2])
+        .map(|string| string.as_str())
+        .collect();
+    let slot = match strings.as_slice() {
+        [] => HeaderSlot::Payee,
+        [payee] => HeaderSlot::Narration {
+            payee: payee.to_string(),
+        },
+        _ => return None,
+    };
+    Some(TransactionHeader {
+        flag: caps.get(1)?.range(),
+        slot,
+        in_string: caps.get(3).is_some(),
+    })
+}
+
+/// Completes the string of a transaction header the cursor is at. The first
+/// string gets payees followed by narrations, as either can go there, and
+/// after `txn` also the flags that can replace it.
+fn complete_transaction_header(
+    data: &HashMap<PathBuf, BeancountData>,
+    header: &TransactionHeader,
+    content: &ropey::Rope,
+    line_prefix: &str,
+    position: lsp_types::Position,
+    encoding: PositionEncoding,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::transaction_header");
+    if let HeaderSlot::Narration { payee } = &header.slot {
+        return complete_narration(data, Some(payee.as_str()));
+    }
+
+    // the forms that differ only in case and spacing are offered once
+    let mut payees: Vec<String> =
+        canonical_descriptions(data.values().flat_map(|data| data.get_payee_counts()))
+            .into_values()
+            .collect();
+    payees.sort();
+    let mut narrations: Vec<String> =
+        canonical_descriptions(data.values().flat_map(|data| data.get_narration_counts()))
+            .into_values()
+            .collect();
+    narrations.sort();
+
+    let mut completions = Vec::new();
+    for payee in payees {
+        completions.push(lsp_types::CompletionItem {
+            sort_text: Some(format!("0{payee}")),
+            label: payee,
+            detail: Some("Beancount Payee".to_string()),
+            kind: Some(lsp_types::CompletionItemKind::TEXT),
+            ..Default::default()
+        });
+    }
+    for narration in narrations {
+        completions.push(lsp_types::CompletionItem {
+            sort_text: Some(format!("1{narration}")),
+            label: narration,
+            detail: Some("Beancount Narration".to_string()),
+            kind: Some(lsp_types::CompletionItemKind::TEXT),
+            ..Default::default()
+        });
+    }
+
+    // the edit has to reach the cursor, so it replaces `txn ` up to it
+    let replaced = &line_prefix[header.flag.start..];
+    if &line_prefix[header.flag.clone()] == "txn" && !header.in_string {
+        let line_start = content.line_to_byte(position.line as usize);
+        let range = lsp_types::Range::new(
+            byte_to_lsp_position(content, line_start + header.flag.start, encoding),
+            position,
+        );
+        for (flag, detail) in [("*", "complete"), ("!", "incomplete")] {
+            completions.push(lsp_types::CompletionItem {
+                label: flag.to_string(),
+                detail: Some(format!("Beancount Flag ({detail})")),
+                kind: Some(lsp_types::CompletionItemKind::TEXT),
+                sort_text: Some(format!("2{flag}")),
+                filter_text: Some(replaced.to_string()),
+                text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                    range,
+                    new_text: format!("{flag}{}", &line_prefix[header.flag.end..]),
+                })),
+                ..Default::default()
+            });
+        }
+    }
+    Ok(Some(completions))
+}
+
+/// The part of a `balance` directive the cursor is at.
+#[derive(Debug, PartialEq)]
+enum BalanceSlot {
+    Account,
+    Amount,
+    Currency,
+}
+
+/// Analyses the line up to the cursor as a `balance` directive, from the
+/// parts typed before the cursor. An account that is being typed is left to
+/// the completion of the account node, which completes it segment by segment.
+fn balance_slot(line_prefix: &str) -> Option<BalanceSlot> {
+    let balance = regex::Regex::new(r"^\d{4}[-/]\d{2}[-/]\d{2}\s+balance\s+(.*)$").unwrap();
+    let caps = balance.captures(line_prefix)?;
+    let rest = caps.get(1)?.as_str();
+    let mut parts: Vec<&str> = rest.split_whitespace().collect();
+    // the part at the cursor is still being typed
+    let typing = !rest.is_empty() && !rest.ends_with(char::is_whitespace);
+    if typing {
+        parts.pop();
+    }
+    match parts.as_slice() {
+        [] if typing => None,
+        [] => Some(BalanceSlot::Account),
+        [_account] => Some(BalanceSlot::Amount),
+        [_account, _number] | [_account, _number, "~", _] => Some(BalanceSlot::Currency),
+        _ => None,
+    }
+}
+
+/// Completes the part of a `balance` directive the cursor is at. Nothing is
+/// offered for the amount.
+fn complete_balance(
+    data: &HashMap<PathBuf, BeancountData>,
+    slot: BalanceSlot,
+    hidden: &HiddenAccounts,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::balance {:?}", slot);
+    match slot {
+        BalanceSlot::Account => complete_account(data, None, hidden),
+        BalanceSlot::Amount => Ok(None),
+        BalanceSlot::Currency => Ok(Some(currency_items(
+            commodities(data, None),
+            "Beancount Currency",
+        ))),
+    }
+}
+
+/// The date of a `close` directive whose account is not typed yet. An
+/// account that is being typed is left to the completion of the account node.
+fn close_slot(line_prefix: &str) -> Option<chrono::NaiveDate> {
+    let close = regex::Regex::new(r"^(\d{4}[-/]\d{2}[-/]\d{2})\s+close\s+$").unwrap();
+    let caps = close.captures(line_prefix)?;
+    parse_date(&caps[1])
+}
+
+/// Completes the accounts that are still open in a `close` directive on
+/// `date` on the line `line`. Each suggests the day after its last posting
+/// as the close date, and moves the date there if `date` is earlier.
+fn complete_close(
+    data: &HashMap<PathBuf, BeancountData>,
+    date: chrono::NaiveDate,
+    line: u32,
+    hidden: &HiddenAccounts,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::close {}", date);
+    let closed: Vec<&str> = data
+        .values()
+        .flat_map(|data| data.get_closes())
+        .map(|close| close.account.as_str())
+        .collect();
+    let last_postings = lint::last_posting_dates(data);
+    let mut completions = Vec::new();
+    for account in data.values().flat_map(|data| data.get_accounts()) {
+        if hidden.contains(&account) || closed.contains(&account.as_str()) {
+            continue;
+        }
+        let mut item = account_item(data, account.clone());
+        if let Some(close_date) = lint::suggested_close_date(&last_postings, &account) {
+            let text = close_date.format("%Y-%m-%d").to_string();
+            item.detail = Some(format!("Last posting on {}", last_postings[&account]));
+            item.label_details = Some(lsp_types::CompletionItemLabelDetails {
+                detail: None,
+                description: Some(format!("close on {text}")),
+            });
+            // the date is ASCII, so its columns are the same in every encoding
+            if date < close_date {
+                item.additional_text_edits = Some(vec![lsp_types::TextEdit {
+                    range: lsp_types::Range::new(
+                        lsp_types::Position::new(line, 0),
+                        lsp_types::Position::new(line, 10),
+                    ),
+                    new_text: text,
+                }]);
+            }
+        }
+        completions.push(item);
+    }
+    Ok(Some(completions))
+}
+
+/// Whether an account argument of a `custom` directive is being typed, which
+/// is left to the completion of the account node like in `balance` directives.
+fn is_typed_account(slot: &CustomSlot, schemas: &BTreeMap<String, CustomDirectiveSchema>) -> bool {
+    matches!(slot, CustomSlot::Argument { typing: true, .. })
+        && slot
+            .argument(schemas)
+            .is_some_and(|argument| argument.kind == CustomArgumentType::Account)
+}
+
+/// Completes the part of a `custom` directive the cursor is at from the
+/// schemas: the names of the directives, and the arguments by their type.
+/// Strings are completed with their allowed values or, without any, with the
+/// ones used before in the same argument.
+fn complete_custom(
+    data: &HashMap<PathBuf, BeancountData>,
+    slot: CustomSlot,
+    schemas: &BTreeMap<String, CustomDirectiveSchema>,
+    hidden: &HiddenAccounts,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::custom {:?}", slot);
+    let item = |label: String, detail: String| lsp_types::CompletionItem {
+        label,
+        detail: Some(detail),
+        kind: Some(lsp_types::CompletionItemKind::TEXT),
+        ..Default::default()
+    };
+    let (name, index, currency) = match &slot {
+        CustomSlot::Name => {
+            let mut names: Vec<&String> = schemas
+                .keys()
+                .chain(
+                    data.values()
+                        .flat_map(|data| data.get_customs().iter().map(|custom| &custom.name)),
+                )
+                .collect();
+            names.sort();
+            names.dedup();
+            let completions = names
+                .into_iter()
+                .map(|name| lsp_types::CompletionItem {
+                    documentation: schemas
+                        .get(name)
+                        .and_then(|schema| schema.description.clone())
+                        .map(lsp_types::Documentation::String),
+                    ..item(format!("\"{name}\""), String::from("Beancount Custom"))
+                })
+                .collect();
+            return Ok(Some(completions));
+        }
+        CustomSlot::Argument {
+            name,
+            index,
+            currency,
+            ..
+        } => (name, *index, *currency),
+    };
+    let Some(argument) = slot.argument(schemas) else {
+        return Ok(None);
+    };
+    let detail = format!("Beancount Custom ({name} {})", argument.name);
+    let labels: Vec<String> = match argument.kind {
+        CustomArgumentType::Amount if currency => commodities(data, None),
+        CustomArgumentType::Account => return complete_account(data, None, hidden),
+        CustomArgumentType::String if !argument.values.is_empty() => argument
+            .values
+            .iter()
+            .map(|value| format!("\"{value}\""))
+            .collect(),
+        CustomArgumentType::String => {
+            let mut used: Vec<String> = data
+                .values()
+                .flat_map(|data| data.get_customs())
+                .filter(|custom| custom.name == *name)
+                .filter_map(|custom| custom.values.get(index))
+                .filter(|value| value.kind == "string")
+                .map(|value| value.text.clone())
+                .collect();
+            used.sort();
+            used.dedup();
+            used
+        }
+        CustomArgumentType::Bool => vec![String::from("TRUE"), String::from("FALSE")],
+        _ => return Ok(None),
+    };
+    let completions = labels
+        .into_iter()
+        .enumerate()
+        .map(|(index, label)| lsp_types::CompletionItem {
+            sort_text: Some(format!("{index:04}")),
+            ..item(label, detail.clone())
+        })
+        .collect();
+    Ok(Some(completions))
+}
+
+/// The string of an `event` or `note` directive the cursor is at.
+#[derive(Debug, PartialEq)]
+enum DirectiveString {
+    EventType,
+    EventValue { event_type: String },
+    Note { account: String },
+}
+
+/// Analyses the line up to the cursor as an `event` or `note` directive.
+fn directive_string(line_prefix: &str) -> Option<DirectiveString> {
+    let directive = regex::Regex::new(
+        r#"^\d{4}[-/]\d{2}[-/]\d{2}\s+(event|note\s+(\S+))\s+((?:"(?:[^"\\]|\\.)*"\s+)*)(?:"(?:[^"\\]|\\.)*)?$"#,
+    )
+    .unwrap();
+    let caps = directive.captures(line_prefix)?;
+    let string = regex::Regex::new(r#""(?:[^"\\]|\\.)*""#).unwrap();
+    let strings: Vec<&str> = string
+        .find_iter(&caps[3])
+        .map(|string| string.as_str())
+        .collect();
+    match (caps.get(2), strings.as_slice()) {
+        (None, []) => Some(DirectiveString::EventType),
+        (None, [event_type]) => Some(DirectiveString::EventValue {
+            event_type: event_type.to_string(),
+        }),
+        (Some(account), []) => Some(DirectiveString::Note {
+            account: account.as_str().to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Completes the strings of `event` and `note` directives from the ones used
+/// before: the event types, the values of the same event type, and the notes
+/// with those about the same account first.
+fn complete_directive_string(
+    data: &HashMap<PathBuf, BeancountData>,
+    slot: DirectiveString,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::directive_string {:?}", slot);
+    // the string, its detail and its rank
+    let mut strings: Vec<(String, String, usize)> = match &slot {
+        DirectiveString::EventType => data
+            .values()
+            .flat_map(|data| data.get_event_types())
+            .map(|event_type| (event_type, String::from("Beancount Event"), 0))
+            .collect(),
+        DirectiveString::EventValue { event_type } => data
+            .values()
+            .flat_map(|data| data.get_event_values(event_type))
+            .map(|value| (value, format!("Beancount Event ({event_type})"), 0))
+            .collect(),
+        DirectiveString::Note { account } => data
+            .values()
+            .flat_map(|data| data.get_notes().iter())
+            .flat_map(|(note_account, notes)| {
+                notes.iter().map(move |note| {
+                    if note_account == account {
+                        (note.clone(), format!("Beancount Note ({account})"), 0)
+                    } else {
+                        (note.clone(), String::from("Beancount Note"), 1)
+                    }
+                })
+            })
+            .collect(),
+    };
+    // a note of this account wins over the same note of another one
+    strings.sort_by(|(a, _, a_rank), (b, _, b_rank)| a.cmp(b).then(a_rank.cmp(b_rank)));
+    strings.dedup_by(|(a, _, _), (b, _, _)| a == b);
+
+    let completions = strings
+        .into_iter()
+        .map(|(label, detail, rank)| lsp_types::CompletionItem {
+            sort_text: Some(format!("{rank}{label}")),
+            label,
+            detail: Some(detail),
+            kind: Some(lsp_types::CompletionItemKind::TEXT),
+            ..Default::default()
+        })
+        .collect();
+    Ok(Some(completions))
+}
+
+/// The part of a `price` directive the cursor is at.
+#[derive(Debug, PartialEq)]
+enum PriceSlot {
+    Commodity,
+    Amount,
+    /// The currency the price of `commodity` is quoted in.
+    Quote {
+        commodity: String,
+    },
+}
+
+/// Analyses the line up to the cursor as a `price` directive.
+fn price_slot(line_prefix: &str) -> Option<PriceSlot> {
+    let price = regex::Regex::new(r"^\d{4}[-/]\d{2}[-/]\d{2}\s+price\s+(.*)$").unwrap();
+    let caps = price.captures(line_prefix)?;
+    let rest = caps.get(1)?.as_str();
+    let mut parts: Vec<&str> = rest.split_whitespace().collect();
+    if !rest.is_empty() && !rest.ends_with(char::is_whitespace) {
+        parts.pop();
+    }
+    match parts.as_slice() {
+        [] => Some(PriceSlot::Commodity),
+        [_commodity] => Some(PriceSlot::Amount),
+        [commodity, _number] => Some(PriceSlot::Quote {
+            commodity: commodity.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Completes the part of a `price` directive the cursor is at. Commodities
+/// held in some account come first for the priced commodity, and the
+/// operating currencies first for the currency it is quoted in.
+fn complete_price(
+    data: &HashMap<PathBuf, BeancountData>,
+    slot: PriceSlot,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::price {:?}", slot);
+    let operating_currencies = operating_currencies(data);
+    let (preferred, exclude, detail): (Vec<String>, _, _) = match &slot {
+        PriceSlot::Commodity => {
+            let balances = ledger::balances(data.values().flat_map(|data| data.get_postings()));
+            let held = balances
+                .values()
+                .flat_map(|inventory| inventory.amounts())
+                .filter(|amount| !amount.number.is_negative())
+                .map(|amount| amount.currency)
+                .filter(|currency| !operating_currencies.contains(currency))
+                .collect();
+            (held, None, "Beancount Commodity")
+        }
+        PriceSlot::Amount => return Ok(None),
+        PriceSlot::Quote { commodity } => (
+            operating_currencies.clone(),
+            Some(commodity.as_str()),
+            "Beancount Price Currency",
+        ),
+    };
+    let mut completions = Vec::new();
+    for (index, currency) in commodities(data, exclude).into_iter().enumerate() {
+        let rank = if preferred.contains(&currency) { 0 } else { 1 };
+        completions.push(lsp_types::CompletionItem {
+            sort_text: Some(format!("{rank}{index:04}")),
+            label: currency,
+            detail: Some(detail.to_string()),
+            kind: Some(lsp_types::CompletionItemKind::UNIT),
+            ..Default::default()
+        });
+    }
+    Ok(Some(completions))
+}
+
+/// Completes narrations. When the transaction already has a payee, narrations
+/// previously used with that payee are listed first, followed by all others.
 fn complete_narration(
-    data: HashMap<PathBuf, BeancountData>,
+    data: &HashMap<PathBuf, BeancountData>,
+    payee: Option<&str>,
 ) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
     debug!("providers::completion::narration");
     let mut completions = Vec::new();
-    for data in data.values() {
-        for txn_string in data.get_narration() {
+    let canonical =
+        canonical_descriptions(data.values().flat_map(|data| data.get_narration_counts()));
+    let Some(payee) = payee else {
+        for txn_string in canonical.into_values() {
             completions.push(lsp_types::CompletionItem {
                 label: txn_string,
                 detail: Some("Beancount Narration".to_string()),
@@ -249,30 +1133,382 @@ fn complete_narration(
                 ..Default::default()
             });
         }
+        return Ok(Some(completions));
+    };
+
+    debug!("providers::completion::narration - payee {}", payee);
+    let mut preferred = Vec::new();
+    for data in data.values() {
+        for txn_string in data.get_payee_narrations(payee) {
+            let Some(txn_string) = canonical.get(&description_key(&txn_string)) else {
+                continue;
+            };
+            if !preferred.contains(txn_string) {
+                preferred.push(txn_string.clone());
+            }
+        }
+    }
+    let mut fallback: Vec<String> = canonical
+        .into_values()
+        .filter(|txn_string| !preferred.contains(txn_string))
+        .collect();
+    fallback.sort();
+    for txn_string in preferred {
+        completions.push(lsp_types::CompletionItem {
+            sort_text: Some(format!("0{txn_string}")),
+            label: txn_string,
+            detail: Some(format!("Beancount Narration ({payee})")),
+            kind: Some(lsp_types::CompletionItemKind::TEXT),
+            ..Default::default()
+        });
+    }
+    for txn_string in fallback {
+        completions.push(lsp_types::CompletionItem {
+            sort_text: Some(format!("1{txn_string}")),
+            label: txn_string,
+            detail: Some("Beancount Narration".to_string()),
+            kind: Some(lsp_types::CompletionItemKind::TEXT),
+            ..Default::default()
+        });
     }
     Ok(Some(completions))
 }
 
+/// Completes accounts. When the account of the first posting of the
+/// transaction is known, accounts are ranked by how often they were used
+/// together with it.
 fn complete_account(
-    data: HashMap<PathBuf, BeancountData>,
+    data: &HashMap<PathBuf, BeancountData>,
+    context_account: Option<&str>,
+    hidden: &HiddenAccounts,
 ) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
     debug!("providers::completion::account");
     let mut completions = Vec::new();
-    for data in data.values() {
-        for account in data.get_accounts() {
-            completions.push(lsp_types::CompletionItem {
-                label: account,
-                detail: Some("Beancount Account".to_string()),
-                kind: Some(lsp_types::CompletionItemKind::TEXT),
-                ..Default::default()
-            });
+    let Some(context_account) = context_account else {
+        for file_data in data.values() {
+            for account in file_data.get_accounts() {
+                if hidden.contains(&account) {
+                    continue;
+                }
+                completions.push(account_item(data, account));
+            }
+        }
+        return Ok(Some(completions));
+    };
+
+    debug!(
+        "providers::completion::account - context {}",
+        context_account
+    );
+    let mut accounts: Vec<(String, usize)> = Vec::new();
+    for account in data.values().flat_map(|data| data.get_accounts()) {
+        if hidden.contains(&account) || accounts.iter().any(|(known, _)| *known == account) {
+            continue;
+        }
+        let count = data
+            .values()
+            .map(|data| data.get_account_pair_count(context_account, &account))
+            .sum();
+        accounts.push((account, count));
+    }
+    accounts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+    for (rank, (account, _)) in accounts.into_iter().enumerate() {
+        completions.push(lsp_types::CompletionItem {
+            sort_text: Some(format!("{rank:05}")),
+            ..account_item(data, account)
+        });
+    }
+    Ok(Some(completions))
+}
+
+/// The completion item for an account. The `description:` and `alias:`
+/// metadata of its `open` directive become the documentation, and the alias
+/// can be typed to find the account.
+fn account_item(
+    data: &HashMap<PathBuf, BeancountData>,
+    account: String,
+) -> lsp_types::CompletionItem {
+    let details = data
+        .values()
+        .find_map(|data| data.get_account_details(&account));
+    let documentation = details.map(|details| {
+        let mut lines = vec![];
+        lines.extend(details.description.clone());
+        lines.extend(details.alias.iter().map(|alias| format!("Alias: {alias}")));
+        lsp_types::Documentation::String(lines.join("\n\n"))
+    });
+    let filter_text = details
+        .and_then(|details| details.alias.as_ref())
+        .map(|alias| format!("{account} {alias}"));
+    lsp_types::CompletionItem {
+        label: account,
+        detail: Some("Beancount Account".to_string()),
+        kind: Some(lsp_types::CompletionItemKind::TEXT),
+        documentation,
+        filter_text,
+        ..Default::default()
+    }
+}
+
+/// Completes the segment of `account` the cursor is in, like `Fo` in
+/// `Expenses:Fo|od:Groceries`, with the segments known under the same parent.
+/// The edit replaces only that segment, keeping the ones around it.
+fn complete_account_segment(
+    data: &HashMap<PathBuf, BeancountData>,
+    content: &ropey::Rope,
+    account: tree_sitter::Node,
+    cursor: tree_sitter::Point,
+    encoding: PositionEncoding,
+    hidden: &HiddenAccounts,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::account_segment");
+    let text = text_for_tree_sitter_node(content, &account);
+    let cursor_byte = content.line_to_byte(cursor.row) + cursor.column;
+    let offset = cursor_byte
+        .saturating_sub(account.start_byte())
+        .min(text.len());
+    let segment_start = text[..offset].rfind(':').map_or(0, |colon| colon + 1);
+    let segment_end = text[offset..]
+        .find(':')
+        .map_or(text.len(), |colon| offset + colon);
+    let parent = &text[..segment_start];
+
+    let range = lsp_types::Range {
+        start: byte_to_lsp_position(content, account.start_byte() + segment_start, encoding),
+        end: byte_to_lsp_position(content, account.start_byte() + segment_end, encoding),
+    };
+    let mut segments: Vec<String> = data
+        .values()
+        .flat_map(|data| data.get_accounts())
+        .filter(|account| !hidden.contains(account))
+        .filter_map(|account| {
+            let rest = account.strip_prefix(parent)?;
+            rest.split(':').next().map(str::to_string)
+        })
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    segments.sort();
+    segments.dedup();
+
+    let completions = segments
+        .into_iter()
+        .map(|segment| lsp_types::CompletionItem {
+            label: segment.clone(),
+            detail: Some("Beancount Account".to_string()),
+            kind: Some(lsp_types::CompletionItemKind::TEXT),
+            text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range,
+                new_text: segment,
+            })),
+            ..Default::default()
+        })
+        .collect();
+    Ok(Some(completions))
+}
+
+/// Finds the account of the first posting of the transaction the cursor is in,
+/// if that posting is before the cursor.
+fn first_posting_account(node: tree_sitter::Node, content: &ropey::Rope) -> Option<String> {
+    let mut top = node;
+    while let Some(parent) = top.parent() {
+        if top.kind() == "transaction" || parent.kind() == "file" {
+            break;
         }
+        top = parent;
     }
+    let transaction = match top.kind() {
+        "transaction" => top,
+        // incomplete postings are not part of the transaction yet
+        "ERROR" => top.prev_sibling().filter(|n| n.kind() == "transaction")?,
+        _ => return None,
+    };
+
+    let mut cursor = transaction.walk();
+    let posting = transaction
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "posting")
+        .find(|c| c.end_position() <= node.start_position())?;
+    let account = posting.child_by_field_name("account")?;
+    Some(text_for_tree_sitter_node(content, &account))
+}
+
+/// Completes the currency of a price annotation, skipping the currency of the
+/// posting itself.
+fn complete_price_currency(
+    data: &HashMap<PathBuf, BeancountData>,
+    posting_currency: Option<&str>,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::price_currency");
+    Ok(Some(currency_items(
+        commodities(data, posting_currency),
+        "Beancount Price Currency",
+    )))
+}
+
+/// Completes the components of a cost specification: the cost currency and
+/// the acquisition date.
+fn complete_cost(
+    data: &HashMap<PathBuf, BeancountData>,
+    posting_currency: Option<&str>,
+) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
+    debug!("providers::completion::cost");
+    let mut completions = currency_items(
+        commodities(data, posting_currency),
+        "Beancount Cost Currency",
+    );
+    let today = chrono::offset::Local::now().naive_local().date();
+    completions.push(lsp_types::CompletionItem {
+        label: today.format("%Y-%m-%d").to_string(),
+        detail: Some("Beancount Cost Date".to_string()),
+        kind: Some(lsp_types::CompletionItemKind::TEXT),
+        ..Default::default()
+    });
     Ok(Some(completions))
 }
 
+/// Completes the lots a posting reducing a position can be booked against:
+/// those of its account and commodity held before it, as `cost, date, "label"`
+/// cost components. `None` if the posting does not reduce a position held at
+/// cost.
+fn complete_lot(
+    data: &HashMap<PathBuf, BeancountData>,
+    file: &Path,
+    posting: tree_sitter::Node,
+    content: &ropey::Rope,
+    format: NumberFormat,
+) -> Option<Vec<lsp_types::CompletionItem>> {
+    debug!("providers::completion::lot");
+    let amount = text_for_tree_sitter_node(content, &posting_amount(posting)?);
+    if !amount.trim_start().starts_with('-') {
+        return None;
+    }
+    let mut cursor = posting.walk();
+    let account = posting
+        .children(&mut cursor)
+        .find(|c| c.kind() == "account")
+        .map(|account| text_for_tree_sitter_node(content, &account))?;
+    let currency = posting_currency(posting, content);
+    let line = posting.start_position().row as u32;
+    let date = std::iter::successors(posting.parent(), |node| node.parent())
+        .find(|node| node.kind() == "transaction")
+        .and_then(|transaction| transaction.child_by_field_name("date"))
+        .and_then(|date| parse_date(&text_for_tree_sitter_node(content, &date)));
+
+    let postings = data.iter().flat_map(|(path, data)| {
+        data.get_postings().iter().filter(move |entry| {
+            !(path == file && entry.line == line) && date.is_none_or(|date| entry.date <= date)
+        })
+    });
+    let completions: Vec<lsp_types::CompletionItem> = ledger::lots(postings)
+        .into_iter()
+        .filter(|lot| {
+            lot.account == account
+                && currency
+                    .as_ref()
+                    .is_none_or(|currency| &lot.units.currency == currency)
+                && !lot.units.number.is_negative()
+        })
+        .filter_map(|lot| {
+            let cost = lot.cost?;
+            let mut components = vec![format!("{} {}", format.display(cost.number), cost.currency)];
+            components.extend(lot.date.map(|date| date.format("%Y-%m-%d").to_string()));
+            components.extend(lot.label.map(|label| format!("\"{label}\"")));
+            Some(lsp_types::CompletionItem {
+                label: components.join(", "),
+                detail: Some(format!(
+                    "Beancount Lot: {} {}",
+                    format.display(lot.units.number),
+                    lot.units.currency
+                )),
+                kind: Some(lsp_types::CompletionItemKind::VALUE),
+                ..Default::default()
+            })
+        })
+        .enumerate()
+        .map(|(index, item)| lsp_types::CompletionItem {
+            // oldest lots first, as they are booked
+            sort_text: Some(format!("{index:04}")),
+            ..item
+        })
+        .collect();
+    (!completions.is_empty()).then_some(completions)
+}
+
+/// All commodities used in the ledger, except `exclude`: the operating
+/// currencies first, in the order of their options, then the others by name.
+fn commodities(data: &HashMap<PathBuf, BeancountData>, exclude: Option<&str>) -> Vec<String> {
+    let operating = operating_currencies(data);
+    let mut others: Vec<String> = data
+        .values()
+        .flat_map(|data| data.get_commodities())
+        .filter(|currency| !operating.contains(currency))
+        .collect();
+    others.sort();
+    others.dedup();
+    operating
+        .into_iter()
+        .chain(others)
+        .filter(|currency| Some(currency.as_str()) != exclude)
+        .collect()
+}
+
+/// Completion items for `currencies`, sorted by clients in the given order.
+fn currency_items(currencies: Vec<String>, detail: &str) -> Vec<lsp_types::CompletionItem> {
+    currencies
+        .into_iter()
+        .enumerate()
+        .map(|(index, currency)| lsp_types::CompletionItem {
+            sort_text: Some(format!("{index:04}")),
+            label: currency,
+            detail: Some(detail.to_string()),
+            kind: Some(lsp_types::CompletionItemKind::UNIT),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Finds the posting containing `node`. A posting that is still being typed
+/// might not parse yet, in which case it is an `ERROR` node holding an account.
+pub(crate) fn enclosing_posting(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    let mut node = Some(node);
+    while let Some(current) = node {
+        if current.kind() == "posting" {
+            return Some(current);
+        }
+        if current.kind() == "ERROR" {
+            let mut cursor = current.walk();
+            if current.children(&mut cursor).any(|c| c.kind() == "account") {
+                return Some(current);
+            }
+        }
+        node = current.parent();
+    }
+    None
+}
+
+/// The amount of a posting, which might still be incomplete.
+fn posting_amount(posting: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    let mut cursor = posting.walk();
+    let amount = posting.child_by_field_name("amount").or_else(|| {
+        posting
+            .children(&mut cursor)
+            .find(|c| c.kind() == "incomplete_amount")
+    });
+    amount
+}
+
+fn posting_currency(posting: tree_sitter::Node, content: &ropey::Rope) -> Option<String> {
+    let amount = posting_amount(posting)?;
+    let mut cursor = amount.walk();
+    let currency = amount
+        .children(&mut cursor)
+        .find(|c| c.kind() == "currency")?;
+    Some(text_for_tree_sitter_node(content, &currency))
+}
+
 fn complete_tag(
-    data: HashMap<PathBuf, BeancountData>,
+    data: &HashMap<PathBuf, BeancountData>,
 ) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
     debug!("providers::completion::tag");
     let mut completions = Vec::new();
@@ -290,7 +1526,7 @@ fn complete_tag(
 }
 
 fn complete_link(
-    data: HashMap<PathBuf, BeancountData>,
+    data: &HashMap<PathBuf, BeancountData>,
 ) -> anyhow::Result<Option<Vec<lsp_types::CompletionItem>>> {
     debug!("providers::completion::tag");
     let mut completions = Vec::new();
@@ -309,203 +1545,198 @@ fn complete_link(
 
 #[cfg(test)]
 mod tests {
+    use crate::config::CompletionOptions;
+    use crate::config::ExternalCompletionProvider;
     use crate::providers::completion::add_one_month;
+    use crate::providers::completion::apply_budget;
+    use crate::providers::completion::balance_slot;
     use crate::providers::completion::completion;
+    use crate::providers::completion::partial_date;
     use crate::providers::completion::sub_one_month;
-    use crate::server::LspServerStateSnapshot;
+    use crate::providers::completion::transaction_header;
+    use crate::providers::completion::BalanceSlot;
+    use crate::providers::completion::HeaderSlot;
+    use crate::providers::completion::TransactionHeader;
     //use insta::assert_yaml_snapshot;
-    use crate::beancount_data::BeancountData;
-    use crate::config::Config;
-    use crate::document::Document;
-    use crate::utils::ToFilePath;
-    use anyhow::Result;
-    use std::collections::HashMap;
+    use crate::server::Journal;
+    use crate::test_utils::TestState;
+    use std::collections::HashSet;
     use std::path::PathBuf;
-    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
     use test_log::test;
 
-    #[derive(Debug)]
-    pub struct Fixture {
-        pub documents: Vec<TestDocument>,
-    }
-    impl Fixture {
-        pub fn parse(input: &str) -> Self {
-            let mut documents = Vec::new();
-            let mut start = 0;
-            if !input.is_empty() {
-                for end in input
-                    .match_indices("%!")
-                    .skip(1)
-                    .map(|(i, _)| i)
-                    .chain(std::iter::once(input.len()))
-                {
-                    documents.push(TestDocument::parse(&input[start..end]));
-                    start = end;
-                }
-            }
-            Self { documents }
-        }
+    #[test]
+    fn handle_sub_one_month() {
+        let input_date = chrono::NaiveDate::from_ymd_opt(2022, 6, 1).expect("valid date");
+        let expected_date = chrono::NaiveDate::from_ymd_opt(2022, 5, 1).expect("valid date");
+        assert_eq!(sub_one_month(input_date), expected_date)
     }
 
-    #[derive(Debug)]
-    pub struct TestDocument {
-        pub path: String,
-        pub text: String,
-        pub cursor: Option<lsp_types::Position>,
-        // pub ranges: Vec<lsp_types::Range>,
+    #[test]
+    fn handle_sub_one_month_in_jan() {
+        let input_date = chrono::NaiveDate::from_ymd_opt(2022, 1, 1).expect("valid date");
+        let expected_date = chrono::NaiveDate::from_ymd_opt(2021, 12, 1).expect("valid date");
+        assert_eq!(sub_one_month(input_date), expected_date)
     }
-    impl TestDocument {
-        pub fn parse(input: &str) -> Self {
-            let mut lines = Vec::new();
-
-            let (path, input) = input
-                .trim()
-                .strip_prefix("%! ")
-                .map(|input| input.split_once('\n').unwrap_or((input, "")))
-                .unwrap();
-
-            let mut ranges = Vec::new();
-            let mut cursor = None;
 
-            for line in input.lines() {
-                if line.chars().all(|c| matches!(c, ' ' | '^' | '|' | '!')) && !line.is_empty() {
-                    let index = (lines.len() - 1) as u32;
-
-                    cursor = cursor.or_else(|| {
-                        let character = line.find('|')?;
-                        Some(lsp_types::Position::new(index, character as u32))
-                    });
+    #[test]
+    fn handle_add_one_month() {
+        let input_date = chrono::NaiveDate::from_ymd_opt(2022, 6, 1).expect("valid date");
+        let expected_date = chrono::NaiveDate::from_ymd_opt(2022, 7, 1).expect("valid date");
+        assert_eq!(add_one_month(input_date), expected_date)
+    }
 
-                    if let Some(start) = line.find('!') {
-                        let position = lsp_types::Position::new(index, start as u32);
-                        ranges.push(lsp_types::Range::new(position, position));
-                    }
-
-                    if let Some(start) = line.find('^') {
-                        let end = line.rfind('^').unwrap() + 1;
-                        ranges.push(lsp_types::Range::new(
-                            lsp_types::Position::new(index, start as u32),
-                            lsp_types::Position::new(index, end as u32),
-                        ));
-                    }
-                } else {
-                    lines.push(line);
-                }
-            }
-
-            Self {
-                path: path.to_string(),
-                text: lines.join("\n"),
-                cursor,
-                // ranges,
-            }
-        }
+    #[test]
+    fn handle_add_one_month_in_dec() {
+        let input_date = chrono::NaiveDate::from_ymd_opt(2021, 12, 1).expect("valid date");
+        let expected_date = chrono::NaiveDate::from_ymd_opt(2022, 1, 1).expect("valid date");
+        assert_eq!(add_one_month(input_date), expected_date)
     }
 
-    pub struct TestState {
-        fixture: Fixture,
-        snapshot: LspServerStateSnapshot,
-    }
-    impl TestState {
-        pub fn new(fixture: &str) -> Result<Self> {
-            let fixture = Fixture::parse(fixture);
-            let forest: HashMap<PathBuf, tree_sitter::Tree> = fixture
-                .documents
-                .iter()
-                .map(|document| {
-                    let path = document.path.as_str();
-                    let k = lsp_types::Uri::from_str(format!("file://{path}").as_str())
-                        .unwrap()
-                        .to_file_path()
-                        .unwrap();
-                    let mut parser = tree_sitter::Parser::new();
-                    parser
-                        .set_language(&tree_sitter_beancount::language())
-                        .unwrap();
-                    let v = parser.parse(document.text.clone(), None).unwrap();
-                    (k, v)
-                })
-                .collect();
-            let beancount_data: HashMap<PathBuf, BeancountData> = fixture
-                .documents
-                .iter()
-                .map(|document| {
-                    let path = document.path.as_str();
-                    let k = lsp_types::Uri::from_str(format!("file://{path}").as_str())
-                        .unwrap()
-                        .to_file_path()
-                        .unwrap();
-                    let content = ropey::Rope::from(document.text.clone());
-                    let v = BeancountData::new(forest.get(&k).unwrap(), &content);
-                    (k, v)
-                })
-                .collect();
-            let open_docs: HashMap<PathBuf, Document> = fixture
-                .documents
-                .iter()
-                .map(|document| {
-                    let path = document.path.as_str();
-                    let k = lsp_types::Uri::from_str(format!("file://{path}").as_str())
-                        .unwrap()
-                        .to_file_path()
-                        .unwrap();
-                    let v = Document {
-                        content: ropey::Rope::from(document.text.clone()),
-                    };
-                    (k, v)
-                })
-                .collect();
-            Ok(TestState {
-                fixture,
-                snapshot: LspServerStateSnapshot {
-                    beancount_data,
-                    config: Config::new(std::env::current_dir()?),
-                    forest,
-                    open_docs,
-                },
+    #[test]
+    fn handle_completion_budget() {
+        let items: Vec<_> = ["c", "a", "b"]
+            .into_iter()
+            .map(|label| lsp_types::CompletionItem {
+                label: label.to_string(),
+                ..Default::default()
             })
-        }
+            .collect();
+        let mut options = CompletionOptions::default();
+        let list = apply_budget(items.clone(), &options, Duration::from_secs(1));
+        assert!(!list.is_incomplete);
+        assert_eq!(list.items, items);
 
-        pub fn cursor(&self) -> Option<lsp_types::TextDocumentPositionParams> {
-            let (document, cursor) = self
-                .fixture
-                .documents
-                .iter()
-                .find_map(|document| document.cursor.map(|cursor| (document, cursor)))?;
-
-            let path = document.path.as_str();
-            let uri = lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap();
-            let id = lsp_types::TextDocumentIdentifier::new(uri);
-            Some(lsp_types::TextDocumentPositionParams::new(id, cursor))
-        }
-    }
+        options.max_items = Some(2);
+        let list = apply_budget(items.clone(), &options, Duration::ZERO);
+        assert!(list.is_incomplete);
+        let labels: Vec<_> = list.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["a", "b"]);
 
-    #[test]
-    fn handle_sub_one_month() {
-        let input_date = chrono::NaiveDate::from_ymd_opt(2022, 6, 1).expect("valid date");
-        let expected_date = chrono::NaiveDate::from_ymd_opt(2022, 5, 1).expect("valid date");
-        assert_eq!(sub_one_month(input_date), expected_date)
+        options.max_items = None;
+        options.time_budget_ms = Some(10);
+        let list = apply_budget(items.clone(), &options, Duration::from_millis(20));
+        assert!(list.is_incomplete);
+        assert_eq!(list.items.len(), 3);
     }
 
     #[test]
-    fn handle_sub_one_month_in_jan() {
-        let input_date = chrono::NaiveDate::from_ymd_opt(2022, 1, 1).expect("valid date");
-        let expected_date = chrono::NaiveDate::from_ymd_opt(2021, 12, 1).expect("valid date");
-        assert_eq!(sub_one_month(input_date), expected_date)
-    }
+    fn handle_include_completion() {
+        let dir = std::env::temp_dir().join(format!("beancount-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("2024")).unwrap();
+        std::fs::write(dir.join("accounts.beancount"), "").unwrap();
+        std::fs::write(dir.join("2024/01.beancount"), "").unwrap();
+        let fixure = format!(
+            r#"
+%! {0}/main.beancount
+include "
+         |
+         ^
+"#,
+            dir.display()
+        );
+        let test_state = TestState::new(&fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('"'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["2024/", "accounts.beancount"]);
 
-    #[test]
-    fn handle_add_one_month() {
-        let input_date = chrono::NaiveDate::from_ymd_opt(2022, 6, 1).expect("valid date");
-        let expected_date = chrono::NaiveDate::from_ymd_opt(2022, 7, 1).expect("valid date");
-        assert_eq!(add_one_month(input_date), expected_date)
+        let fixure = format!(
+            r#"
+%! {0}/main.beancount
+include "2024/
+              |
+              ^
+"#,
+            dir.display()
+        );
+        let test_state = TestState::new(&fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('/'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].text_edit,
+            Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(0, 9),
+                    lsp_types::Position::new(0, 14)
+                ),
+                new_text: String::from("2024/01.beancount"),
+            }))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn handle_add_one_month_in_dec() {
-        let input_date = chrono::NaiveDate::from_ymd_opt(2021, 12, 1).expect("valid date");
-        let expected_date = chrono::NaiveDate::from_ymd_opt(2022, 1, 1).expect("valid date");
-        assert_eq!(add_one_month(input_date), expected_date)
+    fn handle_partial_date_completion() {
+        assert_eq!(partial_date("2"), None);
+        assert_eq!(partial_date("2024-13-"), None);
+        assert_eq!(partial_date("2024-02/"), None);
+
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+2024-0
+      |
+      ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "2024-01-", "2024-02-", "2024-03-", "2024-04-", "2024-05-", "2024-06-", "2024-07-",
+                "2024-08-", "2024-09-"
+            ]
+        );
+        assert_eq!(items[1].detail.as_deref(), Some("February 2024"));
+        assert_eq!(
+            items[1].text_edit,
+            Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(1, 0),
+                    lsp_types::Position::new(1, 6)
+                ),
+                new_text: String::from("2024-02-"),
+            }))
+        );
+
+        let fixure = r#"
+%! /main.beancount
+2024-02-2
+         |
+         ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('2'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "2024-02-20",
+                "2024-02-21",
+                "2024-02-22",
+                "2024-02-23",
+                "2024-02-24",
+                "2024-02-25",
+                "2024-02-26",
+                "2024-02-27",
+                "2024-02-28",
+                "2024-02-29"
+            ]
+        );
     }
 
     #[test]
@@ -627,11 +1858,422 @@ mod tests {
                 label: String::from("\"Test Co\""),
                 kind: Some(lsp_types::CompletionItemKind::TEXT),
                 detail: Some(String::from("Beancount Narration")),
+                sort_text: Some(String::from("1\"Test Co\"")),
                 ..Default::default()
             },]
         )
     }
 
+    #[test]
+    fn handle_payee_case_variants() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 * "AMAZON" "Books"
+    Expenses:Books 10 USD
+    Assets:Bank
+2023-10-02 * "Amazon " "books"
+    Expenses:Books 12 USD
+    Assets:Bank
+2023-10-03 * "AMAZON" "Cables"
+    Expenses:Home 5 USD
+    Assets:Bank
+%! /2023.beancount
+2023-10-04 * "amazon" "BOOKS"
+    Expenses:Books 8 USD
+    Assets:Bank
+2023-10-05 txn 
+               |
+               ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items
+            .iter()
+            .filter(|item| item.detail.as_deref() != Some("Beancount Flag (complete)"))
+            .filter(|item| item.detail.as_deref() != Some("Beancount Flag (incomplete)"))
+            .map(|item| (item.label.as_str(), item.sort_text.as_deref()))
+            .collect();
+        // the form used most often is offered, the first one on a tie
+        assert_eq!(
+            labels,
+            [
+                ("\"AMAZON\"", Some("0\"AMAZON\"")),
+                ("\"BOOKS\"", Some("1\"BOOKS\"")),
+                ("\"Cables\"", Some("1\"Cables\"")),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_payee_completion_after_txn() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Test Co" "Foo Bar"
+    Assets:Test 1 USD
+    Expenses:Test
+2023-10-02 txn 
+               |
+               ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let flag_edit = |flag: &str| {
+            Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(3, 11),
+                    lsp_types::Position::new(3, 15),
+                ),
+                new_text: format!("{flag} "),
+            }))
+        };
+        assert_eq!(
+            items,
+            [
+                lsp_types::CompletionItem {
+                    label: String::from("\"Test Co\""),
+                    kind: Some(lsp_types::CompletionItemKind::TEXT),
+                    detail: Some(String::from("Beancount Payee")),
+                    sort_text: Some(String::from("0\"Test Co\"")),
+                    ..Default::default()
+                },
+                lsp_types::CompletionItem {
+                    label: String::from("\"Foo Bar\""),
+                    kind: Some(lsp_types::CompletionItemKind::TEXT),
+                    detail: Some(String::from("Beancount Narration")),
+                    sort_text: Some(String::from("1\"Foo Bar\"")),
+                    ..Default::default()
+                },
+                lsp_types::CompletionItem {
+                    label: String::from("*"),
+                    kind: Some(lsp_types::CompletionItemKind::TEXT),
+                    detail: Some(String::from("Beancount Flag (complete)")),
+                    sort_text: Some(String::from("2*")),
+                    filter_text: Some(String::from("txn ")),
+                    text_edit: flag_edit("*"),
+                    ..Default::default()
+                },
+                lsp_types::CompletionItem {
+                    label: String::from("!"),
+                    kind: Some(lsp_types::CompletionItemKind::TEXT),
+                    detail: Some(String::from("Beancount Flag (incomplete)")),
+                    sort_text: Some(String::from("2!")),
+                    filter_text: Some(String::from("txn ")),
+                    text_edit: flag_edit("!"),
+                    ..Default::default()
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn handle_event_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 event "location" "Paris"
+2023-02-01 event "location" "Berlin"
+2023-03-01 event "employer" "Grocer"
+2023-04-01 event "location" "
+                             |
+                             ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('"'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["\"Berlin\"", "\"Paris\""]);
+
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 event "location" "Paris"
+2023-03-01 event "employer" "Grocer"
+2023-04-01 event 
+                 |
+                 ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["\"employer\"", "\"location\""]);
+    }
+
+    #[test]
+    fn handle_note_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 note Assets:Bank "Called about the fees"
+2023-01-02 note Assets:Cash "Counted"
+2023-01-03 note Assets:Cash "Called about the fees"
+2023-02-01 note Assets:Bank "
+                             |
+                             ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('"'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.detail.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("\"Called about the fees\"", "Beancount Note (Assets:Bank)"),
+                ("\"Counted\"", "Beancount Note"),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_custom_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Expenses:Food
+2023-01-01 custom "fava-option" "language" "en"
+2023-02-01 custom "
+                  |
+                  ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('"'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "\"budget\"",
+                "\"fava-budget\"",
+                "\"fava-option\"",
+                "\"forecast\""
+            ]
+        );
+
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Expenses:Food
+2023-02-01 custom "budget" Expenses:Food "
+                                         |
+                                         ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('"'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "\"daily\"",
+                "\"weekly\"",
+                "\"monthly\"",
+                "\"quarterly\"",
+                "\"yearly\""
+            ]
+        );
+
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Expenses:Food
+2023-02-01 custom "budget" 
+                           |
+                           ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["Expenses:Food"]);
+    }
+
+    #[test]
+    fn handle_price_directive_completion() {
+        let fixure = r#"
+%! /main.beancount
+option "operating_currency" "USD"
+2023-10-01 open Assets:Broker
+2023-10-01 open Assets:Bank
+2023-10-01 txn "Broker" "Buy"
+    Assets:Broker 10 HOOL {100 USD}
+    Assets:Bank -1000 USD
+2023-10-02 balance Assets:Bank 0 EUR
+2023-10-03 price 
+                 |
+                 ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.sort_text.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            labels,
+            [("USD", "10000"), ("EUR", "10001"), ("HOOL", "00002")]
+        );
+
+        let fixure = r#"
+%! /main.beancount
+option "operating_currency" "USD"
+2023-10-02 balance Assets:Bank 0 USD
+2023-10-02 balance Assets:Cash 0 EUR
+2023-10-02 balance Assets:Broker 0 HOOL
+2023-10-03 price HOOL 101.5 
+                            |
+                            ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.sort_text.as_deref().unwrap()))
+            .collect();
+        assert_eq!(labels, [("USD", "00000"), ("EUR", "10001")]);
+    }
+
+    #[test]
+    fn handle_balance_slots() {
+        assert_eq!(
+            balance_slot("2023-10-01 balance "),
+            Some(BalanceSlot::Account)
+        );
+        assert_eq!(balance_slot("2023-10-01 balance Assets:Ba"), None);
+        assert_eq!(
+            balance_slot("2023-10-01 balance Assets:Bank "),
+            Some(BalanceSlot::Amount)
+        );
+        assert_eq!(
+            balance_slot("2023-10-01 balance Assets:Bank 10"),
+            Some(BalanceSlot::Amount)
+        );
+        assert_eq!(
+            balance_slot("2023-10-01 balance Assets:Bank 10.00 US"),
+            Some(BalanceSlot::Currency)
+        );
+        assert_eq!(
+            balance_slot("2023-10-01 balance Assets:Bank 10.00 ~ 0.01 "),
+            Some(BalanceSlot::Currency)
+        );
+        assert_eq!(
+            balance_slot("2023-10-01 balance Assets:Bank 10.00 USD "),
+            None
+        );
+    }
+
+    #[test]
+    fn handle_balance_account_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Bank USD
+2023-10-01 open Assets:Cash USD
+2023-10-02 balance Assets:Bank 
+                               |
+                               ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor).unwrap();
+        // the account is already there, the amount comes next
+        assert_eq!(items, None);
+    }
+
+    #[test]
+    fn handle_close_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Bank USD
+2023-10-01 open Assets:Cash USD
+2023-10-01 open Assets:Old USD
+2023-10-01 close Assets:Old
+2023-10-09 * "Grocer"
+    Assets:Cash -10 USD
+    Assets:Bank
+2023-10-05 close 
+                 |
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap();
+        let mut labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, ["Assets:Bank", "Assets:Cash"]);
+        let cash = items
+            .iter()
+            .find(|item| item.label == "Assets:Cash")
+            .unwrap();
+        assert_eq!(cash.detail.as_deref(), Some("Last posting on 2023-10-09"));
+        let edits = cash.additional_text_edits.as_ref().unwrap();
+        assert_eq!(edits[0].new_text, "2023-10-10");
+        assert_eq!(edits[0].range.start, lsp_types::Position::new(7, 0));
+    }
+
+    #[test]
+    fn handle_transaction_header_slots() {
+        assert_eq!(
+            transaction_header("2023-10-01 txn "),
+            Some(TransactionHeader {
+                flag: 11..14,
+                slot: HeaderSlot::Payee,
+                in_string: false,
+            })
+        );
+        assert_eq!(
+            transaction_header("2023-10-01 * \"Test Co\" \"Fo"),
+            Some(TransactionHeader {
+                flag: 11..12,
+                slot: HeaderSlot::Narration {
+                    payee: String::from("\"Test Co\"")
+                },
+                in_string: true,
+            })
+        );
+        assert_eq!(
+            transaction_header("2023-10-01 ! \""),
+            Some(TransactionHeader {
+                flag: 11..12,
+                slot: HeaderSlot::Payee,
+                in_string: true,
+            })
+        );
+        assert_eq!(
+            transaction_header("2023-10-01 P \"Test Co\" "),
+            Some(TransactionHeader {
+                flag: 11..12,
+                slot: HeaderSlot::Narration {
+                    payee: String::from("\"Test Co\"")
+                },
+                in_string: false,
+            })
+        );
+        assert_eq!(transaction_header("2023-10-01 txn \"Test Co\""), None);
+        assert_eq!(transaction_header("2023-10-01 txn \"A\" \"B\" "), None);
+        assert_eq!(transaction_header("2023-10-01 open "), None);
+    }
+
     #[test]
     fn handle_payee_completion() {
         let fixure = r#"
@@ -651,7 +2293,86 @@ mod tests {
         let items = completion(test_state.snapshot, Some('"'), cursor)
             .unwrap()
             .unwrap_or_default();
-        assert_eq!(items, [])
+        assert_eq!(
+            items,
+            [lsp_types::CompletionItem {
+                label: String::from("\"Foo Bar\""),
+                kind: Some(lsp_types::CompletionItemKind::TEXT),
+                detail: Some(String::from("Beancount Narration")),
+                sort_text: Some(String::from("1\"Foo Bar\"")),
+                ..Default::default()
+            },]
+        )
+    }
+
+    #[test]
+    fn handle_narration_completion_after_flag() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 * "Test Co" "Foo Bar"
+    Assets:Test 1 USD
+    Expenses:Test
+2023-10-02 ! "Test Co" 
+                       |
+                       ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        assert_eq!(
+            items,
+            [lsp_types::CompletionItem {
+                label: String::from("\"Foo Bar\""),
+                kind: Some(lsp_types::CompletionItemKind::TEXT),
+                detail: Some(String::from("Beancount Narration (\"Test Co\")")),
+                sort_text: Some(String::from("0\"Foo Bar\"")),
+                ..Default::default()
+            },]
+        )
+    }
+
+    #[test]
+    fn handle_narration_completion_for_payee() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Test USD
+2023-10-01 open Expenses:Test USD
+2023-10-01 txn  "Test Co" "Foo Bar"
+    Assets:Test 1 USD
+    Expenses:Test
+2023-10-02 txn  "Other Co" "Baz"
+    Assets:Test 1 USD
+    Expenses:Test
+2023-10-03 txn "Test Co" "
+                          |
+                          ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('"'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        assert_eq!(
+            items,
+            [
+                lsp_types::CompletionItem {
+                    label: String::from("\"Foo Bar\""),
+                    kind: Some(lsp_types::CompletionItemKind::TEXT),
+                    detail: Some(String::from("Beancount Narration (\"Test Co\")")),
+                    sort_text: Some(String::from("0\"Foo Bar\"")),
+                    ..Default::default()
+                },
+                lsp_types::CompletionItem {
+                    label: String::from("\"Baz\""),
+                    kind: Some(lsp_types::CompletionItemKind::TEXT),
+                    detail: Some(String::from("Beancount Narration")),
+                    sort_text: Some(String::from("1\"Baz\"")),
+                    ..Default::default()
+                },
+            ]
+        )
     }
 
     #[test]
@@ -690,6 +2411,263 @@ mod tests {
         )
     }
 
+    #[test]
+    fn handle_account_completion_hidden_accounts() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Test USD
+2023-10-01 open Equity:Opening-Balances USD
+2023-10-01 txn  "Test Co" "Foo Bar"
+    a
+     |
+     ^
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .completion
+            .hidden_account_patterns = vec![String::from("Equity:*")];
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["Assets:Test"]);
+    }
+
+    #[test]
+    fn handle_account_completion_metadata() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Bank:Checking USD
+    description: "Everyday account at the credit union"
+    alias: "checking"
+2023-10-01 txn  "Test Co" "Foo Bar"
+    a
+     |
+     ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        assert_eq!(
+            items,
+            [lsp_types::CompletionItem {
+                label: String::from("Assets:Bank:Checking"),
+                detail: Some(String::from("Beancount Account")),
+                kind: Some(lsp_types::CompletionItemKind::TEXT),
+                documentation: Some(lsp_types::Documentation::String(String::from(
+                    "Everyday account at the credit union\n\nAlias: checking"
+                ))),
+                filter_text: Some(String::from("Assets:Bank:Checking checking")),
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn handle_account_completion_ranked_by_first_posting() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Bank USD
+2023-10-01 open Assets:Cash USD
+2023-10-01 open Expenses:Food USD
+2023-10-01 open Expenses:Rent USD
+2023-10-01 txn  "Landlord" "Rent"
+    Assets:Bank -100 USD
+    Expenses:Rent
+2023-11-01 txn  "Landlord" "Rent"
+    Assets:Bank -100 USD
+    Expenses:Rent
+2023-11-02 txn  "Market" "Food"
+    Assets:Bank -10 USD
+    Expenses:Food
+2023-12-01 txn  "Landlord" "Rent"
+    Assets:Bank -100 USD
+    e
+     |
+     ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "Expenses:Rent",
+                "Expenses:Food",
+                "Assets:Bank",
+                "Assets:Cash"
+            ]
+        );
+        assert_eq!(items[0].sort_text, Some(String::from("00000")));
+    }
+
+    #[test]
+    fn handle_account_completion_in_own_journal() {
+        let fixure = r#"
+%! /personal.beancount
+2023-10-01 open Assets:Checking USD
+2023-10-01 txn  "Test Co" "Foo Bar"
+    a
+     |
+     ^
+%! /business.beancount
+2023-10-01 open Assets:Payroll USD
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        test_state.snapshot.journals = Arc::new(
+            ["/personal.beancount", "/business.beancount"]
+                .into_iter()
+                .map(|root| Journal {
+                    root: PathBuf::from(root),
+                    files: HashSet::from([PathBuf::from(root)]),
+                })
+                .collect(),
+        );
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["Assets:Checking"]);
+    }
+
+    #[test]
+    fn handle_account_segment_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Expenses:Food:Groceries USD
+2023-10-01 open Expenses:Food:Restaurants USD
+2023-10-01 open Expenses:Fun USD
+2023-10-01 open Expenses:Rent USD
+2023-10-01 open Assets:Bank USD
+2023-10-01 txn  "Market" "Food"
+    Assets:Bank -10 USD
+    Expenses:Fo:Groceries
+               |
+               ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, None, cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["Food", "Fun", "Rent"]);
+        let range = lsp_types::Range {
+            start: lsp_types::Position::new(7, 13),
+            end: lsp_types::Position::new(7, 15),
+        };
+        assert_eq!(
+            items[0].text_edit,
+            Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range,
+                new_text: String::from("Food"),
+            }))
+        );
+    }
+
+    #[test]
+    fn handle_price_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Test
+2023-10-01 open Assets:Stock STOCK
+2023-10-01 txn  "Test Co" "Foo Bar"
+    Assets:Stock 1 STOCK @ 10 USD
+    Assets:Test
+2023-10-02 txn  "Test Co" "Foo Bar"
+    Assets:Stock 1 STOCK @
+                          |
+                          ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('@'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        assert_eq!(
+            items,
+            [lsp_types::CompletionItem {
+                label: String::from("USD"),
+                kind: Some(lsp_types::CompletionItemKind::UNIT),
+                detail: Some(String::from("Beancount Price Currency")),
+                sort_text: Some(String::from("0000")),
+                ..Default::default()
+            },]
+        )
+    }
+
+    #[test]
+    fn handle_cost_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Test
+2023-10-01 open Assets:Stock STOCK
+2023-10-01 txn  "Test Co" "Foo Bar"
+    Assets:Stock 1 STOCK {10 USD}
+    Assets:Test
+2023-10-02 txn  "Test Co" "Foo Bar"
+    Assets:Stock 1 STOCK {
+                          |
+                          ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('{'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let labels: Vec<_> = items.iter().map(|item| item.label.clone()).collect();
+        let today = chrono::offset::Local::now().naive_local().date();
+        assert_eq!(
+            labels,
+            [String::from("USD"), today.format("%Y-%m-%d").to_string()]
+        )
+    }
+
+    #[test]
+    fn handle_lot_completion() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 open Assets:Cash
+2023-10-01 open Assets:Stock
+2023-10-01 txn "Buy"
+    Assets:Stock 10 STOCK {10 USD}
+    Assets:Cash
+2023-10-05 txn "Buy"
+    Assets:Stock 5 STOCK {12 USD, "second"}
+    Assets:Cash
+2023-10-07 txn "Sell"
+    Assets:Stock -4 STOCK {10 USD}
+    Assets:Cash 40 USD
+2023-10-09 txn "Sell"
+    Assets:Stock -2 STOCK {
+                           |
+                           ^
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let items = completion(test_state.snapshot, Some('{'), cursor)
+            .unwrap()
+            .unwrap_or_default();
+        let lots: Vec<_> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.detail.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            lots,
+            [
+                ("10 USD, 2023-10-01", "Beancount Lot: 6 STOCK"),
+                ("12 USD, 2023-10-05, \"second\"", "Beancount Lot: 5 STOCK"),
+            ]
+        )
+    }
+
     #[test]
     fn handle_tag_completion() {
         let fixure = r#"
@@ -749,4 +2727,64 @@ mod tests {
             },]
         )
     }
+
+    #[cfg(unix)]
+    const EXTERNAL_FIXTURE: &str = r#"
+%! /main.beancount
+2023-10-01 txn  "Test Co" "Foo Bar" #tag
+    Assets:Test 1 USD
+    Expenses:Test
+2023-10-01 txn  "Test Co" "Foo Bar" #
+                                     |
+                                     ^
+"#;
+
+    #[cfg(unix)]
+    fn external_provider(script: &str, timeout_ms: u64) -> ExternalCompletionProvider {
+        ExternalCompletionProvider {
+            command: PathBuf::from("sh"),
+            args: vec![String::from("-c"), String::from(script)],
+            timeout_ms,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_external_completion() {
+        let mut test_state = TestState::new(EXTERNAL_FIXTURE).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .completion
+            .external_provider = Some(external_provider(
+            r##"grep -q '"triggerCharacter":"#"' && echo '[{"label": "#cost-center-100"}]'"##,
+            5000,
+        ));
+        let cursor = test_state.cursor().unwrap();
+        let labels: Vec<_> = completion(test_state.snapshot, Some('#'), cursor)
+            .unwrap()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(labels, ["#tag", "#cost-center-100"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_external_completion_timeout() {
+        let mut test_state = TestState::new(EXTERNAL_FIXTURE).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .completion
+            .external_provider = Some(external_provider(
+            r##"sleep 5; echo '[{"label": "#late"}]'"##,
+            50,
+        ));
+        let cursor = test_state.cursor().unwrap();
+        let labels: Vec<_> = completion(test_state.snapshot, Some('#'), cursor)
+            .unwrap()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(labels, ["#tag"]);
+    }
 }
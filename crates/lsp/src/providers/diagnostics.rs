@@ -1,9 +1,25 @@
 use crate::beancount_data::BeancountData;
+use crate::beancount_data::QueryEntry;
+use crate::budget;
+use crate::checker;
+use crate::checker::CheckOutput;
+use crate::config::Config;
+use crate::custom_directives;
+use crate::lint;
+use crate::lsp_ext::CheckSummaryParams;
+use crate::lsp_ext::DiagnosticCategory;
+use crate::lsp_ext::DiagnosticChecker;
+use crate::lsp_ext::DiagnosticMetadata;
+use crate::lsp_ext::FileCheckSummary;
+use crate::lsp_ext::SeverityCounts;
+#[cfg(feature = "spellcheck")]
+use crate::spellcheck;
+use crate::treesitter_utils::entry_for_tree_sitter_node;
 use crate::utils::ToFilePath;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 use std::str::FromStr;
 use tracing::debug;
 
@@ -31,29 +47,26 @@ impl Default for DiagnosticData {
     }
 }
 
-/// Provider function for LSP `textDocument/publishDiagnostics`.
-pub fn diagnostics(
+/// Provider function for LSP `textDocument/publishDiagnostics`. Combines the
+/// errors the checker found with the checks of the language server.
+pub(crate) fn diagnostics(
     //previous_diagnostics: &DiagnosticData,
-    beancount_data: HashMap<PathBuf, BeancountData>,
-    bean_check_cmd: &Path,
-    root_journal_file: &Path,
+    beancount_data: &HashMap<PathBuf, BeancountData>,
+    forest: &HashMap<PathBuf, tree_sitter::Tree>,
+    config: &Config,
+    check: &CheckOutput,
 ) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
-    let error_line_regexp = regex::Regex::new(r"^([^:]+):(\d+):\s*(.*)$").unwrap();
+    let error_line_regexp = checker::error_line_regex();
 
     debug!("providers::diagnostics");
-    let output = Command::new(bean_check_cmd)
-        .arg(root_journal_file)
-        .output()
-        .unwrap();
-    debug!("bean-check outupt {:?}", output);
+    debug!("checker output {:?}", check.errors);
 
-    let diags = if !output.status.success() {
-        debug!("bean-check generating diags");
-        let output = std::str::from_utf8(&output.stderr);
+    let diags = if !check.success {
+        debug!("checker generating diags");
 
         let mut map: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
 
-        for line in output.unwrap().lines() {
+        for line in check.errors.lines() {
             debug!("line: {}", line);
             if let Some(caps) = error_line_regexp.captures(line) {
                 debug!("caps: {:?}", caps);
@@ -66,13 +79,16 @@ pub fn diagnostics(
                     .unwrap()
                     .to_file_path()
                     .unwrap();
+                let message = caps[3].trim().to_string();
+                let related = related_locations(&message, &file_url, position.line);
                 let diag = lsp_types::Diagnostic {
                     range: lsp_types::Range {
                         start: position,
                         end: position,
                     },
-                    message: caps[3].trim().to_string(),
+                    message,
                     severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                    related_information: (!related.is_empty()).then_some(related),
                     ..lsp_types::Diagnostic::default()
                 };
                 map.entry(file_url).or_default().push(diag);
@@ -80,24 +96,43 @@ pub fn diagnostics(
         }
         map
     } else {
-        debug!("bean-check return empty");
+        debug!("checker return empty");
         HashMap::new()
     };
 
     let mut ret: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    let mut add = |file: &PathBuf, mut diag: lsp_types::Diagnostic, category, checker| {
+        let metadata = DiagnosticMetadata {
+            category,
+            entry_type: forest
+                .get(file)
+                .and_then(|tree| entry_type(tree, diag.range.start.line)),
+            checker,
+            suggestions: Vec::new(),
+        };
+        diag.data = serde_json::to_value(metadata).ok();
+        ret.entry(file.clone()).or_default().push(diag);
+    };
 
     // Add previous urls to clear out if neccessary
     //for it in previous_diagnostics.current_diagnostics.iter() {
     //    ret.insert(it.key().clone(), vec![]);
     //}
-    // add bean-check errors
+    // add bean-check errors, except for those about forecast entries
     for url in diags.iter() {
+        let forecasts = beancount_data
+            .get(url.0)
+            .map(|data| data.get_forecasts())
+            .unwrap_or_default();
         for diag in url.1.iter() {
-            if ret.contains_key(url.0) {
-                ret.get_mut(url.0).unwrap().push(diag.clone());
-            } else {
-                ret.insert(url.0.clone(), vec![diag.clone()]);
+            if forecasts
+                .iter()
+                .any(|forecast| forecast.contains_line(diag.range.start.line))
+            {
+                continue;
             }
+            let category = DiagnosticCategory::for_bean_check_message(&diag.message);
+            add(url.0, diag.clone(), category, DiagnosticChecker::BeanCheck);
         }
     }
     // add flagged entries
@@ -116,12 +151,661 @@ pub fn diagnostics(
                 severity: Some(lsp_types::DiagnosticSeverity::WARNING),
                 ..lsp_types::Diagnostic::default()
             };
-            if ret.contains_key(data.0) {
-                ret.get_mut(data.0).unwrap().push(diag);
-            } else {
-                ret.insert(data.0.clone(), vec![diag]);
+            add(
+                data.0,
+                diag,
+                DiagnosticCategory::Flagged,
+                DiagnosticChecker::Flagged,
+            );
+        }
+    }
+    // add budget warnings
+    if config.budget.enable {
+        for (file, diags) in budget::diagnostics(beancount_data) {
+            for diag in diags {
+                add(
+                    &file,
+                    diag,
+                    DiagnosticCategory::Budget,
+                    DiagnosticChecker::Budget,
+                );
+            }
+        }
+    }
+    // add lint warnings
+    if config.lint.undeclared_commodities {
+        for (file, diags) in lint::undeclared_commodities(beancount_data) {
+            for diag in diags {
+                add(
+                    &file,
+                    diag,
+                    DiagnosticCategory::Commodity,
+                    DiagnosticChecker::Lint,
+                );
             }
         }
     }
+    if let Some(days) = config.lint.stale_price_days {
+        let today = chrono::Local::now().date_naive();
+        for (file, diags) in lint::stale_prices(beancount_data, today, days) {
+            for diag in diags {
+                add(
+                    &file,
+                    diag,
+                    DiagnosticCategory::Price,
+                    DiagnosticChecker::Lint,
+                );
+            }
+        }
+    }
+    for (file, diags) in lint::self_quoted_prices(beancount_data) {
+        for diag in diags {
+            add(
+                &file,
+                diag,
+                DiagnosticCategory::Commodity,
+                DiagnosticChecker::Lint,
+            );
+        }
+    }
+    for (file, diags) in lint::missing_documents(beancount_data) {
+        for diag in diags {
+            add(
+                &file,
+                diag,
+                DiagnosticCategory::Document,
+                DiagnosticChecker::Lint,
+            );
+        }
+    }
+    for (file, diags) in lint::missing_includes(beancount_data) {
+        for diag in diags {
+            add(
+                &file,
+                diag,
+                DiagnosticCategory::Include,
+                DiagnosticChecker::Lint,
+            );
+        }
+    }
+    for (file, diags) in lint::lot_reductions(beancount_data) {
+        for diag in diags {
+            add(
+                &file,
+                diag,
+                DiagnosticCategory::Booking,
+                DiagnosticChecker::Lint,
+            );
+        }
+    }
+    for (file, diags) in lint::postings_after_close(beancount_data) {
+        for diag in diags {
+            add(
+                &file,
+                diag,
+                DiagnosticCategory::Account,
+                DiagnosticChecker::Lint,
+            );
+        }
+    }
+    for (file, diags) in lint::unsupported_syntax(forest) {
+        for diag in diags {
+            add(
+                &file,
+                diag,
+                DiagnosticCategory::Syntax,
+                DiagnosticChecker::Lint,
+            );
+        }
+    }
+    for (file, diags) in custom_directives::diagnostics(beancount_data, &config.custom_directives) {
+        for diag in diags {
+            add(
+                &file,
+                diag,
+                DiagnosticCategory::Custom,
+                DiagnosticChecker::Lint,
+            );
+        }
+    }
+    // add spelling hints, with the corrections as quick fixes
+    #[cfg(feature = "spellcheck")]
+    if config.spellcheck.enable {
+        for (file, misspellings) in spellcheck::misspellings(beancount_data, &config.spellcheck) {
+            for misspelling in misspellings {
+                let metadata = DiagnosticMetadata {
+                    category: DiagnosticCategory::Spelling,
+                    entry_type: forest
+                        .get(&file)
+                        .and_then(|tree| entry_type(tree, misspelling.range.start.line)),
+                    checker: DiagnosticChecker::Spellcheck,
+                    suggestions: misspelling.suggestions,
+                };
+                ret.entry(file.clone())
+                    .or_default()
+                    .push(lsp_types::Diagnostic {
+                        range: misspelling.range,
+                        message: format!("Unknown word `{}`", misspelling.word),
+                        severity: Some(lsp_types::DiagnosticSeverity::HINT),
+                        data: serde_json::to_value(metadata).ok(),
+                        ..lsp_types::Diagnostic::default()
+                    });
+            }
+        }
+    }
+    for diags in ret.values_mut() {
+        dedup_and_sort(diags);
+    }
     ret
 }
+
+/// Links to the other places of the ledger an error message mentions as
+/// `file:line`, like the entry a plugin generated the failing entry from.
+/// Relative paths are relative to the directory of `file`, the file of the
+/// error on `line`.
+fn related_locations(
+    message: &str,
+    file: &Path,
+    line: u32,
+) -> Vec<lsp_types::DiagnosticRelatedInformation> {
+    let location_regexp =
+        regex::Regex::new(r#"([^\s:'"(),\[\]]+\.(?:beancount|bean)):(\d+)"#).unwrap();
+    let mut related = vec![];
+    for caps in location_regexp.captures_iter(message) {
+        let path = match file.parent() {
+            Some(dir) => dir.join(&caps[1]),
+            None => PathBuf::from(&caps[1]),
+        };
+        let Ok(mentioned_line) = caps[2].parse::<u32>() else {
+            continue;
+        };
+        let mentioned_line = mentioned_line.saturating_sub(1);
+        if path == file && mentioned_line == line {
+            continue;
+        }
+        let Some(uri) = path
+            .to_str()
+            .and_then(|path| lsp_types::Uri::from_str(&format!("file://{path}")).ok())
+        else {
+            continue;
+        };
+        let position = lsp_types::Position::new(mentioned_line, 0);
+        related.push(lsp_types::DiagnosticRelatedInformation {
+            location: lsp_types::Location::new(uri, lsp_types::Range::new(position, position)),
+            message: "Mentioned in the error".to_string(),
+        });
+    }
+    related
+}
+
+/// Drops the diagnostics that report the same problem as an earlier one, on
+/// the same range and in the same category, and sorts the rest by position so
+/// that successive publishes list them in the same order.
+fn dedup_and_sort(diags: &mut Vec<lsp_types::Diagnostic>) {
+    let mut seen = HashSet::new();
+    diags.retain(|diag| {
+        let category = diag
+            .data
+            .as_ref()
+            .and_then(|data| data.get("category"))
+            .cloned();
+        let range = diag.range;
+        seen.insert((
+            (range.start.line, range.start.character),
+            (range.end.line, range.end.character),
+            category.map(|category| category.to_string()),
+        ))
+    });
+    // a stable sort, so bean-check errors stay before the others on a line
+    diags.sort_by_key(|diag| {
+        (
+            (diag.range.start.line, diag.range.start.character),
+            (diag.range.end.line, diag.range.end.character),
+        )
+    });
+}
+
+/// The diagnostics of a file edited since they were published, with their
+/// messages tagged so they do not read like the result of a check.
+pub(crate) fn stale(diags: &[lsp_types::Diagnostic]) -> Vec<lsp_types::Diagnostic> {
+    diags
+        .iter()
+        .map(|diag| lsp_types::Diagnostic {
+            message: format!("{} (stale)", diag.message),
+            ..diag.clone()
+        })
+        .collect()
+}
+
+/// An info diagnostic for a file that none of the journals includes, like a
+/// new month file that has not been added yet.
+pub(crate) fn orphan_diagnostic() -> lsp_types::Diagnostic {
+    let metadata = DiagnosticMetadata {
+        category: DiagnosticCategory::Orphan,
+        entry_type: None,
+        checker: DiagnosticChecker::Lint,
+        suggestions: Vec::new(),
+    };
+    lsp_types::Diagnostic {
+        message: "File is not included by any journal".to_string(),
+        severity: Some(lsp_types::DiagnosticSeverity::INFORMATION),
+        data: serde_json::to_value(metadata).ok(),
+        ..lsp_types::Diagnostic::default()
+    }
+}
+
+/// Errors on the strings of `query` directives whose query `beanquery` could
+/// not compile, from the errors by the index of the query in `queries`.
+pub(crate) fn query_diagnostics(
+    queries: &[(PathBuf, QueryEntry)],
+    errors: Vec<(usize, String)>,
+) -> Vec<(PathBuf, lsp_types::Diagnostic)> {
+    let metadata = DiagnosticMetadata {
+        category: DiagnosticCategory::Query,
+        entry_type: Some(String::from("query")),
+        checker: DiagnosticChecker::Beanquery,
+        suggestions: Vec::new(),
+    };
+    errors
+        .into_iter()
+        .filter_map(|(index, message)| {
+            let (file, query) = queries.get(index)?;
+            Some((
+                file.clone(),
+                lsp_types::Diagnostic {
+                    range: query.range,
+                    message: format!("Query \"{}\" does not compile: {message}", query.name),
+                    severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                    data: serde_json::to_value(&metadata).ok(),
+                    ..lsp_types::Diagnostic::default()
+                },
+            ))
+        })
+        .collect()
+}
+
+/// The kind of the top level entry that covers `line`.
+fn entry_type(tree: &tree_sitter::Tree, line: u32) -> Option<String> {
+    let point = tree_sitter::Point::new(line as usize, 0);
+    let node = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point)?;
+    entry_for_tree_sitter_node(node).map(|entry| entry.kind().to_string())
+}
+
+/// The counts of `diagnostics` by severity, in total and by file, for the
+/// `beancount/checkSummary` notification. Diagnostics without a severity
+/// count as errors, as clients show them.
+pub(crate) fn summary(
+    diagnostics: &HashMap<PathBuf, Vec<lsp_types::Diagnostic>>,
+) -> CheckSummaryParams {
+    let mut paths: Vec<&PathBuf> = diagnostics
+        .iter()
+        .filter(|(_, diags)| !diags.is_empty())
+        .map(|(path, _)| path)
+        .collect();
+    paths.sort();
+    let mut total = SeverityCounts::default();
+    let mut files = vec![];
+    for path in paths {
+        let mut counts = SeverityCounts::default();
+        for diag in diagnostics[path].iter() {
+            match diag.severity {
+                Some(lsp_types::DiagnosticSeverity::WARNING) => counts.warnings += 1,
+                Some(lsp_types::DiagnosticSeverity::INFORMATION) => counts.information += 1,
+                Some(lsp_types::DiagnosticSeverity::HINT) => counts.hints += 1,
+                _ => counts.errors += 1,
+            }
+        }
+        total.errors += counts.errors;
+        total.warnings += counts.warnings;
+        total.information += counts.information;
+        total.hints += counts.hints;
+        let Ok(uri) =
+            lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())
+        else {
+            continue;
+        };
+        files.push(FileCheckSummary { uri, counts });
+    }
+    CheckSummaryParams { total, files }
+}
+
+/// Provider function for the `beancount.gotoNextError` command. Finds the
+/// first error after the cursor, going through the files by path and wrapping
+/// around to the first error of the forest.
+pub(crate) fn next_error(
+    diagnostics: &HashMap<PathBuf, Vec<lsp_types::Diagnostic>>,
+    params: lsp_types::TextDocumentPositionParams,
+) -> anyhow::Result<Option<lsp_types::Location>> {
+    debug!("providers::diagnostics::next_error");
+    let current = params
+        .text_document
+        .uri
+        .to_file_path()
+        .map_err(|_| anyhow::anyhow!("invalid uri: {}", params.text_document.uri.as_str()))?;
+    let mut errors: Vec<(&PathBuf, lsp_types::Range)> = diagnostics
+        .iter()
+        .flat_map(|(path, diags)| {
+            diags
+                .iter()
+                .filter(|diag| diag.severity == Some(lsp_types::DiagnosticSeverity::ERROR))
+                .map(move |diag| (path, diag.range))
+        })
+        .collect();
+    errors.sort_by(|a, b| (a.0, a.1.start).cmp(&(b.0, b.1.start)));
+    let Some((path, range)) = errors
+        .iter()
+        .find(|(path, range)| (*path, range.start) > (&current, params.position))
+        .or(errors.first())
+    else {
+        return Ok(None);
+    };
+    let uri = lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+    Ok(Some(lsp_types::Location::new(uri, *range)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checker::CheckOutput;
+    use crate::lsp_ext::DiagnosticCategory;
+    use crate::providers::diagnostics::diagnostics;
+    use crate::providers::diagnostics::next_error;
+    use crate::providers::diagnostics::query_diagnostics;
+    use crate::providers::diagnostics::stale;
+    use crate::providers::diagnostics::summary;
+    use crate::test_utils::TestState;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use test_log::test;
+
+    #[test]
+    fn handle_diagnostic_metadata() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 custom "budget" Expenses:Food "monthly" 10.00 USD
+2024-01-02 ! "Grocer" "Food"
+    Assets:Bank -60.00 USD
+    Expenses:Food
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config).budget.enable = true;
+        let snapshot = test_state.snapshot;
+        let check = CheckOutput {
+            success: true,
+            errors: String::new(),
+        };
+        let diags = diagnostics(
+            &snapshot.beancount_data,
+            &snapshot.forest,
+            &snapshot.config,
+            &check,
+        );
+        let data: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| diag.data.clone().unwrap())
+            .collect();
+        assert_eq!(
+            data,
+            [
+                serde_json::json!({
+                    "category": "flagged",
+                    "entryType": "transaction",
+                    "checker": "flagged",
+                }),
+                serde_json::json!({
+                    "category": "budget",
+                    "entryType": "transaction",
+                    "checker": "budget",
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_query_diagnostics() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 query "cash" "SELECT sum(position) WHERE account ~ 'Assets'"
+2024-01-01 query "typo" "SELECT acount"
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let file = PathBuf::from("/main.beancount");
+        let queries: Vec<_> = test_state.snapshot.beancount_data[&file]
+            .get_queries()
+            .iter()
+            .map(|query| (file.clone(), query.clone()))
+            .collect();
+        assert_eq!(queries[1].1.query, "SELECT acount");
+        let diags = query_diagnostics(
+            &queries,
+            vec![(1, String::from("Invalid column name 'acount'"))],
+        );
+        assert_eq!(diags.len(), 1);
+        let (diag_file, diag) = &diags[0];
+        assert_eq!(diag_file, &file);
+        assert_eq!(
+            diag.message,
+            "Query \"typo\" does not compile: Invalid column name 'acount'"
+        );
+        assert_eq!(
+            diag.range,
+            lsp_types::Range::new(
+                lsp_types::Position::new(1, 24),
+                lsp_types::Position::new(1, 39)
+            )
+        );
+    }
+
+    #[test]
+    fn handle_duplicate_and_unordered_errors() {
+        let test_state = TestState::new("%! /main.beancount\n").unwrap();
+        let snapshot = test_state.snapshot;
+        let check = CheckOutput {
+            success: false,
+            errors: [
+                "/main.beancount:7: Transaction does not balance: (-1 USD)",
+                "/main.beancount:3: Invalid reference to unknown account 'Assets:Foo'",
+                "/main.beancount:7: Transaction does not balance: (-1.00 USD)",
+                "/main.beancount:7: Invalid reference to unknown account 'Assets:Bar'",
+            ]
+            .join("\n"),
+        };
+        let diags = diagnostics(
+            &snapshot.beancount_data,
+            &snapshot.forest,
+            &snapshot.config,
+            &check,
+        );
+        let messages: Vec<_> = diags[&PathBuf::from("/main.beancount")]
+            .iter()
+            .map(|diag| (diag.range.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (2, "Invalid reference to unknown account 'Assets:Foo'"),
+                (6, "Transaction does not balance: (-1 USD)"),
+                (6, "Invalid reference to unknown account 'Assets:Bar'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_related_locations() {
+        let test_state = TestState::new("%! /ledger/main.beancount\n").unwrap();
+        let snapshot = test_state.snapshot;
+        let check = CheckOutput {
+            success: false,
+            errors: String::from(
+                "/ledger/main.beancount:12: Balance failed for 'Assets:Bank' \
+                 (generated from /ledger/plugins.beancount:4, see accounts.beancount:2)",
+            ),
+        };
+        let diags = diagnostics(
+            &snapshot.beancount_data,
+            &snapshot.forest,
+            &snapshot.config,
+            &check,
+        );
+        let related: Vec<_> = diags[&PathBuf::from("/ledger/main.beancount")][0]
+            .related_information
+            .iter()
+            .flatten()
+            .map(|related| {
+                (
+                    related.location.uri.as_str(),
+                    related.location.range.start.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            related,
+            [
+                ("file:///ledger/plugins.beancount", 3),
+                ("file:///ledger/accounts.beancount", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_bean_check_categories() {
+        let category = DiagnosticCategory::for_bean_check_message;
+        assert_eq!(
+            category("Balance failed for 'Assets:Bank': expected 10 USD != accumulated 5 USD"),
+            DiagnosticCategory::Balance
+        );
+        assert_eq!(
+            category("Transaction does not balance: (-1 USD)"),
+            DiagnosticCategory::Unbalanced
+        );
+        assert_eq!(
+            category("Invalid reference to unknown account 'Assets:Foo'"),
+            DiagnosticCategory::Account
+        );
+        assert_eq!(
+            category("syntax error, unexpected INDENT"),
+            DiagnosticCategory::Syntax
+        );
+        assert_eq!(category("Something else"), DiagnosticCategory::Other);
+    }
+
+    #[test]
+    fn handle_next_error() {
+        let error = |line| lsp_types::Diagnostic {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(line, 0),
+                lsp_types::Position::new(line, 0),
+            ),
+            severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+            ..Default::default()
+        };
+        let warning = lsp_types::Diagnostic {
+            severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+            ..error(4)
+        };
+        let diagnostics = HashMap::from([
+            (PathBuf::from("/a.beancount"), vec![error(7), error(2)]),
+            (PathBuf::from("/b.beancount"), vec![warning, error(9)]),
+            (PathBuf::from("/c.beancount"), vec![]),
+        ]);
+        let params = |path: &str, line| {
+            let uri = lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap();
+            lsp_types::TextDocumentPositionParams::new(
+                lsp_types::TextDocumentIdentifier::new(uri),
+                lsp_types::Position::new(line, 0),
+            )
+        };
+        let next = |path: &str, line| {
+            next_error(&diagnostics, params(path, line))
+                .unwrap()
+                .map(|location| (location.uri.as_str().to_string(), location.range.start.line))
+        };
+        assert_eq!(
+            next("/a.beancount", 0),
+            Some(("file:///a.beancount".into(), 2))
+        );
+        assert_eq!(
+            next("/a.beancount", 2),
+            Some(("file:///a.beancount".into(), 7))
+        );
+        assert_eq!(
+            next("/a.beancount", 8),
+            Some(("file:///b.beancount".into(), 9))
+        );
+        assert_eq!(
+            next("/b.beancount", 9),
+            Some(("file:///a.beancount".into(), 2))
+        );
+        assert_eq!(
+            next("/c.beancount", 0),
+            Some(("file:///a.beancount".into(), 2))
+        );
+        assert_eq!(
+            next_error(&HashMap::new(), params("/a.beancount", 0)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn handle_check_summary() {
+        let diag = |severity| lsp_types::Diagnostic {
+            severity,
+            ..Default::default()
+        };
+        let diagnostics = HashMap::from([
+            (
+                PathBuf::from("/b.beancount"),
+                vec![
+                    diag(Some(lsp_types::DiagnosticSeverity::ERROR)),
+                    diag(Some(lsp_types::DiagnosticSeverity::HINT)),
+                ],
+            ),
+            (
+                PathBuf::from("/a.beancount"),
+                vec![
+                    diag(None),
+                    diag(Some(lsp_types::DiagnosticSeverity::WARNING)),
+                    diag(Some(lsp_types::DiagnosticSeverity::INFORMATION)),
+                ],
+            ),
+            (PathBuf::from("/c.beancount"), vec![]),
+        ]);
+        let summary = summary(&diagnostics);
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({
+                "errors": 2,
+                "warnings": 1,
+                "information": 1,
+                "hints": 1,
+                "files": [
+                    {"uri": "file:///a.beancount", "errors": 1, "warnings": 1, "information": 1, "hints": 0},
+                    {"uri": "file:///b.beancount", "errors": 1, "warnings": 0, "information": 0, "hints": 1},
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn handle_stale_diagnostics() {
+        let diags = vec![lsp_types::Diagnostic {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(6, 0),
+                lsp_types::Position::new(6, 0),
+            ),
+            message: "Transaction does not balance: (-1 USD)".to_string(),
+            severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+            ..lsp_types::Diagnostic::default()
+        }];
+        let stale = stale(&diags);
+        assert_eq!(
+            stale[0].message,
+            "Transaction does not balance: (-1 USD) (stale)"
+        );
+        assert_eq!(stale[0].range, diags[0].range);
+        assert_eq!(stale[0].severity, diags[0].severity);
+    }
+}
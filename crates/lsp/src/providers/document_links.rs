@@ -0,0 +1,169 @@
+use crate::forest::resolve_includes;
+use crate::server::LspServerStateSnapshot;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Provider function for LSP `textDocument/documentLink`. Links the paths of
+/// `document` directives and `documents` options to the files and directories
+/// they name, and those of `include` directives to the files they include, if
+/// those exist. Missing ones are reported as diagnostics.
+pub(crate) fn document_links(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::DocumentLinkParams,
+) -> Result<Option<Vec<lsp_types::DocumentLink>>> {
+    debug!("providers::document_links");
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let Some(data) = snapshot.beancount_data.get(&file) else {
+        return Ok(None);
+    };
+    let mut links = vec![];
+    for document in data.get_documents() {
+        let path = document.resolve(&file);
+        if !path.exists() {
+            continue;
+        }
+        let url = if path.is_dir() {
+            url::Url::from_directory_path(&path)
+        } else {
+            url::Url::from_file_path(&path)
+        };
+        let Ok(url) = url else {
+            continue;
+        };
+        links.push(lsp_types::DocumentLink {
+            range: document.range,
+            target: Some(lsp_types::Uri::from_str(url.as_str())?),
+            tooltip: Some(path.display().to_string()),
+            data: None,
+        });
+    }
+    for include in data.get_include_entries() {
+        for path in included_files(&snapshot, &file, &include.path) {
+            let Ok(url) = url::Url::from_file_path(&path) else {
+                continue;
+            };
+            links.push(lsp_types::DocumentLink {
+                range: include.range,
+                target: Some(lsp_types::Uri::from_str(url.as_str())?),
+                tooltip: Some(path.display().to_string()),
+                data: None,
+            });
+        }
+    }
+    Ok(Some(links))
+}
+
+/// The files an `include` of `path` in `file` refers to: relative to `file`
+/// like beancount does, or else relative to the journal root. A glob gives a
+/// link per matched file.
+fn included_files(snapshot: &LspServerStateSnapshot, file: &Path, path: &str) -> Vec<PathBuf> {
+    let ignore = snapshot.config.files.ignore_globs();
+    let includes = [path.to_string()];
+    let files = resolve_includes(file, &includes, &ignore);
+    if !files.is_empty() {
+        return files;
+    }
+    let Some(root) = snapshot
+        .journal_for(file)
+        .map(|journal| journal.root.clone())
+        .filter(|root| root.parent() != file.parent())
+    else {
+        return files;
+    };
+    resolve_includes(&root, &includes, &ignore)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::document_links::document_links;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_document_links() {
+        let dir = std::env::temp_dir().join(format!("beancount-documents-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("receipts")).unwrap();
+        std::fs::write(dir.join("receipts/invoice.pdf"), "").unwrap();
+        let fixure = format!(
+            r#"
+%! {0}/main.beancount
+option "documents" "receipts"
+2024-01-01 document Assets:Bank "receipts/invoice.pdf"
+2024-01-02 document Assets:Bank "receipts/missing.pdf"
+"#,
+            dir.display()
+        );
+        let test_state = TestState::new(&fixure).unwrap();
+        let uri =
+            lsp_types::Uri::from_str(&format!("file://{}/main.beancount", dir.display())).unwrap();
+        let params = lsp_types::DocumentLinkParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let links = document_links(test_state.snapshot, params)
+            .unwrap()
+            .unwrap();
+        let targets: Vec<_> = links
+            .iter()
+            .map(|link| (link.range.start.line, link.target.clone().unwrap()))
+            .collect();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].0, 0);
+        assert!(targets[0].1.as_str().ends_with("/receipts/"));
+        assert_eq!(targets[1].0, 1);
+        assert!(targets[1].1.as_str().ends_with("/receipts/invoice.pdf"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn handle_include_links() {
+        let dir = std::env::temp_dir().join(format!("beancount-includes-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("accounts")).unwrap();
+        std::fs::write(dir.join("accounts/2023.bean"), "").unwrap();
+        std::fs::write(dir.join("accounts/2024.bean"), "").unwrap();
+        let fixure = format!(
+            r#"
+%! {0}/main.beancount
+include "accounts/2024.bean"
+include "accounts/*.bean"
+include "accounts/2025.bean"
+"#,
+            dir.display()
+        );
+        let test_state = TestState::new(&fixure).unwrap();
+        let uri =
+            lsp_types::Uri::from_str(&format!("file://{}/main.beancount", dir.display())).unwrap();
+        let params = lsp_types::DocumentLinkParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let links = document_links(test_state.snapshot, params)
+            .unwrap()
+            .unwrap();
+        let mut targets: Vec<_> = links
+            .iter()
+            .map(|link| {
+                let target = link.target.clone().unwrap();
+                let name = target.as_str().rsplit('/').next().unwrap().to_string();
+                (link.range.start.line, name)
+            })
+            .collect();
+        targets.sort();
+        assert_eq!(
+            targets,
+            [
+                (0, String::from("2024.bean")),
+                (1, String::from("2023.bean")),
+                (1, String::from("2024.bean")),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,391 @@
+use crate::commented_out::commented_transactions;
+use crate::commented_out::CommentedTransaction;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::PositionEncoding;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use tracing::debug;
+
+/// Provider function for LSP `textDocument/documentSymbol`. Lists the entries
+/// of the document, nested under the org-mode sections they are in, keeping
+/// only the kinds in the `documentSymbols.include` option. The org-mode
+/// `#+TITLE:` and property drawers of the file are listed at the top level.
+/// Transactions commented out with `;` are listed as deprecated.
+pub(crate) fn document_symbols(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::DocumentSymbolParams,
+) -> Result<Option<lsp_types::DocumentSymbolResponse>> {
+    debug!("providers::document_symbols");
+    let uri = params.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) = (snapshot.forest.get(&uri), snapshot.document_content(&uri))
+    else {
+        return Ok(None);
+    };
+    let include = &snapshot.config.document_symbols.include;
+    let mut symbols = children_symbols(
+        tree.root_node(),
+        &content,
+        snapshot.config.position_encoding,
+        include,
+    );
+    if include.iter().any(|included| included == "metadata") {
+        symbols.extend(org_metadata_symbols(
+            &content,
+            snapshot.config.position_encoding,
+        ));
+        symbols.sort_by_key(|symbol| (symbol.range.start.line, symbol.range.start.character));
+    }
+    if include.iter().any(|included| included == "transactions") {
+        for transaction in commented_transactions(&content) {
+            insert_in_section(
+                &mut symbols,
+                commented_transaction_symbol(
+                    &transaction,
+                    &content,
+                    snapshot.config.position_encoding,
+                ),
+            );
+        }
+    }
+    Ok(Some(lsp_types::DocumentSymbolResponse::Nested(symbols)))
+}
+
+/// The symbols of the entries directly in `node`. The entries of a section
+/// that is left out move up to the enclosing level.
+fn children_symbols(
+    node: tree_sitter::Node,
+    content: &ropey::Rope,
+    encoding: PositionEncoding,
+    include: &[String],
+) -> Vec<lsp_types::DocumentSymbol> {
+    let mut symbols = Vec::new();
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let children = if child.kind() == "section" {
+            children_symbols(child, content, encoding, include)
+        } else {
+            vec![]
+        };
+        match extract_symbol(child, content, encoding, include) {
+            Some(mut symbol) => {
+                if !children.is_empty() {
+                    symbol.children = Some(children);
+                }
+                symbols.push(symbol);
+            }
+            None => symbols.extend(children),
+        }
+    }
+    symbols
+}
+
+/// The symbol for an entry, if its kind is shown in the outline.
+#[allow(deprecated)]
+fn extract_symbol(
+    node: tree_sitter::Node,
+    content: &ropey::Rope,
+    encoding: PositionEncoding,
+    include: &[String],
+) -> Option<lsp_types::DocumentSymbol> {
+    let (category, kind) = match node.kind() {
+        "section" => ("sections", lsp_types::SymbolKind::NAMESPACE),
+        "transaction" => ("transactions", lsp_types::SymbolKind::EVENT),
+        "open" => ("opens", lsp_types::SymbolKind::CLASS),
+        "close" => ("closes", lsp_types::SymbolKind::CLASS),
+        "balance" => ("balances", lsp_types::SymbolKind::NUMBER),
+        "pad" => ("pads", lsp_types::SymbolKind::OPERATOR),
+        "price" => ("prices", lsp_types::SymbolKind::NUMBER),
+        "commodity" => ("commodities", lsp_types::SymbolKind::CONSTANT),
+        "note" => ("notes", lsp_types::SymbolKind::STRING),
+        "event" => ("events", lsp_types::SymbolKind::EVENT),
+        "document" => ("documents", lsp_types::SymbolKind::FILE),
+        "custom" => ("customs", lsp_types::SymbolKind::OBJECT),
+        _ => return None,
+    };
+    if !include.iter().any(|included| included == category) {
+        return None;
+    }
+    let text = text_for_tree_sitter_node(content, &node);
+    let first_line = text.lines().next().unwrap_or_default();
+    let name = if category == "sections" {
+        first_line.trim_start_matches('*').trim()
+    } else {
+        first_line.trim()
+    };
+    let range = lsp_range_for_tree_sitter_node(content, &node, encoding);
+    Some(lsp_types::DocumentSymbol {
+        name: name.to_string(),
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range: range,
+        children: None,
+    })
+}
+
+/// The symbol of a commented transaction, tagged deprecated so clients grey
+/// it out.
+#[allow(deprecated)]
+fn commented_transaction_symbol(
+    transaction: &CommentedTransaction,
+    content: &ropey::Rope,
+    encoding: PositionEncoding,
+) -> lsp_types::DocumentSymbol {
+    let range = transaction.range(content, encoding);
+    lsp_types::DocumentSymbol {
+        name: transaction.header.clone(),
+        detail: Some(String::from("commented out")),
+        kind: lsp_types::SymbolKind::EVENT,
+        tags: Some(vec![lsp_types::SymbolTag::DEPRECATED]),
+        deprecated: None,
+        range,
+        selection_range: range,
+        children: None,
+    }
+}
+
+/// Adds `symbol` to the innermost section symbol it is in, in order.
+fn insert_in_section(
+    symbols: &mut Vec<lsp_types::DocumentSymbol>,
+    symbol: lsp_types::DocumentSymbol,
+) {
+    let start = symbol.range.start;
+    let section = symbols.iter_mut().find(|section| {
+        section.kind == lsp_types::SymbolKind::NAMESPACE
+            && section.range.start <= start
+            && start <= section.range.end
+    });
+    match section {
+        Some(section) => insert_in_section(section.children.get_or_insert_with(Vec::new), symbol),
+        None => {
+            let index = symbols.partition_point(|other| other.range.start <= start);
+            symbols.insert(index, symbol);
+        }
+    }
+}
+
+/// The `#+TITLE:` lines and `:PROPERTIES:` drawers of org-mode, also when
+/// they are commented out with `;`. The parser does not know them, so they
+/// are read from the text.
+#[allow(deprecated)]
+fn org_metadata_symbols(
+    content: &ropey::Rope,
+    encoding: PositionEncoding,
+) -> Vec<lsp_types::DocumentSymbol> {
+    let line_range = |start_line: usize, end_line: usize| {
+        let end_text = content.line(end_line).to_string();
+        let end = content.line_to_byte(end_line) + end_text.trim_end_matches(['\r', '\n']).len();
+        lsp_types::Range::new(
+            byte_to_lsp_position(content, content.line_to_byte(start_line), encoding),
+            byte_to_lsp_position(content, end, encoding),
+        )
+    };
+    let symbol = |name: &str, detail: &str, kind, range| lsp_types::DocumentSymbol {
+        name: name.to_string(),
+        detail: Some(detail.to_string()),
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range: range,
+        children: None,
+    };
+
+    let mut symbols = Vec::new();
+    // the start line and properties of the drawer being read
+    let mut drawer: Option<(usize, Vec<lsp_types::DocumentSymbol>)> = None;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.to_string();
+        let text = line.trim_start_matches([' ', '\t', ';']).trim_end();
+        if let Some((start, properties)) = drawer.as_mut() {
+            if text.eq_ignore_ascii_case(":END:") {
+                let mut drawer_symbol = symbol(
+                    "Properties",
+                    ":PROPERTIES:",
+                    lsp_types::SymbolKind::OBJECT,
+                    line_range(*start, index),
+                );
+                drawer_symbol.children = Some(std::mem::take(properties));
+                symbols.push(drawer_symbol);
+                drawer = None;
+            } else if let Some((key, value)) = text
+                .strip_prefix(':')
+                .and_then(|property| property.split_once(':'))
+            {
+                properties.push(symbol(
+                    key,
+                    value.trim(),
+                    lsp_types::SymbolKind::PROPERTY,
+                    line_range(index, index),
+                ));
+            } else {
+                // not a drawer after all
+                drawer = None;
+            }
+        } else if text.eq_ignore_ascii_case(":PROPERTIES:") {
+            drawer = Some((index, vec![]));
+        } else if let Some(title) = text
+            .get(..8)
+            .filter(|keyword| keyword.eq_ignore_ascii_case("#+TITLE:"))
+            .map(|_| text[8..].trim())
+        {
+            symbols.push(symbol(
+                title,
+                "#+TITLE",
+                lsp_types::SymbolKind::FILE,
+                line_range(index, index),
+            ));
+        }
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::document_symbols::document_symbols;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use test_log::test;
+
+    fn outline(test_state: TestState) -> Vec<(String, usize)> {
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::DocumentSymbolParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let Some(lsp_types::DocumentSymbolResponse::Nested(symbols)) =
+            document_symbols(test_state.snapshot, params).unwrap()
+        else {
+            panic!("expected document symbols");
+        };
+        fn flatten(symbols: &[lsp_types::DocumentSymbol], depth: usize) -> Vec<(String, usize)> {
+            symbols
+                .iter()
+                .flat_map(|symbol| {
+                    let children = symbol.children.as_deref().unwrap_or_default();
+                    std::iter::once((symbol.name.clone(), depth))
+                        .chain(flatten(children, depth + 1))
+                })
+                .collect()
+        }
+        flatten(&symbols, 0)
+    }
+
+    const FIXTURE: &str = r#"
+%! /main.beancount
+* Accounts
+2023-01-01 open Assets:Bank
+* Transactions
+2023-01-02 txn "Grocer" "Food"
+    Assets:Bank -10 USD
+    Expenses:Food
+2023-01-03 balance Assets:Bank -10 USD
+"#;
+
+    #[test]
+    fn handle_document_symbols() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        assert_eq!(
+            outline(test_state),
+            [
+                (String::from("Accounts"), 0),
+                (String::from("2023-01-01 open Assets:Bank"), 1),
+                (String::from("Transactions"), 0),
+                (String::from("2023-01-02 txn \"Grocer\" \"Food\""), 1),
+                (String::from("2023-01-03 balance Assets:Bank -10 USD"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_document_symbols_include() {
+        let mut test_state = TestState::new(FIXTURE).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .document_symbols
+            .include = vec![String::from("balances"), String::from("opens")];
+        assert_eq!(
+            outline(test_state),
+            [
+                (String::from("2023-01-01 open Assets:Bank"), 0),
+                (String::from("2023-01-03 balance Assets:Bank -10 USD"), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_document_symbols_org_metadata() {
+        let fixture = r#"
+%! /main.beancount
+#+TITLE: Household ledger
+; :PROPERTIES:
+; :OWNER: Alex
+; :CURRENCY: USD
+; :END:
+* Accounts
+2023-01-01 open Assets:Bank
+"#;
+        let test_state = TestState::new(fixture).unwrap();
+        assert_eq!(
+            outline(test_state),
+            [
+                (String::from("Household ledger"), 0),
+                (String::from("Properties"), 0),
+                (String::from("OWNER"), 1),
+                (String::from("CURRENCY"), 1),
+                (String::from("Accounts"), 0),
+                (String::from("2023-01-01 open Assets:Bank"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_document_symbols_commented_transactions() {
+        let fixture = r#"
+%! /main.beancount
+* Transactions
+2023-01-02 txn "Grocer" "Food"
+    Assets:Bank -10 USD
+    Expenses:Food
+; 2023-01-03 txn "Bakery" "Bread"
+;     Assets:Bank -3 USD
+;     Expenses:Food
+2023-01-04 balance Assets:Bank -10 USD
+"#;
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::DocumentSymbolParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let Some(lsp_types::DocumentSymbolResponse::Nested(symbols)) =
+            document_symbols(TestState::new(fixture).unwrap().snapshot, params).unwrap()
+        else {
+            panic!("expected document symbols");
+        };
+        let entries: Vec<_> = symbols[0]
+            .children
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.tags.clone()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("2023-01-02 txn \"Grocer\" \"Food\"", None),
+                (
+                    "2023-01-03 txn \"Bakery\" \"Bread\"",
+                    Some(vec![lsp_types::SymbolTag::DEPRECATED])
+                ),
+                ("2023-01-04 balance Assets:Bank -10 USD", None),
+            ]
+        );
+    }
+}
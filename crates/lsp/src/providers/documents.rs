@@ -0,0 +1,220 @@
+use crate::beancount_data::parse_date;
+use crate::beancount_data::BeancountData;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Arguments of the `beancount.addDocuments` command.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddDocumentsParams {
+    /// A document of the journal to add the directives to.
+    pub text_document: lsp_types::TextDocumentIdentifier,
+}
+
+/// A file in a documents directory without a `document` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct UndocumentedFile {
+    pub date: chrono::NaiveDate,
+    pub account: String,
+    /// The path as written in the directive: relative to the directory of the
+    /// file with the `documents` option, like the option itself.
+    pub path: String,
+}
+
+impl UndocumentedFile {
+    fn directive(&self) -> String {
+        format!(
+            "{} document {} \"{}\"",
+            self.date.format("%Y-%m-%d"),
+            self.account,
+            self.path
+        )
+    }
+}
+
+/// The files of the `documents` directories of the journal that follow the
+/// `Account/Sub-Account/YYYY-MM-DD.name` convention of beancount but have no
+/// `document` directive, by the file with the `documents` option. Only files
+/// of opened accounts are considered.
+pub(crate) fn undocumented_files(
+    data: &HashMap<PathBuf, BeancountData>,
+) -> HashMap<PathBuf, Vec<UndocumentedFile>> {
+    let accounts: HashSet<String> = data.values().flat_map(|data| data.get_accounts()).collect();
+    let documented: HashSet<PathBuf> = data
+        .iter()
+        .flat_map(|(file, data)| {
+            data.get_documents()
+                .iter()
+                .filter(|document| !document.directory)
+                .map(|document| document.resolve(file))
+        })
+        .collect();
+
+    let mut undocumented: HashMap<PathBuf, Vec<UndocumentedFile>> = HashMap::new();
+    for (file, data) in data.iter() {
+        for directory in data.get_documents().iter().filter(|d| d.directory) {
+            let root = directory.resolve(file);
+            let mut found = vec![];
+            for path in files_under(&root) {
+                if documented.contains(&path) {
+                    continue;
+                }
+                let Ok(relative) = path.strip_prefix(&root) else {
+                    continue;
+                };
+                let mut components: Vec<String> = relative
+                    .iter()
+                    .map(|component| component.to_string_lossy().to_string())
+                    .collect();
+                let Some(name) = components.pop() else {
+                    continue;
+                };
+                let account = components.join(":");
+                let Some(date) = name.split_once('.').and_then(|(date, _)| parse_date(date)) else {
+                    continue;
+                };
+                if !accounts.contains(&account) {
+                    continue;
+                }
+                found.push(UndocumentedFile {
+                    date,
+                    account,
+                    path: format!("{}/{}", directory.path, components_path(relative)),
+                });
+            }
+            if !found.is_empty() {
+                undocumented.entry(file.clone()).or_default().extend(found);
+            }
+        }
+    }
+    for files in undocumented.values_mut() {
+        files.sort_by(|a, b| (a.date, &a.account, &a.path).cmp(&(b.date, &b.account, &b.path)));
+    }
+    undocumented
+}
+
+/// The path with `/` as separator, as beancount writes them on any platform.
+fn components_path(path: &Path) -> String {
+    path.iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The regular files under `directory`, recursively.
+fn files_under(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return vec![];
+    };
+    let mut files = vec![];
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            files.extend(files_under(&path));
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Provider function for the `beancount.addDocuments` command. Appends a
+/// `document` directive for each undocumented file to the file with the
+/// `documents` option of its directory.
+pub(crate) fn add_documents(
+    snapshot: LspServerStateSnapshot,
+    params: AddDocumentsParams,
+) -> Result<lsp_types::WorkspaceEdit> {
+    debug!("providers::documents::add_documents");
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let encoding = snapshot.config.position_encoding;
+    let mut changes: HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>> = HashMap::new();
+    for (path, files) in undocumented_files(&snapshot.journal_data(&file)) {
+        let Some(content) = snapshot.document_content(&path) else {
+            continue;
+        };
+        let directives: Vec<String> = files.iter().map(UndocumentedFile::directive).collect();
+        let ends_with_newline = content
+            .len_chars()
+            .checked_sub(1)
+            .is_none_or(|last| content.char(last) == '\n');
+        let new_text = if ends_with_newline {
+            format!("{}\n", directives.join("\n"))
+        } else {
+            format!("\n{}\n", directives.join("\n"))
+        };
+        let end = byte_to_lsp_position(&content, content.len_bytes(), encoding);
+        let uri = lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+        changes.insert(
+            uri,
+            vec![lsp_types::TextEdit {
+                range: lsp_types::Range::new(end, end),
+                new_text,
+            }],
+        );
+    }
+    Ok(lsp_types::WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::documents::add_documents;
+    use crate::providers::documents::AddDocumentsParams;
+    use crate::test_utils::apply_edits;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_add_documents() {
+        let dir =
+            std::env::temp_dir().join(format!("beancount-attachments-{}", std::process::id()));
+        let bank = dir.join("docs/Assets/Bank");
+        std::fs::create_dir_all(&bank).unwrap();
+        std::fs::create_dir_all(dir.join("docs/Assets/Unknown")).unwrap();
+        std::fs::write(bank.join("2024-02-01.statement.pdf"), "").unwrap();
+        std::fs::write(bank.join("2024-01-01.statement.pdf"), "").unwrap();
+        std::fs::write(bank.join("notes.txt"), "").unwrap();
+        std::fs::write(dir.join("docs/Assets/Unknown/2024-01-01.pdf"), "").unwrap();
+        let fixure = format!(
+            r#"
+%! {0}/main.beancount
+option "documents" "docs"
+2024-01-01 open Assets:Bank
+2024-01-01 document Assets:Bank "docs/Assets/Bank/2024-01-01.statement.pdf"
+"#,
+            dir.display()
+        );
+        let test_state = TestState::new(&fixure).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let uri =
+            lsp_types::Uri::from_str(&format!("file://{}/main.beancount", dir.display())).unwrap();
+        let params = AddDocumentsParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+        };
+        let edit = add_documents(test_state.snapshot, params).unwrap();
+        let added = apply_edits(&text, &edit.changes.unwrap()[&uri]);
+        assert_eq!(
+            added,
+            format!(
+                "{text}\n2024-02-01 document Assets:Bank \"docs/Assets/Bank/2024-02-01.statement.pdf\"\n"
+            )
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use crate::checker;
+use crate::config::ExternalCompletionProvider;
+use serde::Serialize;
+use std::io::Read;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::debug;
+use tracing::warn;
+
+/// What the external completion provider gets on stdin.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionContext {
+    pub uri: lsp_types::Uri,
+    pub position: lsp_types::Position,
+    /// The text of the line up to the cursor.
+    pub line_prefix: String,
+    /// The kind of the syntax node at the cursor, like `account` or `payee`.
+    pub node_kind: Option<String>,
+    pub trigger_character: Option<char>,
+}
+
+/// The completion items of the external provider. A provider that fails,
+/// prints something else than a list of items or does not answer within its
+/// timeout contributes nothing.
+pub(crate) fn external_completion(
+    provider: &ExternalCompletionProvider,
+    context: &CompletionContext,
+) -> Vec<lsp_types::CompletionItem> {
+    debug!("providers::external_completion {:?}", provider.command);
+    match run_provider(provider, context) {
+        Ok(items) => items,
+        Err(err) => {
+            warn!(
+                "external completion provider {:?}: {}",
+                provider.command, err
+            );
+            vec![]
+        }
+    }
+}
+
+fn run_provider(
+    provider: &ExternalCompletionProvider,
+    context: &CompletionContext,
+) -> anyhow::Result<Vec<lsp_types::CompletionItem>> {
+    let mut command = Command::new(&provider.command);
+    command.args(&provider.args);
+    checker::audit_spawn(&command);
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let input = serde_json::to_vec(context)?;
+    let mut stdin = child.stdin.take().unwrap();
+    // write and read in the background so a provider that never reads its
+    // input or fills the pipe cannot block completion past the timeout
+    std::thread::spawn(move || stdin.write_all(&input));
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + Duration::from_millis(provider.timeout_ms);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("timed out after {}ms", provider.timeout_ms);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    if !status.success() {
+        anyhow::bail!("exited with {}", status);
+    }
+    let output = reader
+        .join()
+        .map_err(|_| anyhow::anyhow!("could not read the output"))??;
+    Ok(serde_json::from_slice(&output)?)
+}
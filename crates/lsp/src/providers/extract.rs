@@ -0,0 +1,219 @@
+use crate::beancount_data::parse_date;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::utils::ToFilePath;
+use crate::workspace_edit::WorkspaceEditBuilder;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Arguments of the `beancount.extractToFile` command.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractToFileParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    /// The selection; the entries starting on its lines are extracted.
+    pub range: lsp_types::Range,
+    /// The new file, relative to the document like in an `include`. Clients
+    /// may ask the user for it before running the command.
+    pub path: String,
+}
+
+/// Provider function for the `beancount.extractToFile` command. Moves the
+/// selected entries into a new file, sorted by date, and includes the file
+/// where they were.
+pub(crate) fn extract_to_file(
+    snapshot: LspServerStateSnapshot,
+    params: ExtractToFileParams,
+) -> Result<lsp_types::WorkspaceEdit> {
+    debug!("providers::extract::extract_to_file {}", params.path);
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) =
+        (snapshot.forest.get(&file), snapshot.document_content(&file))
+    else {
+        anyhow::bail!("unknown document: {}", params.text_document.uri.as_str());
+    };
+    let entries = selected_entries(tree, params.range);
+    let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+        anyhow::bail!("no entries selected");
+    };
+    let target = file.parent().unwrap_or(Path::new("")).join(&params.path);
+    if target.exists() || snapshot.forest.contains_key(&target) {
+        anyhow::bail!("{} already exists", target.display());
+    }
+
+    // the start of the line after an entry
+    let line_end = |entry: &tree_sitter::Node| match entry.end_position() {
+        end if end.column == 0 => entry.end_byte(),
+        end if end.row + 1 < content.len_lines() => content.line_to_byte(end.row + 1),
+        _ => content.len_bytes(),
+    };
+    let start = content.line_to_byte(first.start_position().row);
+    let end = line_end(last);
+    // each entry takes the lines since the previous one, with its comments
+    let mut chunks: Vec<(Option<chrono::NaiveDate>, String)> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let chunk_start = match index {
+                0 => start,
+                _ => line_end(&entries[index - 1]),
+            };
+            let mut text = content.byte_slice(chunk_start..line_end(entry)).to_string();
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            let date = entry
+                .child_by_field_name("date")
+                .and_then(|date| parse_date(&text_for_tree_sitter_node(&content, &date)));
+            (date, text)
+        })
+        .collect();
+    // a stable sort, so entries of a day keep their order and undated ones
+    // like options come first
+    chunks.sort_by_key(|(date, _)| *date);
+    let text: String = chunks.into_iter().map(|(_, text)| text).collect();
+
+    let encoding = snapshot.config.position_encoding;
+    let uri = lsp_types::Uri::from_str(format!("file://{}", target.to_str().unwrap()).as_str())?;
+    let include = if content.byte_slice(start..end).to_string().ends_with('\n') {
+        format!("include \"{}\"\n", params.path)
+    } else {
+        format!("include \"{}\"", params.path)
+    };
+    Ok(WorkspaceEditBuilder::new()
+        .create_file(uri.clone(), false)
+        .edit(
+            uri,
+            lsp_types::TextEdit {
+                range: lsp_types::Range::default(),
+                new_text: text,
+            },
+        )
+        .edit(
+            params.text_document.uri,
+            lsp_types::TextEdit {
+                range: lsp_types::Range::new(
+                    byte_to_lsp_position(&content, start, encoding),
+                    byte_to_lsp_position(&content, end, encoding),
+                ),
+                new_text: include,
+            },
+        )
+        .build())
+}
+
+/// The top level entries starting on the lines of `range`, in the order of
+/// the document.
+pub(crate) fn selected_entries(
+    tree: &tree_sitter::Tree,
+    range: lsp_types::Range,
+) -> Vec<tree_sitter::Node<'_>> {
+    let mut cursor = tree.root_node().walk();
+    tree.root_node()
+        .named_children(&mut cursor)
+        .filter(|entry| entry.kind() != "comment" && entry.kind() != "ERROR")
+        .filter(|entry| {
+            let row = entry.start_position().row as u32;
+            range.start.line <= row && row <= range.end.line
+        })
+        .collect()
+}
+
+/// The default name of the file the entries are extracted to: their first
+/// month, next to the document.
+pub(crate) fn default_path(content: &ropey::Rope, entries: &[tree_sitter::Node]) -> String {
+    entries
+        .iter()
+        .filter_map(|entry| entry.child_by_field_name("date"))
+        .filter_map(|date| parse_date(&text_for_tree_sitter_node(content, &date)))
+        .min()
+        .map_or(String::from("extracted.beancount"), |date| {
+            date.format("%Y-%m.beancount").to_string()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::extract::extract_to_file;
+    use crate::providers::extract::ExtractToFileParams;
+    use crate::test_utils::TestState;
+    use crate::utils::apply_edits;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_extract_to_file() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+2024-02-10 * "Grocer"
+    Assets:Bank -10 USD
+    Expenses:Food
+2024-02-01 * "Landlord"
+    Assets:Bank -100 USD
+    Expenses:Rent
+2024-03-01 close Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = ExtractToFileParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(1, 0),
+                lsp_types::Position::new(5, 3),
+            ),
+            path: String::from("2024-02.beancount"),
+        };
+        let edit = extract_to_file(test_state.snapshot, params).unwrap();
+        let Some(lsp_types::DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected operations");
+        };
+        let edits = |uri: &str| -> Vec<lsp_types::TextEdit> {
+            operations
+                .iter()
+                .filter_map(|operation| match operation {
+                    lsp_types::DocumentChangeOperation::Edit(edit)
+                        if edit.text_document.uri.as_str() == uri =>
+                    {
+                        Some(edit.edits.iter().filter_map(|edit| match edit {
+                            lsp_types::OneOf::Left(edit) => Some(edit.clone()),
+                            lsp_types::OneOf::Right(_) => None,
+                        }))
+                    }
+                    _ => None,
+                })
+                .flatten()
+                .collect()
+        };
+        assert!(matches!(
+            &operations[0],
+            lsp_types::DocumentChangeOperation::Op(lsp_types::ResourceOp::Create(create))
+                if create.uri.as_str() == "file:///2024-02.beancount"
+        ));
+        assert_eq!(
+            apply_edits("", &edits("file:///2024-02.beancount")),
+            "\
+2024-02-01 * \"Landlord\"
+    Assets:Bank -100 USD
+    Expenses:Rent
+2024-02-10 * \"Grocer\"
+    Assets:Bank -10 USD
+    Expenses:Food
+"
+        );
+        let main = fixure.split_once("beancount\n").unwrap().1;
+        assert_eq!(
+            apply_edits(main, &edits("file:///main.beancount")),
+            "\
+2024-01-01 open Assets:Bank
+include \"2024-02.beancount\"
+2024-03-01 close Assets:Bank
+"
+        );
+    }
+}
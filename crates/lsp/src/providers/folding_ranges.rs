@@ -0,0 +1,156 @@
+use crate::server::LspServerStateSnapshot;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use tracing::debug;
+
+/// Provider function for LSP `textDocument/foldingRange`. Folds the org-mode
+/// sections and the transactions below their first line, like the outline of
+/// `textDocument/documentSymbol` nests them, and the metadata lines below
+/// each posting.
+pub(crate) fn folding_ranges(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::FoldingRangeParams,
+) -> Result<Option<Vec<lsp_types::FoldingRange>>> {
+    debug!("providers::folding_ranges");
+    let uri = params.text_document.uri.to_file_path().unwrap();
+    let Some(tree) = snapshot.forest.get(&uri) else {
+        return Ok(None);
+    };
+    let mut ranges = Vec::new();
+    children_ranges(tree.root_node(), &mut ranges);
+    Ok(Some(ranges))
+}
+
+/// The folding ranges of the sections and transactions directly in `node`,
+/// and of what they contain.
+fn children_ranges(node: tree_sitter::Node, ranges: &mut Vec<lsp_types::FoldingRange>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "section" => {
+                ranges.extend(folding_range(
+                    child.start_position().row,
+                    last_row(&child),
+                    Some(lsp_types::FoldingRangeKind::Region),
+                ));
+                children_ranges(child, ranges);
+            }
+            "transaction" => {
+                ranges.extend(folding_range(
+                    child.start_position().row,
+                    last_row(&child),
+                    None,
+                ));
+                let mut lines = Vec::new();
+                posting_lines(child, &mut lines);
+                ranges.extend(posting_metadata_ranges(&lines));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A range folding the lines after `start` up to `end`, if there are any.
+fn folding_range(
+    start: usize,
+    end: usize,
+    kind: Option<lsp_types::FoldingRangeKind>,
+) -> Option<lsp_types::FoldingRange> {
+    (end > start).then(|| lsp_types::FoldingRange {
+        start_line: start as u32,
+        end_line: end as u32,
+        kind,
+        ..Default::default()
+    })
+}
+
+/// The last row of a node, which ends at the start of the line after it if
+/// it takes the whole line.
+fn last_row(node: &tree_sitter::Node) -> usize {
+    let start = node.start_position();
+    let end = node.end_position();
+    if end.column == 0 && end.row > start.row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+/// The postings and metadata lines in a transaction, in the order of the
+/// document, as whether the line is a posting and its rows.
+fn posting_lines(node: tree_sitter::Node, lines: &mut Vec<(bool, usize, usize)>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "posting" => {
+                lines.push((true, child.start_position().row, last_row(&child)));
+                // the metadata of a posting may be nested in it
+                posting_lines(child, lines);
+            }
+            "key_value" => lines.push((false, child.start_position().row, last_row(&child))),
+            _ => posting_lines(child, lines),
+        }
+    }
+}
+
+/// Ranges folding the metadata lines that follow each posting.
+fn posting_metadata_ranges(lines: &[(bool, usize, usize)]) -> Vec<lsp_types::FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut lines = lines.iter().peekable();
+    while let Some(&(is_posting, start, end)) = lines.next() {
+        if !is_posting {
+            continue;
+        }
+        let mut last = end;
+        while let Some(&&(false, _, metadata_end)) = lines.peek() {
+            last = metadata_end;
+            lines.next();
+        }
+        ranges.extend(folding_range(
+            start,
+            last,
+            Some(lsp_types::FoldingRangeKind::Region),
+        ));
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::folding_ranges::folding_ranges;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_folding_ranges() {
+        let fixure = r#"
+%! /main.beancount
+* Accounts
+2024-01-01 open Assets:Bank
+* Transactions
+** January
+2024-01-02 * "Grocer"
+    Assets:Bank -10 USD
+        receipt: "grocer.pdf"
+        checked: TRUE
+    Expenses:Food
+2024-01-03 balance Assets:Bank -10 USD
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::FoldingRangeParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let mut ranges: Vec<_> = folding_ranges(test_state.snapshot, params)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line))
+            .collect();
+        ranges.sort();
+        assert_eq!(ranges, [(0, 1), (2, 9), (3, 9), (4, 8), (5, 7)]);
+    }
+}
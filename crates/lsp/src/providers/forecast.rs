@@ -0,0 +1,242 @@
+use crate::beancount_data::forecast_date_for_custom_node;
+use crate::beancount_data::is_forecast_transaction;
+use crate::beancount_data::parse_date;
+use crate::beancount_data::FORECAST_TAG;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::PositionEncoding;
+use anyhow::Result;
+use chrono::Datelike;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Arguments of the `beancount.materializeForecasts` command.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaterializeForecastsParams {
+    /// A date in the month to materialize forecasts for, defaults to today.
+    pub date: Option<String>,
+}
+
+/// Provider function for the `beancount.materializeForecasts` command. Turns
+/// forecast entries dated up to the end of the month into real transactions:
+/// the `#forecast` tag is removed from tagged transactions, and
+/// `custom "forecast" "Narration" Account Amount Account` directives are
+/// rewritten as a transaction between the two accounts.
+pub(crate) fn materialize_forecasts(
+    snapshot: LspServerStateSnapshot,
+    params: MaterializeForecastsParams,
+) -> Result<lsp_types::WorkspaceEdit> {
+    debug!("providers::forecast::materialize_forecasts");
+    let today = match params.date {
+        Some(date) => parse_date(&date).ok_or_else(|| anyhow::anyhow!("invalid date: {}", date))?,
+        None => chrono::Local::now().date_naive(),
+    };
+    let due = last_day_of_month(today);
+
+    let query = tree_sitter::Query::new(
+        &tree_sitter_beancount::language(),
+        "[(transaction) (custom)] @entry",
+    )?;
+    let mut changes: HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>> = HashMap::new();
+    for (path, tree) in snapshot.forest.iter() {
+        let Some(content) = snapshot.document_content(path) else {
+            continue;
+        };
+        let text = content.to_string();
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let mut edits = Vec::new();
+        for matched in query_cursor.matches(&query, tree.root_node(), text.as_bytes()) {
+            for capture in matched.captures {
+                let node = capture.node;
+                let encoding = snapshot.config.position_encoding;
+                let edit = if node.kind() == "transaction" {
+                    materialize_transaction(&content, &node, due, encoding)
+                } else {
+                    materialize_custom(&content, &node, due, encoding)
+                };
+                edits.extend(edit);
+            }
+        }
+        if !edits.is_empty() {
+            let uri =
+                lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+            changes.insert(uri, edits);
+        }
+    }
+
+    Ok(lsp_types::WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    })
+}
+
+fn last_day_of_month(date: chrono::NaiveDate) -> chrono::NaiveDate {
+    let (year, month) = if date.month() == 12 {
+        (date.year() + 1, 1)
+    } else {
+        (date.year(), date.month() + 1)
+    };
+    chrono::NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|first| first.pred_opt())
+        .unwrap_or(date)
+}
+
+/// Removes the `#forecast` tag from a due forecast transaction.
+fn materialize_transaction(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    due: chrono::NaiveDate,
+    encoding: PositionEncoding,
+) -> Option<lsp_types::TextEdit> {
+    if !is_forecast_transaction(content, node) {
+        return None;
+    }
+    let date = parse_date(&text_for_tree_sitter_node(
+        content,
+        &node.child_by_field_name("date")?,
+    ))?;
+    if date > due {
+        return None;
+    }
+    let tags_links = node.child_by_field_name("tags_links")?;
+    let mut cursor = tags_links.walk();
+    let tag = tags_links.children(&mut cursor).find(|tag| {
+        tag.kind() == "tag" && text_for_tree_sitter_node(content, tag) == FORECAST_TAG
+    })?;
+    // remove the tag together with the whitespace before it
+    let previous = tag.prev_sibling().or_else(|| tags_links.prev_sibling())?;
+    Some(lsp_types::TextEdit {
+        range: lsp_types::Range {
+            start: lsp_range_for_tree_sitter_node(content, &previous, encoding).end,
+            end: lsp_range_for_tree_sitter_node(content, &tag, encoding).end,
+        },
+        new_text: String::new(),
+    })
+}
+
+/// The indent of the postings of a materialized `custom "forecast"`.
+const POSTING_INDENT: &str = "    ";
+
+/// Rewrites a due `custom "forecast"` directive as a transaction.
+fn materialize_custom(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    due: chrono::NaiveDate,
+    encoding: PositionEncoding,
+) -> Option<lsp_types::TextEdit> {
+    let date = forecast_date_for_custom_node(content, node)?;
+    if date > due {
+        return None;
+    }
+    let mut cursor = node.walk();
+    let values: Vec<_> = node
+        .children_by_field_name("custom_value_list", &mut cursor)
+        .collect();
+    let [narration, account, amount, other_account] = values.as_slice() else {
+        return None;
+    };
+    let value = |node: &tree_sitter::Node, kind: &str| {
+        node.named_child(0)
+            .filter(|child| child.kind() == kind)
+            .map(|child| text_for_tree_sitter_node(content, &child))
+    };
+    let new_text = format!(
+        "{} * {}\n{POSTING_INDENT}{} {}\n{POSTING_INDENT}{}",
+        date.format("%Y-%m-%d"),
+        value(narration, "string")?,
+        value(account, "account")?,
+        value(amount, "amount")?,
+        value(other_account, "account")?,
+    );
+    Some(lsp_types::TextEdit {
+        range: lsp_types::Range {
+            start: lsp_range_for_tree_sitter_node(content, node, encoding).start,
+            end: lsp_range_for_tree_sitter_node(content, other_account, encoding).end,
+        },
+        new_text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::forecast::materialize_forecasts;
+    use crate::providers::forecast::MaterializeForecastsParams;
+    use crate::test_utils::apply_edits;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_materialize_forecasts() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-31 * "Rent" "January" #forecast ^rent
+    Expenses:Rent 1200.00 USD
+    Assets:Bank
+2024-02-29 * "Rent" "February" #forecast
+    Expenses:Rent 1200.00 USD
+    Assets:Bank
+2024-03-31 * "Rent" "March" #forecast
+    Expenses:Rent 1200.00 USD
+    Assets:Bank
+2024-02-15 custom "forecast" "Gym" Expenses:Gym 30.00 USD Assets:Bank
+2024-03-15 custom "forecast" "Gym" Expenses:Gym 30.00 USD Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let text = test_state.fixture.documents[0].text.clone();
+        let params = MaterializeForecastsParams {
+            date: Some(String::from("2024-02-10")),
+        };
+        let edit = materialize_forecasts(test_state.snapshot, params).unwrap();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let materialized = apply_edits(&text, &edit.changes.unwrap()[&uri]);
+        assert_eq!(
+            materialized,
+            r#"2024-01-31 * "Rent" "January" ^rent
+    Expenses:Rent 1200.00 USD
+    Assets:Bank
+2024-02-29 * "Rent" "February"
+    Expenses:Rent 1200.00 USD
+    Assets:Bank
+2024-03-31 * "Rent" "March" #forecast
+    Expenses:Rent 1200.00 USD
+    Assets:Bank
+2024-02-15 * "Gym"
+    Expenses:Gym 30.00 USD
+    Assets:Bank
+2024-03-15 custom "forecast" "Gym" Expenses:Gym 30.00 USD Assets:Bank"#
+        );
+    }
+
+    #[test]
+    fn handle_forecasts_excluded_from_postings() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 * "Salary"
+    Assets:Bank 100.00 USD
+    Income:Salary
+2024-03-31 * "Rent" #forecast
+    Expenses:Rent 50.00 USD
+    Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let data =
+            &test_state.snapshot.beancount_data[&std::path::PathBuf::from("/main.beancount")];
+        assert_eq!(data.get_postings().len(), 2);
+        assert_eq!(
+            data.get_narration_counts().keys().collect::<Vec<_>>(),
+            ["Salary"]
+        );
+        assert_eq!(
+            data.get_account_pair_count("Expenses:Rent", "Assets:Bank"),
+            0
+        );
+        let forecasts = data.get_forecasts();
+        assert_eq!(forecasts.len(), 1);
+        assert_eq!((forecasts[0].start_line, forecasts[0].end_line), (3, 5));
+    }
+}
@@ -1,4 +1,8 @@
+use crate::amount::NumberFormat;
+use crate::bean_format;
 use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::lsp_position_for_tree_sitter_point;
+use crate::treesitter_utils::PositionEncoding;
 use crate::utils::ToFilePath;
 use anyhow::Result;
 use std::cmp::Ordering;
@@ -12,6 +16,8 @@ struct TSRange {
 struct Match {
     prefix: Option<TSRange>,
     number: Option<TSRange>,
+    /// The tolerance of a balance, like `0.05` in `1000.00 ~ 0.05 USD`.
+    tolerance: Option<TSRange>,
 }
 
 const QUERY_STR: &str = r#"
@@ -27,10 +33,14 @@ const QUERY_STR: &str = r#"
             ( balance
                 (account) @prefix
                 (amount_tolerance
-                    ([
+                    . ([
                         (unary_number_expr)
                         (number)
                     ] @number)
+                    ([
+                        (unary_number_expr)
+                        (number)
+                    ] @tolerance)?
                 )
             )
 "#;
@@ -71,6 +81,14 @@ pub(crate) fn formatting(
     let uri = params.text_document.uri.to_file_path().unwrap();
     let tree = snapshot.forest.get(&uri).unwrap();
     let doc = snapshot.open_docs.get(&uri).unwrap();
+    let encoding = snapshot.config.position_encoding;
+    if snapshot.config.formatting.bean_format {
+        return Ok(Some(bean_format_edits(
+            &doc.content,
+            snapshot.config.formatting.currency_column,
+            encoding,
+        )));
+    }
 
     let query = tree_sitter::Query::new(&tree.language(), QUERY_STR).unwrap();
     let mut query_cursor = tree_sitter::QueryCursor::new();
@@ -80,10 +98,12 @@ pub(crate) fn formatting(
         RopeProvider(doc.content.get_slice(..).unwrap()),
     );
 
+    let number_format = snapshot.config.number_format.for_content(&doc.content);
     let mut match_pairs: Vec<Match> = Vec::new();
     for matched in matches {
         let mut prefix: Option<TSRange> = None;
         let mut number: Option<TSRange> = None;
+        let mut tolerance: Option<TSRange> = None;
         for capture in matched.captures {
             let capture_name = query.capture_names()[capture.index as usize];
             if capture_name == "prefix" {
@@ -93,14 +113,30 @@ pub(crate) fn formatting(
                 });
             } else if capture_name == "number" {
                 number = Some(TSRange {
+                    start: capture.node.start_position(),
+                    end: number_end(&doc.content, capture.node.end_position(), number_format),
+                });
+            } else if capture_name == "tolerance" {
+                tolerance = Some(TSRange {
                     start: capture.node.start_position(),
                     end: capture.node.end_position(),
                 });
             }
         }
-        match_pairs.push(Match { prefix, number });
+        match_pairs.push(Match {
+            prefix,
+            number,
+            tolerance,
+        });
     }
 
+    let tab_size = snapshot
+        .config
+        .formatting
+        .tab_size
+        .unwrap_or(params.options.tab_size)
+        .max(1) as usize;
+
     // TODO
     // Can we normalize the indents of the postings?
     // the optional flags kind of make this hard
@@ -109,14 +145,20 @@ pub(crate) fn formatting(
     let mut max_prefix_width = 0;
     let mut max_number_width = 0;
 
+    // a prefix and number on different lines (only in broken syntax) cannot be aligned
+    match_pairs.retain(
+        |match_pair| match (&match_pair.prefix, &match_pair.number) {
+            (Some(prefix), Some(number)) => prefix.end.row == number.start.row,
+            _ => false,
+        },
+    );
+
     for match_pair in match_pairs.iter() {
-        if match_pair.prefix.is_some() && match_pair.number.is_some() {
-            let prefix = match_pair.prefix.as_ref().unwrap();
-            let mut len = prefix.end.column;
+        if let (Some(prefix), Some(number)) = (&match_pair.prefix, &match_pair.number) {
+            let mut len = display_column(&doc.content, prefix.end, tab_size);
             if len > max_prefix_width {
                 max_prefix_width = len;
             }
-            let number = match_pair.number.as_ref().unwrap();
             len = number.end.column - number.start.column;
             if len > max_number_width {
                 max_number_width = len;
@@ -128,17 +170,33 @@ pub(crate) fn formatting(
     let correct_number_placement = max_prefix_width + prefix_number_buffer;
     let mut text_edits = Vec::new();
     for match_pair in match_pairs {
-        if match_pair.prefix.is_some() && match_pair.number.is_some() {
-            let prefix = match_pair.prefix.as_ref().unwrap();
-            let number = match_pair.number.as_ref().unwrap();
+        if let (Some(number), Some(tolerance)) = (&match_pair.number, &match_pair.tolerance) {
+            text_edits.extend(tolerance_edit(&doc.content, number, tolerance, encoding));
+        }
+        if let (Some(prefix), Some(number)) = (&match_pair.prefix, &match_pair.number) {
             let num_len = number.end.column - number.start.column;
-            let num_col_pos = number.start.column;
+            let prefix_col_pos = display_column(&doc.content, prefix.end, tab_size);
+            let num_col_pos = display_column(&doc.content, number.start, tab_size);
             let new_num_pos = correct_number_placement + (max_number_width - num_len);
 
-            let insert_pos = lsp_types::Position {
-                line: prefix.end.row as u32,
-                character: prefix.end.column as u32,
-            };
+            let insert_pos = lsp_position_for_tree_sitter_point(&doc.content, prefix.end, encoding);
+
+            // A gap containing tabs cannot be adjusted by adding or removing a
+            // few characters without leaving a mix of tabs and spaces behind,
+            // so replace the whole gap with spaces instead.
+            if gap_contains_tab(&doc.content, prefix.end, number.start) {
+                let end_pos =
+                    lsp_position_for_tree_sitter_point(&doc.content, number.start, encoding);
+                let edit = lsp_types::TextEdit {
+                    range: lsp_types::Range {
+                        start: insert_pos,
+                        end: end_pos,
+                    },
+                    new_text: " ".repeat(new_num_pos - prefix_col_pos),
+                };
+                text_edits.push(edit);
+                continue;
+            }
 
             match new_num_pos.cmp(&num_col_pos) {
                 Ordering::Greater => {
@@ -175,3 +233,200 @@ pub(crate) fn formatting(
 
     Ok(Some(text_edits))
 }
+
+/// Replaces the lines that `bean-format` aligns differently.
+fn bean_format_edits(
+    content: &ropey::Rope,
+    currency_column: Option<usize>,
+    encoding: PositionEncoding,
+) -> Vec<lsp_types::TextEdit> {
+    let lines: Vec<String> = content
+        .lines()
+        .map(|line| line.to_string().trim_end_matches(['\r', '\n']).to_string())
+        .collect();
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let aligned = bean_format::align(&lines, currency_column);
+    lines
+        .iter()
+        .zip(aligned)
+        .enumerate()
+        .filter(|(_, (line, aligned))| **line != aligned.as_str())
+        .map(|(row, (line, aligned))| lsp_types::TextEdit {
+            range: lsp_types::Range {
+                start: lsp_position_for_tree_sitter_point(
+                    content,
+                    tree_sitter::Point::new(row, 0),
+                    encoding,
+                ),
+                end: lsp_position_for_tree_sitter_point(
+                    content,
+                    tree_sitter::Point::new(row, line.len()),
+                    encoding,
+                ),
+            },
+            new_text: aligned,
+        })
+        .collect()
+}
+
+/// The end of the number ending at `end` as the grammar sees it. The grammar
+/// only knows decimal points, so the decimals of a number like `1.234,56` are
+/// left out of its node.
+pub(crate) fn number_end(
+    content: &ropey::Rope,
+    end: tree_sitter::Point,
+    format: NumberFormat,
+) -> tree_sitter::Point {
+    let line = content.line(end.row);
+    let rest = line
+        .byte_slice(end.column.min(line.len_bytes())..)
+        .to_string();
+    let extra = rest
+        .chars()
+        .take_while(|c| format.is_number_char(*c))
+        .count();
+    tree_sitter::Point::new(end.row, end.column + extra)
+}
+
+/// Normalizes the gap between the number of a balance and its tolerance to
+/// ` ~ `. The tolerance itself is left as it is, only the number before it is
+/// aligned.
+fn tolerance_edit(
+    content: &ropey::Rope,
+    number: &TSRange,
+    tolerance: &TSRange,
+    encoding: PositionEncoding,
+) -> Option<lsp_types::TextEdit> {
+    if number.end.row != tolerance.start.row || number.end.column > tolerance.start.column {
+        return None;
+    }
+    let line = content.line(number.end.row);
+    let gap = line
+        .byte_slice(number.end.column..tolerance.start.column)
+        .to_string();
+    if gap == " ~ " || gap.trim() != "~" {
+        return None;
+    }
+    Some(lsp_types::TextEdit {
+        range: lsp_types::Range {
+            start: lsp_position_for_tree_sitter_point(content, number.end, encoding),
+            end: lsp_position_for_tree_sitter_point(content, tolerance.start, encoding),
+        },
+        new_text: " ~ ".to_string(),
+    })
+}
+
+/// Converts a tree-sitter point into the column it is displayed at, expanding
+/// tabs to the next multiple of `tab_size`.
+fn display_column(content: &ropey::Rope, point: tree_sitter::Point, tab_size: usize) -> usize {
+    let mut column = 0;
+    let mut byte = 0;
+    for c in content.line(point.row).chars() {
+        if byte >= point.column {
+            break;
+        }
+        column = if c == '\t' {
+            (column / tab_size + 1) * tab_size
+        } else {
+            column + 1
+        };
+        byte += c.len_utf8();
+    }
+    column
+}
+
+fn gap_contains_tab(
+    content: &ropey::Rope,
+    start: tree_sitter::Point,
+    end: tree_sitter::Point,
+) -> bool {
+    let line = content.line(start.row);
+    let start = line.byte_to_char(start.column);
+    let end = line.byte_to_char(end.column.min(line.len_bytes()));
+    line.slice(start..end).chars().any(|c| c == '\t')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::formatting::formatting;
+    use crate::test_utils::apply_edits;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    fn format(fixture: &str, tab_size: u32) -> String {
+        let test_state = TestState::new(fixture).unwrap();
+        let document = &test_state.fixture.documents[0];
+        let text = document.text.clone();
+        let uri = lsp_types::Uri::from_str(format!("file://{}", document.path).as_str()).unwrap();
+        let params = lsp_types::DocumentFormattingParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            options: lsp_types::FormattingOptions {
+                tab_size,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        };
+        let edits = formatting(test_state.snapshot, params)
+            .unwrap()
+            .unwrap_or_default();
+        apply_edits(&text, &edits)
+    }
+
+    #[test]
+    fn handle_alignment() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Test Co"
+    Assets:Test 1.00 USD
+    Expenses:Test:Long  -1.00 USD
+"#;
+        assert_eq!(
+            format(fixure, 4),
+            "2023-10-01 txn \"Test Co\"\n    Assets:Test          1.00 USD\n    Expenses:Test:Long  -1.00 USD"
+        )
+    }
+
+    #[test]
+    fn handle_balance_tolerance_alignment() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 balance Assets:Bank 1000.00~0.05 USD
+2023-10-01 balance Assets:Cash   12.00   ~   0.05 USD
+2023-10-01 balance Assets:Card -5.00 USD
+"#;
+        assert_eq!(
+            format(fixure, 4),
+            "2023-10-01 balance Assets:Bank  1000.00 ~ 0.05 USD\n2023-10-01 balance Assets:Cash    12.00 ~ 0.05 USD\n2023-10-01 balance Assets:Card    -5.00 USD"
+        )
+    }
+
+    #[test]
+    fn handle_tab_indented_alignment() {
+        let fixure = "
+%! /main.beancount
+2023-10-01 txn \"Test Co\"
+\tAssets:Test 1.00 USD
+    Expenses:Test  -1.00 USD
+";
+        assert_eq!(
+            format(fixure, 4),
+            "2023-10-01 txn \"Test Co\"\n\tAssets:Test     1.00 USD\n    Expenses:Test  -1.00 USD"
+        )
+    }
+
+    #[test]
+    fn handle_tab_in_gap() {
+        let fixure = "
+%! /main.beancount
+2023-10-01 txn \"Test Co\"
+    Assets:Test\t1.00 USD
+    Expenses:Test  -1.00 USD
+";
+        assert_eq!(
+            format(fixure, 8),
+            "2023-10-01 txn \"Test Co\"\n    Assets:Test     1.00 USD\n    Expenses:Test  -1.00 USD"
+        )
+    }
+}
@@ -0,0 +1,252 @@
+use crate::amount::Amount;
+use crate::amount::Decimal;
+use crate::ledger;
+use crate::ledger::PriceDatabase;
+use crate::server::LspServerStateSnapshot;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tracing::debug;
+
+/// Arguments of the `beancount.holdings` command.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HoldingsParams {
+    /// The currency to value the holdings in, the first `operating_currency`
+    /// option of the journal if not given.
+    pub operating_currency: Option<String>,
+    #[serde(flatten)]
+    pub filter: ledger::PostingFilter,
+}
+
+/// The units of a commodity held in an account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Holding {
+    pub account: String,
+    pub units: Amount,
+    /// What the units cost, or the units themselves if they are not held at
+    /// cost. `None` if the lots were bought in different currencies.
+    pub book_value: Option<Amount>,
+    /// The units at the latest price in the operating currency, if known.
+    pub market_value: Option<Amount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Holdings {
+    pub operating_currency: Option<String>,
+    pub holdings: Vec<Holding>,
+    /// The holdings rendered as a Markdown table.
+    pub markdown: String,
+}
+
+/// Provider function for the `beancount.holdings` command. Books the postings
+/// to asset and liability accounts into lots and values what is still held
+/// at the end of the period, including the lots bought before it.
+pub(crate) fn holdings(
+    snapshot: LspServerStateSnapshot,
+    params: HoldingsParams,
+) -> Result<Holdings> {
+    debug!("providers::holdings {:?}", params.operating_currency);
+    let data = snapshot.beancount_data.values();
+    let prices = PriceDatabase::new(data.clone().flat_map(|data| data.get_prices()));
+    let operating_currency = params.operating_currency.or_else(|| {
+        data.clone()
+            .find_map(|data| data.get_operating_currencies().first().cloned())
+    });
+    let lots = ledger::lots(
+        data.flat_map(|data| data.get_postings())
+            .filter(|posting| params.filter.books(posting)),
+    );
+
+    let mut grouped: BTreeMap<(String, String), Vec<ledger::Lot>> = BTreeMap::new();
+    for lot in lots {
+        grouped
+            .entry((lot.account.clone(), lot.units.currency.clone()))
+            .or_default()
+            .push(lot);
+    }
+
+    let mut holdings = Vec::new();
+    for ((account, currency), lots) in grouped {
+        let units = Amount::new(
+            lots.iter()
+                .fold(Decimal::default(), |total, lot| total + lot.units.number),
+            currency.clone(),
+        );
+        let book_value = lots
+            .iter()
+            .map(|lot| match &lot.cost {
+                Some(cost) => Amount::new(lot.units.number * cost.number, cost.currency.clone()),
+                None => lot.units.clone(),
+            })
+            .try_fold(None::<Amount>, |total, value| match total {
+                None => Some(Some(value)),
+                Some(total) if total.currency == value.currency => Some(Some(Amount::new(
+                    total.number + value.number,
+                    total.currency,
+                ))),
+                Some(_) => None,
+            })
+            .flatten();
+        let market_value = operating_currency
+            .as_deref()
+            .and_then(|quote| prices.convert(&units, quote))
+            .map(|value| Amount::new(value.number.normalize(), value.currency));
+        holdings.push(Holding {
+            account,
+            units,
+            book_value,
+            market_value,
+        });
+    }
+
+    let markdown = markdown(&holdings, operating_currency.as_deref())?;
+    Ok(Holdings {
+        operating_currency,
+        holdings,
+        markdown,
+    })
+}
+
+fn markdown(holdings: &[Holding], operating_currency: Option<&str>) -> Result<String> {
+    let mut markdown = String::new();
+    writeln!(markdown, "# Holdings\n")?;
+    writeln!(markdown, "| Account | Units | Book Value | Market Value |")?;
+    writeln!(markdown, "| --- | ---: | ---: | ---: |")?;
+    let show = |amount: &Option<Amount>| {
+        amount
+            .as_ref()
+            .map(|amount| amount.to_string())
+            .unwrap_or_default()
+    };
+    for holding in holdings {
+        writeln!(
+            markdown,
+            "| {} | {} | {} | {} |",
+            holding.account,
+            holding.units,
+            show(&holding.book_value),
+            show(&holding.market_value)
+        )?;
+    }
+    if let Some(currency) = operating_currency {
+        let values: Vec<_> = holdings
+            .iter()
+            .map(|holding| holding.market_value.as_ref())
+            .collect();
+        if let Some(values) = values.into_iter().collect::<Option<Vec<_>>>() {
+            let total = values
+                .iter()
+                .fold(Decimal::default(), |total, value| total + value.number);
+            writeln!(markdown, "\n**Total:** {}", Amount::new(total, currency))?;
+        }
+    }
+    Ok(markdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amount::Amount;
+    use crate::ledger::PostingFilter;
+    use crate::providers::holdings::holdings;
+    use crate::providers::holdings::HoldingsParams;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    const FIXTURE: &str = r#"
+%! /main.beancount
+option "operating_currency" "USD"
+2023-01-01 price STK 100.00 USD
+2023-03-01 price STK 120.00 USD
+2023-01-01 price EUR 1.10 USD
+2023-01-02 txn "Broker" "Buy"
+    Assets:Stock 10 STK {100.00 USD}
+    Assets:Bank
+2023-02-02 txn "Broker" "Buy"
+    Assets:Stock 5 STK {110.00 USD}
+    Assets:Bank
+2023-03-02 txn "Broker" "Sell"
+    Assets:Stock -4 STK {100.00 USD}
+    Assets:Bank 480.00 USD
+    Income:Gains
+2023-03-03 txn "Exchange" "Deposit"
+    Assets:Savings 100 EUR
+    Income:Other
+"#;
+
+    fn amount(text: &str) -> Option<Amount> {
+        Amount::parse(text)
+    }
+
+    #[test]
+    fn handle_holdings() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let result = holdings(test_state.snapshot, HoldingsParams::default()).unwrap();
+        assert_eq!(result.operating_currency.as_deref(), Some("USD"));
+        let stock = result
+            .holdings
+            .iter()
+            .find(|holding| holding.account == "Assets:Stock")
+            .unwrap();
+        assert_eq!(Some(stock.units.clone()), amount("11 STK"));
+        assert_eq!(stock.book_value, amount("1150.00 USD"));
+        assert_eq!(stock.market_value, amount("1320 USD"));
+        let savings = result
+            .holdings
+            .iter()
+            .find(|holding| holding.account == "Assets:Savings")
+            .unwrap();
+        assert_eq!(savings.book_value, amount("100 EUR"));
+        assert_eq!(savings.market_value, amount("110 USD"));
+        assert!(result
+            .markdown
+            .contains("| Assets:Stock | 11 STK | 1150.00 USD | 1320 USD |"));
+    }
+
+    #[test]
+    fn handle_holdings_in_other_currency() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let params = HoldingsParams {
+            operating_currency: Some(String::from("EUR")),
+            ..Default::default()
+        };
+        let result = holdings(test_state.snapshot, params).unwrap();
+        let savings = result
+            .holdings
+            .iter()
+            .find(|holding| holding.account == "Assets:Savings")
+            .unwrap();
+        assert_eq!(savings.market_value, amount("100 EUR"));
+        let stock = result
+            .holdings
+            .iter()
+            .find(|holding| holding.account == "Assets:Stock")
+            .unwrap();
+        assert_eq!(stock.market_value, None);
+    }
+
+    #[test]
+    fn handle_holdings_from() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let params = HoldingsParams {
+            filter: PostingFilter {
+                from: chrono::NaiveDate::from_ymd_opt(2023, 3, 1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = holdings(test_state.snapshot, params).unwrap();
+        // the lots bought before the period are still held
+        let stock = result
+            .holdings
+            .iter()
+            .find(|holding| holding.account == "Assets:Stock")
+            .unwrap();
+        assert_eq!(Some(stock.units.clone()), amount("11 STK"));
+        assert_eq!(stock.book_value, amount("1150.00 USD"));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::amount::number_expr_for_tree_sitter_node;
+use crate::amount::Amount;
+use crate::amount::Decimal;
+use crate::beancount_data::parse_date;
+use crate::ledger;
+use crate::ledger::PriceDatabase;
+use crate::providers::completion::enclosing_posting;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::entry_for_tree_sitter_node;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::tree_sitter_point_for_lsp_position;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::path::Path;
+use tracing::debug;
+
+/// Provider function for LSP `textDocument/hover`. Shows the value of the
+/// number expression under the cursor. For the amount of a posting it also
+/// shows the amount in the currency of its price annotation or, without one,
+/// at the latest price in the operating currency. For an account in a dated
+/// entry it shows the balance of the account as of that date.
+pub(crate) fn hover(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::HoverParams,
+) -> Result<Option<lsp_types::Hover>> {
+    debug!("providers::hover");
+    let position = params.text_document_position_params;
+    let file = position.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) =
+        (snapshot.forest.get(&file), snapshot.document_content(&file))
+    else {
+        return Ok(None);
+    };
+    let encoding = snapshot.config.position_encoding;
+    let point = tree_sitter_point_for_lsp_position(&content, position.position, encoding);
+    let node = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point);
+    if let Some(account) = node.filter(|node| node.kind() == "account") {
+        return Ok(account_hover(&snapshot, &file, &content, account));
+    }
+    let Some(expression) = node.and_then(number_expr_for_tree_sitter_node) else {
+        return Ok(None);
+    };
+    let format = snapshot.config.number_format.for_content(&content);
+    let text = text_for_tree_sitter_node(&content, &expression);
+    let Some(value) = Decimal::evaluate_at_literal_scale(&text, format) else {
+        return Ok(None);
+    };
+
+    let mut lines = vec![];
+    if Decimal::parse_with(&text.replace(' ', ""), format).is_none() {
+        lines.push(format!("`{}` = {}", text.trim(), format.display(value)));
+    }
+
+    // only the amount of a posting is converted, not its cost or price
+    let amount = enclosing_posting(expression)
+        .and_then(|posting| posting.child_by_field_name("amount"))
+        .filter(|amount| amount.byte_range().contains(&expression.start_byte()));
+    let currency = amount.and_then(|amount| {
+        let mut cursor = amount.walk();
+        let currency = amount
+            .children(&mut cursor)
+            .find(|child| child.kind() == "currency")
+            .map(|currency| text_for_tree_sitter_node(&content, &currency));
+        currency
+    });
+    if let (Some(amount), Some(currency)) = (amount, currency) {
+        let data = snapshot.journal_data(&file);
+        let line = amount.start_position().row as u32;
+        let price = data
+            .get(&file)
+            .and_then(|data| {
+                data.get_postings()
+                    .iter()
+                    .find(|posting| posting.line == line)
+            })
+            .and_then(|posting| posting.price.clone());
+        match price {
+            Some(price) => lines.push(format!(
+                "= {} {}",
+                format.display((value * price.number).normalize()),
+                price.currency
+            )),
+            None => {
+                let prices = PriceDatabase::new(data.values().flat_map(|data| data.get_prices()));
+                let operating_currency = data
+                    .values()
+                    .find_map(|data| data.get_operating_currencies().first().cloned());
+                let converted = operating_currency
+                    .filter(|operating_currency| *operating_currency != currency)
+                    .and_then(|operating_currency| {
+                        prices.convert(&Amount::new(value, currency), &operating_currency)
+                    });
+                if let Some(converted) = converted {
+                    lines.push(format!(
+                        "≈ {} {} at the latest price",
+                        format.display(converted.number.normalize()),
+                        converted.currency
+                    ));
+                }
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(lsp_types::Hover {
+        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: lines.join("\n\n"),
+        }),
+        range: Some(lsp_range_for_tree_sitter_node(
+            &content,
+            &expression,
+            encoding,
+        )),
+    }))
+}
+
+/// The balance of the account under the cursor as of the date of its entry,
+/// including the postings on that day. For a `balance` directive it is the
+/// balance the directive is checked against instead: that at the start of the
+/// day, including the sub-accounts.
+fn account_hover(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+    account: tree_sitter::Node,
+) -> Option<lsp_types::Hover> {
+    let entry = entry_for_tree_sitter_node(account)?;
+    let date = entry
+        .child_by_field_name("date")
+        .and_then(|date| parse_date(&text_for_tree_sitter_node(content, &date)))?;
+    let name = text_for_tree_sitter_node(content, &account);
+    let data = snapshot.journal_data(file);
+    let (balance, when) = if entry.kind() == "balance" {
+        (
+            ledger::balance_before(&data, &name, true, date),
+            format!("at the start of {}", date.format("%Y-%m-%d")),
+        )
+    } else {
+        (
+            ledger::balance_before(&data, &name, false, date.succ_opt()?),
+            format!("on {}", date.format("%Y-%m-%d")),
+        )
+    };
+    let format = snapshot.config.number_format.for_content(content);
+    let amounts: Vec<String> = balance
+        .amounts()
+        .map(|amount| format!("{} {}", format.display(amount.number), amount.currency))
+        .collect();
+    let amounts = if amounts.is_empty() {
+        String::from("0")
+    } else {
+        amounts.join(", ")
+    };
+    let encoding = snapshot.config.position_encoding;
+    Some(lsp_types::Hover {
+        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: format!("`{name}` {when}: {amounts}"),
+        }),
+        range: Some(lsp_range_for_tree_sitter_node(content, &account, encoding)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::hover::hover;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    fn hover_text(fixture: &str) -> Option<String> {
+        let test_state = TestState::new(fixture).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::HoverParams {
+            text_document_position_params: cursor,
+            work_done_progress_params: Default::default(),
+        };
+        let hover = hover(test_state.snapshot, params).unwrap()?;
+        let lsp_types::HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup");
+        };
+        Some(markup.value)
+    }
+
+    #[test]
+    fn handle_hover_expression_with_price() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Exchange" "Travel money"
+    Assets:Cash (300.00 / 3) EUR @ 1.10 USD
+                  |
+    Assets:Bank
+"#;
+        assert_eq!(
+            hover_text(fixure).as_deref(),
+            Some("`(300.00 / 3)` = 100.00\n\n= 110 USD")
+        );
+    }
+
+    #[test]
+    fn handle_hover_amount_at_latest_price() {
+        let fixure = r#"
+%! /main.beancount
+option "operating_currency" "USD"
+2023-10-01 price EUR 1.20 USD
+2023-10-02 txn "Exchange" "Travel money"
+    Assets:Cash 50 EUR
+                 |
+    Assets:Bank
+"#;
+        assert_eq!(
+            hover_text(fixure).as_deref(),
+            Some("≈ 60 USD at the latest price")
+        );
+    }
+
+    #[test]
+    fn handle_hover_plain_number() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-02 txn "Grocer" "Food"
+    Assets:Cash -50 USD
+                 |
+    Expenses:Food
+"#;
+        assert_eq!(hover_text(fixure), None);
+    }
+
+    #[test]
+    fn handle_hover_account_balance() {
+        let ledger = r#"
+%! /main.beancount
+2023-10-01 txn "Employer" "Salary"
+    Assets:Bank 100.00 USD
+    Income:Salary
+2023-10-02 txn "Grocer" "Food"
+    Assets:Bank:Cash -10.00 USD
+    Expenses:Food
+2023-10-03 txn "Grocer" "Food"
+    Assets:Bank -20.00 USD
+    Expenses:Food
+2023-10-03 balance Assets:Bank 90.00 USD
+"#;
+        // the fixture starts with an empty line and the path
+        let at = |line: usize, marker: &str| {
+            let mut lines: Vec<&str> = ledger.lines().collect();
+            lines.insert(line + 3, marker);
+            hover_text(&lines.join("\n"))
+        };
+        // the salary posting on 2023-10-01
+        assert_eq!(
+            at(1, "       |").as_deref(),
+            Some("`Assets:Bank` on 2023-10-01: 100.00 USD")
+        );
+        // the grocery posting on 2023-10-03 counts
+        assert_eq!(
+            at(7, "       |").as_deref(),
+            Some("`Assets:Bank` on 2023-10-03: 80.00 USD")
+        );
+        // the balance directive sees the start of the day and Assets:Bank:Cash
+        assert_eq!(
+            at(9, "                     |").as_deref(),
+            Some("`Assets:Bank` at the start of 2023-10-03: 90.00 USD")
+        );
+    }
+}
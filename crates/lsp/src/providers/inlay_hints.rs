@@ -0,0 +1,395 @@
+use crate::budget;
+use crate::ledger::PriceDatabase;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::PositionEncoding;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::path::Path;
+use tracing::debug;
+
+/// Provider function for LSP `textDocument/inlayHint`.
+pub(crate) fn inlay_hints(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::InlayHintParams,
+) -> Result<Option<Vec<lsp_types::InlayHint>>> {
+    debug!("providers::inlay_hints");
+    let uri = params.text_document.uri.to_file_path().unwrap();
+    let Some(doc) = snapshot.open_docs.get(&uri) else {
+        return Ok(None);
+    };
+    let in_range = |line: u32| params.range.start.line <= line && line <= params.range.end.line;
+
+    let mut hints = Vec::new();
+    if snapshot.config.budget.enable {
+        for status in budget::posting_budgets(&snapshot.beancount_data) {
+            if status.file != uri || !in_range(status.line) {
+                continue;
+            }
+            hints.push(lsp_types::InlayHint {
+                position: line_end_position(
+                    &doc.content,
+                    status.line,
+                    snapshot.config.position_encoding,
+                ),
+                label: lsp_types::InlayHintLabel::String(format!(
+                    "{} {} left ({})",
+                    status.remaining(),
+                    status.budget.currency,
+                    status.period
+                )),
+                kind: None,
+                text_edits: None,
+                tooltip: Some(lsp_types::InlayHintTooltip::String(format!(
+                    "{} budget for {}: {}",
+                    status.period, status.account, status.budget
+                ))),
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            });
+        }
+    }
+    if snapshot.config.inlay_hints.operating_currency_equivalent {
+        hints.extend(
+            operating_currency_equivalents(&snapshot, &uri, &doc.content)
+                .into_iter()
+                .filter(|hint| in_range(hint.position.line)),
+        );
+    }
+    if snapshot.config.inlay_hints.file_summary {
+        if let Some(hint) = file_summary(&snapshot, &uri, &doc.content) {
+            if in_range(hint.position.line) {
+                hints.push(hint);
+            }
+        }
+    }
+    Ok(Some(hints))
+}
+
+/// The amounts of the postings of `file` in another currency than the first
+/// operating currency, converted to it at the price on the date of their
+/// transaction.
+fn operating_currency_equivalents(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+) -> Vec<lsp_types::InlayHint> {
+    let data = snapshot.journal_data(file);
+    let Some(operating_currency) = data
+        .values()
+        .find_map(|data| data.get_operating_currencies().first().cloned())
+    else {
+        return vec![];
+    };
+    let Some(postings) = data.get(file).map(|data| data.get_postings()) else {
+        return vec![];
+    };
+    let prices = PriceDatabase::new(data.values().flat_map(|data| data.get_prices()));
+    let format = snapshot.config.number_format.for_content(content);
+    postings
+        .iter()
+        .filter_map(|posting| {
+            let amount = posting
+                .amount
+                .as_ref()
+                .filter(|amount| amount.currency != operating_currency)?;
+            let converted = prices.convert_on(amount, &operating_currency, posting.date)?;
+            Some(lsp_types::InlayHint {
+                position: line_end_position(
+                    content,
+                    posting.line,
+                    snapshot.config.position_encoding,
+                ),
+                label: lsp_types::InlayHintLabel::String(format!(
+                    "≈ {} {}",
+                    format.display(converted.number.with_scale(2)),
+                    converted.currency
+                )),
+                kind: None,
+                text_edits: None,
+                tooltip: Some(lsp_types::InlayHintTooltip::String(format!(
+                    "At the price of {} on {}",
+                    amount.currency, posting.date
+                ))),
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// A summary of the journal `file` is the root of, after its last line: the
+/// number of errors, when it was last checked and the date of its newest
+/// transaction.
+fn file_summary(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+) -> Option<lsp_types::InlayHint> {
+    let journal = snapshot
+        .journals
+        .iter()
+        .find(|journal| journal.root == file)?;
+    let errors = journal
+        .files
+        .iter()
+        .filter_map(|file| snapshot.diagnostics.get(file))
+        .flatten()
+        .filter(|diag| diag.severity == Some(lsp_types::DiagnosticSeverity::ERROR))
+        .count();
+    let newest = journal
+        .files
+        .iter()
+        .filter_map(|file| snapshot.beancount_data.get(file))
+        .flat_map(|data| data.get_postings())
+        .map(|posting| posting.date)
+        .max();
+
+    let mut parts = vec![match errors {
+        1 => String::from("1 error"),
+        errors => format!("{errors} errors"),
+    }];
+    parts.push(match snapshot.last_checks.get(file) {
+        Some(time) => format!("checked {}", time.format("%Y-%m-%d %H:%M")),
+        None => String::from("not checked yet"),
+    });
+    if let Some(newest) = newest {
+        parts.push(format!("newest transaction {newest}"));
+    }
+
+    // after the last line with text, not on the empty line after it
+    let mut line = content.len_lines().saturating_sub(1);
+    while line > 0 && content.line(line).chars().all(char::is_whitespace) {
+        line -= 1;
+    }
+    Some(lsp_types::InlayHint {
+        position: line_end_position(content, line as u32, snapshot.config.position_encoding),
+        label: lsp_types::InlayHintLabel::String(parts.join(" · ")),
+        kind: None,
+        text_edits: None,
+        tooltip: None,
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    })
+}
+
+/// The position after the last character of `line`.
+fn line_end_position(
+    content: &ropey::Rope,
+    line: u32,
+    encoding: PositionEncoding,
+) -> lsp_types::Position {
+    let text = content.line(line as usize);
+    let mut end_char = text.len_chars();
+    while end_char > 0 && matches!(text.char(end_char - 1), '\n' | '\r') {
+        end_char -= 1;
+    }
+    byte_to_lsp_position(
+        content,
+        content.line_to_byte(line as usize) + text.char_to_byte(end_char),
+        encoding,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::inlay_hints::inlay_hints;
+    use crate::server::Journal;
+    use crate::test_utils::TestState;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use test_log::test;
+
+    #[test]
+    fn handle_budget_inlay_hints() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 custom "budget" Expenses:Food "monthly" 100.00 USD
+2024-01-02 txn "Grocer" "Food"
+    Assets:Bank -60.00 USD
+    Expenses:Food
+2024-01-20 txn "Grocer" "Food"
+    Assets:Bank -30.00 USD
+    Expenses:Food:Snacks 30.00 USD
+2024-02-01 txn "Grocer" "Food"
+    Assets:Bank -10.00 USD
+    Expenses:Food
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config).budget.enable = true;
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::InlayHintParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(0, 0),
+                lsp_types::Position::new(100, 0),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        let hints = inlay_hints(test_state.snapshot, params).unwrap().unwrap();
+        let labels: Vec<_> = hints
+            .iter()
+            .map(|hint| match &hint.label {
+                lsp_types::InlayHintLabel::String(label) => (hint.position, label.clone()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (
+                    lsp_types::Position::new(3, 17),
+                    String::from("40.00 USD left (2024-01)")
+                ),
+                (
+                    lsp_types::Position::new(6, 34),
+                    String::from("10.00 USD left (2024-01)")
+                ),
+                (
+                    lsp_types::Position::new(9, 17),
+                    String::from("90.00 USD left (2024-02)")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_budget_disabled() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 custom "budget" Expenses:Food "monthly" 100.00 USD
+2024-01-02 txn "Grocer" "Food"
+    Assets:Bank -60.00 USD
+    Expenses:Food
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::InlayHintParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(0, 0),
+                lsp_types::Position::new(100, 0),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        let hints = inlay_hints(test_state.snapshot, params).unwrap().unwrap();
+        assert!(hints.is_empty());
+    }
+
+    #[test]
+    fn handle_operating_currency_equivalent() {
+        let fixure = r#"
+%! /main.beancount
+option "operating_currency" "USD"
+2024-01-01 price EUR 1.10 USD
+2024-02-01 price EUR 1.20 USD
+2024-01-15 txn "Cafe" "Coffee"
+    Assets:Wallet -3.50 EUR
+    Expenses:Food
+2024-02-15 txn "Cafe" "Coffee"
+    Assets:Wallet -3.50 EUR
+    Assets:Bank 4.20 USD
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .inlay_hints
+            .operating_currency_equivalent = true;
+        let uri = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let params = lsp_types::InlayHintParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(0, 0),
+                lsp_types::Position::new(100, 0),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        let hints = inlay_hints(test_state.snapshot, params).unwrap().unwrap();
+        let labels: Vec<_> = hints
+            .iter()
+            .map(|hint| match &hint.label {
+                lsp_types::InlayHintLabel::String(label) => (hint.position.line, label.clone()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (4, String::from("≈ -3.85 USD")),
+                (7, String::from("≈ -4.20 USD")),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_file_summary() {
+        let fixure = r#"
+%! /main.beancount
+include "2024.beancount"
+%! /2024.beancount
+2024-01-02 txn "Grocer" "Food"
+    Assets:Bank -60.00 USD
+    Expenses:Food
+2024-02-01 txn "Grocer" "Food"
+    Assets:Bank -10.00 USD
+    Expenses:Food
+"#;
+        let snapshot = || {
+            let mut snapshot = TestState::new(fixure).unwrap().snapshot;
+            Arc::make_mut(&mut snapshot.config).inlay_hints.file_summary = true;
+            snapshot.journals = Arc::new(vec![Journal {
+                root: PathBuf::from("/main.beancount"),
+                files: [
+                    PathBuf::from("/main.beancount"),
+                    PathBuf::from("/2024.beancount"),
+                ]
+                .into(),
+            }]);
+            let error = lsp_types::Diagnostic {
+                message: "Transaction does not balance".to_string(),
+                severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                ..lsp_types::Diagnostic::default()
+            };
+            Arc::make_mut(&mut snapshot.diagnostics)
+                .insert(PathBuf::from("/2024.beancount"), vec![error]);
+            snapshot
+        };
+        let params = |path: &str| lsp_types::InlayHintParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(
+                lsp_types::Uri::from_str(&format!("file://{path}")).unwrap(),
+            ),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(0, 0),
+                lsp_types::Position::new(100, 0),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+
+        let hints = inlay_hints(snapshot(), params("/main.beancount"))
+            .unwrap()
+            .unwrap();
+        let labels: Vec<_> = hints
+            .iter()
+            .map(|hint| match &hint.label {
+                lsp_types::InlayHintLabel::String(label) => (hint.position, label.clone()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [(
+                lsp_types::Position::new(0, 24),
+                String::from("1 error · not checked yet · newest transaction 2024-02-01")
+            )]
+        );
+
+        // only the root of a journal gets a summary
+        let hints = inlay_hints(snapshot(), params("/2024.beancount"))
+            .unwrap()
+            .unwrap();
+        assert!(hints.is_empty());
+    }
+}
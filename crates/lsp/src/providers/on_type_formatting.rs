@@ -0,0 +1,209 @@
+use crate::providers::completion::enclosing_posting;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::tree_sitter_point_for_lsp_position;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use tracing::debug;
+
+/// The first character registered with the client for on-type formatting.
+pub(crate) const FIRST_TRIGGER_CHARACTER: char = '"';
+/// The other characters registered with the client for on-type formatting.
+pub(crate) const MORE_TRIGGER_CHARACTERS: [char; 1] = ['{'];
+
+/// Provider function for LSP `textDocument/onTypeFormatting`.
+///
+/// Closes the pair the user just opened, a payee or narration quote on a
+/// directive line or a cost brace in a posting, unless the client already
+/// closed it. A quote typed in front of the closing quote the client
+/// inserted replaces it instead of duplicating it.
+pub(crate) fn on_type_formatting(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::DocumentOnTypeFormattingParams,
+) -> Result<Option<Vec<lsp_types::TextEdit>>> {
+    debug!("providers::on_type_formatting");
+    let uri = params
+        .text_document_position
+        .text_document
+        .uri
+        .to_file_path()
+        .unwrap();
+    let (Some(tree), Some(doc)) = (snapshot.forest.get(&uri), snapshot.open_docs.get(&uri)) else {
+        return Ok(None);
+    };
+    let content = &doc.content;
+    let encoding = snapshot.config.position_encoding;
+    let position = params.text_document_position.position;
+    let cursor = tree_sitter_point_for_lsp_position(content, position, encoding);
+    let line = content.line(cursor.row).to_string();
+    let line = line.trim_end_matches(['\r', '\n']);
+    if cursor.column == 0 || cursor.column > line.len() {
+        return Ok(None);
+    }
+    let (before, after) = line.split_at(cursor.column);
+    if !before.ends_with(params.ch.as_str()) {
+        return Ok(None);
+    }
+    let cursor_byte = content.line_to_byte(cursor.row) + cursor.column;
+
+    let edit = match params.ch.as_str() {
+        "\"" => {
+            let header = tree
+                .root_node()
+                .named_descendant_for_point_range(
+                    tree_sitter::Point::new(cursor.row, 0),
+                    tree_sitter::Point::new(cursor.row, 0),
+                )
+                .is_some_and(|node| node.kind() == "date");
+            if !header || unescaped_quotes(line).is_multiple_of(2) {
+                return Ok(None);
+            }
+            if after.starts_with('"') {
+                // the closing quote was already there, drop the duplicate
+                lsp_types::TextEdit {
+                    range: lsp_types::Range {
+                        start: position,
+                        end: byte_to_lsp_position(content, cursor_byte + 1, encoding),
+                    },
+                    new_text: String::new(),
+                }
+            } else if after.trim().is_empty() {
+                lsp_types::TextEdit {
+                    range: lsp_types::Range::new(position, position),
+                    new_text: "\"".to_string(),
+                }
+            } else {
+                return Ok(None);
+            }
+        }
+        "{" => {
+            let start = tree_sitter::Point::new(cursor.row, cursor.column - 1);
+            let in_posting = tree
+                .root_node()
+                .named_descendant_for_point_range(start, cursor)
+                .and_then(enclosing_posting)
+                .is_some();
+            if !in_posting || after.contains('}') {
+                return Ok(None);
+            }
+            lsp_types::TextEdit {
+                range: lsp_types::Range::new(position, position),
+                new_text: "}".to_string(),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(vec![edit]))
+}
+
+/// Counts the quotes on `line` that delimit strings.
+fn unescaped_quotes(line: &str) -> usize {
+    let mut count = 0;
+    let mut escaped = false;
+    for c in line.chars() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => count += 1,
+            _ => escaped = false,
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::on_type_formatting;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    fn format_on_type(fixture: &str, ch: &str) -> Option<Vec<lsp_types::TextEdit>> {
+        let test_state = TestState::new(fixture).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::DocumentOnTypeFormattingParams {
+            text_document_position: cursor,
+            ch: ch.to_string(),
+            options: lsp_types::FormattingOptions::default(),
+        };
+        on_type_formatting(test_state.snapshot, params).unwrap()
+    }
+
+    #[test]
+    fn handle_opening_quote() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "
+                |
+                ^
+"#;
+        let position = lsp_types::Position::new(0, 16);
+        assert_eq!(
+            format_on_type(fixure, "\""),
+            Some(vec![lsp_types::TextEdit {
+                range: lsp_types::Range::new(position, position),
+                new_text: String::from("\""),
+            }])
+        );
+    }
+
+    #[test]
+    fn handle_duplicated_closing_quote() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Foo""
+                    |
+                    ^
+"#;
+        assert_eq!(
+            format_on_type(fixure, "\""),
+            Some(vec![lsp_types::TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(0, 20),
+                    lsp_types::Position::new(0, 21)
+                ),
+                new_text: String::new(),
+            }])
+        );
+    }
+
+    #[test]
+    fn handle_closed_quote() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Foo"
+                    |
+                    ^
+"#;
+        assert_eq!(format_on_type(fixure, "\""), None);
+    }
+
+    #[test]
+    fn handle_cost_brace() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Broker" "Buy"
+    Assets:Stock 10 AAPL {
+                          |
+                          ^
+"#;
+        let position = lsp_types::Position::new(1, 26);
+        assert_eq!(
+            format_on_type(fixure, "{"),
+            Some(vec![lsp_types::TextEdit {
+                range: lsp_types::Range::new(position, position),
+                new_text: String::from("}"),
+            }])
+        );
+    }
+
+    #[test]
+    fn handle_closed_cost_brace() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Broker" "Buy"
+    Assets:Stock 10 AAPL {}
+                          |
+                          ^
+"#;
+        assert_eq!(format_on_type(fixure, "{"), None);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Arguments of the `beancount.normalizePayee` command.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizePayeeParams {
+    /// The payee every matching payee is rewritten to.
+    pub canonical: String,
+    /// Payees to rewrite, compared exactly.
+    #[serde(default)]
+    pub variants: Vec<String>,
+    /// Payees to rewrite, matched with a regular expression.
+    pub regex: Option<String>,
+}
+
+/// Provider function for the `beancount.normalizePayee` command.
+pub(crate) fn normalize_payee(
+    snapshot: LspServerStateSnapshot,
+    params: NormalizePayeeParams,
+) -> Result<lsp_types::WorkspaceEdit> {
+    debug!("providers::payee::normalize_payee");
+    let regex = params.regex.as_deref().map(regex::Regex::new).transpose()?;
+    let canonical = format!("\"{}\"", params.canonical.replace('"', "\\\""));
+
+    let query = tree_sitter::Query::new(
+        &tree_sitter_beancount::language(),
+        "(transaction payee: (payee) @payee)",
+    )?;
+    let mut changes: HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>> = HashMap::new();
+    for (path, tree) in snapshot.forest.iter() {
+        let Some(content) = snapshot.document_content(path) else {
+            continue;
+        };
+        let text = content.to_string();
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let mut edits = Vec::new();
+        for matched in query_cursor.matches(&query, tree.root_node(), text.as_bytes()) {
+            for capture in matched.captures {
+                let payee = text_for_tree_sitter_node(&content, &capture.node);
+                let payee = payee.trim().trim_matches('"');
+                if payee == params.canonical {
+                    continue;
+                }
+                let is_variant = params.variants.iter().any(|variant| variant == payee)
+                    || regex.as_ref().is_some_and(|regex| regex.is_match(payee));
+                if is_variant {
+                    edits.push(lsp_types::TextEdit {
+                        range: lsp_range_for_tree_sitter_node(
+                            &content,
+                            &capture.node,
+                            snapshot.config.position_encoding,
+                        ),
+                        new_text: canonical.clone(),
+                    });
+                }
+            }
+        }
+        if !edits.is_empty() {
+            let uri =
+                lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+            changes.insert(uri, edits);
+        }
+    }
+
+    Ok(lsp_types::WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::payee::normalize_payee;
+    use crate::providers::payee::NormalizePayeeParams;
+    use crate::test_utils::apply_edits;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_normalize_payee() {
+        let fixure = r#"
+%! /main.beancount
+include "other.beancount"
+2023-10-01 txn "AMAZON" "Books"
+    Assets:Test -1 USD
+    Expenses:Test
+2023-10-02 txn "Grocer" "Food"
+    Assets:Test -1 USD
+    Expenses:Test
+%! /other.beancount
+2023-10-03 txn "Amazon.com Inc" "Books"
+    Assets:Test -1 USD
+    Expenses:Test
+2023-10-04 txn "Amazon" "Books"
+    Assets:Test -1 USD
+    Expenses:Test
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let texts: Vec<_> = test_state
+            .fixture
+            .documents
+            .iter()
+            .map(|document| (document.path.clone(), document.text.clone()))
+            .collect();
+        let params = NormalizePayeeParams {
+            canonical: String::from("Amazon"),
+            variants: vec![String::from("AMAZON")],
+            regex: Some(String::from("^Amazon\\.com")),
+        };
+        let edit = normalize_payee(test_state.snapshot, params).unwrap();
+        let changes = edit.changes.unwrap();
+        let normalized: Vec<_> = texts
+            .iter()
+            .map(|(path, text)| {
+                let uri = lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap();
+                apply_edits(text, &changes[&uri])
+            })
+            .collect();
+        assert_eq!(changes.len(), 2);
+        assert!(normalized[0].contains("2023-10-01 txn \"Amazon\" \"Books\""));
+        assert!(normalized[0].contains("2023-10-02 txn \"Grocer\" \"Food\""));
+        assert!(normalized[1].contains("2023-10-03 txn \"Amazon\" \"Books\""));
+        assert!(normalized[1].contains("2023-10-04 txn \"Amazon\" \"Books\""));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::checker;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Arguments of the `beancount.fetchPrices` command.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchPricesParams {
+    /// The document to add the fetched `price` directives to.
+    pub text_document: lsp_types::TextDocumentIdentifier,
+}
+
+/// Provider function for the `beancount.fetchPrices` command. Fetches the
+/// latest prices of the journal with `bean-price` and appends the `price`
+/// directives it prints to the document. It runs on the thread pool, as
+/// `bean-price` can take a while.
+pub(crate) fn fetch_prices(
+    snapshot: LspServerStateSnapshot,
+    params: FetchPricesParams,
+) -> Result<lsp_types::WorkspaceEdit> {
+    debug!("providers::prices::fetch_prices");
+    let uri = params.text_document.uri;
+    let file = uri.to_file_path().unwrap();
+    let Some(content) = snapshot.document_content(&file) else {
+        anyhow::bail!("unknown document: {}", uri.as_str());
+    };
+    let root = snapshot
+        .journal_for(&file)
+        .map(|journal| journal.root.clone())
+        .or_else(|| snapshot.config.journal_roots.first().cloned())
+        .unwrap_or(file);
+    let output = checker::fetch_prices(&snapshot.config.checker, &root, &snapshot.cancelled)?;
+    let directives = price_directives(&output);
+    if directives.is_empty() {
+        anyhow::bail!("no prices fetched");
+    }
+
+    let ends_with_newline = content
+        .len_chars()
+        .checked_sub(1)
+        .is_none_or(|last| content.char(last) == '\n');
+    let new_text = if ends_with_newline {
+        format!("{}\n", directives.join("\n"))
+    } else {
+        format!("\n{}\n", directives.join("\n"))
+    };
+    let end = byte_to_lsp_position(
+        &content,
+        content.len_bytes(),
+        snapshot.config.position_encoding,
+    );
+    let edit = lsp_types::TextEdit {
+        range: lsp_types::Range::new(end, end),
+        new_text,
+    };
+    Ok(lsp_types::WorkspaceEdit {
+        changes: Some(HashMap::from([(uri, vec![edit])])),
+        ..Default::default()
+    })
+}
+
+/// The lines of the output of `bean-price` that are `price` directives.
+fn price_directives(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.split_whitespace().nth(1) == Some("price"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::prices::price_directives;
+    use test_log::test;
+
+    #[test]
+    fn handle_price_directives() {
+        let output = "\
+2024-03-01 price HOOL 123.45 USD
+
+; no price for GOOG
+2024-03-01 price EUR 1.08 USD
+";
+        assert_eq!(
+            price_directives(output),
+            [
+                "2024-03-01 price HOOL 123.45 USD",
+                "2024-03-01 price EUR 1.08 USD"
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::named_node_at_lsp_position;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Provider function for LSP `textDocument/references`. Lists where the
+/// account at the position is written, in the postings and the `open`,
+/// `close`, `balance` and `pad` directives of the journal of the document,
+/// from the account references collected with the data of each file.
+pub(crate) fn references(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::ReferenceParams,
+) -> Result<Option<Vec<lsp_types::Location>>> {
+    debug!("providers::references");
+    let position = params.text_document_position;
+    let file = position.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) =
+        (snapshot.forest.get(&file), snapshot.document_content(&file))
+    else {
+        return Ok(None);
+    };
+    let Some(node) = named_node_at_lsp_position(
+        tree,
+        &content,
+        position.position,
+        snapshot.config.position_encoding,
+    )
+    .filter(|node| node.kind() == "account") else {
+        return Ok(None);
+    };
+    let account = text_for_tree_sitter_node(&content, &node);
+
+    // with several journals, only the one including this file is searched
+    let journal = snapshot.journal_for(&file);
+    let mut paths: Vec<_> = snapshot
+        .beancount_data
+        .keys()
+        .filter(|path| journal.is_none_or(|journal| journal.files.contains(*path)))
+        .collect();
+    paths.sort();
+
+    let mut locations = Vec::new();
+    for path in paths {
+        let references = snapshot.beancount_data[path].get_account_references(&account);
+        if references.is_empty() {
+            continue;
+        }
+        let uri = lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+        locations.extend(
+            references
+                .iter()
+                .filter(|reference| params.context.include_declaration || !reference.declaration)
+                .map(|reference| lsp_types::Location {
+                    uri: uri.clone(),
+                    range: reference.range,
+                }),
+        );
+    }
+    Ok(Some(locations))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::references::references;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    #[test]
+    fn handle_account_references() {
+        let fixure = r#"
+%! /main.beancount
+include "2024.beancount"
+2024-01-01 open Assets:Bank:Checking
+2024-01-01 open Equity:Opening
+2024-01-01 pad Assets:Bank:Checking Equity:Opening
+2024-01-02 balance Assets:Bank:Checking 100 USD
+                   |
+                   ^
+%! /2024.beancount
+2024-02-01 * "Grocer"
+    Assets:Bank:Checking -10 USD
+    Expenses:Food
+2024-12-31 close Assets:Bank:Checking
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = |include_declaration| lsp_types::ReferenceParams {
+            text_document_position: cursor.clone(),
+            context: lsp_types::ReferenceContext {
+                include_declaration,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let found = |include_declaration| {
+            let snapshot = TestState::new(fixure).unwrap().snapshot;
+            references(snapshot, params(include_declaration))
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|location| {
+                    (
+                        location.uri.as_str().to_string(),
+                        location.range.start.line,
+                        location.range.start.character,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let file = |name: &str| format!("file:///{name}");
+        assert_eq!(
+            found(true),
+            [
+                (file("2024.beancount"), 1, 4),
+                (file("2024.beancount"), 3, 17),
+                (file("main.beancount"), 1, 16),
+                (file("main.beancount"), 3, 15),
+                (file("main.beancount"), 4, 19),
+            ]
+        );
+        assert_eq!(found(false).len(), 4);
+    }
+}
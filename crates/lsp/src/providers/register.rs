@@ -0,0 +1,267 @@
+use crate::ledger;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::entry_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::debug;
+
+/// The URI scheme of the generated register documents.
+pub(crate) const REGISTER_SCHEME: &str = "beancount-register";
+
+/// Arguments of the `beancount.showRegister` command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterParams {
+    /// A document of the journal to list the postings of, or all documents
+    /// of the workspace.
+    pub text_document: Option<lsp_types::TextDocumentIdentifier>,
+    pub account: String,
+    /// Also list postings to sub-accounts of `account`.
+    #[serde(default)]
+    pub include_subaccounts: bool,
+    #[serde(flatten)]
+    pub filter: ledger::PostingFilter,
+}
+
+/// A generated read-only document, for the client to show under `uri`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterDocument {
+    pub uri: lsp_types::Uri,
+    pub content: String,
+}
+
+/// Provider function for the `beancount.showRegister` command. Lists the
+/// postings to an account in date order with the running balance, like
+/// `bean-report register`. With a `from` date, the postings before it are
+/// summed up in an opening balance.
+pub(crate) fn register(
+    snapshot: LspServerStateSnapshot,
+    params: RegisterParams,
+) -> Result<RegisterDocument> {
+    debug!("providers::register {}", params.account);
+    let data = match &params.text_document {
+        Some(document) => snapshot.journal_data(&document.uri.to_file_path().unwrap()),
+        None => snapshot.beancount_data.clone(),
+    };
+    let postings = ledger::running_balances(
+        &data,
+        &params.account,
+        params.include_subaccounts,
+        &params.filter,
+    );
+
+    let mut contents: HashMap<&Path, Option<ropey::Rope>> = HashMap::new();
+    let mut content = String::new();
+    writeln!(content, "Register for {}\n", params.account)?;
+    if let Some(from) = params.filter.from {
+        let opening = ledger::running_balances(
+            &data,
+            &params.account,
+            params.include_subaccounts,
+            &ledger::PostingFilter {
+                from: None,
+                to: Some(from),
+                tag_filter: params.filter.tag_filter.clone(),
+            },
+        )
+        .pop()
+        .map(|(_, _, balance)| balance)
+        .unwrap_or_default();
+        writeln!(
+            content,
+            "{} {:<40} {:>16} {:>16}",
+            from,
+            "Opening balance",
+            "",
+            opening.to_string()
+        )?;
+    }
+    for (file, posting, balance) in postings {
+        let description = match (
+            snapshot.forest.get(file),
+            contents
+                .entry(file)
+                .or_insert_with(|| snapshot.document_content(file)),
+        ) {
+            (Some(tree), Some(file_content)) => {
+                transaction_description(tree, file_content, posting.line)
+            }
+            _ => String::new(),
+        };
+        let amount = posting
+            .units
+            .iter()
+            .map(|amount| amount.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            content,
+            "{} {:<40} {:>16} {:>16}",
+            posting.date,
+            description,
+            amount,
+            balance.to_string()
+        )?;
+        if params.include_subaccounts {
+            write!(content, "  {}", posting.account)?;
+        }
+        writeln!(content)?;
+    }
+
+    let uri = lsp_types::Uri::from_str(&format!("{REGISTER_SCHEME}:{}", params.account))?;
+    Ok(RegisterDocument { uri, content })
+}
+
+/// The payee and narration of the transaction of the posting on `line`.
+fn transaction_description(tree: &tree_sitter::Tree, content: &ropey::Rope, line: u32) -> String {
+    let point = tree_sitter::Point::new(line as usize, 0);
+    let Some(transaction) = tree
+        .root_node()
+        .descendant_for_point_range(point, point)
+        .and_then(entry_for_tree_sitter_node)
+    else {
+        return String::new();
+    };
+    let field = |name| {
+        transaction
+            .child_by_field_name(name)
+            .map(|node| text_for_tree_sitter_node(content, &node))
+            .map(|text| text.trim().trim_matches('"').to_string())
+    };
+    match (field("payee"), field("narration")) {
+        (Some(payee), Some(narration)) => format!("{payee} | {narration}"),
+        (payee, narration) => payee.or(narration).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::PostingFilter;
+    use crate::providers::register::register;
+    use crate::providers::register::RegisterParams;
+    use crate::server::Journal;
+    use crate::test_utils::TestState;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use test_log::test;
+
+    #[test]
+    fn handle_register() {
+        let fixure = r#"
+%! /main.beancount
+2023-12-01 txn "Employer" "Salary"
+    Assets:Bank 1,000 USD
+    Income:Salary
+2023-10-01 txn "Food"
+    Assets:Bank -10.50 USD
+    Expenses:Food
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let params = RegisterParams {
+            text_document: None,
+            account: String::from("Assets:Bank"),
+            include_subaccounts: false,
+            filter: Default::default(),
+        };
+        let document = register(test_state.snapshot, params).unwrap();
+        assert_eq!(document.uri.as_str(), "beancount-register:Assets:Bank");
+        let lines: Vec<_> = document.content.lines().map(str::trim_end).collect();
+        assert_eq!(
+            lines,
+            [
+                "Register for Assets:Bank",
+                "",
+                "2023-10-01 Food                                           -10.50 USD       -10.50 USD",
+                "2023-12-01 Employer | Salary                                1000 USD       989.50 USD",
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_register_from() {
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 txn "Food"
+    Assets:Bank -10.50 USD
+    Expenses:Food
+2023-12-01 txn "Employer" "Salary"
+    Assets:Bank 1,000 USD
+    Income:Salary
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let params = RegisterParams {
+            text_document: None,
+            account: String::from("Assets:Bank"),
+            include_subaccounts: false,
+            filter: PostingFilter {
+                from: chrono::NaiveDate::from_ymd_opt(2023, 11, 1),
+                ..Default::default()
+            },
+        };
+        let document = register(test_state.snapshot, params).unwrap();
+        let lines: Vec<_> = document.content.lines().map(str::trim_end).collect();
+        // the earlier posting is in the opening balance, not listed
+        assert_eq!(
+            lines,
+            [
+                "Register for Assets:Bank",
+                "",
+                "2023-11-01 Opening balance                                                 -10.50 USD",
+                "2023-12-01 Employer | Salary                                1000 USD       989.50 USD",
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_register_journal() {
+        let fixure = r#"
+%! /personal.beancount
+2023-10-01 txn "Food"
+    Assets:Bank -10.50 USD
+    Expenses:Food
+%! /business.beancount
+2023-10-02 txn "Client" "Invoice"
+    Assets:Bank 500 USD
+    Income:Sales
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        test_state.snapshot.journals = Arc::new(
+            ["/personal.beancount", "/business.beancount"]
+                .into_iter()
+                .map(|root| Journal {
+                    root: PathBuf::from(root),
+                    files: HashSet::from([PathBuf::from(root)]),
+                })
+                .collect(),
+        );
+        let params = RegisterParams {
+            text_document: Some(lsp_types::TextDocumentIdentifier::new(
+                lsp_types::Uri::from_str("file:///business.beancount").unwrap(),
+            )),
+            account: String::from("Assets:Bank"),
+            include_subaccounts: false,
+            filter: Default::default(),
+        };
+        let document = register(test_state.snapshot, params).unwrap();
+        let lines: Vec<_> = document.content.lines().map(str::trim_end).collect();
+        // the postings of the other journal are left out
+        assert_eq!(
+            lines,
+            [
+                "Register for Assets:Bank",
+                "",
+                "2023-10-02 Client | Invoice                                  500 USD          500 USD",
+            ]
+        );
+    }
+}
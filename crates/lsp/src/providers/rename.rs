@@ -0,0 +1,518 @@
+use crate::lsp_ext::RenamePreviewFile;
+use crate::lsp_ext::RenamePreviewResult;
+use crate::lsp_ext::RenamePreviewSample;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::lsp_position_to_char;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::named_node_at_lsp_position;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::PositionEncoding;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Number of changed lines shown per file by `beancount/renamePreview`.
+const MAX_PREVIEW_SAMPLES: usize = 3;
+
+/// Provider function for LSP `textDocument/prepareRename`.
+pub(crate) fn prepare_rename(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::TextDocumentPositionParams,
+) -> Result<Option<lsp_types::PrepareRenameResponse>> {
+    debug!("providers::rename::prepare_rename");
+    let uri = params.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) = (snapshot.forest.get(&uri), snapshot.document_content(&uri))
+    else {
+        return Ok(None);
+    };
+    let Some(node) = named_node_at_lsp_position(
+        tree,
+        &content,
+        params.position,
+        snapshot.config.position_encoding,
+    )
+    .filter(|node| matches!(node.kind(), "tag" | "link" | "account")) else {
+        return Ok(None);
+    };
+    let text = text_for_tree_sitter_node(&content, &node);
+    if node.kind() == "account" {
+        return Ok(Some(
+            lsp_types::PrepareRenameResponse::RangeWithPlaceholder {
+                range: lsp_range_for_tree_sitter_node(
+                    &content,
+                    &node,
+                    snapshot.config.position_encoding,
+                ),
+                placeholder: text,
+            },
+        ));
+    }
+    let name = &text[1..];
+    if !is_valid_name(name) {
+        return Ok(None);
+    }
+    Ok(Some(
+        lsp_types::PrepareRenameResponse::RangeWithPlaceholder {
+            range: name_range(&content, &node, snapshot.config.position_encoding),
+            placeholder: name.to_string(),
+        },
+    ))
+}
+
+/// Provider function for LSP `textDocument/rename`. Renames a tag or link
+/// everywhere in the forest, including `pushtag` and `poptag` directives,
+/// or an account and the accounts below it in the journal of the document.
+pub(crate) fn rename(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::RenameParams,
+) -> Result<Option<lsp_types::WorkspaceEdit>> {
+    debug!("providers::rename::rename");
+    let changes = rename_edits(&snapshot, params)?;
+    Ok(changes.map(|changes| lsp_types::WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    }))
+}
+
+/// Provider function for `beancount/renamePreview`. Counts the edits the
+/// rename would make in each file and shows a few of the changed lines.
+pub(crate) fn rename_preview(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::RenameParams,
+) -> Result<Option<RenamePreviewResult>> {
+    debug!("providers::rename::rename_preview");
+    let Some(changes) = rename_edits(&snapshot, params)? else {
+        return Ok(None);
+    };
+    let encoding = snapshot.config.position_encoding;
+    let mut files = vec![];
+    for (uri, mut edits) in changes {
+        let Some(content) = snapshot.document_content(&uri.to_file_path().unwrap()) else {
+            continue;
+        };
+        edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        let mut samples: Vec<RenamePreviewSample> = vec![];
+        for edit in edits.iter() {
+            let line = edit.range.start.line;
+            if samples.iter().any(|sample| sample.line == line) {
+                continue;
+            }
+            if samples.len() == MAX_PREVIEW_SAMPLES {
+                break;
+            }
+            let text = content.line(line as usize).to_string();
+            let text = text.trim_end_matches(['\r', '\n']);
+            let line_start = content.line_to_char(line as usize);
+            let mut new_text: Vec<char> = text.chars().collect();
+            for edit in edits
+                .iter()
+                .rev()
+                .filter(|edit| edit.range.start.line == line && edit.range.end.line == line)
+            {
+                let start = lsp_position_to_char(&content, edit.range.start, encoding) - line_start;
+                let end = lsp_position_to_char(&content, edit.range.end, encoding) - line_start;
+                new_text.splice(start..end, edit.new_text.chars());
+            }
+            samples.push(RenamePreviewSample {
+                line,
+                text: text.to_string(),
+                new_text: new_text.into_iter().collect(),
+            });
+        }
+        files.push(RenamePreviewFile {
+            uri,
+            edits: edits.len(),
+            samples,
+        });
+    }
+    files.sort_by(|a, b| a.uri.as_str().cmp(b.uri.as_str()));
+    Ok(Some(RenamePreviewResult {
+        total_edits: files.iter().map(|file| file.edits).sum(),
+        files,
+    }))
+}
+
+/// The edits renaming the tag, link or account at the position, by file.
+fn rename_edits(
+    snapshot: &LspServerStateSnapshot,
+    params: lsp_types::RenameParams,
+) -> Result<Option<HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>>>> {
+    let position = params.text_document_position;
+    let uri = position.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) = (snapshot.forest.get(&uri), snapshot.document_content(&uri))
+    else {
+        return Ok(None);
+    };
+    let Some(node) = named_node_at_lsp_position(
+        tree,
+        &content,
+        position.position,
+        snapshot.config.position_encoding,
+    )
+    .filter(|node| matches!(node.kind(), "tag" | "link" | "account")) else {
+        return Ok(None);
+    };
+    if node.kind() == "account" {
+        let account = text_for_tree_sitter_node(&content, &node);
+        return account_rename_edits(snapshot, &uri, &account, &params.new_name).map(Some);
+    }
+    let kind = node.kind();
+    let old_text = text_for_tree_sitter_node(&content, &node);
+    let sigil = &old_text[..1];
+    let new_name = params
+        .new_name
+        .strip_prefix(sigil)
+        .unwrap_or(&params.new_name);
+    if !is_valid_name(new_name) {
+        return Err(anyhow::anyhow!(
+            "invalid {} name: {}",
+            kind,
+            params.new_name
+        ));
+    }
+
+    let query = tree_sitter::Query::new(
+        &tree_sitter_beancount::language(),
+        format!("({kind}) @{kind}").as_str(),
+    )?;
+    let mut changes: HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>> = HashMap::new();
+    for (path, tree) in snapshot.forest.iter() {
+        let Some(content) = snapshot.document_content(path) else {
+            continue;
+        };
+        let text = content.to_string();
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let edits: Vec<_> = query_cursor
+            .matches(&query, tree.root_node(), text.as_bytes())
+            .flat_map(|matched| matched.captures)
+            .filter(|capture| text_for_tree_sitter_node(&content, &capture.node) == old_text)
+            .map(|capture| lsp_types::TextEdit {
+                range: name_range(&content, &capture.node, snapshot.config.position_encoding),
+                new_text: new_name.to_string(),
+            })
+            .collect();
+        if !edits.is_empty() {
+            let uri =
+                lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+            changes.insert(uri, edits);
+        }
+    }
+    Ok(Some(changes))
+}
+
+/// The edits renaming `account` and the accounts below it, in the postings
+/// and the `open`, `close`, `balance` and `pad` directives of the journal of
+/// `file`, from the account references collected with the data of each file.
+fn account_rename_edits(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    account: &str,
+    new_name: &str,
+) -> Result<HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>>> {
+    if !is_valid_account(new_name) {
+        return Err(anyhow::anyhow!("invalid account name: {}", new_name));
+    }
+    // with several journals, only the one including this file is renamed in
+    let journal = snapshot.journal_for(file);
+    let mut changes: HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>> = HashMap::new();
+    for (path, data) in snapshot.beancount_data.iter() {
+        if journal.is_some_and(|journal| !journal.files.contains(path)) {
+            continue;
+        }
+        let edits: Vec<_> = data
+            .get_account_tree_references(account)
+            .flat_map(|(name, references)| {
+                // a sub-account keeps its own components below the new name
+                let new_text = format!("{new_name}{}", &name[account.len()..]);
+                references.iter().map(move |reference| lsp_types::TextEdit {
+                    range: reference.range,
+                    new_text: new_text.clone(),
+                })
+            })
+            .collect();
+        if !edits.is_empty() {
+            let uri =
+                lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+            changes.insert(uri, edits);
+        }
+    }
+    Ok(changes)
+}
+
+/// Whether `account` is a root name followed by components, each starting
+/// with an uppercase letter or digit and going on with letters, digits or
+/// dashes. The root names are not checked, as options may rename them.
+fn is_valid_account(account: &str) -> bool {
+    let mut components = account.split(':');
+    let root_is_valid = components.next().is_some_and(|root| {
+        root.chars().next().is_some_and(|c| c.is_uppercase())
+            && root.chars().all(|c| c.is_alphanumeric() || c == '-')
+    });
+    let mut components = components.peekable();
+    root_is_valid
+        && components.peek().is_some()
+        && components.all(|component| {
+            component
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_uppercase() || c.is_ascii_digit())
+                && component.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+}
+
+/// Whether `name` only uses characters beancount allows in tags and links.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '/' | '.'))
+}
+
+/// The range of a tag or link without its leading `#` or `^`.
+fn name_range(
+    content: &ropey::Rope,
+    node: &tree_sitter::Node,
+    encoding: PositionEncoding,
+) -> lsp_types::Range {
+    lsp_types::Range {
+        start: byte_to_lsp_position(content, node.start_byte() + 1, encoding),
+        end: byte_to_lsp_position(content, node.end_byte(), encoding),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::rename::prepare_rename;
+    use crate::providers::rename::rename;
+    use crate::providers::rename::rename_preview;
+    use crate::test_utils::apply_edits;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    const FIXTURE: &str = r#"
+%! /main.beancount
+include "other.beancount"
+pushtag #trip
+2023-10-01 txn "Hotel" "Stay" #trip ^booking
+    Assets:Test -1 USD
+    Expenses:Test
+poptag #trip
+%! /other.beancount
+2023-10-03 txn "Taxi" "Ride" #trip #triple
+                                |
+    Assets:Test -1 USD
+    Expenses:Test
+"#;
+
+    #[test]
+    fn handle_prepare_rename() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let response = prepare_rename(test_state.snapshot, cursor).unwrap();
+        assert_eq!(
+            response,
+            Some(lsp_types::PrepareRenameResponse::RangeWithPlaceholder {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(0, 30),
+                    lsp_types::Position::new(0, 34),
+                ),
+                placeholder: String::from("trip"),
+            })
+        );
+    }
+
+    #[test]
+    fn handle_rename_tag() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let texts: Vec<_> = test_state
+            .fixture
+            .documents
+            .iter()
+            .map(|document| (document.path.clone(), document.text.clone()))
+            .collect();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::RenameParams {
+            text_document_position: cursor,
+            new_name: String::from("#vacation"),
+            work_done_progress_params: Default::default(),
+        };
+        let edit = rename(test_state.snapshot, params).unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+        let renamed: Vec<_> = texts
+            .iter()
+            .map(|(path, text)| {
+                let uri = lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap();
+                apply_edits(text, &changes[&uri])
+            })
+            .collect();
+        assert_eq!(
+            renamed[0],
+            r#"include "other.beancount"
+pushtag #vacation
+2023-10-01 txn "Hotel" "Stay" #vacation ^booking
+    Assets:Test -1 USD
+    Expenses:Test
+poptag #vacation"#
+        );
+        assert!(renamed[1].starts_with(r#"2023-10-03 txn "Taxi" "Ride" #vacation #triple"#));
+    }
+
+    #[test]
+    fn handle_rename_preview() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::RenameParams {
+            text_document_position: cursor,
+            new_name: String::from("vacation"),
+            work_done_progress_params: Default::default(),
+        };
+        let preview = rename_preview(test_state.snapshot, params)
+            .unwrap()
+            .unwrap();
+        assert_eq!(preview.total_edits, 4);
+        let files: Vec<_> = preview
+            .files
+            .iter()
+            .map(|file| (file.uri.as_str(), file.edits, file.samples.len()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("file:///main.beancount", 3, 3),
+                ("file:///other.beancount", 1, 1)
+            ]
+        );
+        let sample = &preview.files[1].samples[0];
+        assert_eq!(sample.line, 0);
+        assert_eq!(sample.text, r#"2023-10-03 txn "Taxi" "Ride" #trip #triple"#);
+        assert_eq!(
+            sample.new_text,
+            r#"2023-10-03 txn "Taxi" "Ride" #vacation #triple"#
+        );
+    }
+
+    #[test]
+    fn handle_rename_invalid_name() {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::RenameParams {
+            text_document_position: cursor,
+            new_name: String::from("two words"),
+            work_done_progress_params: Default::default(),
+        };
+        assert!(rename(test_state.snapshot, params).is_err());
+    }
+
+    const ACCOUNT_FIXTURE: &str = r#"
+%! /main.beancount
+include "other.beancount"
+2023-01-01 open Assets:Bank
+2023-01-01 open Assets:Bank:Checking
+2023-01-01 open Assets:Banking
+2023-01-02 pad Assets:Bank:Checking Equity:Opening
+2023-01-03 balance Assets:Bank:Checking 10 USD
+                   |
+%! /other.beancount
+2023-10-03 txn "Taxi" "Ride"
+    Assets:Bank -1 USD
+    Expenses:Taxi
+2023-12-31 close Assets:Bank:Checking
+"#;
+
+    #[test]
+    fn handle_rename_account() {
+        let test_state = TestState::new(ACCOUNT_FIXTURE).unwrap();
+        let texts: Vec<_> = test_state
+            .fixture
+            .documents
+            .iter()
+            .map(|document| (document.path.clone(), document.text.clone()))
+            .collect();
+        let mut cursor = test_state.cursor().unwrap();
+        // on `Assets:Bank` of the parent account instead
+        cursor.position = lsp_types::Position::new(1, 20);
+        let params = lsp_types::RenameParams {
+            text_document_position: cursor,
+            new_name: String::from("Assets:Cash"),
+            work_done_progress_params: Default::default(),
+        };
+        let edit = rename(test_state.snapshot, params).unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+        let renamed: Vec<_> = texts
+            .iter()
+            .map(|(path, text)| {
+                let uri = lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap();
+                apply_edits(text, &changes[&uri])
+            })
+            .collect();
+        assert_eq!(
+            renamed[0],
+            r#"include "other.beancount"
+2023-01-01 open Assets:Cash
+2023-01-01 open Assets:Cash:Checking
+2023-01-01 open Assets:Banking
+2023-01-02 pad Assets:Cash:Checking Equity:Opening
+2023-01-03 balance Assets:Cash:Checking 10 USD"#
+        );
+        assert_eq!(
+            renamed[1],
+            r#"2023-10-03 txn "Taxi" "Ride"
+    Assets:Cash -1 USD
+    Expenses:Taxi
+2023-12-31 close Assets:Cash:Checking"#
+        );
+    }
+
+    #[test]
+    fn handle_rename_account_preview() {
+        let test_state = TestState::new(ACCOUNT_FIXTURE).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::RenameParams {
+            text_document_position: cursor.clone(),
+            new_name: String::from("Assets:Bank:Current"),
+            work_done_progress_params: Default::default(),
+        };
+        let preview = rename_preview(test_state.snapshot, params)
+            .unwrap()
+            .unwrap();
+        assert_eq!(preview.total_edits, 4);
+        let sample = &preview.files[0].samples[2];
+        assert_eq!(
+            sample.text,
+            "2023-01-03 balance Assets:Bank:Checking 10 USD"
+        );
+        assert_eq!(
+            sample.new_text,
+            "2023-01-03 balance Assets:Bank:Current 10 USD"
+        );
+
+        let prepared = prepare_rename(TestState::new(ACCOUNT_FIXTURE).unwrap().snapshot, cursor);
+        assert_eq!(
+            prepared.unwrap(),
+            Some(lsp_types::PrepareRenameResponse::RangeWithPlaceholder {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(5, 19),
+                    lsp_types::Position::new(5, 39),
+                ),
+                placeholder: String::from("Assets:Bank:Checking"),
+            })
+        );
+    }
+
+    #[test]
+    fn handle_rename_account_invalid_name() {
+        let test_state = TestState::new(ACCOUNT_FIXTURE).unwrap();
+        let cursor = test_state.cursor().unwrap();
+        let params = lsp_types::RenameParams {
+            text_document_position: cursor,
+            new_name: String::from("Assets:bank"),
+            work_done_progress_params: Default::default(),
+        };
+        assert!(rename(test_state.snapshot, params).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use crate::beancount_data::operating_currencies;
+use crate::ledger;
+use crate::ledger::Inventory;
+use crate::server::LspServerStateSnapshot;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tracing::debug;
+
+/// Arguments of the `beancount.report` command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportParams {
+    pub kind: ReportKind,
+    #[serde(flatten)]
+    pub filter: ledger::PostingFilter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportKind {
+    /// The balance of every account.
+    Balances,
+    /// Income and expenses, with the resulting net income.
+    Income,
+    /// Assets and liabilities, with the resulting net worth.
+    Networth,
+}
+
+/// Provider function for the `beancount.report` command. The report is
+/// rendered as Markdown, with the operating currencies listed first in each
+/// balance.
+pub(crate) fn report(snapshot: LspServerStateSnapshot, params: ReportParams) -> Result<String> {
+    debug!("providers::report {:?}", params.kind);
+    let balances = ledger::balances(
+        snapshot
+            .beancount_data
+            .values()
+            .flat_map(|data| data.get_postings())
+            .filter(|posting| params.filter.matches(posting)),
+    );
+    let first = operating_currencies(&snapshot.beancount_data);
+
+    let mut markdown = String::new();
+    match params.kind {
+        ReportKind::Balances => {
+            writeln!(markdown, "# Balances\n")?;
+            write_table(&mut markdown, &balances, &first, |_| true)?;
+        }
+        ReportKind::Income => {
+            writeln!(markdown, "# Income Statement\n")?;
+            let income = write_section(&mut markdown, "Income", &balances, &first)?;
+            let expenses = write_section(&mut markdown, "Expenses", &balances, &first)?;
+            let mut net_income = income;
+            net_income.merge(&expenses);
+            writeln!(
+                markdown,
+                "**Net Income:** {}",
+                net_income.negate().format_with_first(&first)
+            )?;
+        }
+        ReportKind::Networth => {
+            writeln!(markdown, "# Net Worth\n")?;
+            let assets = write_section(&mut markdown, "Assets", &balances, &first)?;
+            let liabilities = write_section(&mut markdown, "Liabilities", &balances, &first)?;
+            let mut net_worth = assets;
+            net_worth.merge(&liabilities);
+            writeln!(
+                markdown,
+                "**Net Worth:** {}",
+                net_worth.format_with_first(&first)
+            )?;
+        }
+    }
+    Ok(markdown)
+}
+
+/// Writes the accounts under `root` and returns their total.
+fn write_section(
+    markdown: &mut String,
+    root: &str,
+    balances: &BTreeMap<String, Inventory>,
+    first: &[String],
+) -> Result<Inventory> {
+    writeln!(markdown, "## {root}\n")?;
+    let total = write_table(markdown, balances, first, |account| {
+        ledger::account_root(account) == root
+    })?;
+    writeln!(
+        markdown,
+        "**Total {root}:** {}\n",
+        total.format_with_first(first)
+    )?;
+    Ok(total)
+}
+
+/// Writes a table of the non-empty balances of the accounts matching `filter`,
+/// with the currencies of `first` first, and returns their total.
+fn write_table(
+    markdown: &mut String,
+    balances: &BTreeMap<String, Inventory>,
+    first: &[String],
+    filter: impl Fn(&str) -> bool,
+) -> Result<Inventory> {
+    let mut total = Inventory::default();
+    writeln!(markdown, "| Account | Balance |")?;
+    writeln!(markdown, "| --- | ---: |")?;
+    for (account, inventory) in balances.iter() {
+        if !filter(account) || inventory.is_empty() {
+            continue;
+        }
+        writeln!(
+            markdown,
+            "| {account} | {} |",
+            inventory.format_with_first(first)
+        )?;
+        total.merge(inventory);
+    }
+    writeln!(markdown)?;
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::PostingFilter;
+    use crate::providers::report::report;
+    use crate::providers::report::ReportKind;
+    use crate::providers::report::ReportParams;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    const FIXTURE: &str = r#"
+%! /main.beancount
+2023-10-01 txn "Employer" "Salary"
+    Assets:Bank 1,000.00 USD
+    Income:Salary
+2023-10-02 txn "Grocer" "Food"
+    Assets:Bank -40.00 USD
+    Expenses:Food
+2023-10-03 txn "Bank" "Card" #trip
+    Liabilities:Card -10.00 USD
+    Expenses:Food
+2023-10-04 txn "Broker" "Buy"
+    Assets:Stock 2 STK {100.00 USD}
+    Assets:Bank
+"#;
+
+    fn run(kind: ReportKind) -> String {
+        run_filtered(kind, Default::default())
+    }
+
+    fn run_filtered(kind: ReportKind, filter: PostingFilter) -> String {
+        let test_state = TestState::new(FIXTURE).unwrap();
+        report(test_state.snapshot, ReportParams { kind, filter }).unwrap()
+    }
+
+    #[test]
+    fn handle_balances_report() {
+        let markdown = run(ReportKind::Balances);
+        assert!(markdown.contains("| Assets:Bank | 760.00 USD |"));
+        assert!(markdown.contains("| Assets:Stock | 2 STK |"));
+        assert!(markdown.contains("| Income:Salary | -1000.00 USD |"));
+    }
+
+    #[test]
+    fn handle_income_report() {
+        let markdown = run(ReportKind::Income);
+        assert!(markdown.contains("**Total Expenses:** 50.00 USD"));
+        assert!(markdown.contains("**Net Income:** 950.00 USD"));
+        assert!(!markdown.contains("Assets:Bank"));
+    }
+
+    #[test]
+    fn handle_networth_report() {
+        let markdown = run(ReportKind::Networth);
+        assert!(markdown.contains("**Total Liabilities:** -10.00 USD"));
+        assert!(markdown.contains("**Net Worth:** 2 STK, 750.00 USD"));
+    }
+
+    #[test]
+    fn handle_report_with_operating_currency_first() {
+        let fixture = format!("{}option \"operating_currency\" \"USD\"\n", FIXTURE);
+        let test_state = TestState::new(&fixture).unwrap();
+        let params = ReportParams {
+            kind: ReportKind::Networth,
+            filter: Default::default(),
+        };
+        let markdown = report(test_state.snapshot, params).unwrap();
+        assert!(markdown.contains("**Net Worth:** 750.00 USD, 2 STK"));
+    }
+
+    #[test]
+    fn handle_report_for_period() {
+        let filter = PostingFilter {
+            from: chrono::NaiveDate::from_ymd_opt(2023, 10, 2),
+            to: chrono::NaiveDate::from_ymd_opt(2023, 10, 4),
+            tag_filter: None,
+        };
+        let markdown = run_filtered(ReportKind::Income, filter);
+        assert!(markdown.contains("**Total Expenses:** 50.00 USD"));
+        assert!(!markdown.contains("Income:Salary"));
+        assert!(markdown.contains("**Net Income:** -50.00 USD"));
+    }
+
+    #[test]
+    fn handle_report_for_tag() {
+        let filter = PostingFilter {
+            tag_filter: Some(String::from("trip")),
+            ..Default::default()
+        };
+        let markdown = run_filtered(ReportKind::Balances, filter);
+        assert!(markdown.contains("| Liabilities:Card | -10.00 USD |"));
+        assert!(!markdown.contains("Assets:Bank"));
+    }
+
+    #[test]
+    fn handle_report_with_decimal_comma() {
+        let fixture = r#"
+%! /main.beancount
+2023-10-01 txn "Grocer" "Food"
+    Assets:Bank -10,50 EUR
+    Expenses:Food
+2023-10-02 txn "Grocer" "Food"
+    Assets:Bank -2,25 EUR
+    Expenses:Food
+"#;
+        let test_state = TestState::new(fixture).unwrap();
+        let params = ReportParams {
+            kind: ReportKind::Balances,
+            filter: Default::default(),
+        };
+        let markdown = report(test_state.snapshot, params).unwrap();
+        assert!(markdown.contains("| Expenses:Food | 12.75 EUR |"));
+    }
+}
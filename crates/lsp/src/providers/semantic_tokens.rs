@@ -0,0 +1,150 @@
+use crate::bql;
+use crate::bql::BqlTokenKind;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use tracing::debug;
+
+/// The token types of the legend, in the order of their indices.
+pub(crate) const TOKEN_TYPES: [lsp_types::SemanticTokenType; 6] = [
+    lsp_types::SemanticTokenType::KEYWORD,
+    lsp_types::SemanticTokenType::FUNCTION,
+    lsp_types::SemanticTokenType::VARIABLE,
+    lsp_types::SemanticTokenType::STRING,
+    lsp_types::SemanticTokenType::NUMBER,
+    lsp_types::SemanticTokenType::OPERATOR,
+];
+
+fn token_type(kind: BqlTokenKind) -> u32 {
+    match kind {
+        BqlTokenKind::Keyword => 0,
+        BqlTokenKind::Function => 1,
+        BqlTokenKind::Column => 2,
+        BqlTokenKind::String => 3,
+        BqlTokenKind::Number => 4,
+        BqlTokenKind::Operator => 5,
+    }
+}
+
+/// Provider function for LSP `textDocument/semanticTokens/full`. Highlights
+/// the queries of `query` directives, which are plain strings to the grammar
+/// of the editor. Tokens spanning lines are left out, as not every client
+/// supports them.
+pub(crate) fn semantic_tokens_full(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::SemanticTokensParams,
+) -> Result<Option<lsp_types::SemanticTokensResult>> {
+    debug!("providers::semantic_tokens_full");
+    let file = params.text_document.uri.to_file_path().unwrap();
+    let (Some(tree), Some(content)) =
+        (snapshot.forest.get(&file), snapshot.document_content(&file))
+    else {
+        return Ok(None);
+    };
+    let encoding = snapshot.config.position_encoding;
+
+    let mut data = vec![];
+    let mut previous = lsp_types::Position::new(0, 0);
+    let mut cursor = tree.root_node().walk();
+    for query in tree
+        .root_node()
+        .children(&mut cursor)
+        .filter(|node| node.kind() == "query")
+    {
+        let mut query_cursor = query.walk();
+        let Some(string) = query
+            .children(&mut query_cursor)
+            .filter(|child| child.kind() == "string")
+            .nth(1)
+        else {
+            continue;
+        };
+        // the offsets of the tokens start after the opening quote
+        let offset = string.start_byte() + 1;
+        let text = content
+            .byte_slice(offset..string.end_byte().max(offset))
+            .to_string();
+        for token in bql::tokens(&text) {
+            if text[token.start..token.end].contains('\n') {
+                continue;
+            }
+            let start = byte_to_lsp_position(&content, offset + token.start, encoding);
+            let end = byte_to_lsp_position(&content, offset + token.end, encoding);
+            data.push(lsp_types::SemanticToken {
+                delta_line: start.line - previous.line,
+                delta_start: if start.line == previous.line {
+                    start.character - previous.character
+                } else {
+                    start.character
+                },
+                length: end.character - start.character,
+                token_type: token_type(token.kind),
+                token_modifiers_bitset: 0,
+            });
+            previous = start;
+        }
+    }
+    Ok(Some(lsp_types::SemanticTokensResult::Tokens(
+        lsp_types::SemanticTokens {
+            result_id: None,
+            data,
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestState;
+    use std::str::FromStr;
+    use test_log::test;
+
+    #[test]
+    fn handle_query_semantic_tokens() {
+        let fixure = r#"
+%! /main.beancount
+2024-01-01 open Assets:Bank
+2024-01-01 query "cash" "SELECT sum(position) WHERE account ~ 'Assets'"
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let result = semantic_tokens_full(
+            test_state.snapshot,
+            lsp_types::SemanticTokensParams {
+                text_document: lsp_types::TextDocumentIdentifier::new(
+                    lsp_types::Uri::from_str("file:///main.beancount").unwrap(),
+                ),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        )
+        .unwrap();
+        let Some(lsp_types::SemanticTokensResult::Tokens(tokens)) = result else {
+            panic!("no tokens");
+        };
+        let tokens: Vec<_> = tokens
+            .data
+            .iter()
+            .map(|token| {
+                (
+                    token.delta_line,
+                    token.delta_start,
+                    token.length,
+                    token.token_type,
+                )
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (1, 25, 6, 0),
+                (0, 7, 3, 1),
+                (0, 4, 8, 2),
+                (0, 10, 5, 0),
+                (0, 6, 7, 2),
+                (0, 8, 1, 5),
+                (0, 2, 8, 3),
+            ]
+        );
+    }
+}
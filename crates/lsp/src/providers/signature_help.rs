@@ -0,0 +1,134 @@
+use crate::config::CustomDirectiveSchema;
+use crate::custom_directives::custom_slot;
+use crate::custom_directives::CustomSlot;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::tree_sitter_point_for_lsp_position;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use tracing::debug;
+
+/// Characters registered with the client as signature help triggers.
+pub(crate) const TRIGGER_CHARACTERS: [char; 2] = [' ', '"'];
+
+/// Provider function for LSP `textDocument/signatureHelp`. Shows the
+/// arguments of a `custom` directive with a schema, highlighting the one the
+/// cursor is at.
+pub(crate) fn signature_help(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::SignatureHelpParams,
+) -> Result<Option<lsp_types::SignatureHelp>> {
+    debug!("providers::signature_help");
+    let position = params.text_document_position_params;
+    let file = position.text_document.uri.to_file_path().unwrap();
+    let Some(content) = snapshot.document_content(&file) else {
+        return Ok(None);
+    };
+    let point = tree_sitter_point_for_lsp_position(
+        &content,
+        position.position,
+        snapshot.config.position_encoding,
+    );
+    let line_prefix = content
+        .line(point.row)
+        .byte_slice(..point.column)
+        .to_string();
+    let schemas = &snapshot.config.custom_directives;
+    let Some(CustomSlot::Argument { name, index, .. }) = custom_slot(&line_prefix, schemas) else {
+        return Ok(None);
+    };
+    let Some(schema) = schemas.get(&name) else {
+        return Ok(None);
+    };
+    Ok(Some(lsp_types::SignatureHelp {
+        signatures: vec![signature(&name, schema)],
+        active_signature: Some(0),
+        active_parameter: (index < schema.arguments.len()).then_some(index as u32),
+    }))
+}
+
+/// The signature `custom "name" first second [optional]`, with the type and
+/// the allowed values of each argument as its documentation.
+fn signature(name: &str, schema: &CustomDirectiveSchema) -> lsp_types::SignatureInformation {
+    let mut label = format!("custom \"{name}\"");
+    let mut parameters = vec![];
+    for argument in &schema.arguments {
+        label.push(' ');
+        let start = label.encode_utf16().count() as u32;
+        if argument.optional {
+            label.push_str(&format!("[{}]", argument.name));
+        } else {
+            label.push_str(&argument.name);
+        }
+        let end = label.encode_utf16().count() as u32;
+        let mut documentation = argument.kind.name().to_string();
+        if !argument.values.is_empty() {
+            documentation.push_str(&format!(": {}", argument.values.join(", ")));
+        }
+        parameters.push(lsp_types::ParameterInformation {
+            label: lsp_types::ParameterLabel::LabelOffsets([start, end]),
+            documentation: Some(lsp_types::Documentation::String(documentation)),
+        });
+    }
+    lsp_types::SignatureInformation {
+        label,
+        documentation: schema
+            .description
+            .clone()
+            .map(lsp_types::Documentation::String),
+        parameters: Some(parameters),
+        active_parameter: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    fn signature_help_at(fixture: &str) -> Option<lsp_types::SignatureHelp> {
+        let state = TestState::new(fixture).unwrap();
+        let cursor = state.cursor().unwrap();
+        signature_help(
+            state.snapshot,
+            lsp_types::SignatureHelpParams {
+                context: None,
+                text_document_position_params: cursor,
+                work_done_progress_params: Default::default(),
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn handle_custom_signature() {
+        let help = signature_help_at(
+            r#"
+%! /main.beancount
+2024-01-01 custom "budget" Expenses:Food "monthly"
+                                                  |
+"#,
+        )
+        .unwrap();
+        let signature = &help.signatures[0];
+        assert_eq!(signature.label, "custom \"budget\" account period amount");
+        assert_eq!(help.active_parameter, Some(1));
+        let parameters = signature.parameters.as_ref().unwrap();
+        assert_eq!(
+            parameters[1].label,
+            lsp_types::ParameterLabel::LabelOffsets([24, 30])
+        );
+    }
+
+    #[test]
+    fn handle_custom_signature_without_schema() {
+        let help = signature_help_at(
+            r#"
+%! /main.beancount
+2024-01-01 custom "fava-option" "language"
+                                          |
+"#,
+        );
+        assert_eq!(help, None);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::beancount_data::is_forecast_transaction;
+use crate::beancount_data::parse_date;
+use crate::beancount_data::transaction_tags;
+use crate::beancount_data::FORECAST_TAG;
+use crate::lsp_ext::TaggedTransaction;
+use crate::lsp_ext::TransactionsByTagParams;
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Provider function for `beancount/transactionsByTag`. Lists the
+/// transactions with the tag in the order of their files, both those it is
+/// written on and those between a `pushtag` and `poptag` of it. Forecasts are
+/// left out unless the tag is `#forecast`.
+pub(crate) fn transactions_by_tag(
+    snapshot: LspServerStateSnapshot,
+    params: TransactionsByTagParams,
+) -> Result<Vec<TaggedTransaction>> {
+    debug!("providers::tags::transactions_by_tag {}", params.tag);
+    let tag = if params.tag.starts_with('#') {
+        params.tag.clone()
+    } else {
+        format!("#{}", params.tag)
+    };
+    let journal = params
+        .text_document
+        .as_ref()
+        .and_then(|document| snapshot.journal_for(&document.uri.to_file_path().unwrap()));
+    let mut paths: Vec<_> = snapshot
+        .forest
+        .keys()
+        .filter(|path| journal.is_none_or(|journal| journal.files.contains(*path)))
+        .collect();
+    paths.sort();
+
+    let mut transactions = Vec::new();
+    for path in paths {
+        let (Some(tree), Some(content)) =
+            (snapshot.forest.get(path), snapshot.document_content(path))
+        else {
+            continue;
+        };
+        let uri = lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+        // the tags of the `pushtag` directives not popped yet
+        let mut pushed: Vec<String> = Vec::new();
+        let mut cursor = tree.root_node().walk();
+        for node in tree.root_node().children(&mut cursor) {
+            match node.kind() {
+                "pushtag" | "poptag" => {
+                    let mut tag_cursor = node.walk();
+                    let Some(name) = node
+                        .children(&mut tag_cursor)
+                        .find(|c| c.kind() == "tag")
+                        .map(|c| text_for_tree_sitter_node(&content, &c))
+                    else {
+                        continue;
+                    };
+                    if node.kind() == "pushtag" {
+                        pushed.push(name);
+                    } else if let Some(index) = pushed.iter().rposition(|t| *t == name) {
+                        pushed.remove(index);
+                    }
+                }
+                "transaction" => {
+                    let written = transaction_tags(&content, &node).contains(&tag);
+                    if !written && !pushed.contains(&tag) {
+                        continue;
+                    }
+                    // forecasts only show up when they are asked for
+                    if tag != FORECAST_TAG && is_forecast_transaction(&content, &node) {
+                        continue;
+                    }
+                    let Some(date) = node
+                        .child_by_field_name("date")
+                        .and_then(|date| parse_date(&text_for_tree_sitter_node(&content, &date)))
+                    else {
+                        continue;
+                    };
+                    let description = |field: &str| {
+                        node.child_by_field_name(field).map(|description| {
+                            text_for_tree_sitter_node(&content, &description)
+                                .trim()
+                                .trim_matches('"')
+                                .to_string()
+                        })
+                    };
+                    transactions.push(TaggedTransaction {
+                        location: lsp_types::Location {
+                            uri: uri.clone(),
+                            range: lsp_range_for_tree_sitter_node(
+                                &content,
+                                &node,
+                                snapshot.config.position_encoding,
+                            ),
+                        },
+                        date,
+                        payee: description("payee"),
+                        narration: description("narration"),
+                        pushed: !written,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp_ext::TransactionsByTagParams;
+    use crate::providers::tags::transactions_by_tag;
+    use crate::test_utils::TestState;
+    use test_log::test;
+
+    #[test]
+    fn handle_transactions_by_tag() {
+        let fixure = r#"
+%! /main.beancount
+2024-03-01 * "Airline" "Flight to Tokyo" #trip-japan-2024
+    Assets:Bank -800 USD
+    Expenses:Travel
+pushtag #trip-japan-2024
+2024-03-02 * "Hotel"
+    Assets:Bank -200 USD
+    Expenses:Travel
+poptag #trip-japan-2024
+2024-03-10 * "Grocer"
+    Assets:Bank -20 USD
+    Expenses:Food
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let params = TransactionsByTagParams {
+            tag: String::from("trip-japan-2024"),
+            text_document: None,
+        };
+        let transactions = transactions_by_tag(test_state.snapshot, params).unwrap();
+        let found: Vec<_> = transactions
+            .iter()
+            .map(|transaction| {
+                (
+                    transaction.location.range.start.line,
+                    transaction.payee.as_deref(),
+                    transaction.narration.as_deref(),
+                    transaction.pushed,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0, Some("Airline"), Some("Flight to Tokyo"), false),
+                (4, None, Some("Hotel"), true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::Config;
+use crate::treesitter_utils::byte_to_lsp_position;
+use crate::treesitter_utils::PositionEncoding;
+use std::path::Path;
+use tracing::debug;
+
+/// The label of the built-in template.
+const STARTER: &str = "starter";
+
+/// A template for new files, as the text of a snippet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Template {
+    pub name: String,
+    pub snippet: String,
+}
+
+impl Template {
+    /// The text of the snippet with its placeholders filled in with their
+    /// defaults, for the edits that cannot be snippets.
+    pub fn text(&self) -> String {
+        let placeholder =
+            regex::Regex::new(r"\$\{(\d+):([^}]*)\}|\$\{(\d+)\}|\$(\d+)|\\([$}\\])").unwrap();
+        let defaults: std::collections::HashMap<&str, &str> = placeholder
+            .captures_iter(&self.snippet)
+            .filter_map(|caps| Some((caps.get(1)?.as_str(), caps.get(2)?.as_str())))
+            .collect();
+        placeholder
+            .replace_all(&self.snippet, |caps: &regex::Captures| {
+                if let Some(default) = caps.get(2) {
+                    default.as_str().to_string()
+                } else if let Some(index) = caps.get(3).or(caps.get(4)) {
+                    defaults
+                        .get(index.as_str())
+                        .copied()
+                        .unwrap_or_default()
+                        .to_string()
+                } else {
+                    caps[5].to_string()
+                }
+            })
+            .to_string()
+    }
+}
+
+/// The built-in template: the title, the operating currency and the usual
+/// accounts, opened at the start of the year.
+fn starter(year: i32) -> Template {
+    let snippet = format!(
+        "option \"title\" \"${{1:My Ledger}}\"\n\
+         option \"operating_currency\" \"${{2:USD}}\"\n\
+         \n\
+         ${{3:{year}-01-01}} open Equity:Opening-Balances\n\
+         $3 open Assets:Bank:Checking ${{2}}\n\
+         $3 open Assets:Cash ${{2}}\n\
+         $3 open Liabilities:CreditCard ${{2}}\n\
+         $3 open Income:Salary ${{2}}\n\
+         $3 open Expenses:Groceries\n\
+         $3 open Expenses:Rent\n\
+         \n\
+         $0\n"
+    );
+    Template {
+        name: String::from(STARTER),
+        snippet,
+    }
+}
+
+/// The built-in template and the `.beancount` files of the configured
+/// templates directory, by name.
+pub(crate) fn templates(config: &Config, year: i32) -> Vec<Template> {
+    let mut templates = vec![starter(year)];
+    if let Some(directory) = config.templates_directory() {
+        templates.extend(directory_templates(&directory));
+    }
+    templates
+}
+
+/// The templates of the configured templates directory, for files that are
+/// not the start of a ledger, unlike the built-in template.
+pub(crate) fn file_templates(config: &Config) -> Vec<Template> {
+    config
+        .templates_directory()
+        .map(|directory| directory_templates(&directory))
+        .unwrap_or_default()
+}
+
+fn directory_templates(directory: &Path) -> Vec<Template> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        debug!("templates directory {} not found", directory.display());
+        return Vec::new();
+    };
+    let mut templates: Vec<Template> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "beancount")
+        })
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            let snippet = std::fs::read_to_string(&path).ok()?;
+            Some(Template { name, snippet })
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// Whether the document is new: blank but for the word being typed on the
+/// line `row`, which the templates replace.
+pub(crate) fn is_new_file(content: &ropey::Rope, row: usize) -> bool {
+    content.lines().enumerate().all(|(index, line)| {
+        let line = line.to_string();
+        if index == row {
+            !line.trim().contains(char::is_whitespace)
+        } else {
+            line.trim().is_empty()
+        }
+    })
+}
+
+/// The templates as snippet completions replacing the line `row` up to the
+/// cursor at byte `column`.
+pub(crate) fn completion_items(
+    templates: Vec<Template>,
+    content: &ropey::Rope,
+    row: usize,
+    column: usize,
+    encoding: PositionEncoding,
+) -> Vec<lsp_types::CompletionItem> {
+    let line_start = content.line_to_byte(row);
+    let range = lsp_types::Range::new(
+        byte_to_lsp_position(content, line_start, encoding),
+        byte_to_lsp_position(content, line_start + column, encoding),
+    );
+    templates
+        .into_iter()
+        .map(|template| lsp_types::CompletionItem {
+            label: format!("template: {}", template.name),
+            kind: Some(lsp_types::CompletionItemKind::SNIPPET),
+            detail: Some(String::from("Starter content for a new file")),
+            // typing a few letters of the name still matches
+            filter_text: Some(template.name.clone()),
+            insert_text_format: Some(lsp_types::InsertTextFormat::SNIPPET),
+            text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range,
+                new_text: template.snippet,
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn handle_new_file() {
+        assert!(is_new_file(&ropey::Rope::from_str(""), 0));
+        assert!(is_new_file(&ropey::Rope::from_str("\nsta\n"), 1));
+        assert!(!is_new_file(&ropey::Rope::from_str("sta\n"), 1));
+        assert!(!is_new_file(
+            &ropey::Rope::from_str("2024-01-01 open Assets:Bank\n"),
+            1
+        ));
+        assert!(!is_new_file(&ropey::Rope::from_str("2024-01-01 op"), 0));
+    }
+
+    #[test]
+    fn handle_templates() {
+        let dir = std::env::temp_dir().join(format!("beancount-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("business.beancount"), "option \"title\" \"$1\"\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a template").unwrap();
+
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(serde_json::json!({"templates": {"directory": dir.to_str().unwrap()}}))
+            .unwrap();
+        let templates = templates(&config, 2024);
+        let names: Vec<_> = templates
+            .iter()
+            .map(|template| template.name.as_str())
+            .collect();
+        assert_eq!(names, ["starter", "business"]);
+        assert!(templates[0]
+            .snippet
+            .contains("${3:2024-01-01} open Equity:Opening-Balances"));
+        assert!(templates[0]
+            .text()
+            .contains("option \"operating_currency\" \"USD\"\n\n2024-01-01 open"));
+        assert!(templates[0]
+            .text()
+            .contains("2024-01-01 open Assets:Cash USD\n"));
+        assert_eq!(templates[1].text(), "option \"title\" \"\"\n");
+        assert_eq!(file_templates(&config), templates[1..]);
+
+        let content = ropey::Rope::from_str("bus");
+        let items = completion_items(templates, &content, 0, 3, PositionEncoding::Utf16);
+        assert_eq!(items[1].label, "template: business");
+        let Some(lsp_types::CompletionTextEdit::Edit(edit)) = &items[1].text_edit else {
+            panic!("no text edit");
+        };
+        assert_eq!(edit.range.end, lsp_types::Position::new(0, 3));
+        assert_eq!(edit.new_text, "option \"title\" \"$1\"\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::lsp_range_for_tree_sitter_node;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::str::FromStr;
+use tracing::debug;
+
+/// Provider function for LSP `workspace/symbol`. Lists the opened accounts,
+/// declared commodities, payees and tags whose name contains the query,
+/// ignoring case. Each kind has its own symbol kind and container name so
+/// clients can filter on them. A query starting with `#` only searches tags.
+pub(crate) fn workspace_symbols(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::WorkspaceSymbolParams,
+) -> Result<Option<lsp_types::WorkspaceSymbolResponse>> {
+    debug!("providers::workspace_symbols");
+    let (tags_only, query_text) = match params.query.strip_prefix('#') {
+        Some(tag) => (true, tag.to_lowercase()),
+        None => (false, params.query.to_lowercase()),
+    };
+    let hidden = snapshot.config.completion.hidden_accounts();
+
+    let query = tree_sitter::Query::new(
+        &tree_sitter_beancount::language(),
+        r#"
+        (open account: (account) @account)
+        (commodity (currency) @commodity)
+        (payee) @payee
+        (tag) @tag
+        "#,
+    )?;
+    let mut paths: Vec<_> = snapshot.forest.keys().collect();
+    paths.sort();
+    let mut symbols = Vec::new();
+    // payees and tags are listed once, where they are first used
+    let mut seen: HashSet<(&str, String)> = HashSet::new();
+    for path in paths {
+        let (Some(tree), Some(content)) =
+            (snapshot.forest.get(path), snapshot.document_content(path))
+        else {
+            continue;
+        };
+        let uri = lsp_types::Uri::from_str(format!("file://{}", path.to_str().unwrap()).as_str())?;
+        let text = content.to_string();
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        for capture in query_cursor
+            .matches(&query, tree.root_node(), text.as_bytes())
+            .flat_map(|matched| matched.captures)
+        {
+            let capture_name = query.capture_names()[capture.index as usize];
+            let node_text = text_for_tree_sitter_node(&content, &capture.node);
+            let (name, kind, container) = match capture_name {
+                "account" if !hidden.contains(&node_text) => {
+                    (node_text, lsp_types::SymbolKind::NAMESPACE, "Accounts")
+                }
+                "commodity" => (node_text, lsp_types::SymbolKind::CONSTANT, "Commodities"),
+                "payee" => (
+                    node_text.trim().trim_matches('"').to_string(),
+                    lsp_types::SymbolKind::OBJECT,
+                    "Payees",
+                ),
+                "tag" => (node_text, lsp_types::SymbolKind::KEY, "Tags"),
+                _ => continue,
+            };
+            let searched = if capture_name == "tag" {
+                &name[1..]
+            } else {
+                name.as_str()
+            };
+            if (tags_only && capture_name != "tag")
+                || !searched.to_lowercase().contains(&query_text)
+                || (matches!(capture_name, "payee" | "tag")
+                    && !seen.insert((capture_name, name.clone())))
+            {
+                continue;
+            }
+            symbols.push(lsp_types::WorkspaceSymbol {
+                name,
+                kind,
+                tags: None,
+                container_name: Some(container.to_string()),
+                location: lsp_types::OneOf::Left(lsp_types::Location {
+                    uri: uri.clone(),
+                    range: lsp_range_for_tree_sitter_node(
+                        &content,
+                        &capture.node,
+                        snapshot.config.position_encoding,
+                    ),
+                }),
+                data: None,
+            });
+        }
+    }
+    symbols.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Some(lsp_types::WorkspaceSymbolResponse::Nested(symbols)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::workspace_symbols::workspace_symbols;
+    use crate::test_utils::TestState;
+    use std::sync::Arc;
+    use test_log::test;
+
+    #[test]
+    fn handle_workspace_symbols() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Assets:Bank
+2023-01-01 open Equity:Opening-Balances
+2023-01-01 open Expenses:Bank-Fees
+2023-01-01 open Expenses:Food
+2023-01-01 commodity BANKCOIN
+2023-01-02 txn "Bank of Springfield" "Fees" #banking
+    Expenses:Bank-Fees 1 USD
+    Assets:Bank
+2023-01-03 txn "Bank of Springfield" "Fees" #banking
+    Expenses:Bank-Fees 1 USD
+    Assets:Bank
+"#;
+        let mut test_state = TestState::new(fixure).unwrap();
+        Arc::make_mut(&mut test_state.snapshot.config)
+            .completion
+            .hidden_account_patterns = vec![String::from("Equity:*")];
+        let params = lsp_types::WorkspaceSymbolParams {
+            query: String::from("bank"),
+            ..Default::default()
+        };
+        let Some(lsp_types::WorkspaceSymbolResponse::Nested(symbols)) =
+            workspace_symbols(test_state.snapshot, params).unwrap()
+        else {
+            panic!("expected workspace symbols");
+        };
+        let names: Vec<_> = symbols
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.name.as_str(),
+                    symbol.kind,
+                    symbol.container_name.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("#banking", lsp_types::SymbolKind::KEY, "Tags"),
+                ("Assets:Bank", lsp_types::SymbolKind::NAMESPACE, "Accounts"),
+                ("BANKCOIN", lsp_types::SymbolKind::CONSTANT, "Commodities"),
+                (
+                    "Bank of Springfield",
+                    lsp_types::SymbolKind::OBJECT,
+                    "Payees"
+                ),
+                (
+                    "Expenses:Bank-Fees",
+                    lsp_types::SymbolKind::NAMESPACE,
+                    "Accounts"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_workspace_symbols_tags() {
+        let fixure = r#"
+%! /main.beancount
+2023-01-01 open Assets:Trip
+2023-01-02 txn "Trip Co" "Fees" #trip #other
+    Assets:Trip 1 USD
+    Assets:Cash
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let params = lsp_types::WorkspaceSymbolParams {
+            query: String::from("#tri"),
+            ..Default::default()
+        };
+        let Some(lsp_types::WorkspaceSymbolResponse::Nested(symbols)) =
+            workspace_symbols(test_state.snapshot, params).unwrap()
+        else {
+            panic!("expected workspace symbols");
+        };
+        let names: Vec<_> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["#trip"]);
+    }
+}
@@ -1,22 +1,43 @@
 use crate::beancount_data::BeancountData;
+use crate::checker;
+use crate::checker::FailedCheckers;
+use crate::commands;
 use crate::config::Config;
 use crate::dispatcher::NotificationDispatcher;
 use crate::dispatcher::RequestDispatcher;
 use crate::document::Document;
 use crate::forest;
 use crate::handlers;
+use crate::lsp_ext;
 use crate::progress::Progress;
+use crate::providers::diagnostics;
+#[cfg(feature = "spellcheck")]
+use crate::spellcheck;
+use crate::telemetry::Telemetry;
+use crate::treesitter_utils;
 use crate::utils::ToFilePath;
 use anyhow::Result;
 use crossbeam_channel::{Receiver, Sender};
 use lsp_types::notification::Notification;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::time::Instant;
 
 pub(crate) type RequestHandler = fn(&mut LspServerState, lsp_server::Response);
 
+/// The id the file watchers are registered with the client under.
+const FILE_WATCHERS_ID: &str = "beancount/watchedFiles";
+
+/// The files of the workspace the client watches for the server.
+const WATCHED_FILES_GLOB: &str = "**/*.beancount";
+
 #[derive(Debug)]
 pub(crate) enum ProgressMsg {
     BeanCheck {
@@ -32,9 +53,16 @@ pub(crate) enum ProgressMsg {
 
 #[derive(Debug)]
 pub(crate) enum Task {
-    Response(lsp_server::Response),
+    /// A response computed from a snapshot, along with the versions of the
+    /// open documents the request targets.
+    Response(lsp_server::Response, HashMap<PathBuf, i32>),
     Notify(lsp_server::Notification),
+    /// The diagnostics of a finished check of a journal for every file it
+    /// covered, to replace the published ones at once.
+    Diagnostics(PathBuf, BTreeMap<PathBuf, Vec<lsp_types::Diagnostic>>),
     Progress(ProgressMsg),
+    /// An edit computed in the background, to apply with the given label.
+    ApplyEdit(String, lsp_types::WorkspaceEdit),
 }
 
 #[derive(Debug)]
@@ -51,15 +79,29 @@ struct LspServer {
 */
 
 pub(crate) struct LspServerState {
-    pub beancount_data: HashMap<PathBuf, BeancountData>,
+    // Shared with the snapshots, like the other `Arc` fields, so taking one
+    // does not copy the data of every file; changed through `Arc::make_mut`
+    pub beancount_data: Arc<HashMap<PathBuf, BeancountData>>,
 
     // the lsp server config options
-    pub config: Config,
+    pub config: Arc<Config>,
+
+    // The diagnostics last published for each file
+    pub diagnostics: Arc<HashMap<PathBuf, Vec<lsp_types::Diagnostic>>>,
+
+    // The files edited since their diagnostics were published
+    pub stale_diagnostics: HashSet<PathBuf>,
 
-    pub forest: HashMap<PathBuf, tree_sitter::Tree>,
+    // When each journal was last checked
+    pub last_checks: Arc<HashMap<PathBuf, chrono::DateTime<chrono::Local>>>,
+
+    pub forest: Arc<HashMap<PathBuf, tree_sitter::Tree>>,
+
+    // The ledgers in the workspace and the files each of them includes
+    pub journals: Arc<Vec<Journal>>,
 
     // Documents that are currently kept in memory from the client
-    pub open_docs: HashMap<PathBuf, Document>,
+    pub open_docs: Arc<HashMap<PathBuf, Document>>,
 
     pub parsers: HashMap<PathBuf, tree_sitter::Parser>,
 
@@ -80,14 +122,94 @@ pub(crate) struct LspServerState {
 
     // Thread pool for async execution
     pub thread_pool: threadpool::ThreadPool,
+
+    // Set on shutdown to stop background tasks and checker processes
+    pub cancelled: Arc<AtomicBool>,
+
+    // The checker methods that failed, to skip until the configuration changes
+    pub failed_checkers: FailedCheckers,
+
+    // Set while `beancount.reloadWorkspace` indexes the workspace again
+    pub reloading: bool,
+
+    // Usage statistics, written when `telemetry.localPath` is configured
+    pub telemetry: Telemetry,
+
+    // The globs the client watches files with for the server, once registered
+    pub file_watchers: Vec<String>,
+}
+
+/// A ledger in the workspace: its main file and the files reachable from it.
+#[derive(Debug, Clone)]
+pub(crate) struct Journal {
+    pub root: PathBuf,
+    pub files: HashSet<PathBuf>,
 }
 
 /// A snapshot of the state of the language server
 pub(crate) struct LspServerStateSnapshot {
-    pub beancount_data: HashMap<PathBuf, BeancountData>,
-    pub config: Config,
-    pub forest: HashMap<PathBuf, tree_sitter::Tree>,
-    pub open_docs: HashMap<PathBuf, Document>,
+    pub beancount_data: Arc<HashMap<PathBuf, BeancountData>>,
+    pub config: Arc<Config>,
+    pub forest: Arc<HashMap<PathBuf, tree_sitter::Tree>>,
+    pub journals: Arc<Vec<Journal>>,
+    pub open_docs: Arc<HashMap<PathBuf, Document>>,
+    pub cancelled: Arc<AtomicBool>,
+    pub failed_checkers: FailedCheckers,
+    pub diagnostics: Arc<HashMap<PathBuf, Vec<lsp_types::Diagnostic>>>,
+    pub last_checks: Arc<HashMap<PathBuf, chrono::DateTime<chrono::Local>>>,
+}
+
+impl LspServerStateSnapshot {
+    /// Returns the text of a file in the forest, preferring the in-memory
+    /// version of open documents over the file on disk.
+    pub(crate) fn document_content(&self, path: &Path) -> Option<ropey::Rope> {
+        match self.open_docs.get(path) {
+            Some(document) => Some(document.text()),
+            None => std::fs::read_to_string(path)
+                .ok()
+                .map(|text| ropey::Rope::from_str(&text)),
+        }
+    }
+
+    /// The first configured journal that includes `file`.
+    pub(crate) fn journal_for(&self, file: &Path) -> Option<&Journal> {
+        self.journals
+            .iter()
+            .find(|journal| journal.files.contains(file))
+    }
+
+    /// The data of the files in the same journal as `file`, so that a file
+    /// only sees its own ledger when the workspace holds several. Files that
+    /// no journal includes see the data of all files. The data of the
+    /// snapshot is shared unless the journal leaves out some of its files.
+    pub(crate) fn journal_data(&self, file: &Path) -> Arc<HashMap<PathBuf, BeancountData>> {
+        match self.journal_for(file) {
+            Some(journal)
+                if !self
+                    .beancount_data
+                    .keys()
+                    .all(|path| journal.files.contains(path)) =>
+            {
+                Arc::new(
+                    self.beancount_data
+                        .iter()
+                        .filter(|(path, _)| journal.files.contains(*path))
+                        .map(|(path, data)| (path.clone(), data.clone()))
+                        .collect(),
+                )
+            }
+            _ => self.beancount_data.clone(),
+        }
+    }
+
+    /// The versions of the open documents among `paths` this snapshot was
+    /// taken from.
+    pub(crate) fn document_versions(&self, paths: &[PathBuf]) -> HashMap<PathBuf, i32> {
+        paths
+            .iter()
+            .filter_map(|path| Some((path.clone(), self.open_docs.get(path)?.version)))
+            .collect()
+    }
 }
 
 /*
@@ -104,10 +226,14 @@ impl LspServerState {
         let (task_sender, task_receiver) = crossbeam_channel::unbounded();
         //let (event_tx, event_rx) = crossbeam_channel::unbounded();
         Self {
-            beancount_data: HashMap::new(),
-            config,
-            forest: HashMap::new(),
-            open_docs: HashMap::new(),
+            beancount_data: Arc::default(),
+            config: Arc::new(config),
+            diagnostics: Arc::default(),
+            stale_diagnostics: HashSet::new(),
+            last_checks: Arc::default(),
+            forest: Arc::default(),
+            journals: Arc::default(),
+            open_docs: Arc::default(),
             parsers: HashMap::new(),
             req_queue: lsp_server::ReqQueue::default(),
             sender,
@@ -115,40 +241,26 @@ impl LspServerState {
             task_sender,
             task_receiver,
             thread_pool: threadpool::ThreadPool::default(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            failed_checkers: FailedCheckers::default(),
+            reloading: false,
+            telemetry: Telemetry::default(),
+            file_watchers: Vec::new(),
         }
     }
 
     pub fn run(&mut self, receiver: Receiver<lsp_server::Message>) -> Result<()> {
-        // init forest
-        if self.config.journal_root.is_some() {
-            let file = self.config.journal_root.as_ref().unwrap();
-            let journal_root =
-                lsp_types::Uri::from_str(format!("file://{}", file.to_str().unwrap()).as_str())
-                    .unwrap()
-                    .to_file_path()
-                    .unwrap();
-            // .unwrap_or_else(|()| panic!("Cannot parse URL for file '{file:?}'"));
-
-            tracing::info!("initializing forest...");
-            let snapshot = self.snapshot();
-            let sender = self.task_sender.clone();
-            self.thread_pool.execute(move || {
-                forest::parse_initial_forest(snapshot, journal_root, sender).unwrap();
-            });
-            /*forest::parse_initial_forest(
-                &self.session,
-                lsp_types::Url::from_file_path(
-                    self.session.root_journal_path.read().await.clone().unwrap(),
-                )
-                .unwrap(),
-            )
-            .unwrap();
-            */
-        }
+        self.register_file_watchers();
+        self.index_workspace();
 
         while let Some(event) = self.next_event(&receiver) {
             if let Event::Lsp(lsp_server::Message::Notification(notification)) = &event {
                 if notification.method == lsp_types::notification::Exit::METHOD {
+                    self.cancel_background_tasks();
+                    self.write_telemetry(true);
+                    if !self.shutdown_requested {
+                        anyhow::bail!("exit notification received before shutdown request");
+                    }
                     return Ok(());
                 }
             }
@@ -157,6 +269,80 @@ impl LspServerState {
         Ok(())
     }
 
+    /// Parses the journal roots and the files they include in the background,
+    /// and reports the journal roots that do not exist. Returns whether
+    /// parsing started, which it does not without an existing journal root.
+    fn index_workspace(&mut self) -> bool {
+        let (journal_roots, missing): (Vec<PathBuf>, Vec<PathBuf>) = self
+            .journal_roots()
+            .into_iter()
+            .partition(|journal_root| journal_root.is_file());
+        for journal_root in missing {
+            tracing::warn!("journal file {:#?} not found", journal_root);
+            self.report_missing_journal(&journal_root);
+        }
+        if journal_roots.is_empty() {
+            self.send_journal_resolved();
+            return false;
+        }
+        tracing::info!("initializing forest...");
+        let snapshot = self.snapshot();
+        let sender = self.task_sender.clone();
+        self.thread_pool.execute(move || {
+            forest::parse_initial_forest(snapshot, journal_roots, sender).unwrap();
+        });
+        true
+    }
+
+    /// Handler of the `beancount.reloadWorkspace` command. Drops everything
+    /// parsed or cached and indexes the workspace again from the journal
+    /// roots, for when the state seems out of date with the files. The open
+    /// documents are parsed again from the content the client sent.
+    pub(crate) fn reload_workspace(&mut self) {
+        tracing::info!("reloading workspace");
+        self.forest = Arc::default();
+        self.beancount_data = Arc::default();
+        self.parsers.clear();
+        self.journals = Arc::default();
+        self.failed_checkers.clear();
+        #[cfg(feature = "spellcheck")]
+        spellcheck::clear_cache();
+
+        let encoding = self.config.position_encoding;
+        for (path, document) in self.open_docs.iter() {
+            let mut parser = tree_sitter::Parser::new();
+            parser
+                .set_language(&tree_sitter_beancount::language())
+                .unwrap();
+            let Some(tree) = parser.parse(document.text().to_string(), None) else {
+                continue;
+            };
+            let number_format = self.config.number_format.for_content(&document.content);
+            Arc::make_mut(&mut self.beancount_data).insert(
+                path.clone(),
+                BeancountData::new(&tree, &document.content, encoding, number_format),
+            );
+            Arc::make_mut(&mut self.forest).insert(path.clone(), tree);
+            self.parsers.insert(path.clone(), parser);
+        }
+
+        self.reloading = true;
+        if !self.index_workspace() {
+            self.finish_reload();
+        }
+    }
+
+    /// Tells the client that a reload of the workspace is done.
+    fn finish_reload(&mut self) {
+        self.reloading = false;
+        self.send_notification::<lsp_types::notification::ShowMessage>(
+            lsp_types::ShowMessageParams {
+                typ: lsp_types::MessageType::INFO,
+                message: format!("Workspace reloaded, {} files indexed", self.forest.len()),
+            },
+        );
+    }
+
     // Blocks until new event is received
     pub fn next_event(&self, receiver: &Receiver<lsp_server::Message>) -> Option<Event> {
         crossbeam_channel::select! {
@@ -184,11 +370,32 @@ impl LspServerState {
     // Handles a task sent by another async task
     fn handle_task(&mut self, task: Task) -> anyhow::Result<()> {
         match task {
-            Task::Notify(notification) => {
-                self.send(notification.into());
+            Task::Notify(notification) => self.send(notification.into()),
+            Task::Diagnostics(journal_root, diagnostics) => {
+                Arc::make_mut(&mut self.last_checks).insert(journal_root, chrono::Local::now());
+                self.publish_diagnostics(diagnostics);
+                self.send_notification::<lsp_ext::CheckSummary>(diagnostics::summary(
+                    &self.diagnostics,
+                ));
+                if self.config.inlay_hints.file_summary {
+                    // the summaries count the new errors
+                    self.send_request::<lsp_types::request::InlayHintRefreshRequest>((), |_, _| {});
+                }
+            }
+            Task::Response(response, versions) => {
+                if self.is_outdated(&versions) {
+                    tracing::info!("dropping outdated response to req#{}", response.id);
+                    self.respond(lsp_server::Response::new_err(
+                        response.id,
+                        lsp_server::ErrorCode::ContentModified as i32,
+                        "content modified".to_string(),
+                    ));
+                } else {
+                    self.respond(response);
+                }
             }
-            Task::Response(response) => self.respond(response),
             Task::Progress(task) => self.handle_progress_task(task)?,
+            Task::ApplyEdit(label, edit) => handlers::workspace::apply_edit(self, &label, edit),
         }
         Ok(())
     }
@@ -211,9 +418,12 @@ impl LspServerState {
                 )
             }
             ProgressMsg::ForestInit { total, done, data } => {
-                if let Some(data) = *data {
-                    self.forest.insert(data.0.clone(), data.1);
-                    self.beancount_data.insert(data.0, data.2);
+                // the last message, unlike the first one, has no data either
+                let finished = data.is_none() && done > 0 && done == total;
+                // an open document is newer than the file on disk
+                if let Some(data) = (*data).filter(|data| !self.open_docs.contains_key(&data.0)) {
+                    Arc::make_mut(&mut self.forest).insert(data.0.clone(), data.1);
+                    Arc::make_mut(&mut self.beancount_data).insert(data.0, data.2);
                 }
                 let progress_state = if done == 0 {
                     Progress::Begin
@@ -227,7 +437,14 @@ impl LspServerState {
                     progress_state,
                     Some(format!("{}/{}", done, total)),
                     Some(Progress::fraction(done, total)),
-                )
+                );
+                if finished {
+                    self.refresh_journals();
+                    self.send_journal_resolved();
+                    if self.reloading {
+                        self.finish_reload();
+                    }
+                }
             }
         }
         Ok(())
@@ -257,22 +474,58 @@ impl LspServerState {
         RequestDispatcher::new(self, req)
             .on_sync::<lsp_types::request::Shutdown>(|state, _request| {
                 state.shutdown_requested = true;
+                state.cancel_background_tasks();
                 Ok(())
             })?
             .on::<lsp_types::request::Completion>(handlers::text_document::completion)?
+            .on::<lsp_types::request::CodeActionRequest>(handlers::text_document::code_action)?
+            .on::<lsp_types::request::CodeLensRequest>(handlers::text_document::code_lens)?
+            .on::<lsp_types::request::DocumentLinkRequest>(handlers::text_document::document_link)?
+            .on::<lsp_types::request::DocumentSymbolRequest>(
+                handlers::text_document::document_symbol,
+            )?
+            .on::<lsp_types::request::FoldingRangeRequest>(handlers::text_document::folding_range)?
             .on::<lsp_types::request::Formatting>(handlers::text_document::formatting)?
+            .on::<lsp_types::request::OnTypeFormatting>(
+                handlers::text_document::on_type_formatting,
+            )?
+            .on::<lsp_types::request::HoverRequest>(handlers::text_document::hover)?
+            .on::<lsp_types::request::SemanticTokensFullRequest>(
+                handlers::text_document::semantic_tokens_full,
+            )?
+            .on::<lsp_types::request::SignatureHelpRequest>(
+                handlers::text_document::signature_help,
+            )?
+            .on::<lsp_types::request::InlayHintRequest>(handlers::text_document::inlay_hint)?
+            .on::<lsp_types::request::PrepareRenameRequest>(
+                handlers::text_document::prepare_rename,
+            )?
+            .on::<lsp_types::request::Rename>(handlers::text_document::rename)?
+            .on::<lsp_types::request::References>(handlers::text_document::references)?
+            .on_command(
+                commands::BACKGROUND_COMMANDS,
+                handlers::workspace::execute_background_command,
+            )?
+            .on_sync::<lsp_types::request::ExecuteCommand>(handlers::workspace::execute_command)?
+            .on::<lsp_types::request::WorkspaceSymbolRequest>(handlers::workspace::symbol)?
+            .on::<lsp_ext::AccountActivity>(handlers::beancount::account_activity)?
+            .on::<lsp_ext::BalanceAt>(handlers::beancount::balance_at)?
+            .on::<lsp_ext::InlineBalances>(handlers::beancount::inline_balances)?
+            .on::<lsp_ext::RenamePreview>(handlers::beancount::rename_preview)?
+            .on::<lsp_ext::TransactionsByTag>(handlers::beancount::transactions_by_tag)?
+            .on::<lsp_ext::AstDump>(handlers::beancount::ast_dump)?
+            // advertised with the document sync, but nothing changes on save
+            .stub::<lsp_types::request::WillSaveWaitUntil>()
             .finish();
         Ok(())
     }
 
     // Handles a response to a request we made. The response gets forwarded to where we made the request from.
     fn complete_request(&mut self, resp: lsp_server::Response) {
-        let handler = self
-            .req_queue
-            .outgoing
-            .complete(resp.id.clone())
-            .expect("received response for unknown request");
-        handler(self, resp)
+        match self.req_queue.outgoing.complete(resp.id.clone()) {
+            Some(handler) => handler(self, resp),
+            None => tracing::warn!("received response for unknown request: {}", resp.id),
+        }
     }
 
     // Handles a notification from the language server client
@@ -286,16 +539,36 @@ impl LspServerState {
             .on::<lsp_types::notification::DidChangeTextDocument>(
                 handlers::text_document::did_change,
             )?
+            .on::<lsp_types::notification::DidChangeWatchedFiles>(
+                handlers::workspace::did_change_watched_files,
+            )?
+            .on::<lsp_types::notification::DidChangeConfiguration>(
+                handlers::workspace::did_change_configuration,
+            )?
+            .ignore::<lsp_types::notification::WillSaveTextDocument>()
             .finish();
         Ok(())
     }
 
     // Sends a response to the client. This method logs the time it took us to reply to a request from the client.
     pub(crate) fn respond(&mut self, response: lsp_server::Response) {
-        if let Some((_method, start)) = self.req_queue.incoming.complete(&response.id) {
+        if let Some((method, start)) = self.req_queue.incoming.complete(&response.id) {
             let duration = start.elapsed();
             tracing::info!("handled req#{} in {:?}", response.id, duration);
+            let error = response.error.is_some();
             self.send(response.into());
+            if self.config.telemetry.local_path.is_some() {
+                self.telemetry.record(&method, duration, error);
+                self.write_telemetry(false);
+            }
+        }
+    }
+
+    /// Writes the usage statistics to the configured file, at most once a
+    /// minute unless `force` is set.
+    fn write_telemetry(&mut self, force: bool) {
+        if let Some(path) = self.config.telemetry_path() {
+            self.telemetry.write(&path, force);
         }
     }
 
@@ -328,12 +601,255 @@ impl LspServerState {
         self.send(not.into());
     }
 
+    /// The configured journal files, as the forest refers to them.
+    fn journal_roots(&self) -> Vec<PathBuf> {
+        self.config
+            .journal_roots
+            .iter()
+            .filter_map(|file| {
+                lsp_types::Uri::from_str(format!("file://{}", file.to_str()?).as_str())
+                    .ok()?
+                    .to_file_path()
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Recomputes the files each journal includes.
+    fn refresh_journals(&mut self) {
+        let ignore = self.config.files.ignore_globs();
+        self.journals = Arc::new(
+            self.journal_roots()
+                .into_iter()
+                .filter(|root| self.forest.contains_key(root))
+                .map(|root| Journal {
+                    files: forest::reachable_files([root.clone()], &self.beancount_data, &ignore),
+                    root,
+                })
+                .collect(),
+        );
+        self.register_file_watchers();
+    }
+
+    /// Asks the client to tell the server about changes to the beancount
+    /// files of the workspace and to the files the journals include, which
+    /// may live elsewhere or have another extension. The watchers registered
+    /// before are replaced when the included files changed. Only done for
+    /// clients that can register watchers after `initialized`.
+    fn register_file_watchers(&mut self) {
+        if !self.config.dynamic_file_watchers {
+            return;
+        }
+        let mut globs: Vec<String> = self
+            .journals
+            .iter()
+            .flat_map(|journal| journal.files.iter())
+            .filter(|file| {
+                !file.starts_with(&self.config.root_file)
+                    || file
+                        .extension()
+                        .is_none_or(|extension| extension != "beancount")
+            })
+            .filter_map(|file| file.to_str().map(String::from))
+            .collect();
+        globs.sort();
+        globs.dedup();
+        globs.insert(0, String::from(WATCHED_FILES_GLOB));
+        if globs == self.file_watchers {
+            return;
+        }
+        if !self.file_watchers.is_empty() {
+            self.send_request::<lsp_types::request::UnregisterCapability>(
+                lsp_types::UnregistrationParams {
+                    unregisterations: vec![lsp_types::Unregistration {
+                        id: String::from(FILE_WATCHERS_ID),
+                        method: lsp_types::notification::DidChangeWatchedFiles::METHOD.to_owned(),
+                    }],
+                },
+                |_, _| {},
+            );
+        }
+        let options = lsp_types::DidChangeWatchedFilesRegistrationOptions {
+            watchers: globs
+                .iter()
+                .map(|glob| lsp_types::FileSystemWatcher {
+                    glob_pattern: lsp_types::GlobPattern::String(glob.clone()),
+                    kind: None,
+                })
+                .collect(),
+        };
+        self.send_request::<lsp_types::request::RegisterCapability>(
+            lsp_types::RegistrationParams {
+                registrations: vec![lsp_types::Registration {
+                    id: String::from(FILE_WATCHERS_ID),
+                    method: lsp_types::notification::DidChangeWatchedFiles::METHOD.to_owned(),
+                    register_options: Some(serde_json::to_value(options).unwrap()),
+                }],
+            },
+            |_, _| {},
+        );
+        self.file_watchers = globs;
+    }
+
+    /// Tells the client which journals are in use and how many files each of
+    /// them includes, with a single notification without a journal file when
+    /// none is configured.
+    pub(crate) fn send_journal_resolved(&mut self) {
+        let journal_roots = self.journal_roots();
+        let journal_files: Vec<Option<PathBuf>> = if journal_roots.is_empty() {
+            vec![None]
+        } else {
+            journal_roots.into_iter().map(Some).collect()
+        };
+        for journal_file in journal_files {
+            let journal = journal_file
+                .as_ref()
+                .and_then(|file| self.journals.iter().find(|journal| journal.root == *file));
+            let found = journal.is_some();
+            let included_files = journal.map_or(0, |journal| journal.files.len() - 1);
+            self.send_notification::<lsp_ext::JournalResolved>(lsp_ext::JournalResolvedParams {
+                journal_file,
+                found,
+                included_files,
+                checker: checker::select(
+                    &self.config.checker.method,
+                    &self.failed_checkers,
+                    &checker::bean_check_cmd(&self.config.checker, &self.config.root_file),
+                )
+                .name()
+                .to_string(),
+                grammar_version: treesitter_utils::grammar_version(),
+            });
+        }
+    }
+
+    /// Reports a configured journal file that does not exist with a message,
+    /// as a diagnostic on its path would be shown by no client.
+    fn report_missing_journal(&mut self, journal_root: &std::path::Path) {
+        self.send_notification::<lsp_types::notification::ShowMessage>(
+            lsp_types::ShowMessageParams {
+                typ: lsp_types::MessageType::ERROR,
+                message: format!("journal file {} does not exist", journal_root.display()),
+            },
+        );
+    }
+
+    /// Publishes the diagnostics of a finished check in place of the previous
+    /// ones, and remembers them so commands can navigate between them.
+    fn publish_diagnostics(&mut self, diagnostics: BTreeMap<PathBuf, Vec<lsp_types::Diagnostic>>) {
+        for (file, diagnostics) in diagnostics {
+            self.stale_diagnostics.remove(&file);
+            if diagnostics.is_empty() {
+                Arc::make_mut(&mut self.diagnostics).remove(&file);
+            } else {
+                Arc::make_mut(&mut self.diagnostics).insert(file.clone(), diagnostics.clone());
+            }
+            let Ok(uri) =
+                lsp_types::Uri::from_str(format!("file://{}", file.to_str().unwrap()).as_str())
+            else {
+                continue;
+            };
+            self.send_notification::<lsp_types::notification::PublishDiagnostics>(
+                lsp_types::PublishDiagnosticsParams {
+                    uri,
+                    diagnostics,
+                    version: None,
+                },
+            );
+        }
+    }
+
+    /// Republishes the diagnostics of an edited file tagged as stale, as they
+    /// may no longer match its content until the next check replaces them.
+    pub(crate) fn mark_diagnostics_stale(&mut self, file: &Path) {
+        if !self.stale_diagnostics.insert(file.to_path_buf()) {
+            return;
+        }
+        let Some(diagnostics) = self.diagnostics.get(file) else {
+            return;
+        };
+        let diagnostics = diagnostics::stale(diagnostics);
+        let Ok(uri) =
+            lsp_types::Uri::from_str(format!("file://{}", file.to_str().unwrap()).as_str())
+        else {
+            return;
+        };
+        self.send_notification::<lsp_types::notification::PublishDiagnostics>(
+            lsp_types::PublishDiagnosticsParams {
+                uri,
+                diagnostics,
+                version: None,
+            },
+        );
+    }
+
+    /// Stops running background tasks, killing running `bean-check` processes,
+    /// and waits for them to finish.
+    fn cancel_background_tasks(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.thread_pool.join();
+    }
+
+    /// Drops the files that can no longer be reached from a journal root or
+    /// an open document, like deleted files or files whose include was
+    /// removed, and clears their diagnostics.
+    pub(crate) fn prune_forest(&mut self) {
+        self.refresh_journals();
+        let roots = self
+            .journal_roots()
+            .into_iter()
+            .chain(self.open_docs.keys().cloned());
+        let ignore = self.config.files.ignore_globs();
+        let reachable = forest::reachable_files(roots, &self.beancount_data, &ignore);
+        let stale: Vec<PathBuf> = self
+            .forest
+            .keys()
+            .filter(|file| !reachable.contains(*file))
+            .cloned()
+            .collect();
+        for file in stale {
+            tracing::info!("pruning {:#?}", file);
+            Arc::make_mut(&mut self.forest).remove(&file);
+            Arc::make_mut(&mut self.beancount_data).remove(&file);
+            self.parsers.remove(&file);
+            Arc::make_mut(&mut self.diagnostics).remove(&file);
+            self.stale_diagnostics.remove(&file);
+            if let Ok(uri) =
+                lsp_types::Uri::from_str(format!("file://{}", file.to_str().unwrap()).as_str())
+            {
+                self.send_notification::<lsp_types::notification::PublishDiagnostics>(
+                    lsp_types::PublishDiagnosticsParams {
+                        uri,
+                        diagnostics: vec![],
+                        version: None,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Whether a document has been changed or closed since a snapshot with
+    /// `versions` was taken, so a response computed from it may refer to
+    /// content that no longer exists.
+    fn is_outdated(&self, versions: &HashMap<PathBuf, i32>) -> bool {
+        versions.iter().any(|(path, version)| {
+            self.open_docs
+                .get(path)
+                .is_none_or(|document| document.version != *version)
+        })
+    }
+
     pub(crate) fn snapshot(&self) -> LspServerStateSnapshot {
         LspServerStateSnapshot {
             beancount_data: self.beancount_data.clone(),
             config: self.config.clone(),
             forest: self.forest.clone(),
+            journals: self.journals.clone(),
             open_docs: self.open_docs.clone(),
+            cancelled: self.cancelled.clone(),
+            failed_checkers: self.failed_checkers.clone(),
+            diagnostics: self.diagnostics.clone(),
+            last_checks: self.last_checks.clone(),
         }
     }
 }
@@ -0,0 +1,170 @@
+//! Hints about misspelled words in payees and narrations, checked against a
+//! Hunspell dictionary and the user's own words.
+
+use crate::beancount_data::BeancountData;
+use crate::config::SpellcheckOptions;
+use spellbook::Dictionary;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Number of corrections offered for a misspelled word.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// The last loaded dictionary and the options it was loaded with, as loading
+/// one takes much longer than checking a ledger. `None` if it failed to load.
+#[allow(clippy::type_complexity)]
+static DICTIONARY: Mutex<Option<(SpellcheckOptions, Option<Arc<Dictionary>>)>> = Mutex::new(None);
+
+/// A word of a payee or narration that the dictionary does not know.
+#[derive(Clone, Debug)]
+pub struct Misspelling {
+    pub word: String,
+    pub range: lsp_types::Range,
+    pub suggestions: Vec<String>,
+}
+
+/// The misspelled words of the payees and narrations in each file.
+pub fn misspellings(
+    data: &HashMap<PathBuf, BeancountData>,
+    options: &SpellcheckOptions,
+) -> HashMap<PathBuf, Vec<Misspelling>> {
+    let Some(dictionary) = dictionary(options) else {
+        return HashMap::new();
+    };
+    // the same words come up in many transactions
+    let mut suggestions: HashMap<&str, Option<Vec<String>>> = HashMap::new();
+    let mut misspellings: HashMap<PathBuf, Vec<Misspelling>> = HashMap::new();
+    for (file, data) in data.iter() {
+        for word in data.get_description_words() {
+            let word_suggestions = suggestions.entry(word.word.as_str()).or_insert_with(|| {
+                if dictionary.check(&word.word) {
+                    return None;
+                }
+                let mut corrections = vec![];
+                dictionary.suggest(&word.word, &mut corrections);
+                corrections.truncate(MAX_SUGGESTIONS);
+                Some(corrections)
+            });
+            if let Some(word_suggestions) = word_suggestions {
+                misspellings
+                    .entry(file.clone())
+                    .or_default()
+                    .push(Misspelling {
+                        word: word.word.clone(),
+                        range: word.range,
+                        suggestions: word_suggestions.clone(),
+                    });
+            }
+        }
+    }
+    misspellings
+}
+
+/// Forgets the loaded dictionary, so the next check loads it again.
+pub(crate) fn clear_cache() {
+    *DICTIONARY.lock().unwrap() = None;
+}
+
+fn dictionary(options: &SpellcheckOptions) -> Option<Arc<Dictionary>> {
+    let mut cache = DICTIONARY.lock().unwrap();
+    if let Some((cached_options, dictionary)) = cache.as_ref() {
+        if cached_options == options {
+            return dictionary.clone();
+        }
+    }
+    let dictionary = match load(options) {
+        Ok(dictionary) => Some(Arc::new(dictionary)),
+        Err(err) => {
+            tracing::warn!("spellcheck dictionary {}: {}", options.dictionary, err);
+            None
+        }
+    };
+    *cache = Some((options.clone(), dictionary.clone()));
+    dictionary
+}
+
+fn load(options: &SpellcheckOptions) -> anyhow::Result<Dictionary> {
+    let path = expand(&options.dictionary);
+    let aff = std::fs::read_to_string(format!("{path}.aff"))?;
+    let dic = std::fs::read_to_string(format!("{path}.dic"))?;
+    let mut dictionary =
+        Dictionary::new(&aff, &dic).map_err(|err| anyhow::anyhow!("invalid: {err:?}"))?;
+
+    let mut words = options.words.clone();
+    for file in options.user_dictionaries.iter() {
+        match std::fs::read_to_string(expand(file)) {
+            Ok(text) => words.extend(
+                text.lines()
+                    .map(str::trim)
+                    .filter(|word| !word.is_empty() && !word.starts_with('#'))
+                    .map(String::from),
+            ),
+            Err(err) => tracing::warn!("spellcheck user dictionary {}: {}", file, err),
+        }
+    }
+    for word in words {
+        if let Err(err) = dictionary.add(&word) {
+            tracing::warn!("spellcheck word {}: {:?}", word, err);
+        }
+    }
+    Ok(dictionary)
+}
+
+/// Expands `~` and environment variables in a configured path.
+fn expand(path: &str) -> String {
+    shellexpand::full(path)
+        .unwrap_or_else(|_| shellexpand::tilde(path))
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::SpellcheckOptions;
+    use crate::spellcheck::misspellings;
+    use crate::test_utils::TestState;
+    use std::path::PathBuf;
+    use test_log::test;
+
+    #[test]
+    fn handle_misspellings() {
+        let dir = std::env::temp_dir().join(format!("beancount-spellcheck-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("en.aff"),
+            "SET UTF-8\nTRY esianrtolcdugmphbyfvkwz\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("en.dic"), "4\nweekly\nshopping\nfor\nthe\n").unwrap();
+        std::fs::write(dir.join("payees.dic"), "# shops\nGrocer\n").unwrap();
+        let options = SpellcheckOptions {
+            enable: true,
+            dictionary: dir.join("en").to_str().unwrap().to_string(),
+            user_dictionaries: vec![dir.join("payees.dic").to_str().unwrap().to_string()],
+            words: vec![String::from("ATM")],
+        };
+
+        let fixure = r#"
+%! /main.beancount
+2023-10-01 * "Grocer" "Weekly shoping for the ATM"
+    Expenses:Food 42.10 USD
+    Assets:Bank
+"#;
+        let test_state = TestState::new(fixure).unwrap();
+        let misspellings = misspellings(&test_state.snapshot.beancount_data, &options);
+        let misspellings = &misspellings[&PathBuf::from("/main.beancount")];
+        assert_eq!(misspellings.len(), 1);
+        assert_eq!(misspellings[0].word, "shoping");
+        assert_eq!(
+            misspellings[0].range,
+            lsp_types::Range::new(
+                lsp_types::Position::new(0, 30),
+                lsp_types::Position::new(0, 37),
+            )
+        );
+        assert!(misspellings[0]
+            .suggestions
+            .contains(&String::from("shopping")));
+    }
+}
@@ -0,0 +1,150 @@
+//! Opt-in usage statistics, written to the local file of the
+//! `telemetry.localPath` option and never sent anywhere. Only the methods of
+//! the requests, how often they failed and how long they took are recorded,
+//! nothing about the documents or the ledger.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often the statistics are written while the server runs, besides on
+/// exit.
+const WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The statistics of one request method.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MethodStats {
+    pub count: u64,
+    pub errors: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+impl MethodStats {
+    fn mean_ms(&self) -> f64 {
+        self.total_ms / self.count.max(1) as f64
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report<'a> {
+    server_version: &'static str,
+    since: &'a str,
+    methods: BTreeMap<&'a str, MethodReport<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MethodReport<'a> {
+    #[serde(flatten)]
+    stats: &'a MethodStats,
+    mean_ms: f64,
+}
+
+/// The statistics collected since the server started.
+#[derive(Debug)]
+pub(crate) struct Telemetry {
+    since: String,
+    methods: BTreeMap<String, MethodStats>,
+    last_write: Instant,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            since: chrono::Local::now().to_rfc3339(),
+            methods: BTreeMap::new(),
+            last_write: Instant::now(),
+        }
+    }
+}
+
+impl Telemetry {
+    /// Records a request to `method` that took `duration`.
+    pub(crate) fn record(&mut self, method: &str, duration: Duration, error: bool) {
+        let ms = duration.as_micros() as f64 / 1000.0;
+        let stats = self.methods.entry(method.to_string()).or_default();
+        stats.count += 1;
+        stats.errors += u64::from(error);
+        stats.total_ms += ms;
+        stats.max_ms = stats.max_ms.max(ms);
+    }
+
+    /// The statistics as the JSON written to the file.
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let report = Report {
+            server_version: env!("CARGO_PKG_VERSION"),
+            since: &self.since,
+            methods: self
+                .methods
+                .iter()
+                .map(|(method, stats)| {
+                    let report = MethodReport {
+                        stats,
+                        mean_ms: stats.mean_ms(),
+                    };
+                    (method.as_str(), report)
+                })
+                .collect(),
+        };
+        serde_json::to_value(report).unwrap_or_default()
+    }
+
+    /// Writes the statistics to `path`, unless they were written less than
+    /// a minute ago and `force` is false.
+    pub(crate) fn write(&mut self, path: &Path, force: bool) {
+        if !force && self.last_write.elapsed() < WRITE_INTERVAL {
+            return;
+        }
+        self.last_write = Instant::now();
+        let json = serde_json::to_string_pretty(&self.to_json()).unwrap_or_default();
+        if let Err(e) = std::fs::write(path, json) {
+            tracing::warn!("could not write telemetry to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_telemetry_record() {
+        let mut telemetry = Telemetry::default();
+        telemetry.record("textDocument/completion", Duration::from_millis(10), false);
+        telemetry.record("textDocument/completion", Duration::from_millis(30), true);
+        telemetry.record("textDocument/hover", Duration::from_millis(2), false);
+
+        let json = telemetry.to_json();
+        let completion = &json["methods"]["textDocument/completion"];
+        assert_eq!(completion["count"], 2);
+        assert_eq!(completion["errors"], 1);
+        assert_eq!(completion["totalMs"], 40.0);
+        assert_eq!(completion["maxMs"], 30.0);
+        assert_eq!(completion["meanMs"], 20.0);
+        assert_eq!(json["methods"]["textDocument/hover"]["count"], 1);
+        assert_eq!(json["serverVersion"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn handle_telemetry_write() {
+        let path =
+            std::env::temp_dir().join(format!("beancount-telemetry-{}.json", std::process::id()));
+        let mut telemetry = Telemetry::default();
+        telemetry.record("textDocument/formatting", Duration::from_millis(5), false);
+
+        // too soon after the start
+        telemetry.write(&path, false);
+        assert!(!path.exists());
+
+        telemetry.write(&path, true);
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["methods"]["textDocument/formatting"]["count"], 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use crate::beancount_data::BeancountData;
+use crate::config::Config;
+use crate::config::NumberFormatOptions;
+use crate::document::Document;
+use crate::server::LspServerStateSnapshot;
+pub use crate::utils::apply_edits;
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct Fixture {
+    pub documents: Vec<TestDocument>,
+}
+impl Fixture {
+    pub fn parse(input: &str) -> Self {
+        let mut documents = Vec::new();
+        let mut start = 0;
+        if !input.is_empty() {
+            for end in input
+                .match_indices("%!")
+                .skip(1)
+                .map(|(i, _)| i)
+                .chain(std::iter::once(input.len()))
+            {
+                documents.push(TestDocument::parse(&input[start..end]));
+                start = end;
+            }
+        }
+        Self { documents }
+    }
+}
+
+#[derive(Debug)]
+pub struct TestDocument {
+    pub path: String,
+    pub text: String,
+    pub cursor: Option<lsp_types::Position>,
+    // pub ranges: Vec<lsp_types::Range>,
+}
+impl TestDocument {
+    pub fn parse(input: &str) -> Self {
+        let mut lines = Vec::new();
+
+        let (path, input) = input
+            .trim()
+            .strip_prefix("%! ")
+            .map(|input| input.split_once('\n').unwrap_or((input, "")))
+            .unwrap();
+
+        let mut ranges = Vec::new();
+        let mut cursor = None;
+
+        for line in input.lines() {
+            if line.chars().all(|c| matches!(c, ' ' | '^' | '|' | '!')) && !line.is_empty() {
+                let index = (lines.len() - 1) as u32;
+
+                cursor = cursor.or_else(|| {
+                    let character = line.find('|')?;
+                    Some(lsp_types::Position::new(index, character as u32))
+                });
+
+                if let Some(start) = line.find('!') {
+                    let position = lsp_types::Position::new(index, start as u32);
+                    ranges.push(lsp_types::Range::new(position, position));
+                }
+
+                if let Some(start) = line.find('^') {
+                    let end = line.rfind('^').unwrap() + 1;
+                    ranges.push(lsp_types::Range::new(
+                        lsp_types::Position::new(index, start as u32),
+                        lsp_types::Position::new(index, end as u32),
+                    ));
+                }
+            } else {
+                lines.push(line);
+            }
+        }
+
+        Self {
+            path: path.to_string(),
+            text: lines.join("\n"),
+            cursor,
+            // ranges,
+        }
+    }
+}
+
+pub struct TestState {
+    pub fixture: Fixture,
+    pub snapshot: LspServerStateSnapshot,
+}
+impl TestState {
+    pub fn new(fixture: &str) -> Result<Self> {
+        Self::from_fixture(Fixture::parse(fixture))
+    }
+
+    pub fn from_fixture(fixture: Fixture) -> Result<Self> {
+        let forest: HashMap<PathBuf, tree_sitter::Tree> = fixture
+            .documents
+            .iter()
+            .map(|document| {
+                let path = document.path.as_str();
+                let k = lsp_types::Uri::from_str(format!("file://{path}").as_str())
+                    .unwrap()
+                    .to_file_path()
+                    .unwrap();
+                let mut parser = tree_sitter::Parser::new();
+                parser
+                    .set_language(&tree_sitter_beancount::language())
+                    .unwrap();
+                let v = parser.parse(document.text.clone(), None).unwrap();
+                (k, v)
+            })
+            .collect();
+        let beancount_data: HashMap<PathBuf, BeancountData> = fixture
+            .documents
+            .iter()
+            .map(|document| {
+                let path = document.path.as_str();
+                let k = lsp_types::Uri::from_str(format!("file://{path}").as_str())
+                    .unwrap()
+                    .to_file_path()
+                    .unwrap();
+                let content = ropey::Rope::from(document.text.clone());
+                let v = BeancountData::new(
+                    forest.get(&k).unwrap(),
+                    &content,
+                    Default::default(),
+                    NumberFormatOptions::default().for_content(&content),
+                );
+                (k, v)
+            })
+            .collect();
+        let open_docs: HashMap<PathBuf, Document> = fixture
+            .documents
+            .iter()
+            .map(|document| {
+                let path = document.path.as_str();
+                let k = lsp_types::Uri::from_str(format!("file://{path}").as_str())
+                    .unwrap()
+                    .to_file_path()
+                    .unwrap();
+                let v = Document {
+                    content: ropey::Rope::from(document.text.clone()),
+                    version: 0,
+                };
+                (k, v)
+            })
+            .collect();
+        Ok(TestState {
+            fixture,
+            snapshot: LspServerStateSnapshot {
+                beancount_data: Arc::new(beancount_data),
+                config: Arc::new(Config::new(std::env::current_dir()?)),
+                forest: Arc::new(forest),
+                journals: Arc::default(),
+                open_docs: Arc::new(open_docs),
+                cancelled: Default::default(),
+                failed_checkers: Default::default(),
+                diagnostics: Arc::default(),
+                last_checks: Arc::default(),
+            },
+        })
+    }
+
+    pub fn cursor(&self) -> Option<lsp_types::TextDocumentPositionParams> {
+        let (document, cursor) = self
+            .fixture
+            .documents
+            .iter()
+            .find_map(|document| document.cursor.map(|cursor| (document, cursor)))?;
+
+        let path = document.path.as_str();
+        let uri = lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap();
+        let id = lsp_types::TextDocumentIdentifier::new(uri);
+        Some(lsp_types::TextDocumentPositionParams::new(id, cursor))
+    }
+}
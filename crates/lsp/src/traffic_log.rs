@@ -0,0 +1,255 @@
+//! Recording of the messages exchanged with the client, for debugging
+//! protocol issues with specific clients. Each message is a line of JSON in
+//! the log file, which is rolled over to `<file>.1`, `<file>.2`, ... once it
+//! grows past a size. Document text is redacted unless asked otherwise.
+
+use crossbeam_channel::Receiver;
+use crossbeam_channel::Sender;
+use lsp_server::Connection;
+use lsp_server::Message;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// The size past which the log file is rolled over.
+pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// How many rolled over files are kept besides the log file.
+pub const DEFAULT_MAX_FILES: usize = 3;
+
+/// The keys whose string values hold document text: the content of
+/// `didOpen`, `didChange` and `didSave` and the text of edits.
+const REDACTED_KEYS: [&str; 2] = ["text", "newText"];
+
+/// Where and how the traffic is recorded.
+#[derive(Debug, Clone)]
+pub struct TrafficLogOptions {
+    pub path: PathBuf,
+    /// Whether document text is replaced by its length.
+    pub redact: bool,
+    pub max_bytes: u64,
+    pub max_files: usize,
+}
+
+impl TrafficLogOptions {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            redact: true,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    time: String,
+    direction: &'a str,
+    message: serde_json::Value,
+}
+
+/// The log file, shared by the threads forwarding each direction.
+#[derive(Debug)]
+pub(crate) struct TrafficLog {
+    options: TrafficLogOptions,
+    file: Option<fs::File>,
+    size: u64,
+}
+
+impl TrafficLog {
+    pub(crate) fn new(options: TrafficLogOptions) -> Self {
+        Self {
+            options,
+            file: None,
+            size: 0,
+        }
+    }
+
+    /// Appends `message` to the log, `direction` being `in` for messages of
+    /// the client and `out` for those of the server.
+    pub(crate) fn record(&mut self, direction: &str, message: &Message) {
+        let mut message = serde_json::to_value(message).unwrap_or_default();
+        if self.options.redact {
+            redact(&mut message);
+        }
+        let entry = Entry {
+            time: chrono::Local::now().to_rfc3339(),
+            direction,
+            message,
+        };
+        let mut line = serde_json::to_string(&entry).unwrap_or_default();
+        line.push('\n');
+        if let Err(e) = self.write(line.as_bytes()) {
+            tracing::warn!(
+                "could not write lsp traffic to {}: {}",
+                self.options.path.display(),
+                e
+            );
+        }
+    }
+
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.file.is_some() && self.size + line.len() as u64 > self.options.max_bytes {
+            self.file = None;
+            self.roll_over()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.options.path)?;
+                self.size = file.metadata()?.len();
+                self.file.insert(file)
+            }
+        };
+        file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Moves the log file to `<file>.1`, shifting the older files and
+    /// dropping the oldest one.
+    fn roll_over(&self) -> std::io::Result<()> {
+        let rolled = |index: usize| {
+            let mut path = self.options.path.clone().into_os_string();
+            path.push(format!(".{index}"));
+            PathBuf::from(path)
+        };
+        if self.options.max_files == 0 {
+            return fs::remove_file(&self.options.path);
+        }
+        let _ = fs::remove_file(rolled(self.options.max_files));
+        for index in (1..self.options.max_files).rev() {
+            if rolled(index).exists() {
+                fs::rename(rolled(index), rolled(index + 1))?;
+            }
+        }
+        fs::rename(&self.options.path, rolled(1))
+    }
+}
+
+/// Replaces the document text in `value` by its length.
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    serde_json::Value::String(text) if REDACTED_KEYS.contains(&key.as_str()) => {
+                        *text = format!("<redacted {} bytes>", text.len());
+                    }
+                    _ => redact(value),
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// A connection recording the messages of `connection` as they pass, with
+/// threads forwarding them in each direction. The threads stop when the
+/// client closes its side or the server drops its sender.
+pub(crate) fn record(connection: Connection, log: TrafficLog) -> Connection {
+    let log = Arc::new(Mutex::new(log));
+    let (sender, outgoing) = crossbeam_channel::unbounded();
+    let (incoming, receiver) = crossbeam_channel::unbounded();
+    forward(connection.receiver, incoming, "in", log.clone());
+    forward(outgoing, connection.sender, "out", log);
+    Connection { sender, receiver }
+}
+
+fn forward(
+    from: Receiver<Message>,
+    to: Sender<Message>,
+    direction: &'static str,
+    log: Arc<Mutex<TrafficLog>>,
+) {
+    std::thread::spawn(move || {
+        for message in from {
+            if let Ok(mut log) = log.lock() {
+                log.record(direction, &message);
+            }
+            if to.send(message).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn did_open(text: &str) -> Message {
+        Message::Notification(lsp_server::Notification::new(
+            String::from("textDocument/didOpen"),
+            serde_json::json!({
+                "textDocument": {
+                    "uri": "file:///main.beancount",
+                    "languageId": "beancount",
+                    "version": 1,
+                    "text": text,
+                }
+            }),
+        ))
+    }
+
+    #[test]
+    fn handle_traffic_log_redaction() {
+        let path =
+            std::env::temp_dir().join(format!("beancount-traffic-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let text = "2024-01-01 open Assets:Bank\n";
+
+        let mut log = TrafficLog::new(TrafficLogOptions::new(path.clone()));
+        log.record("in", &did_open(text));
+        log.options.redact = false;
+        log.record("in", &did_open(text));
+
+        let written = fs::read_to_string(&path).unwrap();
+        let entries: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["direction"], "in");
+        assert_eq!(entries[0]["message"]["method"], "textDocument/didOpen");
+        let document = |index: usize| &entries[index]["message"]["params"]["textDocument"];
+        assert_eq!(document(0)["text"], "<redacted 28 bytes>");
+        assert_eq!(document(0)["uri"], "file:///main.beancount");
+        assert_eq!(document(1)["text"], text);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn handle_traffic_log_roll_over() {
+        let dir = std::env::temp_dir().join(format!("beancount-traffic-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lsp.jsonl");
+
+        let mut options = TrafficLogOptions::new(path.clone());
+        options.max_bytes = 1;
+        options.max_files = 2;
+        let mut log = TrafficLog::new(options);
+        for _ in 0..4 {
+            log.record("out", &did_open(""));
+        }
+
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["lsp.jsonl", "lsp.jsonl.1", "lsp.jsonl.2"]);
+        // each file holds a single message, as each one is too big
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,45 @@
+/// The unit LSP positions count columns in, negotiated with the client at
+/// initialization. Tree-sitter always counts bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Picks the cheapest encoding the client supports: UTF-8 matches the
+    /// tree-sitter byte columns, and UTF-16 is the fallback every client has
+    /// to understand.
+    pub fn negotiate(capabilities: &lsp_types::ClientCapabilities) -> Self {
+        let supported = capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_deref())
+            .unwrap_or_default();
+        if supported.contains(&lsp_types::PositionEncodingKind::UTF8) {
+            Self::Utf8
+        } else if supported.contains(&lsp_types::PositionEncodingKind::UTF32) {
+            Self::Utf32
+        } else {
+            Self::Utf16
+        }
+    }
+
+    pub fn kind(self) -> lsp_types::PositionEncodingKind {
+        match self {
+            Self::Utf8 => lsp_types::PositionEncodingKind::UTF8,
+            Self::Utf16 => lsp_types::PositionEncodingKind::UTF16,
+            Self::Utf32 => lsp_types::PositionEncodingKind::UTF32,
+        }
+    }
+}
+
 pub fn lsp_textdocchange_to_ts_inputedit(
     source: &ropey::Rope,
     change: &lsp_types::TextDocumentContentChangeEvent,
+    encoding: PositionEncoding,
 ) -> anyhow::Result<tree_sitter::InputEdit> {
     let text = change.text.as_str();
     let text_bytes = text.as_bytes();
@@ -9,27 +48,25 @@ pub fn lsp_textdocchange_to_ts_inputedit(
     let range = if let Some(range) = change.range {
         range
     } else {
-        let start = byte_to_lsp_position(source, 0);
-        let end = byte_to_lsp_position(source, text_end_byte_idx);
+        let start = byte_to_lsp_position(source, 0, encoding);
+        let end = byte_to_lsp_position(source, source.len_bytes(), encoding);
         lsp_types::Range { start, end }
     };
 
-    let start = lsp_position_to_core(source, range.start)?;
-    let old_end = lsp_position_to_core(source, range.end)?;
+    let start = lsp_position_to_core(source, range.start, encoding)?;
+    let old_end = lsp_position_to_core(source, range.end, encoding)?;
 
     let new_end_byte = start.byte as usize + text_end_byte_idx;
 
-    let new_end_position = {
-        if new_end_byte >= source.len_bytes() {
-            let line_idx = text.lines().count();
-            let line_byte_idx = ropey::str_utils::line_to_byte_idx(text, line_idx);
-            let row = u32::try_from(source.len_lines() + line_idx).unwrap() as usize;
-            let column = u32::try_from(text_end_byte_idx - line_byte_idx)? as usize;
-            Ok(tree_sitter::Point::new(row, column))
-        } else {
-            byte_to_tree_sitter_point(source, new_end_byte)
-        }
-    }?;
+    // tree-sitter only starts a new row after `\n`, so a `\r` before it
+    // stays at the end of the previous row
+    let new_end_position = match text.rfind('\n') {
+        Some(last_newline) => tree_sitter::Point::new(
+            start.point.row + text.matches('\n').count(),
+            text_end_byte_idx - last_newline - 1,
+        ),
+        None => tree_sitter::Point::new(start.point.row, start.point.column + text_end_byte_idx),
+    };
 
     Ok(tree_sitter::InputEdit {
         start_byte: start.byte as usize,
@@ -41,23 +78,46 @@ pub fn lsp_textdocchange_to_ts_inputedit(
     })
 }
 
-fn byte_to_lsp_position(text: &ropey::Rope, byte_idx: usize) -> lsp_types::Position {
+/// Converts a byte index into an LSP position with columns in `encoding`.
+pub fn byte_to_lsp_position(
+    text: &ropey::Rope,
+    byte_idx: usize,
+    encoding: PositionEncoding,
+) -> lsp_types::Position {
     let line_idx = text.byte_to_line(byte_idx);
+    let line_char_idx = text.line_to_char(line_idx);
+    let char_idx = text.byte_to_char(byte_idx);
 
-    let line_utf16_cu_idx = {
-        let char_idx = text.line_to_char(line_idx);
-        text.char_to_utf16_cu(char_idx)
-    };
-
-    let character_utf16_cu_idx = {
-        let char_idx = text.byte_to_char(byte_idx);
-        text.char_to_utf16_cu(char_idx)
+    let character = match encoding {
+        PositionEncoding::Utf8 => byte_idx - text.line_to_byte(line_idx),
+        PositionEncoding::Utf16 => {
+            text.char_to_utf16_cu(char_idx) - text.char_to_utf16_cu(line_char_idx)
+        }
+        PositionEncoding::Utf32 => char_idx - line_char_idx,
     };
 
-    let line = line_idx;
-    let character = character_utf16_cu_idx - line_utf16_cu_idx;
+    lsp_types::Position::new(line_idx as u32, character as u32)
+}
 
-    lsp_types::Position::new(line as u32, character as u32)
+/// Converts an LSP position with a column in `encoding` into a char index,
+/// clamping positions past the end of a line or of the document.
+pub fn lsp_position_to_char(
+    source: &ropey::Rope,
+    position: lsp_types::Position,
+    encoding: PositionEncoding,
+) -> usize {
+    let row = position.line as usize;
+    if row >= source.len_lines() {
+        return source.len_chars();
+    }
+    let line = source.line(row);
+    let column = position.character as usize;
+    let col_char_idx = match encoding {
+        PositionEncoding::Utf8 => line.byte_to_char(column.min(line.len_bytes())),
+        PositionEncoding::Utf16 => line.utf16_cu_to_char(column.min(line.len_utf16_cu())),
+        PositionEncoding::Utf32 => column.min(line.len_chars()),
+    };
+    source.line_to_char(row) + col_char_idx
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -71,30 +131,16 @@ struct TextPosition {
 fn lsp_position_to_core(
     source: &ropey::Rope,
     position: lsp_types::Position,
+    encoding: PositionEncoding,
 ) -> anyhow::Result<TextPosition> {
-    let row_idx = position.line as usize;
-
-    let col_code_idx = position.character as usize;
-
-    let row_char_idx = source.line_to_char(row_idx);
-    let col_char_idx = source.utf16_cu_to_char(col_code_idx);
-
-    let row_byte_idx = source.line_to_byte(row_idx);
-    let col_byte_idx = source.char_to_byte(col_char_idx);
-
-    let row_code_idx = source.char_to_utf16_cu(row_char_idx);
-
-    let point = {
-        let row = position.line as usize;
-        let col = u32::try_from(col_byte_idx)? as usize;
-        tree_sitter::Point::new(row, col)
-    };
+    let char_idx = lsp_position_to_char(source, position, encoding);
+    let byte_idx = source.char_to_byte(char_idx);
 
     Ok(TextPosition {
-        char: u32::try_from(row_char_idx + col_char_idx)?,
-        byte: u32::try_from(row_byte_idx + col_byte_idx)?,
-        code: u32::try_from(row_code_idx + col_code_idx)?,
-        point,
+        char: u32::try_from(char_idx)?,
+        byte: u32::try_from(byte_idx)?,
+        code: u32::try_from(source.char_to_utf16_cu(char_idx))?,
+        point: byte_to_tree_sitter_point(source, byte_idx)?,
     })
 }
 
@@ -118,3 +164,162 @@ pub fn text_for_tree_sitter_node(
     let slice = source.slice(start..end);
     slice.into()
 }
+
+/// Converts the span of `node` into an LSP range with columns in `encoding`.
+pub fn lsp_range_for_tree_sitter_node(
+    source: &ropey::Rope,
+    node: &tree_sitter::Node,
+    encoding: PositionEncoding,
+) -> lsp_types::Range {
+    lsp_types::Range {
+        start: byte_to_lsp_position(source, node.start_byte(), encoding),
+        end: byte_to_lsp_position(source, node.end_byte(), encoding),
+    }
+}
+
+/// Converts a tree-sitter point with a byte column into an LSP position with a
+/// column in `encoding`.
+pub fn lsp_position_for_tree_sitter_point(
+    source: &ropey::Rope,
+    point: tree_sitter::Point,
+    encoding: PositionEncoding,
+) -> lsp_types::Position {
+    byte_to_lsp_position(
+        source,
+        source.line_to_byte(point.row) + point.column,
+        encoding,
+    )
+}
+
+/// Converts an LSP position with a column in `encoding` into a tree-sitter
+/// point with a byte column.
+pub fn tree_sitter_point_for_lsp_position(
+    source: &ropey::Rope,
+    position: lsp_types::Position,
+    encoding: PositionEncoding,
+) -> tree_sitter::Point {
+    let row = (position.line as usize).min(source.len_lines().saturating_sub(1));
+    let position = lsp_types::Position::new(row as u32, position.character);
+    let char_idx = lsp_position_to_char(source, position, encoding);
+    tree_sitter::Point::new(
+        row,
+        source.char_to_byte(char_idx) - source.line_to_byte(row),
+    )
+}
+
+/// The smallest named node at `position`. A position right after a node, as
+/// when the cursor is at the end of a word, also selects that node.
+pub fn named_node_at_lsp_position<'a>(
+    tree: &'a tree_sitter::Tree,
+    source: &ropey::Rope,
+    position: lsp_types::Position,
+    encoding: PositionEncoding,
+) -> Option<tree_sitter::Node<'a>> {
+    let point = tree_sitter_point_for_lsp_position(source, position, encoding);
+    let node = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point)?;
+    if node.start_position() < point && point < node.end_position() || point.column == 0 {
+        return Some(node);
+    }
+    let before = tree_sitter::Point::new(point.row, point.column - 1);
+    tree.root_node()
+        .named_descendant_for_point_range(before, before)
+        .filter(|before| before.end_position() == point)
+        .or(Some(node))
+}
+
+/// The ABI version of the bundled tree-sitter-beancount grammar.
+pub fn grammar_version() -> usize {
+    tree_sitter_beancount::language().version()
+}
+
+/// The top level entry, such as a transaction or an `open` directive, that
+/// contains `node`.
+pub fn entry_for_tree_sitter_node<'a>(
+    node: tree_sitter::Node<'a>,
+) -> Option<tree_sitter::Node<'a>> {
+    let mut node = node;
+    while let Some(parent) = node.parent() {
+        if matches!(parent.kind(), "file" | "section") {
+            return Some(node);
+        }
+        node = parent;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "é" is two bytes and one UTF-16 code unit, "📚" four bytes and two code units
+    const TEXT: &str = "2024-01-01 * \"Café\"\n  Expenses:📚  5 EUR\n";
+
+    #[test]
+    fn handle_position_encodings() {
+        let source = ropey::Rope::from_str(TEXT);
+        let amount = TEXT.find('5').unwrap();
+        for (encoding, character) in [
+            (PositionEncoding::Utf8, 17),
+            (PositionEncoding::Utf16, 15),
+            (PositionEncoding::Utf32, 14),
+        ] {
+            let position = lsp_types::Position::new(1, character);
+            assert_eq!(byte_to_lsp_position(&source, amount, encoding), position);
+            assert_eq!(
+                source.char_to_byte(lsp_position_to_char(&source, position, encoding)),
+                amount
+            );
+            assert_eq!(
+                tree_sitter_point_for_lsp_position(&source, position, encoding),
+                tree_sitter::Point::new(1, 17)
+            );
+        }
+    }
+
+    #[test]
+    fn handle_positions_past_the_end() {
+        let source = ropey::Rope::from_str(TEXT);
+        let position = lsp_types::Position::new(0, 100);
+        assert_eq!(
+            lsp_position_to_char(&source, position, PositionEncoding::Utf16),
+            source.line_to_char(1)
+        );
+        let position = lsp_types::Position::new(10, 0);
+        assert_eq!(
+            lsp_position_to_char(&source, position, PositionEncoding::Utf8),
+            source.len_chars()
+        );
+    }
+
+    #[test]
+    fn handle_position_encoding_negotiation() {
+        let capabilities = |encodings: Option<Vec<lsp_types::PositionEncodingKind>>| {
+            lsp_types::ClientCapabilities {
+                general: Some(lsp_types::GeneralClientCapabilities {
+                    position_encodings: encodings,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        };
+        assert_eq!(
+            PositionEncoding::negotiate(&Default::default()),
+            PositionEncoding::Utf16
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(&capabilities(Some(vec![
+                lsp_types::PositionEncodingKind::UTF16,
+                lsp_types::PositionEncodingKind::UTF8,
+            ]))),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(&capabilities(Some(vec![
+                lsp_types::PositionEncodingKind::UTF32
+            ]))),
+            PositionEncoding::Utf32
+        );
+    }
+}
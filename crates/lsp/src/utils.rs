@@ -1,3 +1,5 @@
+use crate::treesitter_utils::lsp_position_to_char;
+use crate::treesitter_utils::PositionEncoding;
 use std::path::PathBuf;
 use std::str::FromStr;
 
@@ -14,3 +16,17 @@ impl ToFilePath for lsp_types::Uri {
         url.to_file_path()
     }
 }
+
+/// Applies `edits` to `text`, assuming the edits don't overlap.
+pub fn apply_edits(text: &str, edits: &[lsp_types::TextEdit]) -> String {
+    let mut content = ropey::Rope::from_str(text);
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    for edit in edits.iter().rev() {
+        let start = lsp_position_to_char(&content, edit.range.start, PositionEncoding::Utf16);
+        let end = lsp_position_to_char(&content, edit.range.end, PositionEncoding::Utf16);
+        content.remove(start..end);
+        content.insert(start, &edit.new_text);
+    }
+    content.to_string()
+}
@@ -0,0 +1,108 @@
+//! Workspace edits that create files as well as edit documents, which the
+//! `changes` map of [`lsp_types::WorkspaceEdit`] cannot express.
+
+/// Collects file creations and text edits in the order they are to be
+/// applied.
+#[derive(Debug, Default)]
+pub(crate) struct WorkspaceEditBuilder {
+    operations: Vec<lsp_types::DocumentChangeOperation>,
+}
+
+impl WorkspaceEditBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty file, failing if it exists unless `ignore_if_exists`.
+    pub fn create_file(&mut self, uri: lsp_types::Uri, ignore_if_exists: bool) -> &mut Self {
+        self.operations.push(lsp_types::DocumentChangeOperation::Op(
+            lsp_types::ResourceOp::Create(lsp_types::CreateFile {
+                uri,
+                options: Some(lsp_types::CreateFileOptions {
+                    overwrite: Some(false),
+                    ignore_if_exists: Some(ignore_if_exists),
+                }),
+                annotation_id: None,
+            }),
+        ));
+        self
+    }
+
+    /// Edits the document at `uri`. Consecutive edits of a document are
+    /// grouped, their ranges referring to the document before any of them.
+    pub fn edit(&mut self, uri: lsp_types::Uri, edit: lsp_types::TextEdit) -> &mut Self {
+        if let Some(lsp_types::DocumentChangeOperation::Edit(document_edit)) =
+            self.operations.last_mut()
+        {
+            if document_edit.text_document.uri == uri {
+                document_edit.edits.push(lsp_types::OneOf::Left(edit));
+                return self;
+            }
+        }
+        self.operations
+            .push(lsp_types::DocumentChangeOperation::Edit(
+                lsp_types::TextDocumentEdit {
+                    text_document: lsp_types::OptionalVersionedTextDocumentIdentifier {
+                        uri,
+                        version: None,
+                    },
+                    edits: vec![lsp_types::OneOf::Left(edit)],
+                },
+            ));
+        self
+    }
+
+    pub fn build(&self) -> lsp_types::WorkspaceEdit {
+        lsp_types::WorkspaceEdit {
+            document_changes: Some(lsp_types::DocumentChanges::Operations(
+                self.operations.clone(),
+            )),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn handle_workspace_edit_builder() {
+        let new = lsp_types::Uri::from_str("file:///new.beancount").unwrap();
+        let main = lsp_types::Uri::from_str("file:///main.beancount").unwrap();
+        let insert = |text: &str| lsp_types::TextEdit {
+            range: lsp_types::Range::default(),
+            new_text: text.to_string(),
+        };
+        let edit = WorkspaceEditBuilder::new()
+            .create_file(new.clone(), false)
+            .edit(new.clone(), insert("a"))
+            .edit(new.clone(), insert("b"))
+            .edit(main.clone(), insert("c"))
+            .build();
+        let Some(lsp_types::DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected operations");
+        };
+        let uris: Vec<_> = operations
+            .iter()
+            .map(|operation| match operation {
+                lsp_types::DocumentChangeOperation::Op(lsp_types::ResourceOp::Create(create)) => {
+                    (create.uri.as_str(), 0)
+                }
+                lsp_types::DocumentChangeOperation::Edit(edit) => {
+                    (edit.text_document.uri.as_str(), edit.edits.len())
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            uris,
+            [
+                ("file:///new.beancount", 0),
+                ("file:///new.beancount", 2),
+                ("file:///main.beancount", 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,14 @@
+#!/bin/sh
+# Regenerates the expected outputs of the `bean_format_corpus` test with the
+# real `bean-format`, plain and with `--currency-column 50` and `60`, and
+# records the version of beancount they come from in BEANCOUNT_VERSION.
+set -eu
+cd "$(dirname "$0")"
+for input in *.beancount; do
+    name=${input%.beancount}
+    bean-format "$input" >"$name.formatted"
+    for column in 50 60; do
+        bean-format --currency-column "$column" "$input" >"$name.c$column.formatted"
+    done
+done
+python3 -c 'import beancount; print(beancount.__version__)' >BEANCOUNT_VERSION
@@ -0,0 +1,349 @@
+mod support;
+
+use beancount_language_server::lsp_ext::JournalResolved;
+use insta::assert_json_snapshot;
+use lsp_types::notification::PublishDiagnostics;
+use serde_json::json;
+use support::TestServer;
+
+#[test]
+fn initialize_advertises_capabilities() {
+    let server = TestServer::new(json!({}));
+    let result = &server.initialize_result;
+    assert_eq!(result["serverInfo"]["name"], "beancount-language-server");
+    assert_json_snapshot!(result["capabilities"]);
+    server.shutdown();
+}
+
+#[test]
+fn file_watchers_are_registered() {
+    let capabilities = lsp_types::ClientCapabilities {
+        workspace: Some(lsp_types::WorkspaceClientCapabilities {
+            did_change_watched_files: Some(lsp_types::DidChangeWatchedFilesClientCapabilities {
+                dynamic_registration: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut server = TestServer::with_capabilities(capabilities, json!({}));
+    let params = server.wait_for_request::<lsp_types::request::RegisterCapability>();
+    assert_json_snapshot!(params);
+    server.shutdown();
+}
+
+#[test]
+fn completion_after_change() {
+    let mut server = TestServer::new(json!({}));
+    let document = server.open("/main.beancount", "2023-10-01 \n");
+    server.notify::<lsp_types::notification::DidChangeTextDocument>(
+        lsp_types::DidChangeTextDocumentParams {
+            text_document: lsp_types::VersionedTextDocumentIdentifier::new(document.uri.clone(), 2),
+            content_changes: vec![lsp_types::TextDocumentContentChangeEvent {
+                range: Some(lsp_types::Range::new(
+                    lsp_types::Position::new(0, 11),
+                    lsp_types::Position::new(0, 11),
+                )),
+                range_length: None,
+                text: "t".to_string(),
+            }],
+        },
+    );
+    let result = server.request::<lsp_types::request::Completion>(lsp_types::CompletionParams {
+        text_document_position: lsp_types::TextDocumentPositionParams::new(
+            document,
+            lsp_types::Position::new(0, 12),
+        ),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    });
+    assert_json_snapshot!(result);
+    server.shutdown();
+}
+
+#[test]
+fn formatting_aligns_amounts() {
+    let mut server = TestServer::new(json!({}));
+    let document = server.open(
+        "/main.beancount",
+        "2023-10-01 txn \"Test Co\"\n    Assets:Test 1.00 USD\n    Expenses:Test:Long  -1.00 USD\n",
+    );
+    let result =
+        server.request::<lsp_types::request::Formatting>(lsp_types::DocumentFormattingParams {
+            text_document: document,
+            options: lsp_types::FormattingOptions {
+                tab_size: 4,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        });
+    assert_json_snapshot!(result);
+    server.shutdown();
+}
+
+#[test]
+fn diagnostics_are_published_on_open() {
+    if !support::has_bean_check() {
+        eprintln!("skipping: bean-check is not installed");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("beancount-lsp-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.beancount");
+    let text = "2023-10-01 txn \"Test Co\"\n    Assets:Unknown  1.00 USD\n    Expenses:Unknown\n";
+    std::fs::write(&path, text).unwrap();
+
+    let mut server = TestServer::new(json!({}));
+    let document = server.open(path.to_str().unwrap(), text);
+    let params =
+        server.wait_for_notification::<lsp_types::notification::PublishDiagnostics>(|params| {
+            params.uri == document.uri
+        });
+    assert!(
+        !params.diagnostics.is_empty(),
+        "expected diagnostics for undeclared accounts"
+    );
+    server.shutdown();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn requests_after_shutdown_are_rejected() {
+    let mut server = TestServer::new(json!({}));
+    server.request::<lsp_types::request::Shutdown>(());
+    let response = server.request_response::<lsp_types::request::WorkspaceSymbolRequest>(
+        lsp_types::WorkspaceSymbolParams {
+            query: String::new(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        },
+    );
+    assert_eq!(
+        response.error.map(|error| error.code),
+        Some(lsp_server::ErrorCode::InvalidRequest as i32)
+    );
+    server.notify::<lsp_types::notification::Exit>(());
+    server.join().unwrap();
+}
+
+#[test]
+fn exit_without_shutdown_fails() {
+    let mut server = TestServer::new(json!({}));
+    server.notify::<lsp_types::notification::Exit>(());
+    assert!(server.join().is_err());
+}
+
+enum UnknownRequest {}
+
+impl lsp_types::request::Request for UnknownRequest {
+    type Params = serde_json::Value;
+    type Result = serde_json::Value;
+    const METHOD: &'static str = "beancount/unknown";
+}
+
+#[test]
+fn unsupported_methods_are_answered() {
+    let mut server = TestServer::new(json!({}));
+    let response = server.request_response::<UnknownRequest>(json!({}));
+    assert_eq!(
+        response.error.map(|error| error.code),
+        Some(lsp_server::ErrorCode::MethodNotFound as i32)
+    );
+
+    let document = server.open("/main.beancount", "2023-10-01 \n");
+    let result = server.request::<lsp_types::request::WillSaveWaitUntil>(
+        lsp_types::WillSaveTextDocumentParams {
+            text_document: document.clone(),
+            reason: lsp_types::TextDocumentSaveReason::MANUAL,
+        },
+    );
+    assert_eq!(result, serde_json::Value::Null);
+
+    // notebooks are not advertised, and their notifications are dropped
+    server.notify::<lsp_types::notification::DidOpenNotebookDocument>(
+        lsp_types::DidOpenNotebookDocumentParams {
+            notebook_document: lsp_types::NotebookDocument {
+                uri: document.uri.clone(),
+                notebook_type: "jupyter-notebook".to_string(),
+                version: 1,
+                metadata: None,
+                cells: Vec::new(),
+            },
+            cell_text_documents: Vec::new(),
+        },
+    );
+    let result =
+        server.request::<lsp_types::request::Formatting>(lsp_types::DocumentFormattingParams {
+            text_document: document,
+            options: Default::default(),
+            work_done_progress_params: Default::default(),
+        });
+    assert_eq!(result, json!([]));
+    server.shutdown();
+}
+
+#[test]
+fn stale_changes_are_dropped() {
+    let mut server = TestServer::new(json!({}));
+    let document = server.open("/main.beancount", "2023-10-01 \n");
+    for (version, text) in [(2, "t"), (1, "zzz")] {
+        server.notify::<lsp_types::notification::DidChangeTextDocument>(
+            lsp_types::DidChangeTextDocumentParams {
+                text_document: lsp_types::VersionedTextDocumentIdentifier::new(
+                    document.uri.clone(),
+                    version,
+                ),
+                content_changes: vec![lsp_types::TextDocumentContentChangeEvent {
+                    range: Some(lsp_types::Range::new(
+                        lsp_types::Position::new(0, 11),
+                        lsp_types::Position::new(0, 11),
+                    )),
+                    range_length: None,
+                    text: text.to_string(),
+                }],
+            },
+        );
+    }
+    let result =
+        server.request::<lsp_types::request::Formatting>(lsp_types::DocumentFormattingParams {
+            text_document: document.clone(),
+            options: Default::default(),
+            work_done_progress_params: Default::default(),
+        });
+    assert_eq!(result, json!([]));
+    let result = server.request::<lsp_types::request::Completion>(lsp_types::CompletionParams {
+        text_document_position: lsp_types::TextDocumentPositionParams::new(
+            document,
+            lsp_types::Position::new(0, 12),
+        ),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    });
+    assert_eq!(result[0], json!({ "label": "txn", "kind": 1 }));
+    server.shutdown();
+}
+
+#[test]
+fn journal_resolution_is_reported() {
+    let mut server = TestServer::new(json!({}));
+    let params = server.wait_for_notification::<JournalResolved>(|_| true);
+    assert_eq!(params.journal_file, None);
+    assert!(!params.found);
+    server.shutdown();
+
+    let dir = std::env::temp_dir().join(format!("beancount-journal-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.beancount");
+    std::fs::write(&main, "include \"accounts.beancount\"\n").unwrap();
+    std::fs::write(
+        dir.join("accounts.beancount"),
+        "2023-01-01 open Assets:Bank\n",
+    )
+    .unwrap();
+
+    let mut server = TestServer::new(json!({
+        "journal_file": main.to_str().unwrap(),
+        "checker": { "method": ["native"] },
+    }));
+    let params = server.wait_for_notification::<JournalResolved>(|_| true);
+    assert_eq!(params.journal_file, Some(main));
+    assert!(params.found);
+    assert_eq!(params.included_files, 1);
+    assert_eq!(params.checker, "native");
+    server.shutdown();
+
+    let mut server = TestServer::new(json!({ "journal_file": "/does/not/exist.beancount" }));
+    let params = server.wait_for_notification::<JournalResolved>(|_| true);
+    assert!(!params.found);
+    let diagnostics = server.wait_for_notification::<PublishDiagnostics>(|_| true);
+    assert_eq!(diagnostics.uri.as_str(), "file:///does/not/exist.beancount");
+    assert_eq!(diagnostics.diagnostics.len(), 1);
+    server.shutdown();
+
+    let mut server = TestServer::new_in(&dir, json!({ "journal_file": "main.beancount" }));
+    let params = server.wait_for_notification::<JournalResolved>(|_| true);
+    assert_eq!(params.journal_file, Some(dir.join("main.beancount")));
+    assert!(params.found);
+    server.shutdown();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn multiple_journals_are_resolved() {
+    let dir = std::env::temp_dir().join(format!("beancount-journals-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("personal.beancount"),
+        "include \"commodities.beancount\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("business.beancount"),
+        "include \"commodities.beancount\"\ninclude \"payroll.beancount\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("commodities.beancount"),
+        "2023-01-01 commodity USD\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("payroll.beancount"),
+        "2023-01-01 open Assets:Payroll\n",
+    )
+    .unwrap();
+
+    let mut server = TestServer::new_in(
+        &dir,
+        json!({ "journal_files": ["personal.beancount", "business.beancount"] }),
+    );
+    let personal = server.wait_for_notification::<JournalResolved>(|_| true);
+    assert_eq!(personal.journal_file, Some(dir.join("personal.beancount")));
+    assert!(personal.found);
+    assert_eq!(personal.included_files, 1);
+    let business = server.wait_for_notification::<JournalResolved>(|_| true);
+    assert_eq!(business.journal_file, Some(dir.join("business.beancount")));
+    assert!(business.found);
+    assert_eq!(business.included_files, 2);
+    server.shutdown();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reload_follows_includes_of_open_documents() {
+    let dir = std::env::temp_dir().join(format!("beancount-reload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.beancount");
+    std::fs::write(&main, "include \"accounts.beancount\"\n").unwrap();
+    let accounts = "include \"ledger.beancount\"\n2023-01-01 open Assets:Bank\n";
+    std::fs::write(dir.join("accounts.beancount"), accounts).unwrap();
+    std::fs::write(
+        dir.join("ledger.beancount"),
+        "2023-01-02 * \"Grocer\"\n    Assets:Bank -1 USD\n    Expenses:Food\n",
+    )
+    .unwrap();
+
+    let mut server = TestServer::new(json!({
+        "journal_file": main.to_str().unwrap(),
+        "checker": { "method": ["native"] },
+    }));
+    let params = server.wait_for_notification::<JournalResolved>(|_| true);
+    assert_eq!(params.included_files, 2);
+    // the included file is open, so the reload takes it from the editor
+    server.open(dir.join("accounts.beancount").to_str().unwrap(), accounts);
+    server.request::<lsp_types::request::ExecuteCommand>(lsp_types::ExecuteCommandParams {
+        command: "beancount.reloadWorkspace".to_string(),
+        arguments: vec![],
+        work_done_progress_params: Default::default(),
+    });
+    let message = server.wait_for_notification::<lsp_types::notification::ShowMessage>(|params| {
+        params.message.starts_with("Workspace reloaded")
+    });
+    assert_eq!(message.message, "Workspace reloaded, 3 files indexed");
+    server.shutdown();
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,223 @@
+//! A client that drives the server over an in-memory connection, so tests can
+//! exercise the whole protocol instead of calling providers directly.
+
+use lsp_server::Connection;
+use lsp_server::Message;
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use std::str::FromStr;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct TestServer {
+    client: Connection,
+    server: Option<JoinHandle<anyhow::Result<()>>>,
+    next_id: i32,
+    /// Notifications received while waiting for something else.
+    notifications: Vec<lsp_server::Notification>,
+    /// Requests of the server received while waiting for something else.
+    requests: Vec<lsp_server::Request>,
+    pub initialize_result: serde_json::Value,
+}
+
+impl TestServer {
+    /// Starts a server and completes the `initialize` handshake.
+    pub fn new(initialization_options: serde_json::Value) -> Self {
+        Self::start(None, Default::default(), initialization_options)
+    }
+
+    /// Starts a server for the workspace rooted at `root`.
+    pub fn new_in(root: &std::path::Path, initialization_options: serde_json::Value) -> Self {
+        Self::start(
+            Some(uri(root.to_str().unwrap())),
+            Default::default(),
+            initialization_options,
+        )
+    }
+
+    /// Starts a server for a client with `capabilities`.
+    pub fn with_capabilities(
+        capabilities: lsp_types::ClientCapabilities,
+        initialization_options: serde_json::Value,
+    ) -> Self {
+        Self::start(None, capabilities, initialization_options)
+    }
+
+    #[allow(deprecated)]
+    fn start(
+        root_uri: Option<lsp_types::Uri>,
+        capabilities: lsp_types::ClientCapabilities,
+        initialization_options: serde_json::Value,
+    ) -> Self {
+        let (client, server) = Connection::memory();
+        let server = std::thread::spawn(move || beancount_language_server::serve(server));
+        let mut test_server = Self {
+            client,
+            server: Some(server),
+            next_id: 0,
+            notifications: Vec::new(),
+            requests: Vec::new(),
+            initialize_result: serde_json::Value::Null,
+        };
+        test_server.initialize_result =
+            test_server.request::<lsp_types::request::Initialize>(lsp_types::InitializeParams {
+                root_uri,
+                capabilities,
+                initialization_options: Some(initialization_options),
+                ..Default::default()
+            });
+        test_server.notify::<lsp_types::notification::Initialized>(lsp_types::InitializedParams {});
+        test_server
+    }
+
+    /// Sends a request and waits for its result, failing the test on an error
+    /// response.
+    pub fn request<R: Request>(&mut self, params: R::Params) -> serde_json::Value {
+        let response = self.request_response::<R>(params);
+        if let Some(error) = response.error {
+            panic!("{} failed: {:?}", R::METHOD, error);
+        }
+        response.result.unwrap_or_default()
+    }
+
+    /// Sends a request and waits for its response.
+    pub fn request_response<R: Request>(&mut self, params: R::Params) -> lsp_server::Response {
+        self.next_id += 1;
+        let id = lsp_server::RequestId::from(self.next_id);
+        self.send(lsp_server::Request::new(id.clone(), R::METHOD.to_string(), params).into());
+        loop {
+            match self.recv() {
+                Message::Response(response) if response.id == id => return response,
+                Message::Response(response) => panic!("unexpected response: {:?}", response),
+                message => self.handle(message),
+            }
+        }
+    }
+
+    pub fn notify<N: Notification>(&mut self, params: N::Params) {
+        self.send(lsp_server::Notification::new(N::METHOD.to_string(), params).into());
+    }
+
+    /// Waits for a notification of type `N` matching `predicate`, including
+    /// ones that arrived earlier.
+    pub fn wait_for_notification<N: Notification>(
+        &mut self,
+        predicate: impl Fn(&N::Params) -> bool,
+    ) -> N::Params {
+        let parse = |notification: &lsp_server::Notification| {
+            if notification.method != N::METHOD {
+                return None;
+            }
+            serde_json::from_value::<N::Params>(notification.params.clone())
+                .ok()
+                .filter(|params| predicate(params))
+        };
+        if let Some(index) = self
+            .notifications
+            .iter()
+            .position(|notification| parse(notification).is_some())
+        {
+            return parse(&self.notifications.remove(index)).unwrap();
+        }
+        loop {
+            match self.recv() {
+                Message::Notification(notification) => match parse(&notification) {
+                    Some(params) => return params,
+                    None => self.notifications.push(notification),
+                },
+                Message::Response(response) => panic!("unexpected response: {:?}", response),
+                message => self.handle(message),
+            }
+        }
+    }
+
+    /// Waits for a request of type `R` from the server, including ones that
+    /// arrived earlier. It has been answered with an empty result.
+    pub fn wait_for_request<R: Request>(&mut self) -> R::Params {
+        loop {
+            if let Some(index) = self
+                .requests
+                .iter()
+                .position(|request| request.method == R::METHOD)
+            {
+                let request = self.requests.remove(index);
+                return serde_json::from_value(request.params).unwrap();
+            }
+            match self.recv() {
+                Message::Response(response) => panic!("unexpected response: {:?}", response),
+                message => self.handle(message),
+            }
+        }
+    }
+
+    /// Opens `text` as `path` in the editor.
+    pub fn open(&mut self, path: &str, text: &str) -> lsp_types::TextDocumentIdentifier {
+        let uri = uri(path);
+        self.notify::<lsp_types::notification::DidOpenTextDocument>(
+            lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem::new(
+                    uri.clone(),
+                    "beancount".to_string(),
+                    1,
+                    text.to_string(),
+                ),
+            },
+        );
+        lsp_types::TextDocumentIdentifier::new(uri)
+    }
+
+    /// Sends `shutdown` and `exit` and waits for the server to stop.
+    pub fn shutdown(mut self) {
+        self.request::<lsp_types::request::Shutdown>(());
+        self.notify::<lsp_types::notification::Exit>(());
+        self.server
+            .take()
+            .unwrap()
+            .join()
+            .expect("server panicked")
+            .expect("server failed");
+    }
+
+    /// Takes the server thread, for tests that stop the server themselves.
+    pub fn join(mut self) -> anyhow::Result<()> {
+        self.server.take().unwrap().join().expect("server panicked")
+    }
+
+    fn send(&self, message: Message) {
+        self.client.sender.send(message).unwrap();
+    }
+
+    fn recv(&self) -> Message {
+        self.client
+            .receiver
+            .recv_timeout(TIMEOUT)
+            .expect("timed out waiting for the server")
+    }
+
+    /// Answers requests from the server, like progress creation, and keeps
+    /// them and notifications around for later.
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Request(request) => {
+                self.send(lsp_server::Response::new_ok(request.id.clone(), ()).into());
+                self.requests.push(request);
+            }
+            Message::Notification(notification) => self.notifications.push(notification),
+            Message::Response(_) => unreachable!(),
+        }
+    }
+}
+
+pub fn uri(path: &str) -> lsp_types::Uri {
+    lsp_types::Uri::from_str(format!("file://{path}").as_str()).unwrap()
+}
+
+/// Whether `bean-check` can be run, which the diagnostics tests need.
+pub fn has_bean_check() -> bool {
+    std::process::Command::new("bean-check")
+        .arg("--version")
+        .output()
+        .is_ok()
+}
@@ -0,0 +1,78 @@
+//! Python bindings of the language server. `main` runs the server on stdio
+//! like the binary does, and the other functions are those of `api`, working
+//! on the text of one document without a language server session. Results
+//! are the JSON of their LSP types as Python objects, like `json.loads` gives.
+
+use beancount_language_server::api;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+fn to_error(e: impl ToString) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// `value` as the Python object of its JSON.
+fn to_python(py: Python<'_>, value: impl serde::Serialize) -> PyResult<PyObject> {
+    let json = serde_json::to_string(&value).map_err(to_error)?;
+    Ok(py
+        .import("json")?
+        .call_method1("loads", (json,))?
+        .unbind())
+}
+
+/// The options a client would send, from a Python object of their JSON like
+/// `{"formatting": {"currencyColumn": 60}}`.
+fn from_python(config: Option<&Bound<'_, PyAny>>) -> PyResult<serde_json::Value> {
+    let Some(config) = config else {
+        return Ok(serde_json::Value::Null);
+    };
+    let json: String = config
+        .py()
+        .import("json")?
+        .call_method1("dumps", (config,))?
+        .extract()?;
+    serde_json::from_str(&json).map_err(to_error)
+}
+
+/// Runs the language server on stdio until the client exits.
+#[pyfunction]
+fn main() -> PyResult<()> {
+    beancount_language_server::run_server(None).map_err(to_error)
+}
+
+/// Formats `text` like `textDocument/formatting` does.
+#[pyfunction]
+#[pyo3(signature = (text, config=None))]
+fn format_string(text: &str, config: Option<&Bound<'_, PyAny>>) -> PyResult<String> {
+    api::format_string(text, from_python(config)?).map_err(to_error)
+}
+
+/// The diagnostics the server itself finds in `text`, without `bean-check`.
+#[pyfunction]
+#[pyo3(signature = (text, config=None))]
+fn lint(py: Python<'_>, text: &str, config: Option<&Bound<'_, PyAny>>) -> PyResult<PyObject> {
+    let diagnostics = api::lint(text, from_python(config)?).map_err(to_error)?;
+    to_python(py, diagnostics)
+}
+
+/// The outline of `text`, as `textDocument/documentSymbol` returns it.
+#[pyfunction]
+fn parse_symbols(py: Python<'_>, text: &str) -> PyResult<PyObject> {
+    to_python(py, api::parse_symbols(text).map_err(to_error)?)
+}
+
+/// The completions at the zero-based `line` and UTF-16 column `col` of `text`.
+#[pyfunction]
+fn complete(py: Python<'_>, text: &str, line: u32, col: u32) -> PyResult<PyObject> {
+    to_python(py, api::complete(text, line, col).map_err(to_error)?)
+}
+
+#[pymodule]
+fn beancount_lsp(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(main, module)?)?;
+    module.add_function(wrap_pyfunction!(format_string, module)?)?;
+    module.add_function(wrap_pyfunction!(lint, module)?)?;
+    module.add_function(wrap_pyfunction!(parse_symbols, module)?)?;
+    module.add_function(wrap_pyfunction!(complete, module)?)?;
+    Ok(())
+}